@@ -7,6 +7,8 @@ fn main() {
     let env_vars: HashSet<String> = std::env::vars().map(|(name, _)| name).collect();
     if env_vars.contains("CARGO_FEATURE_DB_SQLITE") {
         println!("cargo:rustc-env=DATABASE_URL=sqlite:db.sqlite")
+    } else if env_vars.contains("CARGO_FEATURE_DB_REDB") {
+        // no sqlx queries to check
     } else {
         panic!("unknown db configuration")
     }
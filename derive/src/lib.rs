@@ -0,0 +1,123 @@
+use proc_macro::TokenStream;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, spanned::Spanned, Data, DeriveInput, Error, Fields, LitStr};
+
+/**
+ *  derives value_store::document::Document for a struct with named fields.
+ *  generates `<Name>Fields` with one method per field returning a typed
+ *  value_store::document::Field at the path of that field.
+ *  the map key defaults to the field name and can be changed with `#[document(rename = "key")]`.
+ *  `#[document(crate = "path")]` on the struct sets the path of the value_store crate.
+ *  */
+#[proc_macro_derive(Document, attributes(document))]
+pub fn derive_document(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn key(field: &syn::Field) -> syn::Result<String> {
+    let mut key = field
+        .ident
+        .as_ref()
+        .expect("named field")
+        .to_string()
+        .trim_start_matches("r#")
+        .to_string();
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("document")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                key = meta.value()?.parse::<LitStr>()?.value();
+                Ok(())
+            } else {
+                Err(meta.error("unknown document attribute"))
+            }
+        })?;
+    }
+    Ok(key)
+}
+
+fn krate(input: &DeriveInput) -> syn::Result<syn::Path> {
+    let mut krate = syn::parse_quote!(::value_store);
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("document")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("crate") {
+                krate = meta.value()?.parse::<LitStr>()?.parse()?;
+                Ok(())
+            } else {
+                Err(meta.error("unknown document attribute"))
+            }
+        })?;
+    }
+    Ok(krate)
+}
+
+fn expand(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let krate = krate(&input)?;
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new(
+                    input.span(),
+                    "Document can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new(
+                input.span(),
+                "Document can only be derived for structs",
+            ))
+        }
+    };
+    if !input.generics.params.is_empty() {
+        return Err(Error::new(
+            input.generics.span(),
+            "Document can't be derived for generic structs",
+        ));
+    }
+    let name = &input.ident;
+    let vis = &input.vis;
+    let fields_name = format_ident!("{}Fields", name);
+    let accessors = fields
+        .iter()
+        .map(|field| {
+            let ident = field.ident.as_ref().expect("named field");
+            let ty = &field.ty;
+            let field_vis = &field.vis;
+            let key = key(field)?;
+            Ok(quote! {
+                #field_vis fn #ident(&self) -> #krate::document::Field<#ty> {
+                    #krate::document::Field::new(self.path.join(
+                        #krate::types::PathElement::Field(#key.to_string()),
+                    ))
+                }
+            })
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+    let doc = format!("paths of the fields of [`{name}`] inside a document");
+    Ok(quote! {
+        #[doc = #doc]
+        #[derive(Debug, Clone)]
+        #vis struct #fields_name {
+            path: #krate::types::Path,
+        }
+
+        impl #fields_name {
+            #vis fn path(&self) -> &#krate::types::Path {
+                &self.path
+            }
+            #(#accessors)*
+        }
+
+        impl #krate::document::Document for #name {
+            type Fields = #fields_name;
+
+            fn fields(path: #krate::types::Path) -> Self::Fields {
+                #fields_name { path }
+            }
+        }
+    })
+}
@@ -0,0 +1,10 @@
+-- Add migration script here
+
+CREATE TABLE change_paths(
+    change INT NOT NULL REFERENCES changes (id),
+    prefix BLOB NOT NULL,
+        CONSTRAINT uniqueness UNIQUE (prefix,change)
+) STRICT;
+
+-- existing changes are indexed on startup by decoding their content
+ALTER TABLE changes ADD COLUMN paths_indexed INT NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+-- Add migration script here
+
+-- id of the HashAlgorithm used for changes.hash, 1 is blake3
+ALTER TABLE changes ADD COLUMN hash_alg INT NOT NULL DEFAULT 1;
@@ -0,0 +1,9 @@
+-- Add migration script here
+
+-- mutable annotations of changes, not part of the change hash
+CREATE TABLE change_notes(
+    change INT NOT NULL REFERENCES changes (id),
+    name TEXT NOT NULL,
+    content BLOB NOT NULL,
+        CONSTRAINT uniqueness UNIQUE (change,name)
+) STRICT;
@@ -0,0 +1,16 @@
+-- Add migration script here
+
+CREATE TABLE repo_config(
+    repo INT NOT NULL REFERENCES repositories (id),
+    name TEXT NOT NULL,
+    content BLOB NOT NULL,
+        CONSTRAINT uniqueness UNIQUE (repo,name)
+) STRICT;
+
+-- overrides repo_config for a single branch
+CREATE TABLE branch_config(
+    branch INT NOT NULL REFERENCES branch (id),
+    name TEXT NOT NULL,
+    content BLOB NOT NULL,
+        CONSTRAINT uniqueness UNIQUE (branch,name)
+) STRICT;
@@ -0,0 +1,9 @@
+-- Add migration script here
+
+-- commits per repository, seq increases by one for every commit starting at 1
+CREATE TABLE events(
+    repo INT NOT NULL REFERENCES repositories (id),
+    seq INT NOT NULL,
+    change INT NOT NULL REFERENCES changes (id),
+        CONSTRAINT uniqueness UNIQUE (repo,seq)
+) STRICT;
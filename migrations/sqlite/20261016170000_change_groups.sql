@@ -0,0 +1,11 @@
+-- Add migration script here
+
+-- parts of change sets split by apply::split, written with the commit of each part
+CREATE TABLE change_groups(
+    repo INT NOT NULL REFERENCES repositories (id),
+    grp BLOB NOT NULL,
+    part INT NOT NULL,
+    parts INT NOT NULL,
+    change INT NOT NULL REFERENCES changes (id),
+        CONSTRAINT uniqueness UNIQUE (repo,grp,part)
+) STRICT;
@@ -0,0 +1,18 @@
+-- Add migration script here
+
+-- changes reachable from a branch head of the repository, added whenever a head moves
+CREATE TABLE repo_changes(
+    repo INT NOT NULL REFERENCES repositories (id),
+    change INT NOT NULL REFERENCES changes (id),
+        CONSTRAINT uniqueness UNIQUE (repo,change)
+) STRICT;
+
+WITH RECURSIVE reachable(repo, id) AS (
+    SELECT repo, head FROM branch
+    UNION
+    SELECT reachable.repo, change_rels.parent FROM change_rels JOIN reachable ON change_rels.child == reachable.id
+)
+INSERT INTO repo_changes (repo, change) SELECT repo, id FROM reachable;
+
+-- changes stored before the path index, see SqliteStorage::backfill_path_index
+CREATE INDEX unindexed_changes ON changes (id) WHERE paths_indexed == 0;
@@ -0,0 +1,438 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use crate::{
+    apply::simple::{try_delete, try_insert, try_replace},
+    types::{change::ChangeContent, PathElement, Value},
+};
+
+enum Relation {
+    /// the changes commute
+    Independent,
+    Same,
+    /// the second change is located below the first one
+    Below,
+    /// the first change is located below the second one
+    Above,
+    Dependent,
+}
+
+/// depth of the array whose elements get shifted by change
+fn shifted_depth(change: &ChangeContent) -> Option<usize> {
+    match (change, change.path().last()) {
+        (ChangeContent::Insert { path, .. } | ChangeContent::Delete { path, .. }, Some(last))
+            if !matches!(last, PathElement::Field(_)) =>
+        {
+            Some(path.len() - 1)
+        }
+        _ => None,
+    }
+}
+
+fn relation(first: &ChangeContent, second: &ChangeContent) -> Relation {
+    let (p1, p2) = (first.path(), second.path());
+    if let Some(depth) = p1.iter().zip(p2.iter()).position(|(e1, e2)| e1 != e2) {
+        match (&p1[depth], &p2[depth]) {
+            (PathElement::Field(_), PathElement::Field(_)) => Relation::Independent,
+            (PathElement::Index(_), PathElement::Index(_))
+            | (PathElement::FromEnd(_), PathElement::FromEnd(_))
+                if shifted_depth(first) != Some(depth) && shifted_depth(second) != Some(depth) =>
+            {
+                Relation::Independent
+            }
+            _ => Relation::Dependent,
+        }
+    } else if p1.len() == p2.len() {
+        Relation::Same
+    } else if p1.len() < p2.len() {
+        Relation::Below
+    } else {
+        Relation::Above
+    }
+}
+
+/// applies change to value located at the path of the first depth elements of change
+fn apply_relative(value: &mut Value, change: &ChangeContent, depth: usize) -> bool {
+    match change {
+        ChangeContent::Insert { path, value: new } => {
+            try_insert(value, &path[depth..], new).is_ok()
+        }
+        ChangeContent::Replace { path, old, new } => {
+            try_replace(value, &path[depth..], old, new).is_ok()
+        }
+        ChangeContent::Delete { path, old } => try_delete(value, &path[depth..], old).is_ok(),
+    }
+}
+
+/**
+ *  merges two consecutive changes.
+ *  Err if the changes can't be merged, Ok(None) if they cancel each other.
+ *  */
+fn merge(
+    first: ChangeContent,
+    second: ChangeContent,
+) -> Result<Option<ChangeContent>, (ChangeContent, ChangeContent)> {
+    match relation(&first, &second) {
+        Relation::Same => match (first, second) {
+            (ChangeContent::Insert { value, .. }, ChangeContent::Delete { old, .. })
+                if value == old =>
+            {
+                Ok(None)
+            }
+            (ChangeContent::Insert { path, value }, ChangeContent::Replace { old, new, .. })
+                if value == old =>
+            {
+                Ok(Some(ChangeContent::Insert { path, value: new }))
+            }
+            (
+                ChangeContent::Replace { path, old, new },
+                ChangeContent::Replace {
+                    old: old2,
+                    new: new2,
+                    ..
+                },
+            ) if new == old2 => Ok((old != new2).then_some(ChangeContent::Replace {
+                path,
+                old,
+                new: new2,
+            })),
+            (
+                ChangeContent::Replace { path, old, new },
+                ChangeContent::Delete { old: old2, .. },
+            ) if new == old2 => Ok(Some(ChangeContent::Delete { path, old })),
+            (ChangeContent::Delete { path, old }, ChangeContent::Insert { value, .. }) => {
+                Ok((old != value).then_some(ChangeContent::Replace {
+                    path,
+                    old,
+                    new: value,
+                }))
+            }
+            (first, second) => Err((first, second)),
+        },
+        Relation::Below => {
+            let depth = first.path().len();
+            match first {
+                ChangeContent::Insert { path, mut value } => {
+                    if apply_relative(&mut value, &second, depth) {
+                        Ok(Some(ChangeContent::Insert { path, value }))
+                    } else {
+                        Err((ChangeContent::Insert { path, value }, second))
+                    }
+                }
+                ChangeContent::Replace { path, old, mut new } => {
+                    if apply_relative(&mut new, &second, depth) {
+                        Ok((old != new).then_some(ChangeContent::Replace { path, old, new }))
+                    } else {
+                        Err((ChangeContent::Replace { path, old, new }, second))
+                    }
+                }
+                first => Err((first, second)),
+            }
+        }
+        Relation::Above => {
+            let depth = second.path().len();
+            let reverted = first.clone().revert();
+            match second {
+                ChangeContent::Replace { path, mut old, new } => {
+                    if apply_relative(&mut old, &reverted, depth) {
+                        Ok((old != new).then_some(ChangeContent::Replace { path, old, new }))
+                    } else {
+                        Err((first, ChangeContent::Replace { path, old, new }))
+                    }
+                }
+                ChangeContent::Delete { path, mut old } => {
+                    if apply_relative(&mut old, &reverted, depth) {
+                        Ok(Some(ChangeContent::Delete { path, old }))
+                    } else {
+                        Err((first, ChangeContent::Delete { path, old }))
+                    }
+                }
+                second => Err((first, second)),
+            }
+        }
+        Relation::Independent | Relation::Dependent => Err((first, second)),
+    }
+}
+
+/**
+ *  collapses redundant changes without altering the result of applying them.
+ *  each change is moved backwards past changes it commutes with until it
+ *  can be merged with a related change.
+ *  invalid change sets are left partially coalesced and stay invalid.
+ *  */
+pub fn coalesce(changes: Vec<ChangeContent>) -> Vec<ChangeContent> {
+    let mut res: Vec<ChangeContent> = Vec::with_capacity(changes.len());
+    for change in changes {
+        let mut current = change;
+        let mut place = res.len();
+        let mut pos = res.len();
+        loop {
+            if pos == 0 {
+                res.insert(place, current);
+                break;
+            }
+            pos -= 1;
+            match relation(&res[pos], &current) {
+                Relation::Independent => continue,
+                Relation::Dependent => {
+                    res.insert(place, current);
+                    break;
+                }
+                _ => {}
+            }
+            let previous = res.remove(pos);
+            match merge(previous, current) {
+                Ok(Some(merged)) => {
+                    current = merged;
+                    place = pos;
+                }
+                Ok(None) => break,
+                Err((previous, unmerged)) => {
+                    res.insert(pos, previous);
+                    res.insert(place, unmerged);
+                    break;
+                }
+            }
+        }
+    }
+    res
+}
+
+/**
+ *  reorders changes into the canonical order of their commutation class, so change sets
+ *  differing only in the order of independent changes encode and hash identically.
+ *  changes that don't commute keep their relative order, among the rest the change with the
+ *  smallest path comes first. changes at the same path never commute.
+ *  compares every pair of changes, so it is quadratic in the number of changes.
+ *  */
+pub fn canonical_order(changes: Vec<ChangeContent>) -> Vec<ChangeContent> {
+    let mut blockers = vec![0usize; changes.len()];
+    let mut dependents = vec![Vec::new(); changes.len()];
+    for (i, change) in changes.iter().enumerate() {
+        for (j, earlier) in changes[..i].iter().enumerate() {
+            if !matches!(relation(earlier, change), Relation::Independent) {
+                blockers[i] += 1;
+                dependents[j].push(i);
+            }
+        }
+    }
+    let key = |i: usize| Reverse((changes[i].path(), i));
+    let mut ready: BinaryHeap<_> = (0..changes.len())
+        .filter(|i| blockers[*i] == 0)
+        .map(key)
+        .collect();
+    let mut order = Vec::with_capacity(changes.len());
+    while let Some(Reverse((_, i))) = ready.pop() {
+        order.push(i);
+        for dependent in &dependents[i] {
+            blockers[*dependent] -= 1;
+            if blockers[*dependent] == 0 {
+                ready.push(key(*dependent));
+            }
+        }
+    }
+    let mut slots: Vec<_> = changes.into_iter().map(Some).collect();
+    order.into_iter().filter_map(|i| slots[i].take()).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::{canonical_order, coalesce};
+    use crate::{
+        types::{change::ChangeContent, PathElement, Value},
+        util::test_util::field,
+    };
+
+    fn check(initial: Value, changes: Vec<ChangeContent>, expected_len: usize) {
+        let mut expected = initial.clone();
+        expected.apply_iter(&changes).unwrap();
+        let coalesced = coalesce(changes);
+        assert_eq!(coalesced.len(), expected_len, "{coalesced:?}");
+        let mut res = initial;
+        res.apply_iter(&coalesced).unwrap();
+        assert_eq!(res, expected);
+    }
+
+    #[test]
+    fn insert_delete_cancels() {
+        check(
+            Value::default(),
+            vec![
+                ChangeContent::Insert {
+                    path: vec![field("a")].into(),
+                    value: Value::Integer(1),
+                },
+                ChangeContent::Insert {
+                    path: vec![field("b")].into(),
+                    value: Value::Integer(2),
+                },
+                ChangeContent::Delete {
+                    path: vec![field("a")].into(),
+                    old: Value::Integer(1),
+                },
+            ],
+            1,
+        )
+    }
+
+    #[test]
+    fn replace_chain() {
+        check(
+            Value::Map(HashMap::from_iter([("a".to_string(), Value::Integer(0))]).into()),
+            vec![
+                ChangeContent::Replace {
+                    path: vec![field("a")].into(),
+                    old: Value::Integer(0),
+                    new: Value::Integer(1),
+                },
+                ChangeContent::Replace {
+                    path: vec![field("a")].into(),
+                    old: Value::Integer(1),
+                    new: Value::Integer(2),
+                },
+            ],
+            1,
+        );
+        check(
+            Value::Map(HashMap::from_iter([("a".to_string(), Value::Integer(0))]).into()),
+            vec![
+                ChangeContent::Replace {
+                    path: vec![field("a")].into(),
+                    old: Value::Integer(0),
+                    new: Value::Integer(1),
+                },
+                ChangeContent::Replace {
+                    path: vec![field("a")].into(),
+                    old: Value::Integer(1),
+                    new: Value::Integer(0),
+                },
+            ],
+            0,
+        )
+    }
+
+    #[test]
+    fn fold_into_insert() {
+        check(
+            Value::default(),
+            vec![
+                ChangeContent::Insert {
+                    path: vec![field("a")].into(),
+                    value: Value::Array(vec![].into()),
+                },
+                ChangeContent::Insert {
+                    path: vec![field("a"), PathElement::Index(0)].into(),
+                    value: Value::Integer(1),
+                },
+                ChangeContent::Insert {
+                    path: vec![field("a"), PathElement::Index(0)].into(),
+                    value: Value::Integer(2),
+                },
+                ChangeContent::Delete {
+                    path: vec![field("a"), PathElement::Index(1)].into(),
+                    old: Value::Integer(1),
+                },
+            ],
+            1,
+        )
+    }
+
+    #[test]
+    fn array_shift_is_kept() {
+        check(
+            Value::Map(
+                HashMap::from_iter([(
+                    "a".to_string(),
+                    Value::Array(vec![Value::Integer(0)].into()),
+                )])
+                .into(),
+            ),
+            vec![
+                ChangeContent::Insert {
+                    path: vec![field("a"), PathElement::Index(1)].into(),
+                    value: Value::Integer(1),
+                },
+                ChangeContent::Insert {
+                    path: vec![field("a"), PathElement::Index(0)].into(),
+                    value: Value::Integer(2),
+                },
+                ChangeContent::Delete {
+                    path: vec![field("a"), PathElement::Index(1)].into(),
+                    old: Value::Integer(0),
+                },
+            ],
+            3,
+        )
+    }
+
+    #[test]
+    fn fold_child_into_delete() {
+        check(
+            Value::Map(
+                HashMap::from_iter([(
+                    "a".to_string(),
+                    Value::Map(HashMap::from_iter([("b".to_string(), Value::Bool(true))]).into()),
+                )])
+                .into(),
+            ),
+            vec![
+                ChangeContent::Replace {
+                    path: vec![field("a"), field("b")].into(),
+                    old: Value::Bool(true),
+                    new: Value::Bool(false),
+                },
+                ChangeContent::Delete {
+                    path: vec![field("a")].into(),
+                    old: Value::Map(
+                        HashMap::from_iter([("b".to_string(), Value::Bool(false))]).into(),
+                    ),
+                },
+            ],
+            1,
+        )
+    }
+
+    #[test]
+    fn canonical() {
+        let insert = |path: Vec<PathElement>, value: i64| ChangeContent::Insert {
+            path: path.into(),
+            value: Value::Integer(value),
+        };
+        let replace = ChangeContent::Replace {
+            path: vec![field("b")].into(),
+            old: Value::Integer(2),
+            new: Value::Integer(3),
+        };
+        let changes = vec![
+            insert(vec![field("c")], 1),
+            insert(vec![field("b")], 2),
+            replace.clone(),
+            insert(vec![field("a")], 0),
+        ];
+        let canonical = canonical_order(changes.clone());
+        assert_eq!(
+            canonical,
+            vec![
+                changes[3].clone(),
+                changes[1].clone(),
+                replace,
+                changes[0].clone()
+            ]
+        );
+        let reordered = vec![
+            changes[3].clone(),
+            changes[1].clone(),
+            changes[0].clone(),
+            changes[2].clone(),
+        ];
+        assert_eq!(canonical_order(reordered), canonical);
+
+        // both inserts shift the array, so their order is kept
+        let shifting = vec![
+            insert(vec![field("l"), PathElement::Index(1)], 1),
+            insert(vec![field("l"), PathElement::Index(0)], 0),
+        ];
+        assert_eq!(canonical_order(shifting.clone()), shifting);
+    }
+}
@@ -0,0 +1,172 @@
+use crate::{
+    export::redact::Redaction,
+    types::{change::ChangeContent, value::FloatPolicy, PathElement, Value},
+};
+
+/**
+ *  changes turning old into new when applied in order.
+ *  maps are compared by key, arrays after stripping the common prefix and suffix,
+ *  so a state can be synced without sending the history in between.
+ *  both values are redacted first, the changes turn the redacted old into the redacted new,
+ *  so syncing to an untrusted peer sends nothing the rules remove.
+ *  */
+pub fn diff(old: &Value, new: &Value, redaction: &Redaction) -> Vec<ChangeContent> {
+    diff_with(old, new, FloatPolicy::default(), redaction)
+}
+
+/**
+ *  diff treating floats equal according to policy as unchanged.
+ *  with FloatPolicy::Epsilon applying the changes to old only yields a value equal to new
+ *  within epsilon, floats that moved by less are kept at their old value.
+ *  */
+pub fn diff_with(
+    old: &Value,
+    new: &Value,
+    policy: FloatPolicy,
+    redaction: &Redaction,
+) -> Vec<ChangeContent> {
+    let mut changes = Vec::new();
+    if redaction.is_empty() {
+        diff_inner(old, new, policy, &mut Vec::new(), &mut changes);
+    } else {
+        let (old, new) = (redaction.apply(old), redaction.apply(new));
+        diff_inner(&old, &new, policy, &mut Vec::new(), &mut changes);
+    }
+    changes
+}
+
+fn diff_inner(
+    old: &Value,
+    new: &Value,
+    policy: FloatPolicy,
+    path: &mut Vec<PathElement>,
+    changes: &mut Vec<ChangeContent>,
+) {
+    if old.eq_with(new, policy) {
+        return;
+    }
+    match (old, new) {
+        (Value::Map(old_map), Value::Map(new_map)) => {
+            let mut keys: Vec<_> = old_map.keys().chain(new_map.keys()).collect();
+            keys.sort_unstable();
+            keys.dedup();
+            for key in keys {
+                path.push(PathElement::Field(key.clone()));
+                match (old_map.get(key), new_map.get(key)) {
+                    (Some(old), Some(new)) => diff_inner(old, new, policy, path, changes),
+                    (Some(old), None) => changes.push(ChangeContent::Delete {
+                        path: path.as_slice().into(),
+                        old: old.clone(),
+                    }),
+                    (None, Some(new)) => changes.push(ChangeContent::Insert {
+                        path: path.as_slice().into(),
+                        value: new.clone(),
+                    }),
+                    (None, None) => unreachable!("key from one of the maps"),
+                }
+                path.pop();
+            }
+        }
+        (Value::Array(old_arr), Value::Array(new_arr)) => {
+            let prefix = old_arr
+                .iter()
+                .zip(new_arr.iter())
+                .take_while(|(old, new)| old.eq_with(new, policy))
+                .count();
+            let max_suffix = old_arr.len().min(new_arr.len()) - prefix;
+            let suffix = old_arr
+                .iter()
+                .rev()
+                .zip(new_arr.iter().rev())
+                .take(max_suffix)
+                .take_while(|(old, new)| old.eq_with(new, policy))
+                .count();
+            let old_mid = &old_arr[prefix..old_arr.len() - suffix];
+            let new_mid = &new_arr[prefix..new_arr.len() - suffix];
+            let common = old_mid.len().min(new_mid.len());
+            for (index, (old, new)) in old_mid.iter().zip(new_mid.iter()).enumerate() {
+                path.push(PathElement::Index((prefix + index) as u32));
+                diff_inner(old, new, policy, path, changes);
+                path.pop();
+            }
+            // removing or inserting at the same index keeps the suffix in place
+            path.push(PathElement::Index((prefix + common) as u32));
+            for old in &old_mid[common..] {
+                changes.push(ChangeContent::Delete {
+                    path: path.as_slice().into(),
+                    old: old.clone(),
+                });
+            }
+            for (index, new) in new_mid[common..].iter().enumerate() {
+                *path.last_mut().expect("pushed above") =
+                    PathElement::Index((prefix + common + index) as u32);
+                changes.push(ChangeContent::Insert {
+                    path: path.as_slice().into(),
+                    value: new.clone(),
+                });
+            }
+            path.pop();
+        }
+        _ => changes.push(ChangeContent::Replace {
+            path: path.as_slice().into(),
+            old: old.clone(),
+            new: new.clone(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{export::redact::RedactAction, util::test_util::map};
+
+    fn array<const N: usize>(values: [i64; N]) -> Value {
+        Value::Array(Arc::new(values.into_iter().map(Value::Integer).collect()))
+    }
+
+    fn check(old: Value, new: Value) -> usize {
+        let changes = diff(&old, &new, &Redaction::new());
+        let mut value = old;
+        value.apply_iter(&changes).expect("diff does not apply");
+        assert_eq!(value, new);
+        changes.len()
+    }
+
+    #[test]
+    fn round_trip() {
+        assert_eq!(check(array([1, 2, 3]), array([1, 2, 3])), 0);
+        assert_eq!(check(array([1, 2, 3]), array([1, 4, 2, 3])), 1);
+        assert_eq!(check(array([1, 2, 3, 4]), array([1, 4])), 2);
+        assert_eq!(check(array([1, 2, 3]), array([5, 6, 7, 8, 9])), 5);
+        assert_eq!(check(array([1, 1, 1]), array([1, 1])), 1);
+        assert_eq!(
+            check(
+                map([("a", array([1])), ("b", Value::Integer(2))]),
+                map([("a", array([1, 2])), ("c", Value::Bool(true))]),
+            ),
+            3
+        );
+        assert_eq!(check(Value::Integer(1), array([1])), 1);
+    }
+
+    #[test]
+    fn redacted() {
+        let redaction = Redaction::new().with_rule("secret".parse().unwrap(), RedactAction::Drop);
+        let old = map([("a", Value::Integer(1)), ("secret", Value::Integer(2))]);
+        let new = map([("a", Value::Integer(3)), ("secret", Value::Integer(4))]);
+        let changes = diff(&old, &new, &redaction);
+        assert_eq!(
+            changes,
+            vec![ChangeContent::Replace {
+                path: vec![PathElement::Field("a".to_string())].into(),
+                old: Value::Integer(1),
+                new: Value::Integer(3),
+            }]
+        );
+        let mut value = redaction.apply(&old);
+        value.apply_iter(&changes).unwrap();
+        assert_eq!(value, redaction.apply(&new));
+    }
+}
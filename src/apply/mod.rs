@@ -1,6 +1,9 @@
 use crate::{types::{Value, change::ChangeContent}, error::ValueStoreError};
 
+pub mod coalesce;
+pub mod diff;
 pub mod simple;
+pub mod split;
 
 pub trait ApplyChange {
     fn apply(&self,value:&mut Value)->Result<(),ValueStoreError>;
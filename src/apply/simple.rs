@@ -2,84 +2,124 @@ use std::sync::Arc;
 
 use crate::{
     error::ValueStoreError,
-    types::{change::ChangeContent, PathElement, Value},
+    types::{change::ChangeContent, value::FloatPolicy, PathElement, Value},
 };
 
-pub fn apply_delete(
+/**
+ *  change that doesn't fit the value it is applied to.
+ *  carries no data, so callers only interested in whether a change applies don't pay for
+ *  building a ValueStoreError::InvalidChange.
+ *  */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rejected;
+
+pub fn try_delete(this: &mut Value, path: &[PathElement], old: &Value) -> Result<(), Rejected> {
+    try_delete_with(this, path, old, FloatPolicy::default())
+}
+
+/// try_delete comparing the old value according to policy
+pub fn try_delete_with(
     this: &mut Value,
     path: &[PathElement],
     old: &Value,
-    full_path: &[PathElement],
-) -> Result<(), ValueStoreError> {
-    if path.is_empty() {
-        Err(ValueStoreError::InvalidChange {
-            change: ChangeContent::Delete {
-                path: full_path.to_vec(),
-                old:old.clone(),
-            },
-        })
-    } else if let Some(parent) = this.get_mut(&path[..path.len() - 1]) {
-        match (parent, &path[path.len() - 1]) {
-            (Value::Map(map), PathElement::Field(name)) => match Arc::make_mut(map).entry(name.clone()) {
-                std::collections::hash_map::Entry::Occupied(e) => {
-                    if PartialEq::eq(old, e.get()) {
-                        e.remove();
-                        Ok(())
-                    } else {
-                        Err(ValueStoreError::InvalidChange {
-                            change: ChangeContent::Delete {
-                                path: full_path.to_vec(),
-                                old:old.clone(),
-                            },
-                        })
-                    }
-                }
-                std::collections::hash_map::Entry::Vacant(_) => {
-                    Err(ValueStoreError::InvalidChange {
-                        change: ChangeContent::Delete {
-                            path: full_path.to_vec(),
-                            old:old.clone(),
-                        },
-                    })
-                }
-            },
-            (Value::Array(vec), PathElement::Index(index)) => {
-                if *index as usize >= vec.len() {
-                    Err(ValueStoreError::InvalidChange {
-                        change: ChangeContent::Delete {
-                            path: full_path.to_vec(),
-                            old:old.clone(),
-                        },
-                    })
-                } else if PartialEq::eq(&vec[*index as usize], old) {
-                    Arc::make_mut(vec).remove(*index as usize);
-                    Ok(())
-                } else {
-                    Err(ValueStoreError::InvalidChange {
-                        change: ChangeContent::Delete {
-                            path: full_path.to_vec(),
-                            old:old.clone(),
-                        },
-                    })
-                }
+    policy: FloatPolicy,
+) -> Result<(), Rejected> {
+    let (last, parent) = path.split_last().ok_or(Rejected)?;
+    match (this.get_mut(parent).ok_or(Rejected)?, last) {
+        (Value::Map(map), PathElement::Field(name)) => {
+            if !map.get(name).is_some_and(|val| val.eq_with(old, policy)) {
+                return Err(Rejected);
             }
-            _ => Err(ValueStoreError::InvalidChange {
-                change: ChangeContent::Delete {
-                    path: full_path.to_vec(),
-                    old:old.clone(),
-                },
-            }),
+            Arc::make_mut(map).remove(name);
+            Ok(())
         }
-    } else {
-        Err(ValueStoreError::InvalidChange {
-            change: ChangeContent::Delete {
-                path: full_path.to_vec(),
-                old:old.clone(),
-            },
-        })
+        (Value::Array(vec), elem @ (PathElement::Index(_) | PathElement::FromEnd(_))) => {
+            let index = elem
+                .resolve_index(vec.len())
+                .filter(|index| vec[*index].eq_with(old, policy))
+                .ok_or(Rejected)?;
+            Arc::make_mut(vec).remove(index);
+            Ok(())
+        }
+        _ => Err(Rejected),
+    }
+}
+
+pub fn try_replace(
+    this: &mut Value,
+    path: &[PathElement],
+    old: &Value,
+    new: &Value,
+) -> Result<(), Rejected> {
+    try_replace_with(this, path, old, new, FloatPolicy::default())
+}
+
+/// try_replace comparing the old value according to policy
+pub fn try_replace_with(
+    this: &mut Value,
+    path: &[PathElement],
+    old: &Value,
+    new: &Value,
+    policy: FloatPolicy,
+) -> Result<(), Rejected> {
+    match this.get_mut(path) {
+        Some(val) if val.eq_with(old, policy) => {
+            *val = new.clone();
+            Ok(())
+        }
+        _ => Err(Rejected),
     }
 }
 
+pub fn try_insert(this: &mut Value, path: &[PathElement], value: &Value) -> Result<(), Rejected> {
+    let (last, parent) = path.split_last().ok_or(Rejected)?;
+    match (this.get_mut(parent).ok_or(Rejected)?, last) {
+        (Value::Map(map), PathElement::Field(name)) => {
+            if map.contains_key(name) {
+                return Err(Rejected);
+            }
+            Arc::make_mut(map).insert(name.clone(), value.clone());
+            Ok(())
+        }
+        (Value::Array(vec), elem) if !matches!(elem, PathElement::Field(_)) => {
+            let index = elem.resolve_insert_index(vec.len()).ok_or(Rejected)?;
+            Arc::make_mut(vec).insert(index, value.clone());
+            Ok(())
+        }
+        _ => Err(Rejected),
+    }
+}
+
+pub fn try_apply(this: &mut Value, change: &ChangeContent) -> Result<(), Rejected> {
+    try_apply_with(this, change, FloatPolicy::default())
+}
+
+pub fn try_apply_with(
+    this: &mut Value,
+    change: &ChangeContent,
+    policy: FloatPolicy,
+) -> Result<(), Rejected> {
+    match change {
+        ChangeContent::Insert { path, value } => try_insert(this, path, value),
+        ChangeContent::Replace { path, old, new } => try_replace_with(this, path, old, new, policy),
+        ChangeContent::Delete { path, old } => try_delete_with(this, path, old, policy),
+    }
+}
+
+pub fn apply_delete(
+    this: &mut Value,
+    path: &[PathElement],
+    old: &Value,
+    full_path: &[PathElement],
+) -> Result<(), ValueStoreError> {
+    try_delete(this, path, old).map_err(|_| ValueStoreError::InvalidChange {
+        change: ChangeContent::Delete {
+            path: full_path.into(),
+            old: old.clone(),
+        },
+    })
+}
+
 pub fn apply_replace(
     this: &mut Value,
     path: &[PathElement],
@@ -87,28 +127,13 @@ pub fn apply_replace(
     new: Value,
     full_path: &[PathElement],
 ) -> Result<(), ValueStoreError> {
-    if let Some(val) = this.get_mut(path) {
-        if PartialEq::eq(old, val) {
-            *val = new;
-            Ok(())
-        } else {
-            Err(ValueStoreError::InvalidChange {
-                change: ChangeContent::Replace {
-                    path: full_path.to_vec(),
-                    old:old.clone(),
-                    new,
-                },
-            })
-        }
-    } else {
-        Err(ValueStoreError::InvalidChange {
-            change: ChangeContent::Replace {
-                path: full_path.to_vec(),
-                old:old.clone(),
-                new,
-            },
-        })
-    }
+    try_replace(this, path, old, &new).map_err(|_| ValueStoreError::InvalidChange {
+        change: ChangeContent::Replace {
+            path: full_path.into(),
+            old: old.clone(),
+            new,
+        },
+    })
 }
 
 pub fn apply_insert(
@@ -117,63 +142,148 @@ pub fn apply_insert(
     value: Value,
     full_path: &[PathElement],
 ) -> Result<(), ValueStoreError> {
-    if path.is_empty() {
-        Err(ValueStoreError::InvalidChange {
-            change: ChangeContent::Insert {
-                path: full_path.to_vec(),
-                value,
-            },
-        })
-    } else if let Some(parent) = this.get_mut(&path[..path.len() - 1]) {
-        match (parent, &path[path.len() - 1]) {
-            (Value::Map(map), PathElement::Field(name)) => match Arc::make_mut(map).entry(name.clone()) {
-                std::collections::hash_map::Entry::Occupied(_) => {
-                    Err(ValueStoreError::InvalidChange {
-                        change: ChangeContent::Insert {
-                            path: full_path.to_vec(),
-                            value,
-                        },
-                    })
-                }
-                std::collections::hash_map::Entry::Vacant(e) => {
-                    e.insert(value);
-                    Ok(())
-                }
-            },
-            (Value::Array(vec), PathElement::Index(index)) => {
-                if *index as usize > vec.len() {
-                    Err(ValueStoreError::InvalidChange {
-                        change: ChangeContent::Insert {
-                            path: full_path.to_vec(),
-                            value,
-                        },
-                    })
-                } else {
-                    Arc::make_mut(vec).insert(*index as usize, value);
-                    Ok(())
-                }
+    try_insert(this, path, &value).map_err(|_| ValueStoreError::InvalidChange {
+        change: ChangeContent::Insert {
+            path: full_path.into(),
+            value,
+        },
+    })
+}
+
+pub fn apply(this: &mut Value, change: &ChangeContent) -> Result<(), ValueStoreError> {
+    apply_with(this, change, FloatPolicy::default())
+}
+
+/// applies change, comparing old values of replacements and deletions according to policy
+pub fn apply_with(
+    this: &mut Value,
+    change: &ChangeContent,
+    policy: FloatPolicy,
+) -> Result<(), ValueStoreError> {
+    try_apply_with(this, change, policy).map_err(|_| ValueStoreError::InvalidChange {
+        change: change.clone(),
+    })
+}
+
+/**
+ *  applies changes in order like Value::apply_iter, but walks to the parent of a run of
+ *  consecutive changes with the same parent only once and applies them there together.
+ *  as with apply_iter, changes before a failing one stay applied.
+ *  */
+pub fn apply_batch(this: &mut Value, changes: &[ChangeContent]) -> Result<(), ValueStoreError> {
+    let invalid = |change: &ChangeContent| ValueStoreError::InvalidChange {
+        change: change.clone(),
+    };
+    let mut rest = changes;
+    while let Some(first) = rest.first() {
+        let Some((_, parent_path)) = first.path().split_last() else {
+            // the root has no parent to share
+            apply(this, first)?;
+            rest = &rest[1..];
+            continue;
+        };
+        let len = rest
+            .iter()
+            .take_while(|change| {
+                change
+                    .path()
+                    .split_last()
+                    .is_some_and(|(_, parent)| parent == parent_path)
+            })
+            .count();
+        let (group, next) = rest.split_at(len);
+        let parent = this.get_mut(parent_path).ok_or_else(|| invalid(first))?;
+        for change in group {
+            let last = &change.path()[parent_path.len()..];
+            match change {
+                ChangeContent::Insert { value, .. } => try_insert(parent, last, value),
+                ChangeContent::Replace { old, new, .. } => try_replace(parent, last, old, new),
+                ChangeContent::Delete { old, .. } => try_delete(parent, last, old),
             }
-            _ => Err(ValueStoreError::InvalidChange {
-                change: ChangeContent::Insert {
-                    path: full_path.to_vec(),
-                    value,
-                },
-            }),
+            .map_err(|_| invalid(change))?;
         }
-    } else {
-        Err(ValueStoreError::InvalidChange {
-            change: ChangeContent::Insert {
-                path: full_path.to_vec(),
-                value,
-            },
-        })
+        rest = next;
     }
+    Ok(())
 }
-pub fn apply(this: &mut Value, change: &ChangeContent) -> Result<(), ValueStoreError> {
-    match change {
-        ChangeContent::Insert { path, value } => apply_insert(this, path, value.clone(), path)?,
-        ChangeContent::Replace { path, old, new } => apply_replace(this, path, old, new.clone(), path)?,
-        ChangeContent::Delete { path, old } => apply_delete(this, path, old, path)?,
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::util::test_util::field;
+
+    #[test]
+    fn batch() {
+        let initial = Value::from(HashMap::from([(
+            "users".to_string(),
+            Value::from(HashMap::from([("ann".to_string(), 1)])),
+        )]));
+        let changes = vec![
+            ChangeContent::Insert {
+                path: vec![field("users"), field("bob")].into(),
+                value: Value::Integer(2),
+            },
+            ChangeContent::Replace {
+                path: vec![field("users"), field("ann")].into(),
+                old: Value::Integer(1),
+                new: Value::Integer(3),
+            },
+            ChangeContent::Insert {
+                path: vec![field("list")].into(),
+                value: Value::Array(Default::default()),
+            },
+            ChangeContent::Insert {
+                path: vec![field("list"), PathElement::Append].into(),
+                value: Value::Bool(true),
+            },
+            ChangeContent::Insert {
+                path: vec![field("list"), PathElement::Index(0)].into(),
+                value: Value::Bool(false),
+            },
+            ChangeContent::Delete {
+                path: vec![field("users"), field("bob")].into(),
+                old: Value::Integer(2),
+            },
+        ];
+        let mut expected = initial.clone();
+        expected.apply_iter(&changes).unwrap();
+        let mut value = initial.clone();
+        apply_batch(&mut value, &changes).unwrap();
+        assert_eq!(value, expected);
+
+        let conflicting = [
+            changes[0].clone(),
+            ChangeContent::Delete {
+                path: vec![field("users"), field("bob")].into(),
+                old: Value::Integer(7),
+            },
+        ];
+        let mut value = initial;
+        assert!(matches!(
+            apply_batch(&mut value, &conflicting),
+            Err(ValueStoreError::InvalidChange { change }) if change == conflicting[1]
+        ));
+    }
+
+    #[test]
+    fn float_policy() {
+        let initial = Value::from(HashMap::from([("x".to_string(), Value::Float(0.1 + 0.2))]));
+        let change = ChangeContent::Replace {
+            path: vec![field("x")].into(),
+            old: Value::Float(0.3),
+            new: Value::Float(1.0),
+        };
+        assert!(apply(&mut initial.clone(), &change).is_err());
+        assert!(apply_with(&mut initial.clone(), &change, FloatPolicy::Epsilon(1e-9)).is_ok());
+
+        let zero = Value::from(HashMap::from([("x".to_string(), Value::Float(0.0))]));
+        let delete = ChangeContent::Delete {
+            path: vec![field("x")].into(),
+            old: Value::Float(-0.0),
+        };
+        assert!(apply(&mut zero.clone(), &delete).is_ok());
+        assert!(apply_with(&mut zero.clone(), &delete, FloatPolicy::BitExact).is_err());
     }
-    Ok(())
 }
@@ -0,0 +1,277 @@
+use uuid::Uuid;
+
+use crate::{
+    error::ValueStoreError,
+    types::{change::ChangeContent, value::cbor_header_size, Path, PathElement, Value},
+    Result,
+};
+
+/**
+ *  marks a change as part of a change set that was split up, stored with the commit of the
+ *  change. the change set is only complete once all parts of the group are committed.
+ *  */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SplitMarker {
+    pub group: Uuid,
+    /// position of the change in the group, starting at 0
+    pub part: u32,
+    pub parts: u32,
+}
+
+fn child(path: &[PathElement], elem: PathElement) -> Path {
+    let mut res = path.to_vec();
+    res.push(elem);
+    res.into()
+}
+
+/// path of the value inserted at path, relative positions are resolved
+fn inserted_at(path: &[PathElement]) -> Vec<PathElement> {
+    let mut res = path.to_vec();
+    if let Some(last @ PathElement::Append) = res.last_mut() {
+        *last = PathElement::FromEnd(0);
+    }
+    res
+}
+
+/// container of the same kind without children, None for values that can't be split
+fn shell(value: &Value) -> Option<Value> {
+    match value {
+        Value::Map(map) if !map.is_empty() => Some(Value::Map(Default::default())),
+        Value::Array(array) if !array.is_empty() => Some(Value::Array(Default::default())),
+        _ => None,
+    }
+}
+
+/// changes building value up below path, after an empty shell was inserted there
+fn insert_children(
+    path: &[PathElement],
+    value: &Value,
+    limit: usize,
+    out: &mut Vec<ChangeContent>,
+) {
+    match value {
+        Value::Map(map) => {
+            for (key, value) in map.iter() {
+                decompose(
+                    ChangeContent::Insert {
+                        path: child(path, PathElement::Field(key.clone())),
+                        value: value.clone(),
+                    },
+                    limit,
+                    out,
+                );
+            }
+        }
+        Value::Array(array) => {
+            for (index, value) in array.iter().enumerate() {
+                decompose(
+                    ChangeContent::Insert {
+                        path: child(path, PathElement::Index(index as u32)),
+                        value: value.clone(),
+                    },
+                    limit,
+                    out,
+                );
+            }
+        }
+        _ => {}
+    }
+}
+
+/// changes removing all children of value at path, leaving an empty shell
+fn delete_children(
+    path: &[PathElement],
+    value: &Value,
+    limit: usize,
+    out: &mut Vec<ChangeContent>,
+) {
+    match value {
+        Value::Map(map) => {
+            for (key, value) in map.iter() {
+                decompose(
+                    ChangeContent::Delete {
+                        path: child(path, PathElement::Field(key.clone())),
+                        old: value.clone(),
+                    },
+                    limit,
+                    out,
+                );
+            }
+        }
+        Value::Array(array) => {
+            // from the back, so the indices of the remaining elements don't shift
+            for (index, value) in array.iter().enumerate().rev() {
+                decompose(
+                    ChangeContent::Delete {
+                        path: child(path, PathElement::Index(index as u32)),
+                        old: value.clone(),
+                    },
+                    limit,
+                    out,
+                );
+            }
+        }
+        _ => {}
+    }
+}
+
+/// replaces change by a sequence of smaller changes with the same effect if it exceeds limit
+fn decompose(change: ChangeContent, limit: usize, out: &mut Vec<ChangeContent>) {
+    if change.encoded_size_hint() <= limit {
+        out.push(change);
+        return;
+    }
+    match change {
+        ChangeContent::Insert { path, value } => match shell(&value) {
+            Some(empty) => {
+                out.push(ChangeContent::Insert {
+                    path: path.clone(),
+                    value: empty,
+                });
+                insert_children(&inserted_at(&path), &value, limit, out);
+            }
+            None => out.push(ChangeContent::Insert { path, value }),
+        },
+        ChangeContent::Delete { path, old } => match shell(&old) {
+            Some(empty) => {
+                delete_children(&path, &old, limit, out);
+                out.push(ChangeContent::Delete { path, old: empty });
+            }
+            None => out.push(ChangeContent::Delete { path, old }),
+        },
+        ChangeContent::Replace { path, old, new } => {
+            let old_shell = shell(&old);
+            let new_shell = shell(&new);
+            if old_shell.is_none() && new_shell.is_none() {
+                out.push(ChangeContent::Replace { path, old, new });
+                return;
+            }
+            if old_shell.is_some() {
+                delete_children(&path, &old, limit, out);
+            }
+            let old_shell = old_shell.unwrap_or_else(|| old.clone());
+            let new_shell = new_shell.unwrap_or_else(|| new.clone());
+            if old_shell != new_shell {
+                out.push(ChangeContent::Replace {
+                    path: path.clone(),
+                    old: old_shell,
+                    new: new_shell,
+                });
+            }
+            if shell(&new).is_some() {
+                insert_children(&path, &new, limit, out);
+            }
+        }
+    }
+}
+
+/**
+ *  splits changes into consecutive change sets with an estimated encoded size of at most limit.
+ *  applying the parts in order has the same effect as applying changes.
+ *  inserts, replacements and deletions of large maps and arrays are broken up into
+ *  changes of their children, a single scalar larger than limit fails with
+ *  ValueStoreError::ChangeTooLarge.
+ *  */
+pub fn split(changes: Vec<ChangeContent>, limit: usize) -> Result<Vec<Vec<ChangeContent>>> {
+    let mut decomposed = Vec::new();
+    for change in changes {
+        // a part holding a single change needs one byte for the array header
+        decompose(change, limit.saturating_sub(1), &mut decomposed);
+    }
+    let mut parts = Vec::new();
+    let mut part = Vec::new();
+    let mut size = 0;
+    for change in decomposed {
+        let change_size = change.encoded_size_hint();
+        let with_change =
+            |len: usize, size: usize| size + change_size + cbor_header_size(len as u64 + 1);
+        if with_change(0, 0) > limit {
+            return Err(ValueStoreError::ChangeTooLarge {
+                size: with_change(0, 0),
+                limit,
+            }
+            .into());
+        }
+        if with_change(part.len(), size) > limit {
+            parts.push(std::mem::take(&mut part));
+            size = 0;
+        }
+        size += change_size;
+        part.push(change);
+    }
+    if !part.is_empty() {
+        parts.push(part);
+    }
+    Ok(parts)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, sync::Arc};
+
+    use super::*;
+    use crate::util::test_util::field;
+    use crate::Error;
+
+    fn big(n: i64) -> Value {
+        Value::from(HashMap::from_iter((0..n).map(|i| {
+            (
+                format!("key{i}"),
+                Value::Array(Arc::new((0..n).map(Value::Integer).collect())),
+            )
+        })))
+    }
+
+    #[test]
+    fn split_changes() {
+        let initial = Value::from(HashMap::from([(
+            "list".to_string(),
+            Value::Array(Arc::new(vec![big(3)])),
+        )]));
+        let changes = vec![
+            ChangeContent::Insert {
+                path: vec![field("big")].into(),
+                value: big(8),
+            },
+            ChangeContent::Insert {
+                path: vec![field("list"), PathElement::Append].into(),
+                value: big(6),
+            },
+            ChangeContent::Replace {
+                path: vec![field("list"), PathElement::Index(0)].into(),
+                old: big(3),
+                new: big(5),
+            },
+            ChangeContent::Delete {
+                path: vec![field("big")].into(),
+                old: big(8),
+            },
+        ];
+        let mut expected = initial.clone();
+        expected.apply_iter(&changes).unwrap();
+
+        let limit = 100;
+        let parts = split(changes, limit).unwrap();
+        assert!(parts.len() > 4);
+        let mut value = initial;
+        for part in parts {
+            let size = part
+                .iter()
+                .map(ChangeContent::encoded_size_hint)
+                .sum::<usize>()
+                + cbor_header_size(part.len() as u64);
+            assert!(size <= limit);
+            value.apply_iter(&part).unwrap();
+        }
+        assert_eq!(value, expected);
+
+        let blob = ChangeContent::Insert {
+            path: vec![field("text")].into(),
+            value: Value::String(Arc::new("a".repeat(limit))),
+        };
+        assert!(matches!(
+            split(vec![blob], limit),
+            Err(Error::ValueStore(ValueStoreError::ChangeTooLarge { .. }))
+        ));
+    }
+}
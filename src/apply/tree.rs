@@ -15,7 +15,7 @@
                 } else {
                     Err(ValueStoreError::InvalidTreeChange {
                         change: ChangeTree::Replace { old, new, changes },
-                        path: path.to_vec_mapped(PathElementRef::to_owned),
+                        path: path.to_vec_mapped(PathElementRef::to_owned).into(),
                     })
                 }
             }
@@ -23,7 +23,7 @@
                 if path.is_empty() {
                     Err(ValueStoreError::InvalidTreeChange {
                         change: ChangeTree::Remove { old, changes },
-                        path: Vec::with_capacity(0),
+                        path: Path::new(),
                     })
                 } else {
                     unreachable!()
@@ -33,7 +33,7 @@
                 if path.is_empty() {
                     Err(ValueStoreError::InvalidTreeChange {
                         change: ChangeTree::Add { new, changes },
-                        path: Vec::with_capacity(0),
+                        path: Path::new(),
                     })
                 } else {
                     unreachable!()
@@ -48,7 +48,7 @@
                 } else {
                     Err(ValueStoreError::InvalidTreeChange {
                         change: ChangeTree::Array(map),
-                        path: path.to_vec_mapped(PathElementRef::to_owned),
+                        path: path.to_vec_mapped(PathElementRef::to_owned).into(),
                     })
                 }
             }
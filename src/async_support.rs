@@ -15,6 +15,7 @@ pub mod not_send {
     impl<T> MaybeSend for T {}
     pub trait MaybeSync {}
     impl<T> MaybeSync for T {}
+    pub type BoxFuture<'a, T> = futures_util::future::LocalBoxFuture<'a, T>;
     pub struct Mutex<T> {
         locked: UnsafeCell<bool>,
         content: UnsafeCell<T>,
@@ -354,8 +355,8 @@ pub mod not_send {
 #[doc(no_inline)]
 #[cfg(target_arch = "wasm32")]
 pub use not_send::{
-    MappedMutexGuard, MaybeSend, MaybeSync, Mutex, MutexGuard, MutexLockFuture, OwnedMutexGuard,
-    OwnedMutexLockFuture,
+    BoxFuture, MappedMutexGuard, MaybeSend, MaybeSync, Mutex, MutexGuard, MutexLockFuture,
+    OwnedMutexGuard, OwnedMutexLockFuture,
 };
 
 #[cfg(any(not(target_arch = "wasm32"), doc))]
@@ -364,11 +365,12 @@ pub mod send {
     impl<T: Send> MaybeSend for T {}
     pub trait MaybeSync: Sync {}
     impl<T: Sync> MaybeSync for T {}
+    pub type BoxFuture<'a, T> = futures_util::future::BoxFuture<'a, T>;
 }
 
 #[doc(no_inline)]
 #[cfg(not(target_arch = "wasm32"))]
-pub use send::{MaybeSend, MaybeSync};
+pub use send::{BoxFuture, MaybeSend, MaybeSync};
 
 #[doc(no_inline)]
 #[cfg(not(target_arch = "wasm32"))]
@@ -0,0 +1,120 @@
+use std::{future::Future, sync::Arc};
+
+use futures_intrusive::sync::ManualResetEvent;
+use futures_util::{
+    future::{select, Either},
+    pin_mut,
+};
+
+use crate::{error::ValueStoreError, Result};
+
+/**
+ *  shared flag to abort running operations, clones cancel together.
+ *  cancelling is permanent, use a new token for the next operation.
+ *  */
+#[derive(Debug, Clone)]
+pub struct CancellationToken {
+    event: Arc<ManualResetEvent>,
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self {
+            event: Arc::new(ManualResetEvent::new(false)),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.event.set();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.event.is_set()
+    }
+
+    /// completes once the token is cancelled
+    pub async fn cancelled(&self) {
+        self.event.wait().await
+    }
+}
+
+/**
+ *  runs operation until it completes or token is cancelled.
+ *  on cancellation the operation is dropped, storage operations are cancellation safe:
+ *  open transactions are rolled back on drop, so either all or nothing of a write is stored.
+ *  */
+pub async fn with_cancellation<T, F>(operation: F, token: &CancellationToken) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    if token.is_cancelled() {
+        return Err(ValueStoreError::Cancelled.into());
+    }
+    let cancelled = token.cancelled();
+    pin_mut!(operation, cancelled);
+    match select(cancelled, operation).await {
+        Either::Left(_) => Err(ValueStoreError::Cancelled.into()),
+        Either::Right((res, _)) => res,
+    }
+}
+
+/**
+ *  runs operation until it completes or deadline elapses, see with_cancellation.
+ *  deadline is any future, usually the sleep of the async runtime in use.
+ *  */
+pub async fn with_deadline<T, F, D>(operation: F, deadline: D) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+    D: Future<Output = ()>,
+{
+    pin_mut!(operation, deadline);
+    match select(deadline, operation).await {
+        Either::Left(_) => Err(ValueStoreError::DeadlineExceeded.into()),
+        Either::Right((res, _)) => res,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures_util::{
+        future::{pending, ready},
+        FutureExt,
+    };
+
+    use super::*;
+    use crate::Error;
+
+    #[test]
+    fn cancellation() {
+        let token = CancellationToken::new();
+        let res = with_cancellation(ready(Ok(1)), &token).now_or_never();
+        assert!(matches!(res, Some(Ok(1))));
+        assert!(with_cancellation(pending::<Result<()>>(), &token)
+            .now_or_never()
+            .is_none());
+        token.clone().cancel();
+        assert!(token.is_cancelled());
+        let res = with_cancellation(pending::<Result<()>>(), &token).now_or_never();
+        assert!(matches!(
+            res,
+            Some(Err(Error::ValueStore(ValueStoreError::Cancelled)))
+        ));
+    }
+
+    #[test]
+    fn deadline() {
+        let res = with_deadline(pending::<Result<()>>(), ready(())).now_or_never();
+        assert!(matches!(
+            res,
+            Some(Err(Error::ValueStore(ValueStoreError::DeadlineExceeded)))
+        ));
+        let res = with_deadline(ready(Ok(1)), pending()).now_or_never();
+        assert!(matches!(res, Some(Ok(1))));
+    }
+}
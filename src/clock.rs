@@ -0,0 +1,119 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use uuid::Uuid;
+
+use crate::async_support::{MaybeSend, MaybeSync};
+
+/// source of all timestamps taken by the store
+pub trait Clock: MaybeSend + MaybeSync {
+    /// wall clock time, used for timestamps that are persisted
+    fn now(&self) -> SystemTime;
+    /// monotonic time, used for rate limits and timeouts
+    fn instant(&self) -> Instant;
+}
+
+/// source of the uuids of repos and branches created through the store
+pub trait IdGenerator: MaybeSend + MaybeSync {
+    fn new_id(&self) -> Uuid;
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// time sortable v7 uuids
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RandomIds;
+
+impl IdGenerator for RandomIds {
+    fn new_id(&self) -> Uuid {
+        Uuid::now_v7()
+    }
+}
+
+/**
+ *  clock that only moves when advanced, for reproducible tests.
+ *  instant starts at the time of creation, now at the given time.
+ *  */
+#[derive(Debug)]
+pub struct ManualClock {
+    start: SystemTime,
+    start_instant: Instant,
+    elapsed: Mutex<Duration>,
+}
+
+impl ManualClock {
+    pub fn new(start: SystemTime) -> Self {
+        Self {
+            start,
+            start_instant: Instant::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+    pub fn advance(&self, by: Duration) {
+        *self.elapsed.lock().expect("clock poisoned") += by;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        self.start + *self.elapsed.lock().expect("clock poisoned")
+    }
+    fn instant(&self) -> Instant {
+        self.start_instant + *self.elapsed.lock().expect("clock poisoned")
+    }
+}
+
+/// ids counting up from the given start, for reproducible tests
+#[derive(Debug, Default)]
+pub struct SequentialIds {
+    next: AtomicU64,
+}
+
+impl SequentialIds {
+    pub fn new(start: u64) -> Self {
+        Self {
+            next: AtomicU64::new(start),
+        }
+    }
+}
+
+impl IdGenerator for SequentialIds {
+    fn new_id(&self) -> Uuid {
+        Uuid::from_u64_pair(0, self.next.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+
+    #[test]
+    fn deterministic() {
+        let clock = ManualClock::new(UNIX_EPOCH);
+        let instant = clock.instant();
+        clock.advance(Duration::from_secs(3));
+        assert_eq!(clock.now(), UNIX_EPOCH + Duration::from_secs(3));
+        assert_eq!(clock.instant() - instant, Duration::from_secs(3));
+
+        let ids = SequentialIds::new(1);
+        assert_eq!(ids.new_id(), Uuid::from_u128(1));
+        assert_eq!(ids.new_id(), Uuid::from_u128(2));
+    }
+}
@@ -0,0 +1,83 @@
+use std::collections::BTreeMap;
+
+use crate::{storage::ConfigEntry, types::Value, Result};
+
+/// configuration of a branch, values set for the branch override the repo wide ones
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Config {
+    entries: BTreeMap<String, Value>,
+}
+
+impl Config {
+    pub const MERGE_STRATEGY: &'static str = "merge.strategy";
+    pub const SCHEMA_ID: &'static str = "schema.id";
+    /// last data migration applied, see schema::Migrations
+    pub const SCHEMA_VERSION: &'static str = "schema.version";
+    pub const RETENTION: &'static str = "retention";
+
+    /// decodes the cbor encoded entries read from storage
+    pub fn from_entries(repo: Vec<ConfigEntry>, branch: Vec<ConfigEntry>) -> Result<Self> {
+        let mut entries = BTreeMap::new();
+        for (name, content) in repo.into_iter().chain(branch) {
+            entries.insert(name, ciborium::from_reader(content.as_slice())?);
+        }
+        Ok(Self { entries })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.entries.get(name)
+    }
+    pub fn get_str(&self, name: &str) -> Option<&str> {
+        match self.get(name) {
+            Some(Value::String(v)) => Some(v),
+            _ => None,
+        }
+    }
+    pub fn get_int(&self, name: &str) -> Option<i64> {
+        match self.get(name) {
+            Some(Value::Integer(v)) => Some(*v),
+            _ => None,
+        }
+    }
+    pub fn get_bool(&self, name: &str) -> Option<bool> {
+        match self.get(name) {
+            Some(Value::Bool(v)) => Some(*v),
+            _ => None,
+        }
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Value)> {
+        self.entries.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn entry(name: &str, value: Value) -> ConfigEntry {
+        let mut content = Vec::new();
+        ciborium::into_writer(&value, &mut content).unwrap();
+        (name.to_string(), content)
+    }
+
+    #[test]
+    fn branch_overrides_repo() {
+        let config = Config::from_entries(
+            vec![
+                entry(
+                    Config::MERGE_STRATEGY,
+                    Value::String(Arc::new("ours".to_string())),
+                ),
+                entry(Config::RETENTION, Value::Integer(30)),
+            ],
+            vec![entry(Config::RETENTION, Value::Integer(7))],
+        )
+        .unwrap();
+        assert_eq!(config.get_str(Config::MERGE_STRATEGY), Some("ours"));
+        assert_eq!(config.get_int(Config::RETENTION), Some(7));
+        assert_eq!(config.get_bool(Config::RETENTION), None);
+        assert_eq!(config.get(Config::SCHEMA_ID), None);
+    }
+}
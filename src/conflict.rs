@@ -5,9 +5,12 @@ use std::{
 };
 
 use crate::{
+    apply::simple::{apply_delete, apply_insert, apply_replace},
     error::ValueStoreError,
-    types::{change::ChangeContent, PathElement, Value}, apply::simple::{apply_insert, apply_replace},
+    types::{change::ChangeContent, path::MAX_DEPTH, Path, PathElement, Value},
 };
+pub mod markers;
+
 pub struct ActiveConflict {
     pub common_value: Value,
     pub conflicts: [ChangeTree; 2],
@@ -34,31 +37,94 @@ pub fn check_conflicts_common_ancestor<
     None
 }
 
-#[derive(Debug)]
+/// change with its position in the changes a ChangeTree was built from
+pub type Recorded = (u64, ChangeContent);
+
+#[derive(Debug, PartialEq)]
 pub enum ChangeTree {
     Replace {
         old: Value,
         new: Value,
-        changes: Vec<ChangeContent>,
+        changes: Vec<Recorded>,
     },
     Remove {
         old: Value,
-        changes: Vec<ChangeContent>,
+        changes: Vec<Recorded>,
     },
     Add {
         new: Value,
-        changes: Vec<ChangeContent>,
+        changes: Vec<Recorded>,
+    },
+    Array {
+        /// changed elements by their current position
+        data: BTreeMap<u32, ChangeTree>,
+        /// inserts at PathElement::Append, these never conflict with each other
+        appends: Vec<Recorded>,
+        /**
+         *  removed elements as ChangeTree::Remove, by the original index they were at or,
+         *  for inserted elements, in front of. old is the value of the original element
+         *  where it is known, the removed value otherwise.
+         *  */
+        removed: Vec<(u32, ChangeTree)>,
+        /// maps indices of the original array to their current position, see translate_index
+        offsets: BTreeMap<u32, u32>,
     },
-    Array(BTreeMap<u32, ChangeTree>),
     Map(HashMap<String, ChangeTree>),
 }
 
+/**
+ *  offsets describe how indices of an array move through inserts and deletes.
+ *  every entry maps an original index to its current position, indices between
+ *  two entries move like the lower one. indices below the first entry don't move.
+ *  */
+pub fn translate_index(offsets: &BTreeMap<u32, u32>, index: u32) -> u32 {
+    match offsets.range(..=index).next_back() {
+        Some((point, val)) => val + (index - point),
+        None => index,
+    }
+}
+
+/// smallest original index currently at or behind position current
+pub fn original_index(offsets: &BTreeMap<u32, u32>, current: u32) -> u32 {
+    let mut start = 0;
+    let mut base = 0;
+    for (point, val) in offsets {
+        if base + (point - start) > current {
+            break;
+        }
+        if *val >= current {
+            return *point;
+        }
+        start = *point;
+        base = *val;
+    }
+    start + (current - base)
+}
+
+/// moves all original indices starting at from by delta
+fn shift_offsets(offsets: &mut BTreeMap<u32, u32>, from: u32, delta: i64) {
+    let at = translate_index(offsets, from);
+    offsets.insert(from, at);
+    for val in offsets.range_mut(from..).map(|(_, val)| val) {
+        *val = (*val as i64 + delta) as u32;
+    }
+}
+
+/// records an insert of a new element at the current position index
 pub fn increase_offset(offsets: &mut BTreeMap<u32, u32>, index: u32) {
-    if let Some((point, val)) = offsets.range(..=index).next_back() {
-        let add = index - point;
-        offsets.insert(index, val + add + 1);
+    let from = original_index(offsets, index);
+    shift_offsets(offsets, from, 1);
+}
+
+/// records the removal of the element currently at index
+pub fn decrease_offset(offsets: &mut BTreeMap<u32, u32>, index: u32) {
+    let from = original_index(offsets, index);
+    if translate_index(offsets, from) == index {
+        // an original element, the ones behind it move forward
+        shift_offsets(offsets, from + 1, -1);
     } else {
-        offsets.insert(index, index + 1);
+        // an inserted element in front of from
+        shift_offsets(offsets, from, -1);
     }
 }
 
@@ -67,23 +133,120 @@ impl ChangeTree {
         iter: I,
     ) -> Result<Option<ChangeTree>, ValueStoreError> {
         let mut res = None;
-        for change in iter {
-            Self::add_change(&mut res, change)?
-        }
+        Self::extend(&mut res, iter)?;
         Ok(res)
     }
 
+    /// appends changes made after the ones this was constructed from
+    pub fn extend<I: IntoIterator<Item = ChangeContent>>(
+        this: &mut Option<ChangeTree>,
+        iter: I,
+    ) -> Result<(), ValueStoreError> {
+        let first = this
+            .as_ref()
+            .and_then(ChangeTree::last_seq)
+            .map_or(0, |seq| seq + 1);
+        for (seq, change) in (first..).zip(iter) {
+            Self::add_change(this, seq, change)?;
+        }
+        Ok(())
+    }
+
+    /**
+     *  removes changes that were already merged and rebuilds the tree from the remaining ones
+     *  in their original order. merged has to be a prefix of the changes this was constructed
+     *  from, otherwise the first merged change that differs fails with
+     *  ValueStoreError::InvalidChange.
+     *  */
+    pub fn subtract_prefix(
+        this: Option<ChangeTree>,
+        merged: &[ChangeContent],
+    ) -> Result<Option<ChangeTree>, ValueStoreError> {
+        let mut changes = Vec::new();
+        if let Some(this) = this {
+            this.into_changes(&mut changes);
+        }
+        changes.sort_unstable_by_key(|(seq, _)| *seq);
+        for (index, change) in merged.iter().enumerate() {
+            if changes
+                .get(index)
+                .is_none_or(|(_, recorded)| recorded != change)
+            {
+                return Err(ValueStoreError::InvalidChange {
+                    change: change.clone(),
+                });
+            }
+        }
+        Self::construct(
+            changes
+                .into_iter()
+                .skip(merged.len())
+                .map(|(_, change)| change),
+        )
+    }
+
+    /// position of the last change recorded in the subtree
+    fn last_seq(&self) -> Option<u64> {
+        match self {
+            ChangeTree::Replace { changes, .. }
+            | ChangeTree::Remove { changes, .. }
+            | ChangeTree::Add { changes, .. } => changes.last().map(|(seq, _)| *seq),
+            ChangeTree::Array {
+                data,
+                appends,
+                removed,
+                ..
+            } => removed
+                .iter()
+                .map(|(_, child)| child)
+                .chain(data.values())
+                .filter_map(ChangeTree::last_seq)
+                .chain(appends.last().map(|(seq, _)| *seq))
+                .max(),
+            ChangeTree::Map(map) => map.values().filter_map(ChangeTree::last_seq).max(),
+        }
+    }
+
+    /// all changes recorded in the subtree
+    fn into_changes(self, out: &mut Vec<Recorded>) {
+        match self {
+            ChangeTree::Replace { changes, .. }
+            | ChangeTree::Remove { changes, .. }
+            | ChangeTree::Add { changes, .. } => out.extend(changes),
+            ChangeTree::Array {
+                data,
+                appends,
+                removed,
+                ..
+            } => {
+                for (_, child) in removed {
+                    child.into_changes(out);
+                }
+                for child in data.into_values() {
+                    child.into_changes(out);
+                }
+                out.extend(appends);
+            }
+            ChangeTree::Map(map) => {
+                for child in map.into_values() {
+                    child.into_changes(out);
+                }
+            }
+        }
+    }
+
     fn add_change_insert(
         &mut self,
-        path: Vec<PathElement>,
+        path: Path,
         value: Value,
         index: usize,
+        seq: u64,
     ) -> Result<(), ValueStoreError> {
         if let Some(elem) = path.get(index) {
             match self {
                 ChangeTree::Replace { old, new, changes } => {
                     apply_insert(new, &path[index + 1..], value.clone(), &path)?;
-                    changes.push(ChangeContent::Insert { path, value });
+                    changes.push((seq, ChangeContent::Insert { path, value }));
                     Ok(())
                 }
                 ChangeTree::Remove { old, changes } => Err(ValueStoreError::InvalidChange {
@@ -91,33 +254,48 @@ impl ChangeTree {
                 }),
                 ChangeTree::Add { new, changes } => {
                     apply_insert(new, &path[index + 1..], value.clone(), &path)?;
-                    changes.push(ChangeContent::Insert { path, value });
+                    changes.push((seq, ChangeContent::Insert { path, value }));
                     Ok(())
                 }
-                ChangeTree::Array(map) => {
-                    if let PathElement::Index(i) = elem {
-                        if let Some(new) = map.get_mut(i) {
-                            new.add_change_insert(path, value, index + 1)
-                        } else {
-                            let after = map.split_off(i);
-                            map.insert(*i, Self::from_insert(path, value, index + 1));
+                ChangeTree::Array {
+                    data: map,
+                    appends,
+                    offsets,
+                    ..
+                } => match elem {
+                    PathElement::Index(i) => {
+                        let i = *i;
+                        if index + 1 == path.len() {
+                            // a new element, everything from i on moves back by one
+                            let after = map.split_off(&i);
+                            map.insert(i, Self::from_insert(path, value, index + 1, seq)?);
                             for (key, value) in after.into_iter() {
                                 map.insert(key + 1, value);
                             }
+                            increase_offset(offsets, i);
+                            Ok(())
+                        } else if let Some(new) = map.get_mut(&i) {
+                            new.add_change_insert(path, value, index + 1, seq)
+                        } else {
+                            map.insert(i, Self::from_insert(path, value, index + 1, seq)?);
                             Ok(())
                         }
-                    } else {
-                        Err(ValueStoreError::InvalidChange {
-                            change: ChangeContent::Insert { path, value },
-                        })
                     }
-                }
+                    PathElement::Append if index + 1 == path.len() => {
+                        appends.push((seq, ChangeContent::Insert { path, value }));
+                        Ok(())
+                    }
+                    _ => Err(ValueStoreError::InvalidChange {
+                        change: ChangeContent::Insert { path, value },
+                    }),
+                },
                 ChangeTree::Map(map) => {
                     if let PathElement::Field(name) = elem {
                         if let Some(new) = map.get_mut(name) {
-                            new.add_change_insert(path, value, index + 1)
+                            new.add_change_insert(path, value, index + 1, seq)
                         } else {
-                            map.insert(name.clone(), Self::from_insert(path, value, index + 1));
+                            let name = name.clone();
+                            map.insert(name, Self::from_insert(path, value, index + 1, seq)?);
                             Ok(())
                         }
                     } else {
@@ -132,10 +310,13 @@ impl ChangeTree {
                 ChangeTree::Remove { old, changes } => {
                     let mut changes = mem::replace(changes, Vec::with_capacity(0));
                     let old = mem::replace(old, Value::Integer(0));
-                    changes.push(ChangeContent::Insert {
-                        path,
-                        value: value.clone(),
-                    });
+                    changes.push((
+                        seq,
+                        ChangeContent::Insert {
+                            path,
+                            value: value.clone(),
+                        },
+                    ));
                     *self = Self::Replace {
                         old,
                         new: value,
@@ -151,20 +332,24 @@ impl ChangeTree {
     }
     fn add_change_replace(
         &mut self,
-        path: Vec<PathElement>,
+        path: Path,
         old_val: Value,
         new_val: Value,
         index: usize,
+        seq: u64,
     ) -> Result<(), ValueStoreError> {
         if let Some(elem) = path.get(index) {
             match self {
                 ChangeTree::Replace { old, new, changes } => {
-                    apply_replace(new,&path[index + 1..], &old_val, new_val.clone(), &path)?;
-                    changes.push(ChangeContent::Replace {
-                        path,
-                        old: old_val,
-                        new: new_val,
-                    });
+                    apply_replace(new, &path[index + 1..], &old_val, new_val.clone(), &path)?;
+                    changes.push((
+                        seq,
+                        ChangeContent::Replace {
+                            path,
+                            old: old_val,
+                            new: new_val,
+                        },
+                    ));
                     Ok(())
                 }
                 ChangeTree::Remove { old, changes } => Err(ValueStoreError::InvalidChange {
@@ -175,24 +360,26 @@ impl ChangeTree {
                     },
                 }),
                 ChangeTree::Add { new, changes } => {
-                    apply_replace(new,&path[index + 1..], &old_val, new_val.clone(), &path)?;
-                    changes.push(ChangeContent::Replace {
-                        path,
-                        old: old_val,
-                        new: new_val,
-                    });
+                    apply_replace(new, &path[index + 1..], &old_val, new_val.clone(), &path)?;
+                    changes.push((
+                        seq,
+                        ChangeContent::Replace {
+                            path,
+                            old: old_val,
+                            new: new_val,
+                        },
+                    ));
                     Ok(())
                 }
-                ChangeTree::Array(map) => {
+                ChangeTree::Array { data: map, .. } => {
                     if let PathElement::Index(i) = elem {
                         if let Some(new) = map.get_mut(i) {
-                            new.add_change_replace(path, old_val, new_val, index + 1)
+                            new.add_change_replace(path, old_val, new_val, index + 1, seq)
                         } else {
-                            let after = map.split_off(i);
-                            map.insert(*i, Self::from_replace(path, old_val, new_val, index + 1));
-                            for (key, value) in after.into_iter() {
-                                map.insert(key + 1, value);
-                            }
+                            map.insert(
+                                *i,
+                                Self::from_replace(path, old_val, new_val, index + 1, seq)?,
+                            );
                             Ok(())
                         }
                     } else {
@@ -208,11 +395,12 @@ impl ChangeTree {
                 ChangeTree::Map(map) => {
                     if let PathElement::Field(name) = elem {
                         if let Some(new) = map.get_mut(name) {
-                            new.add_change_replace(path, old_val, new_val, index + 1)
+                            new.add_change_replace(path, old_val, new_val, index + 1, seq)
                         } else {
+                            let name = name.clone();
                             map.insert(
-                                name.clone(),
-                                Self::from_replace(path, old_val, new_val, index + 1),
+                                name,
+                                Self::from_replace(path, old_val, new_val, index + 1, seq)?,
                             );
                             Ok(())
                         }
@@ -228,84 +416,573 @@ impl ChangeTree {
                 }
             }
         } else {
-            todo!()
+            match self {
+                ChangeTree::Replace { new, changes, .. } | ChangeTree::Add { new, changes }
+                    if *new == old_val =>
+                {
+                    *new = new_val.clone();
+                    changes.push((
+                        seq,
+                        ChangeContent::Replace {
+                            path,
+                            old: old_val,
+                            new: new_val,
+                        },
+                    ));
+                    Ok(())
+                }
+                ChangeTree::Array { .. } | ChangeTree::Map(_) => {
+                    // the value as a whole is replaced, old is the value after the changes so far
+                    let mut changes = Vec::new();
+                    mem::replace(self, ChangeTree::Map(HashMap::new())).into_changes(&mut changes);
+                    changes.push((
+                        seq,
+                        ChangeContent::Replace {
+                            path,
+                            old: old_val.clone(),
+                            new: new_val.clone(),
+                        },
+                    ));
+                    *self = Self::Replace {
+                        old: old_val,
+                        new: new_val,
+                        changes,
+                    };
+                    Ok(())
+                }
+                _ => Err(ValueStoreError::InvalidChange {
+                    change: ChangeContent::Replace {
+                        path,
+                        old: old_val,
+                        new: new_val,
+                    },
+                }),
+            }
         }
     }
-    fn from_insert(path: Vec<PathElement>, value: Value, index: usize) -> Self {
-        match path.get(index) {
-            Some(PathElement::Field(name)) => {
-                let mut new = HashMap::new();
-                new.insert(name.clone(), Self::from_insert(path, value, index + 1));
-                Self::Map(new)
-            }
-            Some(PathElement::Index(i)) => {
-                let mut new = BTreeMap::new();
-                new.insert(*i, Self::from_insert(path, value, index + 1));
-                Self::Array(new)
+    fn add_change_delete(
+        &mut self,
+        path: Path,
+        old_val: Value,
+        index: usize,
+        seq: u64,
+    ) -> Result<(), ValueStoreError> {
+        let Some(elem) = path.get(index) else {
+            let mut changes = Vec::new();
+            let old = match mem::replace(self, ChangeTree::Map(HashMap::new())) {
+                ChangeTree::Replace {
+                    old,
+                    new,
+                    changes: prev,
+                } if new == old_val => {
+                    changes = prev;
+                    old
+                }
+                ChangeTree::Add { new, changes: prev } if new == old_val => {
+                    changes = prev;
+                    new
+                }
+                tree @ (ChangeTree::Array { .. } | ChangeTree::Map(_)) => {
+                    tree.into_changes(&mut changes);
+                    old_val.clone()
+                }
+                tree => {
+                    *self = tree;
+                    return Err(ValueStoreError::InvalidChange {
+                        change: ChangeContent::Delete { path, old: old_val },
+                    });
+                }
+            };
+            changes.push((seq, ChangeContent::Delete { path, old: old_val }));
+            *self = Self::Remove { old, changes };
+            return Ok(());
+        };
+        match self {
+            ChangeTree::Replace { new, changes, .. } | ChangeTree::Add { new, changes } => {
+                apply_delete(new, &path[index + 1..], &old_val, &path)?;
+                changes.push((seq, ChangeContent::Delete { path, old: old_val }));
+                Ok(())
             }
-            None => Self::Add {
-                new: value.clone(),
-                changes: vec![ChangeContent::Insert { path, value }],
+            ChangeTree::Remove { .. } => Err(ValueStoreError::InvalidChange {
+                change: ChangeContent::Delete { path, old: old_val },
+            }),
+            ChangeTree::Array {
+                data,
+                removed,
+                offsets,
+                ..
+            } => match elem {
+                PathElement::Index(i) if index + 1 == path.len() => {
+                    let i = *i;
+                    let (old, mut changes) = match data.remove(&i) {
+                        Some(ChangeTree::Replace { old, new, changes }) if new == old_val => {
+                            (old, changes)
+                        }
+                        Some(ChangeTree::Add { new, changes }) if new == old_val => (new, changes),
+                        Some(tree @ (ChangeTree::Array { .. } | ChangeTree::Map(_))) => {
+                            let mut changes = Vec::new();
+                            tree.into_changes(&mut changes);
+                            (old_val.clone(), changes)
+                        }
+                        None => (old_val.clone(), Vec::new()),
+                        Some(tree) => {
+                            data.insert(i, tree);
+                            return Err(ValueStoreError::InvalidChange {
+                                change: ChangeContent::Delete { path, old: old_val },
+                            });
+                        }
+                    };
+                    changes.push((seq, ChangeContent::Delete { path, old: old_val }));
+                    removed.push((
+                        original_index(offsets, i),
+                        ChangeTree::Remove { old, changes },
+                    ));
+                    decrease_offset(offsets, i);
+                    // everything behind i moves forward by one
+                    let after = data.split_off(&i);
+                    data.extend(after.into_iter().map(|(key, tree)| (key - 1, tree)));
+                    Ok(())
+                }
+                PathElement::Index(i) => {
+                    if let Some(child) = data.get_mut(i) {
+                        child.add_change_delete(path, old_val, index + 1, seq)
+                    } else {
+                        data.insert(*i, Self::from_delete(path, old_val, index + 1, seq)?);
+                        Ok(())
+                    }
+                }
+                _ => Err(ValueStoreError::InvalidChange {
+                    change: ChangeContent::Delete { path, old: old_val },
+                }),
+            },
+            ChangeTree::Map(map) => match elem {
+                PathElement::Field(name) => {
+                    if let Some(child) = map.get_mut(name) {
+                        child.add_change_delete(path, old_val, index + 1, seq)
+                    } else {
+                        let name = name.clone();
+                        map.insert(name, Self::from_delete(path, old_val, index + 1, seq)?);
+                        Ok(())
+                    }
+                }
+                _ => Err(ValueStoreError::InvalidChange {
+                    change: ChangeContent::Delete { path, old: old_val },
+                }),
             },
         }
     }
+    /**
+     *  wraps leaf into the maps and arrays along path[index..end], built bottom up
+     *  so deep paths don't recurse. None if an element addresses an array from the end,
+     *  arrays are tracked by index so those elements can't be placed.
+     *  */
+    fn nest(path: &[PathElement], index: usize, end: usize, leaf: Self) -> Option<Self> {
+        let mut res = leaf;
+        for elem in path[index..end].iter().rev() {
+            res = match elem {
+                PathElement::Field(name) => Self::Map(HashMap::from([(name.clone(), res)])),
+                PathElement::Index(i) => Self::Array {
+                    data: BTreeMap::from([(*i, res)]),
+                    appends: Vec::new(),
+                    removed: Vec::new(),
+                    offsets: BTreeMap::new(),
+                },
+                PathElement::Append | PathElement::FromEnd(_) => return None,
+            };
+        }
+        Some(res)
+    }
 
-    fn from_replace(path: Vec<PathElement>, old: Value, new: Value, index: usize) -> Self {
-        match path.get(index) {
-            Some(PathElement::Field(name)) => {
-                let mut m = HashMap::new();
-                m.insert(name.clone(), Self::from_replace(path, old, new, index + 1));
-                Self::Map(m)
+    fn from_insert(
+        path: Path,
+        value: Value,
+        index: usize,
+        seq: u64,
+    ) -> Result<Self, ValueStoreError> {
+        let nested = match path.last() {
+            Some(PathElement::Append) if index < path.len() => {
+                let leaf = Self::Array {
+                    data: BTreeMap::new(),
+                    offsets: BTreeMap::new(),
+                    appends: vec![(
+                        seq,
+                        ChangeContent::Insert {
+                            path: path.clone(),
+                            value: value.clone(),
+                        },
+                    )],
+                    removed: Vec::new(),
+                };
+                Self::nest(&path, index, path.len() - 1, leaf)
             }
-            Some(PathElement::Index(i)) => {
-                let mut m = BTreeMap::new();
-                m.insert(*i, Self::from_replace(path, old, new, index + 1));
-                Self::Array(m)
+            last => {
+                let add = Self::Add {
+                    new: value.clone(),
+                    changes: vec![(
+                        seq,
+                        ChangeContent::Insert {
+                            path: path.clone(),
+                            value: value.clone(),
+                        },
+                    )],
+                };
+                match last {
+                    // a new element moves the ones behind it
+                    Some(PathElement::Index(i)) if index < path.len() => {
+                        let mut offsets = BTreeMap::new();
+                        increase_offset(&mut offsets, *i);
+                        let leaf = Self::Array {
+                            data: BTreeMap::from([(*i, add)]),
+                            appends: Vec::new(),
+                            removed: Vec::new(),
+                            offsets,
+                        };
+                        Self::nest(&path, index, path.len() - 1, leaf)
+                    }
+                    _ => Self::nest(&path, index, path.len(), add),
+                }
             }
-            None => Self::Replace {
-                old: old.clone(),
-                new: new.clone(),
-                changes: vec![ChangeContent::Replace { path, old, new }],
-            },
-        }
+        };
+        nested.ok_or(ValueStoreError::InvalidChange {
+            change: ChangeContent::Insert { path, value },
+        })
     }
 
-    fn from_delete(path: Vec<PathElement>, old: Value, index: usize) -> Self {
-        match path.get(index) {
-            Some(PathElement::Field(name)) => {
-                let mut m = HashMap::new();
-                m.insert(name.clone(), Self::from_delete(path, old, index + 1));
-                Self::Map(m)
-            }
-            Some(PathElement::Index(i)) => {
-                let mut m = BTreeMap::new();
-                m.insert(*i, Self::from_delete(path, old, index + 1));
-                Self::Array(m)
+    fn from_replace(
+        path: Path,
+        old: Value,
+        new: Value,
+        index: usize,
+        seq: u64,
+    ) -> Result<Self, ValueStoreError> {
+        let leaf = Self::Replace {
+            old: old.clone(),
+            new: new.clone(),
+            changes: vec![(
+                seq,
+                ChangeContent::Replace {
+                    path: path.clone(),
+                    old: old.clone(),
+                    new: new.clone(),
+                },
+            )],
+        };
+        Self::nest(&path, index, path.len(), leaf).ok_or(ValueStoreError::InvalidChange {
+            change: ChangeContent::Replace { path, old, new },
+        })
+    }
+
+    fn from_delete(
+        path: Path,
+        old: Value,
+        index: usize,
+        seq: u64,
+    ) -> Result<Self, ValueStoreError> {
+        let remove = Self::Remove {
+            old: old.clone(),
+            changes: vec![(
+                seq,
+                ChangeContent::Delete {
+                    path: path.clone(),
+                    old: old.clone(),
+                },
+            )],
+        };
+        let nested = match path.last() {
+            // removing an element moves the ones behind it
+            Some(PathElement::Index(i)) if index < path.len() => {
+                let mut offsets = BTreeMap::new();
+                decrease_offset(&mut offsets, *i);
+                let leaf = Self::Array {
+                    data: BTreeMap::new(),
+                    appends: Vec::new(),
+                    removed: vec![(*i, remove)],
+                    offsets,
+                };
+                Self::nest(&path, index, path.len() - 1, leaf)
             }
-            None => Self::Remove {
-                old: old.clone(),
-                changes: vec![ChangeContent::Delete { path, old }],
-            },
-        }
+            _ => Self::nest(&path, index, path.len(), remove),
+        };
+        nested.ok_or(ValueStoreError::InvalidChange {
+            change: ChangeContent::Delete { path, old },
+        })
     }
 
     fn add_change(
         this: &mut Option<ChangeTree>,
+        seq: u64,
         change: ChangeContent,
     ) -> Result<(), ValueStoreError> {
+        // adding a change recurses once per path element
+        if change.path().len() > MAX_DEPTH {
+            return Err(ValueStoreError::PathTooDeep {
+                depth: change.path().len(),
+            });
+        }
         if let Some(this) = this.as_mut() {
             match change {
-                ChangeContent::Insert { path, value } => this.add_change_insert(path, value, 0),
-                ChangeContent::Replace { path, old, new } => todo!(),
-                ChangeContent::Delete { path, old } => todo!(),
+                ChangeContent::Insert { path, value } => {
+                    this.add_change_insert(path, value, 0, seq)
+                }
+                ChangeContent::Replace { path, old, new } => {
+                    this.add_change_replace(path, old, new, 0, seq)
+                }
+                ChangeContent::Delete { path, old } => this.add_change_delete(path, old, 0, seq),
             }
         } else {
             *this = Some(match change {
-                ChangeContent::Insert { path, value } => Self::from_insert(path, value, 0),
-                ChangeContent::Replace { path, old, new } => Self::from_replace(path, old, new, 0),
-                ChangeContent::Delete { path, old } => Self::from_delete(path, old, 0),
+                ChangeContent::Insert { path, value } => Self::from_insert(path, value, 0, seq)?,
+                ChangeContent::Replace { path, old, new } => {
+                    Self::from_replace(path, old, new, 0, seq)?
+                }
+                ChangeContent::Delete { path, old } => Self::from_delete(path, old, 0, seq)?,
             });
             Ok(())
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn insert(field: &str, key: &str, value: i64) -> ChangeContent {
+        ChangeContent::Insert {
+            path: vec![
+                PathElement::Field(field.to_string()),
+                PathElement::Field(key.to_string()),
+            ]
+            .into(),
+            value: Value::Integer(value),
+        }
+    }
+
+    #[test]
+    fn offsets() {
+        let mut offsets = BTreeMap::new();
+        increase_offset(&mut offsets, 5);
+        increase_offset(&mut offsets, 2);
+        assert_eq!(translate_index(&offsets, 1), 1);
+        assert_eq!(translate_index(&offsets, 2), 3);
+        assert_eq!(translate_index(&offsets, 5), 7);
+        assert_eq!(original_index(&offsets, 2), 2);
+        assert_eq!(original_index(&offsets, 7), 5);
+        // remove original 3, currently at 4
+        decrease_offset(&mut offsets, 4);
+        assert_eq!(translate_index(&offsets, 4), 4);
+        assert_eq!(translate_index(&offsets, 5), 6);
+        // remove the element inserted at 2, then the one inserted in front of original 5
+        decrease_offset(&mut offsets, 2);
+        decrease_offset(&mut offsets, 4);
+        for i in [0, 1, 2, 4, 5, 9] {
+            assert_eq!(translate_index(&offsets, i), if i > 3 { i - 1 } else { i });
+        }
+    }
+
+    #[test]
+    fn insert_delete_interleaving() {
+        let item = |i: u32, value: &str| {
+            (
+                vec![
+                    PathElement::Field("list".to_string()),
+                    PathElement::Index(i),
+                ]
+                .into(),
+                Value::String(std::sync::Arc::new(value.to_string())),
+            )
+        };
+        let insert = |i, value| {
+            let (path, value) = item(i, value);
+            ChangeContent::Insert { path, value }
+        };
+        let delete = |i, old| {
+            let (path, old) = item(i, old);
+            ChangeContent::Delete { path, old }
+        };
+        let changes = [
+            insert(1, "a"),
+            delete(3, "o2"),
+            insert(0, "b"),
+            ChangeContent::Replace {
+                path: item(1, "o0").0,
+                old: item(1, "o0").1,
+                new: item(1, "c").1,
+            },
+            delete(2, "a"),
+            delete(1, "c"),
+        ];
+        let original: Vec<Value> = ["o0", "o1", "o2", "o3"]
+            .iter()
+            .map(|value| item(0, value).1)
+            .collect();
+        let mut value = Value::from(HashMap::from([(
+            "list".to_string(),
+            Value::Array(std::sync::Arc::new(original.clone())),
+        )]));
+        value.apply_iter(&changes).unwrap();
+        let Value::Map(root) = &value else {
+            panic!("root not a map")
+        };
+        let Some(Value::Array(current)) = root.get("list") else {
+            panic!("list not an array")
+        };
+        assert_eq!(
+            **current,
+            vec![item(0, "b").1, item(0, "o1").1, item(0, "o3").1]
+        );
+
+        let tree = ChangeTree::construct(changes.clone()).unwrap().unwrap();
+        let ChangeTree::Map(map) = &tree else {
+            panic!("root not a map")
+        };
+        let Some(ChangeTree::Array {
+            data,
+            removed,
+            offsets,
+            ..
+        }) = map.get("list")
+        else {
+            panic!("list not an array")
+        };
+        // remaining original elements are found at their current position
+        for (index, value) in original.iter().enumerate() {
+            if [1, 3].contains(&index) {
+                assert_eq!(
+                    &current[translate_index(offsets, index as u32) as usize],
+                    value
+                );
+            }
+        }
+        assert_eq!(data.keys().copied().collect::<Vec<_>>(), vec![0]);
+        let removed: Vec<_> = removed
+            .iter()
+            .map(|(index, tree)| match tree {
+                ChangeTree::Remove { old, changes } => (*index, old.clone(), changes.len()),
+                tree => panic!("{tree:?} is no removal"),
+            })
+            .collect();
+        assert_eq!(
+            removed,
+            vec![
+                (2, item(0, "o2").1, 1),
+                (1, item(0, "a").1, 2),
+                (0, item(0, "o0").1, 2)
+            ]
+        );
+
+        assert!(matches!(
+            ChangeTree::construct([delete(0, "x"), delete(0, "y")]),
+            Ok(Some(_))
+        ));
+        assert!(matches!(
+            ChangeTree::construct([insert(0, "x"), delete(0, "y")]),
+            Err(ValueStoreError::InvalidChange { .. })
+        ));
+    }
+
+    #[test]
+    fn incremental() {
+        let mut tree = ChangeTree::construct([insert("a", "x", 1), insert("b", "y", 2)]).unwrap();
+        ChangeTree::extend(&mut tree, [insert("a", "z", 3)]).unwrap();
+        assert_eq!(
+            tree,
+            ChangeTree::construct([
+                insert("a", "x", 1),
+                insert("b", "y", 2),
+                insert("a", "z", 3)
+            ])
+            .unwrap()
+        );
+        let tree = ChangeTree::subtract_prefix(tree, &[insert("a", "x", 1)]).unwrap();
+        assert_eq!(
+            tree,
+            ChangeTree::construct([insert("b", "y", 2), insert("a", "z", 3)]).unwrap()
+        );
+        let tree =
+            ChangeTree::subtract_prefix(tree, &[insert("b", "y", 2), insert("a", "z", 3)]).unwrap();
+        assert_eq!(tree, None);
+    }
+
+    #[test]
+    fn subtract_deletes() {
+        let list = |i: u32| {
+            vec![
+                PathElement::Field("list".to_string()),
+                PathElement::Index(i),
+            ]
+            .into()
+        };
+        let changes = vec![
+            insert("a", "x", 1),
+            ChangeContent::Delete {
+                path: list(0),
+                old: Value::Integer(1),
+            },
+            insert("b", "y", 2),
+            ChangeContent::Insert {
+                path: list(0),
+                value: Value::Integer(2),
+            },
+            ChangeContent::Delete {
+                path: vec![
+                    PathElement::Field("a".to_string()),
+                    PathElement::Field("x".to_string()),
+                ]
+                .into(),
+                old: Value::Integer(1),
+            },
+            insert("a", "z", 3),
+        ];
+        let tree = ChangeTree::construct(changes.clone()).unwrap();
+        for merged in 0..=changes.len() {
+            // the remaining changes keep their order across subtrees
+            let all = ChangeTree::construct(changes.clone()).unwrap();
+            let rest = ChangeTree::subtract_prefix(all, &changes[..merged]).unwrap();
+            assert_eq!(
+                rest,
+                ChangeTree::construct(changes[merged..].to_vec()).unwrap()
+            );
+        }
+        // extending continues after the changes the tree holds
+        let mut extended = ChangeTree::construct(changes[..3].to_vec()).unwrap();
+        ChangeTree::extend(&mut extended, changes[3..].to_vec()).unwrap();
+        assert_eq!(extended, tree);
+
+        // merged changes that are not a prefix
+        for merged in [&changes[1..2], &[changes[0].clone(), changes[2].clone()]] {
+            assert!(matches!(
+                ChangeTree::subtract_prefix(
+                    ChangeTree::construct(changes.clone()).unwrap(),
+                    merged
+                ),
+                Err(ValueStoreError::InvalidChange { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn depth_limit() {
+        let path: Path = vec![PathElement::Index(0); MAX_DEPTH].into();
+        let tree = ChangeTree::construct([ChangeContent::Insert {
+            path: path.clone(),
+            value: Value::Bool(true),
+        }])
+        .unwrap()
+        .unwrap();
+        assert!(matches!(tree, ChangeTree::Array { .. }));
+        assert!(matches!(
+            ChangeTree::construct([ChangeContent::Insert {
+                path: path.join(PathElement::Index(0)),
+                value: Value::Bool(true),
+            }]),
+            Err(ValueStoreError::PathTooDeep { .. })
+        ));
+        assert!(matches!(
+            ChangeTree::construct([ChangeContent::Insert {
+                path: vec![PathElement::Append, PathElement::Index(0)].into(),
+                value: Value::Bool(true),
+            }]),
+            Err(ValueStoreError::InvalidChange { .. })
+        ));
+    }
+}
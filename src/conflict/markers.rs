@@ -0,0 +1,427 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::Arc,
+};
+
+use crate::{
+    conflict::{translate_index, ChangeTree},
+    error::ValueStoreError,
+    types::{change::ChangeContent, Path, PathElement, Value},
+};
+
+/// key of the map replacing a conflicting value
+pub const CONFLICT_KEY: &str = "__conflict";
+pub const BASE_KEY: &str = "base";
+pub const OURS_KEY: &str = "ours";
+pub const THEIRS_KEY: &str = "theirs";
+/// added by the user to a marker to pick the final value
+pub const RESOLVED_KEY: &str = "resolved";
+
+/**
+ *  applies ours and theirs to base and merges the results.
+ *  every place both sides changed differently is replaced by
+ *  `{"__conflict": {"base": .., "ours": .., "theirs": ..}}`, sides where the value doesn't exist are left out.
+ *  the sites are taken from the ChangeTrees of both sides, maps are merged by key and arrays by
+ *  the original index of their elements, elements inserted by either side never conflict.
+ *  keys of user maps made of two or more underscores followed by "conflict" get another
+ *  underscore, so they can't be mistaken for markers. resolve removes it again.
+ *  */
+pub fn materialize(
+    base: &Value,
+    ours: &[ChangeContent],
+    theirs: &[ChangeContent],
+) -> Result<Value, ValueStoreError> {
+    let ours_tree = ChangeTree::construct(ours.iter().cloned())?;
+    let theirs_tree = ChangeTree::construct(theirs.iter().cloned())?;
+    let mut ours_value = base.clone();
+    ours_value.apply_iter(ours)?;
+    let mut theirs_value = base.clone();
+    theirs_value.apply_iter(theirs)?;
+    Ok(merge(
+        Some(base),
+        (Some(&ours_value), ours_tree.as_ref()),
+        (Some(&theirs_value), theirs_tree.as_ref()),
+    )
+    .expect("root exists on all sides"))
+}
+
+/// value of one side at a site and the changes it made there
+type Side<'a> = (Option<&'a Value>, Option<&'a ChangeTree>);
+
+fn merge(base: Option<&Value>, ours: Side, theirs: Side) -> Option<Value> {
+    // removed array elements have no tree
+    let changed = |(value, tree): Side| tree.is_some() || (value.is_none() && base.is_some());
+    if !changed(theirs) || ours.0 == theirs.0 {
+        return ours.0.map(escape);
+    }
+    if !changed(ours) {
+        return theirs.0.map(escape);
+    }
+    match (base, ours, theirs) {
+        (
+            Some(Value::Map(base)),
+            (Some(Value::Map(ours)), Some(ChangeTree::Map(ours_tree))),
+            (Some(Value::Map(theirs)), Some(ChangeTree::Map(theirs_tree))),
+        ) => {
+            let keys: BTreeSet<_> = base
+                .keys()
+                .chain(ours.keys())
+                .chain(theirs.keys())
+                .collect();
+            let mut res = HashMap::with_capacity(keys.len());
+            for key in keys {
+                let value = merge(
+                    base.get(key),
+                    (ours.get(key), ours_tree.get(key)),
+                    (theirs.get(key), theirs_tree.get(key)),
+                );
+                if let Some(value) = value {
+                    res.insert(escape_key(key), value);
+                }
+            }
+            Some(Value::Map(Arc::new(res)))
+        }
+        (
+            Some(Value::Array(base)),
+            (
+                Some(Value::Array(ours)),
+                Some(ChangeTree::Array {
+                    data: ours_data,
+                    offsets: ours_offsets,
+                    ..
+                }),
+            ),
+            (
+                Some(Value::Array(theirs)),
+                Some(ChangeTree::Array {
+                    data: theirs_data,
+                    offsets: theirs_offsets,
+                    ..
+                }),
+            ),
+        ) => {
+            let mut ours = ArraySide::new(ours, ours_data, ours_offsets);
+            let mut theirs = ArraySide::new(theirs, theirs_data, theirs_offsets);
+            let mut res = Vec::new();
+            for (index, base) in base.iter().enumerate() {
+                let index = index as u32;
+                res.extend(ours.inserted(index).chain(theirs.inserted(index)));
+                if let Some(value) = merge(Some(base), ours.original(index), theirs.original(index))
+                {
+                    res.push(value);
+                }
+            }
+            res.extend(ours.appended().chain(theirs.appended()));
+            Some(Value::Array(Arc::new(res)))
+        }
+        _ => {
+            let mut marker = HashMap::new();
+            for (key, value) in [(BASE_KEY, base), (OURS_KEY, ours.0), (THEIRS_KEY, theirs.0)] {
+                if let Some(value) = value {
+                    marker.insert(key.to_string(), escape(value));
+                }
+            }
+            Some(Value::Map(Arc::new(HashMap::from([(
+                CONFLICT_KEY.to_string(),
+                Value::Map(Arc::new(marker)),
+            )]))))
+        }
+    }
+}
+
+/// elements of one side of an array, consumed in order of the original elements
+struct ArraySide<'a> {
+    values: &'a [Value],
+    data: &'a BTreeMap<u32, ChangeTree>,
+    offsets: &'a BTreeMap<u32, u32>,
+    /// position of the first element not consumed yet
+    next: usize,
+}
+
+impl<'a> ArraySide<'a> {
+    fn new(
+        values: &'a [Value],
+        data: &'a BTreeMap<u32, ChangeTree>,
+        offsets: &'a BTreeMap<u32, u32>,
+    ) -> Self {
+        Self {
+            values,
+            data,
+            offsets,
+            next: 0,
+        }
+    }
+
+    fn consume(&mut self, end: usize) -> impl Iterator<Item = Value> + 'a {
+        let start = self.next.min(end);
+        self.next = end;
+        self.values[start..end.min(self.values.len())]
+            .iter()
+            .map(escape)
+    }
+
+    /// elements inserted in front of the original element index
+    fn inserted(&mut self, index: u32) -> impl Iterator<Item = Value> + 'a {
+        self.consume(translate_index(self.offsets, index) as usize)
+    }
+
+    /**
+     *  the original element index and its changes, none if it was removed.
+     *  a removed element shares its position with the element behind it.
+     *  */
+    fn original(&mut self, index: u32) -> Side<'a> {
+        let position = translate_index(self.offsets, index);
+        if position == translate_index(self.offsets, index + 1) {
+            return (None, None);
+        }
+        self.next = position as usize + 1;
+        (self.values.get(position as usize), self.data.get(&position))
+    }
+
+    /// elements behind the last original element
+    fn appended(&mut self) -> impl Iterator<Item = Value> + 'a {
+        self.consume(self.values.len())
+    }
+}
+
+/// keys that would be read as markers or as escaped keys
+fn is_reserved(key: &str) -> bool {
+    key.strip_prefix("__")
+        .is_some_and(|rest| rest.trim_start_matches('_') == "conflict")
+}
+
+fn escape_key(key: &String) -> String {
+    if is_reserved(key) {
+        format!("_{key}")
+    } else {
+        key.clone()
+    }
+}
+
+fn unescape_key(key: &str) -> &str {
+    if is_reserved(key) && key != CONFLICT_KEY {
+        &key[1..]
+    } else {
+        key
+    }
+}
+
+/// copy of value with its reserved map keys escaped
+fn escape(value: &Value) -> Value {
+    match value {
+        Value::Map(map) => Value::Map(Arc::new(
+            map.iter()
+                .map(|(key, value)| (escape_key(key), escape(value)))
+                .collect(),
+        )),
+        Value::Array(array) => Value::Array(Arc::new(array.iter().map(escape).collect())),
+        value => value.clone(),
+    }
+}
+
+fn marker(value: &Value) -> Option<&HashMap<String, Value>> {
+    match value {
+        Value::Map(map) if map.len() == 1 => match map.get(CONFLICT_KEY) {
+            Some(Value::Map(marker)) => Some(marker),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/**
+ *  replaces every marker with its "resolved" entry and unescapes the map keys escaped by
+ *  materialize, including those of resolved entries.
+ *  fails with the paths of all markers that are not resolved yet.
+ *  */
+pub fn resolve(value: &Value) -> Result<Value, Vec<Path>> {
+    let mut unresolved = Vec::new();
+    let res = resolve_inner(value, &mut Vec::new(), &mut unresolved);
+    if unresolved.is_empty() {
+        Ok(res)
+    } else {
+        Err(unresolved)
+    }
+}
+
+fn resolve_inner(value: &Value, path: &mut Vec<PathElement>, unresolved: &mut Vec<Path>) -> Value {
+    if let Some(marker) = marker(value) {
+        return match marker.get(RESOLVED_KEY) {
+            Some(resolved) => resolve_inner(resolved, path, unresolved),
+            None => {
+                unresolved.push(path.as_slice().into());
+                value.clone()
+            }
+        };
+    }
+    match value {
+        Value::Map(map) => Value::Map(Arc::new(
+            map.iter()
+                .map(|(key, value)| {
+                    let key = unescape_key(key).to_string();
+                    path.push(PathElement::Field(key.clone()));
+                    let value = resolve_inner(value, path, unresolved);
+                    path.pop();
+                    (key, value)
+                })
+                .collect(),
+        )),
+        Value::Array(array) => Value::Array(Arc::new(
+            array
+                .iter()
+                .enumerate()
+                .map(|(index, value)| {
+                    path.push(PathElement::Index(index as u32));
+                    let value = resolve_inner(value, path, unresolved);
+                    path.pop();
+                    value
+                })
+                .collect(),
+        )),
+        value => value.clone(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::test_util::map;
+
+    fn replace(field: &str, old: i64, new: i64) -> ChangeContent {
+        ChangeContent::Replace {
+            path: vec![PathElement::Field(field.to_string())].into(),
+            old: Value::Integer(old),
+            new: Value::Integer(new),
+        }
+    }
+
+    #[test]
+    fn markers() {
+        let base = map([("a", Value::Integer(1)), ("b", Value::Integer(2))]);
+        let merged = materialize(
+            &base,
+            &[replace("a", 1, 3), replace("b", 2, 4)],
+            &[replace("a", 1, 5)],
+        )
+        .unwrap();
+        let conflict = map([(
+            CONFLICT_KEY,
+            map([
+                (BASE_KEY, Value::Integer(1)),
+                (OURS_KEY, Value::Integer(3)),
+                (THEIRS_KEY, Value::Integer(5)),
+            ]),
+        )]);
+        assert_eq!(merged, map([("a", conflict), ("b", Value::Integer(4))]));
+        assert_eq!(
+            resolve(&merged),
+            Err(vec![Path::from([PathElement::Field("a".to_string())])])
+        );
+
+        let resolved = map([
+            (
+                "a",
+                map([(
+                    CONFLICT_KEY,
+                    map([
+                        (OURS_KEY, Value::Integer(3)),
+                        (RESOLVED_KEY, Value::Integer(8)),
+                    ]),
+                )]),
+            ),
+            ("b", Value::Integer(4)),
+        ]);
+        assert_eq!(
+            resolve(&resolved),
+            Ok(map([("a", Value::Integer(8)), ("b", Value::Integer(4))]))
+        );
+    }
+
+    fn item(index: u32) -> Path {
+        vec![PathElement::Index(index)].into()
+    }
+
+    fn array<const N: usize>(values: [Value; N]) -> Value {
+        Value::Array(Arc::new(values.into()))
+    }
+
+    #[test]
+    fn arrays() {
+        let int = Value::Integer;
+        let base = array([int(1), int(2), int(3), int(4)]);
+        let ours = [
+            ChangeContent::Insert {
+                path: item(0),
+                value: int(0),
+            },
+            ChangeContent::Replace {
+                path: item(3),
+                old: int(3),
+                new: int(30),
+            },
+        ];
+        let theirs = [
+            ChangeContent::Delete {
+                path: item(1),
+                old: int(2),
+            },
+            ChangeContent::Replace {
+                path: item(2),
+                old: int(4),
+                new: int(40),
+            },
+            ChangeContent::Insert {
+                path: item(3),
+                value: int(50),
+            },
+        ];
+        assert_eq!(
+            materialize(&base, &ours, &theirs).unwrap(),
+            array([int(0), int(1), int(30), int(40), int(50)])
+        );
+
+        // changing an element the other side removed conflicts at the element only
+        let ours = [ChangeContent::Replace {
+            path: item(1),
+            old: int(2),
+            new: int(20),
+        }];
+        let theirs = [ChangeContent::Delete {
+            path: item(1),
+            old: int(2),
+        }];
+        let conflict = map([(CONFLICT_KEY, map([(BASE_KEY, int(2)), (OURS_KEY, int(20))]))]);
+        assert_eq!(
+            materialize(&base, &ours, &theirs).unwrap(),
+            array([int(1), conflict, int(3), int(4)])
+        );
+    }
+
+    #[test]
+    fn escaping() {
+        let user = map([(CONFLICT_KEY, map([(OURS_KEY, Value::Integer(1))]))]);
+        let base = map([("user", user.clone()), ("a", Value::Integer(1))]);
+        let merged = materialize(&base, &[replace("a", 1, 2)], &[replace("a", 1, 3)]).unwrap();
+        let escaped = map([("___conflict", map([(OURS_KEY, Value::Integer(1))]))]);
+        let Value::Map(fields) = &merged else {
+            panic!("not a map");
+        };
+        assert_eq!(fields.get("user"), Some(&escaped));
+        assert_eq!(
+            resolve(&merged),
+            Err(vec![Path::from([PathElement::Field("a".to_string())])])
+        );
+
+        let resolved = map([
+            ("user", escaped),
+            (
+                "a",
+                map([(CONFLICT_KEY, map([(RESOLVED_KEY, Value::Integer(4))]))]),
+            ),
+        ]);
+        assert_eq!(
+            resolve(&resolved),
+            Ok(map([("user", user), ("a", Value::Integer(4))]))
+        );
+    }
+}
@@ -0,0 +1,248 @@
+use std::{collections::HashMap, fmt::Debug, marker::PhantomData};
+
+use crate::types::{change::ChangeContent, value::Blob, Path, PathElement, Value};
+
+pub use value_store_derive::Document;
+
+/**
+ *  struct stored as a map inside a value.
+ *  usually derived, Fields has one method per struct field returning a Field at its path.
+ *  */
+pub trait Document {
+    type Fields;
+
+    /// accessors for a document located at path
+    fn fields(path: Path) -> Self::Fields;
+
+    /// accessors for a document at the root of a value
+    fn root() -> Self::Fields {
+        Self::fields(Path::new())
+    }
+}
+
+/// conversion of a stored value back to its rust type
+pub trait FromValue: Sized {
+    fn from_value(value: &Value) -> Option<Self>;
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Option<Self> {
+        Some(value.clone())
+    }
+}
+impl FromValue for i64 {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Integer(v) => Some(*v),
+            _ => None,
+        }
+    }
+}
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Float(v) => Some(*v),
+            _ => None,
+        }
+    }
+}
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Bool(v) => Some(*v),
+            _ => None,
+        }
+    }
+}
+impl FromValue for String {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::String(v) => Some(v.to_string()),
+            _ => None,
+        }
+    }
+}
+impl FromValue for Blob {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Blob(v) => Some(Blob::clone(v)),
+            _ => None,
+        }
+    }
+}
+impl<V: FromValue> FromValue for Vec<V> {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Array(v) => v.iter().map(V::from_value).collect(),
+            _ => None,
+        }
+    }
+}
+impl<V: FromValue> FromValue for HashMap<String, V> {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Map(v) => v
+                .iter()
+                .map(|(k, v)| V::from_value(v).map(|v| (k.clone(), v)))
+                .collect(),
+            _ => None,
+        }
+    }
+}
+
+/// path to a value of type T
+pub struct Field<T> {
+    path: Path,
+    ty: PhantomData<fn() -> T>,
+}
+
+impl<T> Field<T> {
+    pub fn new(path: Path) -> Self {
+        Self {
+            path,
+            ty: PhantomData,
+        }
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    pub fn get_value<'v>(&self, value: &'v Value) -> Option<&'v Value> {
+        value.get(&self.path)
+    }
+}
+
+impl<T: FromValue> Field<T> {
+    /// None if the field is missing or has a different type
+    pub fn get(&self, value: &Value) -> Option<T> {
+        self.get_value(value).and_then(T::from_value)
+    }
+}
+
+impl<T: Into<Value>> Field<T> {
+    pub fn insert(&self, value: T) -> ChangeContent {
+        ChangeContent::Insert {
+            path: self.path.clone(),
+            value: value.into(),
+        }
+    }
+    pub fn replace(&self, old: T, new: T) -> ChangeContent {
+        ChangeContent::Replace {
+            path: self.path.clone(),
+            old: old.into(),
+            new: new.into(),
+        }
+    }
+    pub fn delete(&self, old: T) -> ChangeContent {
+        ChangeContent::Delete {
+            path: self.path.clone(),
+            old: old.into(),
+        }
+    }
+}
+
+impl<T: FromValue + Into<Value>> Field<T> {
+    /// replaces the current value in value, None if the field is missing or has a different type
+    pub fn set(&self, value: &Value, new: T) -> Option<ChangeContent> {
+        let old = self.get_value(value)?.clone();
+        T::from_value(&old)?;
+        Some(ChangeContent::Replace {
+            path: self.path.clone(),
+            old,
+            new: new.into(),
+        })
+    }
+}
+
+impl<T: Document> Field<T> {
+    pub fn fields(&self) -> T::Fields {
+        T::fields(self.path.clone())
+    }
+}
+
+impl<T> Field<Vec<T>> {
+    pub fn index(&self, index: u32) -> Field<T> {
+        Field::new(self.path.join(PathElement::Index(index)))
+    }
+    pub fn append(&self) -> Field<T> {
+        Field::new(self.path.join(PathElement::Append))
+    }
+}
+
+impl<T> Field<HashMap<String, T>> {
+    pub fn entry(&self, key: &str) -> Field<T> {
+        Field::new(self.path.join(PathElement::Field(key.to_string())))
+    }
+}
+
+impl<T> Clone for Field<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.path.clone())
+    }
+}
+
+impl<T> Debug for Field<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Field").field(&self.path).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Document)]
+    #[document(crate = "crate")]
+    struct Address {
+        city: String,
+    }
+
+    #[derive(Document)]
+    #[document(crate = "crate")]
+    struct User {
+        name: String,
+        #[document(rename = "years")]
+        age: i64,
+        address: Address,
+        tags: Vec<String>,
+    }
+
+    #[test]
+    fn typed_paths() {
+        let user = User::root();
+        let mut value: Value = HashMap::from([
+            ("name".to_string(), Value::from("ann")),
+            ("years".to_string(), Value::from(31)),
+            (
+                "address".to_string(),
+                HashMap::from([("city".to_string(), "berlin")]).into(),
+            ),
+            ("tags".to_string(), Value::from(Vec::<Value>::new())),
+        ])
+        .into();
+        assert_eq!(user.name().get(&value), Some("ann".to_string()));
+        assert_eq!(
+            user.age().path().as_slice(),
+            &[PathElement::Field("years".to_string())]
+        );
+        assert_eq!(user.name().get_value(&value), Some(&Value::from("ann")));
+        assert_eq!(
+            Field::<i64>::new(user.name().path().clone()).get(&value),
+            None
+        );
+        let changes = [
+            user.age().set(&value, 32).unwrap(),
+            user.address()
+                .fields()
+                .city()
+                .replace("berlin".to_string(), "paris".to_string()),
+            user.tags().append().insert("admin".to_string()),
+        ];
+        value.apply_iter(&changes).unwrap();
+        assert_eq!(user.age().get(&value), Some(32));
+        assert_eq!(
+            user.address().fields().city().get(&value),
+            Some("paris".to_string())
+        );
+        assert_eq!(user.tags().get(&value), Some(vec!["admin".to_string()]));
+    }
+}
@@ -1,14 +1,34 @@
-use std::fmt::Display;
+use std::{fmt::Display, time::Duration};
 
-use crate::{types::{change::{format_hash_lower, ChangeContent, Hash}, PathElement}, conflict::ChangeTree};
+use uuid::Uuid;
+
+use crate::{types::{change::{ChangeContent, Hash}, path::MAX_DEPTH, Path}, conflict::ChangeTree};
 
 #[derive(Debug)]
 pub enum Error {
     #[cfg(feature = "db_sqlx")]
     Sqlx(sqlx::Error),
+    #[cfg(feature = "db_sqlx")]
     Migrate(sqlx::migrate::MigrateError),
+    #[cfg(feature = "db_redb")]
+    Redb(Box<redb::Error>),
     CborDe(ciborium::de::Error<std::io::Error>),
     CborSer(ciborium::ser::Error<std::io::Error>),
+    Uuid(uuid::Error),
+    #[cfg(feature = "json")]
+    Json(serde_json::Error),
+    #[cfg(feature = "csv")]
+    Csv(csv::Error),
+    #[cfg(feature = "automerge")]
+    Automerge(automerge::AutomergeError),
+    #[cfg(feature = "git")]
+    Git(Box<dyn std::error::Error + Send + Sync>),
+    #[cfg(feature = "arrow")]
+    Arrow(arrow_schema::ArrowError),
+    #[cfg(feature = "parquet")]
+    Parquet(parquet::errors::ParquetError),
+    #[cfg(feature = "nats")]
+    Nats(Box<dyn std::error::Error + Send + Sync>),
     ValueStore(ValueStoreError),
     NoOP,
 }
@@ -18,7 +38,39 @@ pub enum ValueStoreError {
     HeadParentMismatch { parent: Hash },
     ParentHashSame,
     InvalidChange { change: ChangeContent },
-    InvalidTreeChange {change:ChangeTree,path:Vec<PathElement>}
+    InvalidTreeChange {change:ChangeTree,path:Path},
+    ForeignStorageId,
+    ReadOnly,
+    MimeMismatch { path: Path, declared: String, detected: &'static str },
+    /// parent links form a cycle or point to a missing change
+    CorruptHistory { change: Option<Hash> },
+    UnknownChange { hash: Hash },
+    UnknownRepo { uuid: Uuid },
+    UnknownBranch { uuid: Uuid },
+    EmptyChangeSet,
+    RateLimited { retry_after: Duration },
+    Cancelled,
+    DeadlineExceeded,
+    Closed,
+    /// estimated encoded size of a change set exceeds the configured limit
+    ChangeTooLarge { size: usize, limit: usize },
+    /// hash of a change doesn't match its parents and content
+    HashMismatch { hash: Hash },
+    /// commit log of a repo holds another change at seq
+    LogConflict { seq: u64 },
+    /// path longer than types::path::MAX_DEPTH
+    PathTooDeep { depth: usize },
+    /// failure simulated by storage::fault::FaultStorage
+    InjectedFault { operation: &'static str },
+    PathLocked { path: Path, owner: String },
+    /// locks can only be placed on fields and absolute indices
+    InvalidLockPath { path: Path },
+    /// expiry of a lock is not representable as a SystemTime
+    InvalidLockTtl { ttl: Duration },
+    /// configuration value of the wrong type or out of range
+    InvalidConfig { name: String },
+    /// proposal head lacks the approvals required by review::ApprovalPolicy
+    NotApproved { approvals: usize, required: usize },
 }
 
 impl Display for Error {
@@ -28,9 +80,26 @@ impl Display for Error {
             Error::Sqlx(e) => Display::fmt(e, f),
             #[cfg(feature = "db_sqlx")]
             Error::Migrate(e) => Display::fmt(e, f),
+            #[cfg(feature = "db_redb")]
+            Error::Redb(e) => Display::fmt(e, f),
             Error::NoOP => panic!("no op error actually constructed"),
             Error::CborDe(e) => Display::fmt(e, f),
             Error::CborSer(e) => Display::fmt(e, f),
+            Error::Uuid(e) => Display::fmt(e, f),
+            #[cfg(feature = "json")]
+            Error::Json(e) => Display::fmt(e, f),
+            #[cfg(feature = "csv")]
+            Error::Csv(e) => Display::fmt(e, f),
+            #[cfg(feature = "automerge")]
+            Error::Automerge(e) => Display::fmt(e, f),
+            #[cfg(feature = "git")]
+            Error::Git(e) => Display::fmt(e, f),
+            #[cfg(feature = "arrow")]
+            Error::Arrow(e) => Display::fmt(e, f),
+            #[cfg(feature = "parquet")]
+            Error::Parquet(e) => Display::fmt(e, f),
+            #[cfg(feature = "nats")]
+            Error::Nats(e) => Display::fmt(e, f),
             Error::ValueStore(e) => Display::fmt(e, f),
         }
     }
@@ -38,12 +107,41 @@ impl Display for Error {
 
 impl std::error::Error for Error {}
 
+impl Error {
+    /**
+     *  errors that may go away when the operation is retried unchanged,
+     *  e.g. a locked sqlite database or a dropped connection.
+     *  */
+    pub fn is_transient(&self) -> bool {
+        match self {
+            #[cfg(feature = "db_sqlx")]
+            Error::Sqlx(e) => match e {
+                sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut => true,
+                sqlx::Error::Database(e) => {
+                    #[cfg(feature = "db_sqlite")]
+                    if let Some(e) = e.try_downcast_ref::<sqlx::sqlite::SqliteError>() {
+                        // busy and locked, including their extended codes
+                        return sqlx::error::DatabaseError::code(e)
+                            .and_then(|code| code.parse::<i32>().ok())
+                            .is_some_and(|code| matches!(code & 0xff, 5 | 6));
+                    }
+                    // postgres serialization failure and deadlock
+                    e.code()
+                        .is_some_and(|code| matches!(code.as_ref(), "40001" | "40P01"))
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+}
+
 impl Display for ValueStoreError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ValueStoreError::HeadParentMismatch { parent } => {
                 f.write_str("Head not one of the parents of the change. Head hash: ")?;
-                format_hash_lower(parent, f)
+                write!(f, "{parent:#x}")
             }
             ValueStoreError::ParentHashSame => {
                 f.write_str("Tried to construct Parents with two times the same parent")
@@ -54,6 +152,59 @@ impl Display for ValueStoreError {
             ValueStoreError::InvalidTreeChange { change, path } => {
                 write!(f,"invalid change at {:?}: {change:x?}",path.as_slice())
             }
+            ValueStoreError::ForeignStorageId => {
+                f.write_str("id does not belong to this storage backend")
+            }
+            ValueStoreError::ReadOnly => f.write_str("store was opened read only"),
+            ValueStoreError::MimeMismatch { path, declared, detected } => {
+                write!(f, "blob at {:?} declared as {declared} but looks like {detected}", path.as_slice())
+            }
+            ValueStoreError::CorruptHistory { change: Some(change) } => {
+                write!(f, "corrupt change history at {change:#x}")
+            }
+            ValueStoreError::CorruptHistory { change: None } => {
+                f.write_str("corrupt change history")
+            }
+            ValueStoreError::UnknownChange { hash } => write!(f, "unknown change {hash:#x}"),
+            ValueStoreError::UnknownRepo { uuid } => write!(f, "unknown repository {uuid}"),
+            ValueStoreError::UnknownBranch { uuid } => write!(f, "unknown branch {uuid}"),
+            ValueStoreError::EmptyChangeSet => f.write_str("change set contains no changes"),
+            ValueStoreError::RateLimited { retry_after } => {
+                write!(f, "commit rate limit exceeded, retry after {retry_after:?}")
+            }
+            ValueStoreError::Cancelled => f.write_str("operation cancelled"),
+            ValueStoreError::DeadlineExceeded => f.write_str("operation deadline exceeded"),
+            ValueStoreError::Closed => f.write_str("store was closed"),
+            ValueStoreError::HashMismatch { hash } => {
+                write!(f, "{hash:#x} doesn't match the parents and content")
+            }
+            ValueStoreError::LogConflict { seq } => {
+                write!(f, "commit {seq} of the log holds another change")
+            }
+            ValueStoreError::PathTooDeep { depth } => {
+                write!(f, "path of depth {depth} exceeds the limit of {MAX_DEPTH}")
+            }
+            ValueStoreError::ChangeTooLarge { size, limit } => {
+                write!(f, "change of about {size} bytes exceeds the limit of {limit}")
+            }
+            ValueStoreError::InjectedFault { operation } => {
+                write!(f, "injected fault in {operation}")
+            }
+            ValueStoreError::PathLocked { path, owner } => {
+                write!(f, "{:?} is locked by {owner}", path.as_slice())
+            }
+            ValueStoreError::InvalidLockPath { path } => {
+                write!(f, "can't lock relative path {:?}", path.as_slice())
+            }
+            ValueStoreError::InvalidLockTtl { ttl } => {
+                write!(f, "lock ttl of {ttl:?} is out of range")
+            }
+            ValueStoreError::InvalidConfig { name } => {
+                write!(f, "invalid value of configuration {name}")
+            }
+            ValueStoreError::NotApproved { approvals, required } => {
+                write!(f, "proposal has {approvals} of {required} required approvals")
+            }
         }
     }
 }
@@ -84,6 +235,89 @@ impl From<ciborium::ser::Error<std::io::Error>> for Error {
         Self::CborSer(value)
     }
 }
+#[cfg(feature = "json")]
+impl From<serde_json::Error> for Error {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Json(value)
+    }
+}
+#[cfg(feature = "csv")]
+impl From<csv::Error> for Error {
+    fn from(value: csv::Error) -> Self {
+        Self::Csv(value)
+    }
+}
+#[cfg(feature = "automerge")]
+impl From<automerge::AutomergeError> for Error {
+    fn from(value: automerge::AutomergeError) -> Self {
+        Self::Automerge(value)
+    }
+}
+#[cfg(feature = "db_redb")]
+impl From<redb::DatabaseError> for Error {
+    fn from(value: redb::DatabaseError) -> Self {
+        Self::Redb(Box::new(value.into()))
+    }
+}
+#[cfg(feature = "db_redb")]
+impl From<redb::TransactionError> for Error {
+    fn from(value: redb::TransactionError) -> Self {
+        Self::Redb(Box::new(value.into()))
+    }
+}
+#[cfg(feature = "db_redb")]
+impl From<redb::TableError> for Error {
+    fn from(value: redb::TableError) -> Self {
+        Self::Redb(Box::new(value.into()))
+    }
+}
+#[cfg(feature = "db_redb")]
+impl From<redb::StorageError> for Error {
+    fn from(value: redb::StorageError) -> Self {
+        Self::Redb(Box::new(value.into()))
+    }
+}
+#[cfg(feature = "db_redb")]
+impl From<redb::CommitError> for Error {
+    fn from(value: redb::CommitError) -> Self {
+        Self::Redb(Box::new(value.into()))
+    }
+}
+#[cfg(feature = "git")]
+impl From<gix::init::Error> for Error {
+    fn from(value: gix::init::Error) -> Self {
+        Self::Git(value.into())
+    }
+}
+#[cfg(feature = "git")]
+impl From<gix::object::write::Error> for Error {
+    fn from(value: gix::object::write::Error) -> Self {
+        Self::Git(value.into())
+    }
+}
+#[cfg(feature = "git")]
+impl From<gix::reference::edit::Error> for Error {
+    fn from(value: gix::reference::edit::Error) -> Self {
+        Self::Git(value.into())
+    }
+}
+#[cfg(feature = "arrow")]
+impl From<arrow_schema::ArrowError> for Error {
+    fn from(value: arrow_schema::ArrowError) -> Self {
+        Self::Arrow(value)
+    }
+}
+#[cfg(feature = "parquet")]
+impl From<parquet::errors::ParquetError> for Error {
+    fn from(value: parquet::errors::ParquetError) -> Self {
+        Self::Parquet(value)
+    }
+}
+impl From<uuid::Error> for Error {
+    fn from(value: uuid::Error) -> Self {
+        Self::Uuid(value)
+    }
+}
 
 impl From<ValueStoreError> for Error {
     fn from(value: ValueStoreError) -> Self {
@@ -0,0 +1,241 @@
+use std::sync::Arc;
+
+use arrow_array::{
+    builder::{FixedSizeBinaryBuilder, ListBuilder, StringBuilder, UInt64Builder},
+    ArrayRef, RecordBatch,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+
+use crate::{
+    fmt::PathDisplay,
+    types::change::{Change, ChangeContent},
+    Result,
+};
+
+pub const DEFAULT_BATCH_SIZE: usize = 8192;
+
+/// one row per operation: hash, parents, path rendered by PathDisplay, op, payload_size
+pub fn change_log_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("hash", DataType::FixedSizeBinary(32), false),
+        Field::new(
+            "parents",
+            DataType::List(Arc::new(Field::new(
+                "item",
+                DataType::FixedSizeBinary(32),
+                true,
+            ))),
+            false,
+        ),
+        Field::new("path", DataType::Utf8, false),
+        Field::new("op", DataType::Utf8, false),
+        Field::new("payload_size", DataType::UInt64, false),
+    ]))
+}
+
+fn op_name(change: &ChangeContent) -> &'static str {
+    match change {
+        ChangeContent::Insert { .. } => "insert",
+        ChangeContent::Replace { .. } => "replace",
+        ChangeContent::Delete { .. } => "delete",
+    }
+}
+
+/// cbor encoded size of the values carried by change
+fn payload_size(change: &ChangeContent) -> Result<u64> {
+    let mut buf = Vec::new();
+    match change {
+        ChangeContent::Insert { value, .. } => ciborium::into_writer(value, &mut buf)?,
+        ChangeContent::Replace { old, new, .. } => {
+            ciborium::into_writer(old, &mut buf)?;
+            ciborium::into_writer(new, &mut buf)?;
+        }
+        ChangeContent::Delete { old, .. } => ciborium::into_writer(old, &mut buf)?,
+    }
+    Ok(buf.len() as u64)
+}
+
+struct Builders {
+    hash: FixedSizeBinaryBuilder,
+    parents: ListBuilder<FixedSizeBinaryBuilder>,
+    path: StringBuilder,
+    op: StringBuilder,
+    payload_size: UInt64Builder,
+    rows: usize,
+}
+
+impl Builders {
+    fn new() -> Self {
+        Self {
+            hash: FixedSizeBinaryBuilder::new(32),
+            parents: ListBuilder::new(FixedSizeBinaryBuilder::new(32)),
+            path: StringBuilder::new(),
+            op: StringBuilder::new(),
+            payload_size: UInt64Builder::new(),
+            rows: 0,
+        }
+    }
+
+    fn push(&mut self, change: &Change, content: &ChangeContent) -> Result<()> {
+        self.hash.append_value(change.hash.as_slice())?;
+        for parent in change.parents.iter() {
+            self.parents.values().append_value(parent.as_slice())?;
+        }
+        self.parents.append(true);
+        self.path
+            .append_value(PathDisplay(content.path()).to_string());
+        self.op.append_value(op_name(content));
+        self.payload_size.append_value(payload_size(content)?);
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self, schema: &SchemaRef) -> Result<RecordBatch> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.hash.finish()),
+            Arc::new(self.parents.finish()),
+            Arc::new(self.path.finish()),
+            Arc::new(self.op.finish()),
+            Arc::new(self.payload_size.finish()),
+        ];
+        self.rows = 0;
+        Ok(RecordBatch::try_new(schema.clone(), columns)?)
+    }
+}
+
+/**
+ *  record batches of the change log, see change_log_schema.
+ *  changes are consumed lazily and at most batch_size rows are buffered.
+ *  changes carry no timestamp, join with the change feed for ordering in time.
+ *  */
+pub struct ChangeLogBatches<I> {
+    changes: I,
+    schema: SchemaRef,
+    builders: Builders,
+    batch_size: usize,
+    /// change with operations that didn't fit into the last batch
+    pending: Option<(Change, usize)>,
+}
+
+impl<I: Iterator<Item = Result<Change>>> ChangeLogBatches<I> {
+    pub fn new(changes: I, batch_size: usize) -> Self {
+        Self {
+            changes,
+            schema: change_log_schema(),
+            builders: Builders::new(),
+            batch_size: batch_size.max(1),
+            pending: None,
+        }
+    }
+
+    pub fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn next_batch(&mut self) -> Result<Option<RecordBatch>> {
+        loop {
+            let (change, start) = match self.pending.take() {
+                Some(pending) => pending,
+                None => match self.changes.next() {
+                    Some(change) => (change?, 0),
+                    None if self.builders.rows > 0 => {
+                        return self.builders.finish(&self.schema).map(Some)
+                    }
+                    None => return Ok(None),
+                },
+            };
+            for (index, content) in change.content.iter().enumerate().skip(start) {
+                if self.builders.rows == self.batch_size {
+                    self.pending = Some((change, index));
+                    return self.builders.finish(&self.schema).map(Some);
+                }
+                self.builders.push(&change, content)?;
+            }
+        }
+    }
+}
+
+impl<I: Iterator<Item = Result<Change>>> Iterator for ChangeLogBatches<I> {
+    type Item = Result<RecordBatch>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_batch().transpose()
+    }
+}
+
+/// streams the change log into a parquet file, returns the number of rows written
+#[cfg(feature = "parquet")]
+pub fn write_parquet<I, W>(changes: I, writer: W, batch_size: usize) -> Result<u64>
+where
+    I: Iterator<Item = Result<Change>>,
+    W: std::io::Write + Send,
+{
+    let batches = ChangeLogBatches::new(changes, batch_size);
+    let mut writer = parquet::arrow::ArrowWriter::try_new(writer, batches.schema(), None)?;
+    let mut rows = 0;
+    for batch in batches {
+        let batch = batch?;
+        rows += batch.num_rows() as u64;
+        writer.write(&batch)?;
+    }
+    writer.close()?;
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod test {
+    use arrow_array::{Array, StringArray};
+
+    use super::*;
+    use crate::types::{
+        change::{Hash, Parents},
+        PathElement, Value,
+    };
+
+    fn change(n: u8, ops: usize) -> Change {
+        Change {
+            hash: Hash([n; 32]),
+            parents: Parents::One(Hash([n - 1; 32])),
+            content: (0..ops)
+                .map(|i| ChangeContent::Insert {
+                    path: vec![PathElement::Field(format!("f{i}"))].into(),
+                    value: Value::Integer(i as i64),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn batches() {
+        let changes = vec![Ok(change(1, 2)), Ok(change(2, 3))];
+        let batches: Vec<_> = ChangeLogBatches::new(changes.into_iter(), 2)
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(
+            batches.iter().map(|b| b.num_rows()).collect::<Vec<_>>(),
+            vec![2, 2, 1]
+        );
+        let paths = batches[1]
+            .column(2)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(paths.value(0), "f0");
+        assert_eq!(paths.value(1), "f1");
+        assert_eq!(batches[1].column(1).len(), 2);
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn parquet() {
+        let mut buf = Vec::new();
+        let rows = write_parquet(
+            vec![Ok(change(1, 2)), Ok(change(2, 1))].into_iter(),
+            &mut buf,
+            DEFAULT_BATCH_SIZE,
+        )
+        .unwrap();
+        assert_eq!(rows, 3);
+        assert_eq!(&buf[..4], b"PAR1");
+    }
+}
@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+
+use gix::{
+    actor::Signature,
+    objs::{
+        tree::{Entry, EntryKind},
+        Commit, Tree,
+    },
+    refs::transaction::PreviousValue,
+    ObjectId, Repository,
+};
+
+use super::redact::Redaction;
+use crate::{
+    types::{
+        change::{Change, Hash},
+        Value,
+    },
+    Result,
+};
+
+/// file holding the value if the root is not a map
+pub const ROOT_FILE: &str = "value.json";
+
+/// file name of a top level key, escaping characters git doesn't allow in names
+fn file_name(key: &str) -> String {
+    let mut res = String::with_capacity(key.len() + 5);
+    for c in key.chars() {
+        match c {
+            '%' => res.push_str("%25"),
+            '/' => res.push_str("%2F"),
+            '\0' => res.push_str("%00"),
+            c => res.push(c),
+        }
+    }
+    res.push_str(".json");
+    res
+}
+
+/**
+ *  writes the history of a branch into a git repository for auditing with git tooling.
+ *  every change becomes a commit with the change hash as message, the tree of a commit
+ *  holds one pretty printed json file per top level key of the value after the change.
+ *  */
+pub struct GitExport {
+    repo: Repository,
+    initial: Value,
+    commits: HashMap<Hash, (ObjectId, Value)>,
+    signature: Signature,
+    redaction: Redaction,
+}
+
+impl GitExport {
+    /// creates a bare repository at path
+    pub fn init(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        Ok(Self {
+            repo: gix::init_bare(path)?,
+            initial: Value::default(),
+            commits: HashMap::new(),
+            signature: Signature {
+                name: "value-store".into(),
+                email: "value-store@localhost".into(),
+                time: Default::default(),
+            },
+            redaction: Redaction::default(),
+        })
+    }
+
+    /// value changes are applied to if none of their parents was exported
+    pub fn with_initial(mut self, initial: Value) -> Self {
+        self.initial = initial;
+        self
+    }
+
+    /// author and committer of all commits, changes carry no timestamp so it is fixed as well
+    pub fn with_signature(mut self, signature: Signature) -> Self {
+        self.signature = signature;
+        self
+    }
+
+    /// redacts the value of every commit, history is applied to the unredacted values
+    pub fn with_redaction(mut self, redaction: Redaction) -> Self {
+        self.redaction = redaction;
+        self
+    }
+
+    fn write_tree(&self, value: &Value) -> Result<ObjectId> {
+        let mut entries = Vec::new();
+        let mut add = |name: String, value: &Value| -> Result<()> {
+            let json = serde_json::to_vec_pretty(&value.canonicalize())?;
+            entries.push(Entry {
+                mode: EntryKind::Blob.into(),
+                filename: name.into(),
+                oid: self.repo.write_blob(json)?.detach(),
+            });
+            Ok(())
+        };
+        match value {
+            Value::Map(map) => {
+                for (key, value) in map.iter() {
+                    add(file_name(key), value)?;
+                }
+            }
+            value => add(ROOT_FILE.to_string(), value)?,
+        }
+        // only blobs, so plain byte order is git's order
+        entries.sort_by(|e1, e2| e1.filename.cmp(&e2.filename));
+        Ok(self.repo.write_object(Tree { entries })?.detach())
+    }
+
+    /**
+     *  exports change, parents have to be added first to be linked.
+     *  the content is applied to the value of the first exported parent.
+     *  */
+    pub fn add(&mut self, change: &Change) -> Result<ObjectId> {
+        if let Some((id, _)) = self.commits.get(&change.hash) {
+            return Ok(*id);
+        }
+        let parents: Vec<_> = change
+            .parents
+            .iter()
+            .filter_map(|parent| self.commits.get(parent))
+            .collect();
+        let mut value = parents
+            .first()
+            .map_or(&self.initial, |(_, value)| value)
+            .clone();
+        value.apply_iter(&change.content)?;
+        let commit = Commit {
+            tree: self.write_tree(&self.redaction.apply(&value))?,
+            parents: parents.iter().map(|(id, _)| *id).collect(),
+            author: self.signature.clone(),
+            committer: self.signature.clone(),
+            encoding: None,
+            message: change.hash.to_string().into(),
+            extra_headers: Vec::new(),
+        };
+        let id = self.repo.write_object(commit)?.detach();
+        self.commits.insert(change.hash, (id, value));
+        Ok(id)
+    }
+
+    /// points refs/heads/name at the commit of an exported change
+    pub fn set_branch(&self, name: &str, head: Hash) -> Result<Option<ObjectId>> {
+        let Some((id, _)) = self.commits.get(&head) else {
+            return Ok(None);
+        };
+        self.repo.reference(
+            format!("refs/heads/{name}").as_str(),
+            *id,
+            PreviousValue::Any,
+            "value-store export",
+        )?;
+        Ok(Some(*id))
+    }
+
+    pub fn repository(&self) -> &Repository {
+        &self.repo
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::types::{
+        change::{ChangeContent, Parents},
+        PathElement,
+    };
+
+    #[test]
+    fn file_names() {
+        assert_eq!(file_name("users"), "users.json");
+        assert_eq!(file_name("a/b%"), "a%2Fb%25.json");
+    }
+
+    #[test]
+    fn export() {
+        let dir = std::env::temp_dir().join(format!("value-store-git-{}", uuid::Uuid::now_v7()));
+        let mut export = GitExport::init(&dir).unwrap();
+        let root = Change {
+            hash: Hash([1; 32]),
+            parents: Parents::One(Hash([0; 32])),
+            content: vec![ChangeContent::Insert {
+                path: vec![PathElement::Field("users".to_string())].into(),
+                value: Value::from(HashMap::from([("ann".to_string(), 31)])),
+            }],
+        };
+        let next = Change {
+            hash: Hash([2; 32]),
+            parents: Parents::One(root.hash),
+            content: vec![ChangeContent::Insert {
+                path: vec![PathElement::Field("flag".to_string())].into(),
+                value: Value::Bool(true),
+            }],
+        };
+        let first = export.add(&root).unwrap();
+        let second = export.add(&next).unwrap();
+        assert_eq!(export.set_branch("main", next.hash).unwrap(), Some(second));
+
+        let repo = export.repository();
+        let commit = repo.find_commit(second).unwrap();
+        assert_eq!(commit.parent_ids().next().unwrap().detach(), first);
+        let tree = commit.tree().unwrap();
+        let names: Vec<_> = tree
+            .iter()
+            .map(|e| e.unwrap().filename().to_string())
+            .collect();
+        assert_eq!(names, vec!["flag.json", "users.json"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,5 @@
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(feature = "git")]
+pub mod git;
+pub mod redact;
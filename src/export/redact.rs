@@ -0,0 +1,153 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::types::{path_pattern::PathPattern, PathElement, Value};
+
+/// replacement of masked values
+pub const MASK: &str = "***";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedactAction {
+    /// removes the value, array elements after it move up
+    Drop,
+    /**
+     *  replaces the value with the hex encoded structural hash, so equal values stay
+     *  recognizable. the hash is unsalted, guessable values like emails should be masked.
+     *  */
+    Hash,
+    /// replaces the value with MASK
+    Mask,
+}
+
+/**
+ *  rules applied to values before they leave the store.
+ *  the first rule whose pattern matches a path decides what happens to the value there.
+ *  */
+#[derive(Debug, Clone, Default)]
+pub struct Redaction {
+    rules: Vec<(PathPattern, RedactAction)>,
+}
+
+impl Redaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_rule(mut self, pattern: PathPattern, action: RedactAction) -> Self {
+        self.rules.push((pattern, action));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// redacted copy of value, a dropped root becomes the default value
+    pub fn apply(&self, value: &Value) -> Value {
+        self.redact(value, &mut Vec::new()).unwrap_or_default()
+    }
+
+    fn redact(&self, value: &Value, path: &mut Vec<PathElement>) -> Option<Value> {
+        if let Some((_, action)) = self.rules.iter().find(|(pattern, _)| pattern.matches(path)) {
+            return match action {
+                RedactAction::Drop => None,
+                RedactAction::Hash => Some(Value::String(Arc::new(format!(
+                    "{:x}",
+                    value.structural_hash()
+                )))),
+                RedactAction::Mask => Some(Value::String(Arc::new(MASK.to_string()))),
+            };
+        }
+        // untouched subtrees are shared with the original
+        if !self
+            .rules
+            .iter()
+            .any(|(pattern, _)| pattern.len() > path.len() && pattern.matches_prefix(path))
+        {
+            return Some(value.clone());
+        }
+        match value {
+            Value::Map(map) => {
+                let mut res = HashMap::with_capacity(map.len());
+                for (key, child) in map.iter() {
+                    path.push(PathElement::Field(key.clone()));
+                    if let Some(child) = self.redact(child, path) {
+                        res.insert(key.clone(), child);
+                    }
+                    path.pop();
+                }
+                Some(Value::Map(Arc::new(res)))
+            }
+            Value::Array(array) => {
+                let mut res = Vec::with_capacity(array.len());
+                for (index, child) in array.iter().enumerate() {
+                    path.push(PathElement::Index(index as u32));
+                    if let Some(child) = self.redact(child, path) {
+                        res.push(child);
+                    }
+                    path.pop();
+                }
+                Some(Value::Array(Arc::new(res)))
+            }
+            value => Some(value.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn redact() {
+        let user = |name: &str, email: &str| {
+            Value::from(HashMap::from([
+                (
+                    "name".to_string(),
+                    Value::String(Arc::new(name.to_string())),
+                ),
+                (
+                    "email".to_string(),
+                    Value::String(Arc::new(email.to_string())),
+                ),
+                ("phone".to_string(), Value::Integer(1234)),
+            ]))
+        };
+        let value = Value::from(HashMap::from([(
+            "users".to_string(),
+            Value::Array(Arc::new(vec![user("ann", "ann@x"), user("bob", "bob@x")])),
+        )]));
+        let redaction = Redaction::new()
+            .with_rule("users[*].email".parse().unwrap(), RedactAction::Mask)
+            .with_rule("users[*].phone".parse().unwrap(), RedactAction::Drop)
+            .with_rule("users[1].name".parse().unwrap(), RedactAction::Hash);
+        let redacted = redaction.apply(&value);
+
+        let Value::Map(root) = &redacted else {
+            panic!("root not a map")
+        };
+        let Some(Value::Array(users)) = root.get("users") else {
+            panic!("users not an array")
+        };
+        let Value::Map(ann) = &users[0] else {
+            panic!("user not a map")
+        };
+        assert_eq!(ann.len(), 2);
+        assert_eq!(
+            ann.get("email"),
+            Some(&Value::String(Arc::new(MASK.to_string())))
+        );
+        assert_eq!(
+            ann.get("name"),
+            Some(&Value::String(Arc::new("ann".to_string())))
+        );
+        let Value::Map(bob) = &users[1] else {
+            panic!("user not a map")
+        };
+        let hash = Value::String(Arc::new("bob".to_string())).structural_hash();
+        assert_eq!(
+            bob.get("name"),
+            Some(&Value::String(Arc::new(format!("{hash:x}"))))
+        );
+        assert_eq!(redaction.apply(&Value::Integer(1)), Value::Integer(1));
+    }
+}
@@ -0,0 +1,227 @@
+use std::fmt::{self, Display, Write};
+
+use crate::types::{change::ChangeContent, PathElement, Value};
+
+pub const DEFAULT_MAX_STRING: usize = 64;
+
+/**
+ *  renders a path like `users[3].email`, Append as `[+]` and FromEnd as `[-1]`.
+ *  field names that are empty or contain any of `.[]"\` are quoted like `["a.b"]`,
+ *  so different paths never render the same.
+ *  */
+pub struct PathDisplay<'p>(pub &'p [PathElement]);
+
+impl Display for PathDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str(".");
+        }
+        for (pos, elem) in self.0.iter().enumerate() {
+            match elem {
+                PathElement::Field(name) if needs_quotes(name) => write!(f, "[{name:?}]")?,
+                PathElement::Field(name) => {
+                    if pos != 0 {
+                        f.write_char('.')?
+                    }
+                    f.write_str(name)?
+                }
+                PathElement::Index(index) => write!(f, "[{index}]")?,
+                PathElement::Append => f.write_str("[+]")?,
+                PathElement::FromEnd(back) => write!(f, "[-{}]", *back as u64 + 1)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn needs_quotes(name: &str) -> bool {
+    name.is_empty() || name.contains(['.', '[', ']', '"', '\\'])
+}
+
+/**
+ *  indented rendering of a value. maps are sorted by key, strings longer than
+ *  max_string chars are truncated and blobs are summarized by mime type and size.
+ *  */
+#[derive(Clone, Copy)]
+pub struct Pretty<'v> {
+    pub value: &'v Value,
+    pub max_string: usize,
+}
+
+impl<'v> Pretty<'v> {
+    pub fn new(value: &'v Value) -> Self {
+        Pretty {
+            value,
+            max_string: DEFAULT_MAX_STRING,
+        }
+    }
+
+    fn write(&self, value: &Value, indent: usize, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match value {
+            Value::Integer(v) => Display::fmt(v, f),
+            Value::Float(v) => fmt::Debug::fmt(v, f),
+            Value::Bool(v) => Display::fmt(v, f),
+            Value::String(v) => {
+                if let Some((cut, _)) = v.char_indices().nth(self.max_string) {
+                    write!(f, "{:?}… ({} chars)", &v[..cut], v.chars().count())
+                } else {
+                    fmt::Debug::fmt(v.as_str(), f)
+                }
+            }
+            Value::Blob(blob) => write!(f, "<{} blob, {} bytes>", blob.mime, blob.data.len()),
+            Value::Array(arr) if arr.is_empty() => f.write_str("[]"),
+            Value::Array(arr) => {
+                f.write_str("[\n")?;
+                for v in arr.iter() {
+                    write!(f, "{:width$}", "", width = (indent + 1) * 2)?;
+                    self.write(v, indent + 1, f)?;
+                    f.write_str(",\n")?;
+                }
+                write!(f, "{:width$}]", "", width = indent * 2)
+            }
+            Value::Map(map) if map.is_empty() => f.write_str("{}"),
+            Value::Map(_) => {
+                f.write_str("{\n")?;
+                for (k, v) in value.iter_sorted().into_iter().flatten() {
+                    write!(f, "{:width$}{k:?}: ", "", width = (indent + 1) * 2)?;
+                    self.write(v, indent + 1, f)?;
+                    f.write_str(",\n")?;
+                }
+                write!(f, "{:width$}}}", "", width = indent * 2)
+            }
+        }
+    }
+}
+
+impl Display for Pretty<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(self.value, 0, f)
+    }
+}
+
+fn write_prefixed(
+    prefix: char,
+    path: &[PathElement],
+    value: &Value,
+    f: &mut fmt::Formatter<'_>,
+) -> fmt::Result {
+    let rendered = format!("{}: {}", PathDisplay(path), Pretty::new(value));
+    for line in rendered.lines() {
+        writeln!(f, "{prefix} {line}")?;
+    }
+    Ok(())
+}
+
+/// unified diff like rendering, removed values prefixed with `-`, added ones with `+`
+pub struct ChangeSetDisplay<'c>(pub &'c [ChangeContent]);
+
+impl Display for ChangeSetDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in self.0 {
+            match change {
+                ChangeContent::Insert { path, value } => write_prefixed('+', path, value, f)?,
+                ChangeContent::Replace { path, old, new } => {
+                    write_prefixed('-', path, old, f)?;
+                    write_prefixed('+', path, new, f)?;
+                }
+                ChangeContent::Delete { path, old } => write_prefixed('-', path, old, f)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&Pretty::new(self), f)
+    }
+}
+
+impl Display for ChangeContent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&ChangeSetDisplay(std::slice::from_ref(self)), f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::{ChangeSetDisplay, PathDisplay, Pretty};
+    use crate::types::{change::ChangeContent, value::Blob, PathElement, Value};
+
+    #[test]
+    fn pretty_value() {
+        let value = Value::Map(
+            HashMap::from_iter([
+                (
+                    "b".to_string(),
+                    Value::Array(vec![Value::Integer(1), Value::Float(2.0)].into()),
+                ),
+                ("a".to_string(), Value::String("abcdef".to_string().into())),
+                (
+                    "c".to_string(),
+                    Value::Blob(
+                        Blob {
+                            mime: "image/png".to_string(),
+                            data: vec![0; 12],
+                        }
+                        .into(),
+                    ),
+                ),
+                ("d".to_string(), Value::Map(HashMap::new().into())),
+            ])
+            .into(),
+        );
+        assert_eq!(
+            Pretty {
+                value: &value,
+                max_string: 3
+            }
+            .to_string(),
+            "{\n  \"a\": \"abc\"… (6 chars),\n  \"b\": [\n    1,\n    2.0,\n  ],\n  \"c\": <image/png blob, 12 bytes>,\n  \"d\": {},\n}"
+        );
+    }
+
+    #[test]
+    fn change_set() {
+        let changes = [
+            ChangeContent::Replace {
+                path: vec![PathElement::Field("a".to_string()), PathElement::Index(3)].into(),
+                old: Value::Bool(true),
+                new: Value::Array(vec![Value::Integer(1)].into()),
+            },
+            ChangeContent::Insert {
+                path: vec![PathElement::Field("b".to_string()), PathElement::Append].into(),
+                value: Value::Integer(2),
+            },
+            ChangeContent::Delete {
+                path: vec![].into(),
+                old: Value::Integer(3),
+            },
+        ];
+        assert_eq!(
+            ChangeSetDisplay(&changes).to_string(),
+            "- a[3]: true\n+ a[3]: [\n+   1,\n+ ]\n+ b[+]: 2\n- .: 3\n"
+        );
+    }
+
+    #[test]
+    fn quoted_fields() {
+        let path = [
+            PathElement::Field("a.b".to_string()),
+            PathElement::Field("c".to_string()),
+            PathElement::Field(String::new()),
+            PathElement::FromEnd(0),
+        ];
+        assert_eq!(PathDisplay(&path).to_string(), r#"["a.b"].c[""][-1]"#);
+        let nested = [
+            PathElement::Field("a".to_string()),
+            PathElement::Field("b".to_string()),
+        ];
+        assert_ne!(
+            PathDisplay(&path[..1]).to_string(),
+            PathDisplay(&nested).to_string()
+        );
+    }
+}
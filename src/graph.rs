@@ -0,0 +1,233 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    hash::Hash,
+};
+
+use crate::{error::ValueStoreError, storage::Storage, Result};
+
+/**
+ *  in memory view of (a part of) the change DAG, mapping every change to its parents.
+ *  parents that are not part of the graph themselves are treated as already applied.
+ *  */
+#[derive(Debug, Clone)]
+pub struct ChangeGraph<N> {
+    parents: HashMap<N, Vec<N>>,
+}
+
+impl<N> Default for ChangeGraph<N> {
+    fn default() -> Self {
+        Self {
+            parents: HashMap::new(),
+        }
+    }
+}
+
+impl<N: Eq + Hash + Ord + Clone> ChangeGraph<N> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, node: N, parents: Vec<N>) {
+        self.parents.insert(node, parents);
+    }
+
+    pub fn contains(&self, node: &N) -> bool {
+        self.parents.contains_key(node)
+    }
+
+    pub fn len(&self) -> usize {
+        self.parents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parents.is_empty()
+    }
+
+    pub fn parents(&self, node: &N) -> &[N] {
+        self.parents
+            .get(node)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// loads all changes reachable from heads
+    pub async fn load<S: Storage<ChangeId = N>>(storage: &S, heads: &[N]) -> Result<Self> {
+        let mut res = Self::new();
+        let mut todo = heads.to_vec();
+        while let Some(node) = todo.pop() {
+            if res.contains(&node) {
+                continue;
+            }
+            let parents = storage.get_change_rels(node.clone()).await?;
+            todo.extend(parents.iter().filter(|p| !res.contains(p)).cloned());
+            res.insert(node, parents);
+        }
+        Ok(res)
+    }
+
+    /// heads and all their ancestors in the graph
+    pub fn reachable<'l, I: IntoIterator<Item = &'l N>>(&self, heads: I) -> HashSet<N>
+    where
+        N: 'l,
+    {
+        let mut res = HashSet::new();
+        let mut todo: Vec<&N> = heads.into_iter().filter(|n| self.contains(n)).collect();
+        while let Some(node) = todo.pop() {
+            if res.insert(node.clone()) {
+                todo.extend(self.parents(node).iter().filter(|p| self.contains(p)));
+            }
+        }
+        res
+    }
+
+    /// changes reachable from a but not from b
+    pub fn difference(&self, a: &[N], b: &[N]) -> HashSet<N> {
+        let exclude = self.reachable(b);
+        let mut res = HashSet::new();
+        let mut todo: Vec<&N> = a
+            .iter()
+            .filter(|n| self.contains(n) && !exclude.contains(n))
+            .collect();
+        while let Some(node) = todo.pop() {
+            if res.insert(node.clone()) {
+                todo.extend(
+                    self.parents(node)
+                        .iter()
+                        .filter(|p| self.contains(p) && !exclude.contains(p)),
+                );
+            }
+        }
+        res
+    }
+
+    /// fails with ValueStoreError::CorruptHistory if parent links form a cycle
+    pub fn check_acyclic(&self) -> Result<()> {
+        let mut order = self.topological();
+        order.by_ref().for_each(drop);
+        if order.remaining() == 0 {
+            Ok(())
+        } else {
+            Err(ValueStoreError::CorruptHistory { change: None }.into())
+        }
+    }
+
+    /// all changes of the graph, parents before children
+    pub fn topological(&self) -> Topological<N> {
+        Topological::new(self, self.parents.keys().cloned())
+    }
+
+    /// the given changes ordered parents before children. ancestors not in nodes are ignored.
+    pub fn topological_of<I: IntoIterator<Item = N>>(&self, nodes: I) -> Topological<N> {
+        Topological::new(self, nodes)
+    }
+}
+
+/**
+ *  Kahn's algorithm. ready changes are yielded smallest first, so the order is deterministic.
+ *  changes that are part of a cycle are never yielded, see Topological::remaining.
+ *  */
+pub struct Topological<N> {
+    missing: HashMap<N, usize>,
+    children: HashMap<N, Vec<N>>,
+    ready: BinaryHeap<Reverse<N>>,
+}
+
+impl<N: Eq + Hash + Ord + Clone> Topological<N> {
+    fn new<I: IntoIterator<Item = N>>(graph: &ChangeGraph<N>, nodes: I) -> Self {
+        let nodes: HashSet<N> = nodes.into_iter().filter(|n| graph.contains(n)).collect();
+        let mut missing = HashMap::new();
+        let mut children: HashMap<N, Vec<N>> = HashMap::new();
+        let mut ready = BinaryHeap::new();
+        for node in &nodes {
+            let mut count = 0;
+            for parent in graph.parents(node) {
+                if nodes.contains(parent) {
+                    count += 1;
+                    children
+                        .entry(parent.clone())
+                        .or_default()
+                        .push(node.clone());
+                }
+            }
+            if count == 0 {
+                ready.push(Reverse(node.clone()));
+            } else {
+                missing.insert(node.clone(), count);
+            }
+        }
+        Self {
+            missing,
+            children,
+            ready,
+        }
+    }
+
+    /// number of changes still waiting for a parent. non zero after exhaustion means a cycle.
+    pub fn remaining(&self) -> usize {
+        self.missing.len()
+    }
+}
+
+impl<N: Eq + Hash + Ord + Clone> Iterator for Topological<N> {
+    type Item = N;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse(node) = self.ready.pop()?;
+        for child in self.children.remove(&node).unwrap_or_default() {
+            if let Some(count) = self.missing.get_mut(&child) {
+                *count -= 1;
+                if *count == 0 {
+                    self.missing.remove(&child);
+                    self.ready.push(Reverse(child));
+                }
+            }
+        }
+        Some(node)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn diamond() -> ChangeGraph<u32> {
+        // 1 <- 2 <- 4, 1 <- 3 <- 4, 3 <- 5
+        let mut graph = ChangeGraph::new();
+        graph.insert(1, vec![0]);
+        graph.insert(2, vec![1]);
+        graph.insert(3, vec![1]);
+        graph.insert(4, vec![2, 3]);
+        graph.insert(5, vec![3]);
+        graph
+    }
+
+    #[test]
+    fn topological_order() {
+        let graph = diamond();
+        assert_eq!(graph.topological().collect::<Vec<_>>(), vec![1, 2, 3, 4, 5]);
+        assert_eq!(
+            graph.topological_of([5, 4, 2]).collect::<Vec<_>>(),
+            vec![2, 4, 5]
+        );
+    }
+
+    #[test]
+    fn reachability() {
+        let graph = diamond();
+        assert_eq!(graph.reachable(&[2]), HashSet::from([1, 2]));
+        assert_eq!(graph.difference(&[4], &[5]), HashSet::from([2, 4]));
+        assert_eq!(graph.difference(&[5], &[4]), HashSet::from([5]));
+    }
+
+    #[test]
+    fn cycle() {
+        let mut graph = diamond();
+        graph.insert(1, vec![4]);
+        let mut iter = graph.topological();
+        assert_eq!(iter.by_ref().count(), 0);
+        assert_eq!(iter.remaining(), 5);
+        assert!(diamond().check_acyclic().is_ok());
+        assert!(graph.check_acyclic().is_err());
+    }
+}
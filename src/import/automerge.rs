@@ -0,0 +1,167 @@
+use std::{collections::HashMap, sync::Arc};
+
+use automerge::{
+    hydrate, transaction::Transactable, AutoCommit, Automerge, ChangeHash, ObjId, ObjType,
+    ScalarValue, ROOT,
+};
+
+use crate::{
+    apply::diff::diff,
+    export::redact::Redaction,
+    types::{change::ChangeContent, value::Blob, Value},
+    Result,
+};
+
+/// mime type of blobs read from automerge byte values
+pub const BYTES_MIME: &str = "application/octet-stream";
+
+/**
+ *  best effort conversion of automerge scalars.
+ *  nulls and values of unknown type have no counterpart and are left out,
+ *  counters and timestamps become integers, text becomes a string and bytes a blob.
+ *  */
+fn scalar(value: &ScalarValue) -> Option<Value> {
+    Some(match value {
+        ScalarValue::Bytes(data) => Value::Blob(Arc::new(Blob {
+            mime: BYTES_MIME.to_string(),
+            data: data.clone(),
+        })),
+        ScalarValue::Str(s) => Value::String(Arc::new(s.to_string())),
+        ScalarValue::Int(v) | ScalarValue::Timestamp(v) => Value::Integer(*v),
+        ScalarValue::Uint(v) => match i64::try_from(*v) {
+            Ok(v) => Value::Integer(v),
+            Err(_) => Value::Float(*v as f64),
+        },
+        ScalarValue::Counter(_) => Value::Integer(value.to_i64()?),
+        ScalarValue::F64(v) => Value::Float(*v),
+        ScalarValue::Boolean(v) => Value::Bool(*v),
+        ScalarValue::Unknown { .. } | ScalarValue::Null => return None,
+    })
+}
+
+fn convert(value: &hydrate::Value) -> Option<Value> {
+    match value {
+        hydrate::Value::Scalar(s) => scalar(s),
+        hydrate::Value::Map(map) => Some(Value::Map(Arc::new(
+            map.iter()
+                .filter_map(|(key, v)| Some((key.clone(), convert(&v.value)?)))
+                .collect(),
+        ))),
+        hydrate::Value::List(list) => Some(Value::Array(Arc::new(
+            list.iter().filter_map(|v| convert(&v.value)).collect(),
+        ))),
+        hydrate::Value::Text(text) => Some(Value::String(Arc::new(text.to_string()))),
+    }
+}
+
+/// root map of doc at heads, the current state if None
+pub fn from_automerge(doc: &Automerge, heads: Option<&[ChangeHash]>) -> Value {
+    convert(&doc.hydrate(heads)).unwrap_or_default()
+}
+
+/// changes turning the document at before into the document at after
+pub fn changes_between(
+    doc: &Automerge,
+    before: &[ChangeHash],
+    after: &[ChangeHash],
+) -> Vec<ChangeContent> {
+    diff(
+        &from_automerge(doc, Some(before)),
+        &from_automerge(doc, Some(after)),
+        &Redaction::new(),
+    )
+}
+
+fn to_scalar(value: &Value) -> Option<ScalarValue> {
+    match value {
+        Value::Integer(v) => Some(ScalarValue::Int(*v)),
+        Value::Float(v) => Some(ScalarValue::F64(*v)),
+        Value::Bool(v) => Some(ScalarValue::Boolean(*v)),
+        Value::String(v) => Some(ScalarValue::Str(v.as_str().into())),
+        // the mime type is lost
+        Value::Blob(v) => Some(ScalarValue::Bytes(v.data.clone())),
+        Value::Array(_) | Value::Map(_) => None,
+    }
+}
+
+fn put(doc: &mut AutoCommit, obj: &ObjId, key: &str, value: &Value) -> Result<()> {
+    match value {
+        Value::Array(arr) => {
+            let list = doc.put_object(obj, key, ObjType::List)?;
+            write_list(doc, &list, arr)
+        }
+        Value::Map(map) => {
+            let child = doc.put_object(obj, key, ObjType::Map)?;
+            write_map(doc, &child, map)
+        }
+        value => Ok(doc.put(obj, key, to_scalar(value).expect("scalar value"))?),
+    }
+}
+
+fn write_map(doc: &mut AutoCommit, obj: &ObjId, map: &HashMap<String, Value>) -> Result<()> {
+    for (key, value) in map.iter() {
+        put(doc, obj, key, value)?;
+    }
+    Ok(())
+}
+
+fn write_list(doc: &mut AutoCommit, obj: &ObjId, arr: &[Value]) -> Result<()> {
+    for (index, value) in arr.iter().enumerate() {
+        match value {
+            Value::Array(arr) => {
+                let list = doc.insert_object(obj, index, ObjType::List)?;
+                write_list(doc, &list, arr)?;
+            }
+            Value::Map(map) => {
+                let child = doc.insert_object(obj, index, ObjType::Map)?;
+                write_map(doc, &child, map)?;
+            }
+            value => doc.insert(obj, index, to_scalar(value).expect("scalar value"))?,
+        }
+    }
+    Ok(())
+}
+
+/// new automerge document with root as content, committed as a single change
+pub fn to_automerge(root: &HashMap<String, Value>) -> Result<AutoCommit> {
+    let mut doc = AutoCommit::new();
+    write_map(&mut doc, &ROOT, root)?;
+    doc.commit();
+    Ok(doc)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::PathElement;
+
+    #[test]
+    fn round_trip() {
+        let root = HashMap::from([
+            ("name".to_string(), Value::from("ann")),
+            ("age".to_string(), Value::from(31)),
+            (
+                "tags".to_string(),
+                Value::from(vec![Value::from(true), Value::from(0.5)]),
+            ),
+        ]);
+        let mut doc = to_automerge(&root).unwrap();
+        let before = doc.get_heads();
+        assert_eq!(
+            from_automerge(doc.document(), None),
+            Value::from(root.clone())
+        );
+
+        doc.put(ROOT, "age", ScalarValue::Uint(32)).unwrap();
+        doc.put(ROOT, "gone", ScalarValue::Null).unwrap();
+        let after = doc.get_heads();
+        assert_eq!(
+            changes_between(doc.document(), &before, &after),
+            vec![ChangeContent::Replace {
+                path: vec![PathElement::Field("age".to_string())].into(),
+                old: Value::Integer(31),
+                new: Value::Integer(32),
+            }]
+        );
+    }
+}
@@ -0,0 +1,112 @@
+use std::{collections::HashMap, future::Future, io::Read, sync::Arc};
+
+use crate::{
+    types::{change::ChangeContent, Path, PathElement, Value},
+    Result,
+};
+
+#[derive(Debug, Clone)]
+pub struct CsvImport {
+    /// array the rows are appended to, has to exist before the first batch is committed
+    pub path: Path,
+    /// rows per committed change set
+    pub batch_size: usize,
+}
+
+/**
+ *  streams csv rows as maps from header to string value into the array at options.path.
+ *  every batch of appends is passed to commit, afterwards progress is called with the
+ *  number of rows committed so far. returns the total number of rows.
+ *  */
+pub async fn import_csv<R, C, F, P>(
+    reader: R,
+    options: &CsvImport,
+    mut commit: C,
+    mut progress: P,
+) -> Result<u64>
+where
+    R: Read,
+    C: FnMut(Vec<ChangeContent>) -> F,
+    F: Future<Output = Result<()>>,
+    P: FnMut(u64),
+{
+    let mut reader = csv::Reader::from_reader(reader);
+    let headers: Vec<String> = reader.headers()?.iter().map(str::to_string).collect();
+    let path = options.path.join(PathElement::Append);
+    let batch_size = options.batch_size.max(1);
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut rows = 0;
+    for record in reader.records() {
+        let record = record?;
+        let row: HashMap<String, Value> = headers
+            .iter()
+            .zip(record.iter())
+            .map(|(header, field)| (header.clone(), Value::String(Arc::new(field.to_string()))))
+            .collect();
+        batch.push(ChangeContent::Insert {
+            path: path.clone(),
+            value: Value::Map(row.into()),
+        });
+        if batch.len() == batch_size {
+            rows += batch.len() as u64;
+            commit(std::mem::replace(
+                &mut batch,
+                Vec::with_capacity(batch_size),
+            ))
+            .await?;
+            progress(rows);
+        }
+    }
+    if !batch.is_empty() {
+        rows += batch.len() as u64;
+        commit(batch).await?;
+        progress(rows);
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use futures_util::FutureExt;
+
+    use super::{import_csv, CsvImport};
+    use crate::types::{PathElement, Value};
+
+    #[test]
+    fn import_batches() {
+        let data = "name,age\nann,31\nbob,42\ncid,7\n";
+        let mut value = Value::Map(
+            HashMap::from_iter([("rows".to_string(), Value::Array(vec![].into()))]).into(),
+        );
+        let mut batches = 0;
+        let mut reported = Vec::new();
+        let rows = import_csv(
+            data.as_bytes(),
+            &CsvImport {
+                path: vec![PathElement::Field("rows".to_string())].into(),
+                batch_size: 2,
+            },
+            |changes| {
+                batches += 1;
+                let res = value.apply_iter(&changes).map_err(Into::into);
+                async move { res }
+            },
+            |rows| reported.push(rows),
+        )
+        .now_or_never()
+        .expect("import does not wait")
+        .expect("import failed");
+        assert_eq!(rows, 3);
+        assert_eq!(batches, 2);
+        assert_eq!(reported, vec![2, 3]);
+        let rows = value
+            .get(&[PathElement::Field("rows".to_string())])
+            .unwrap();
+        assert_eq!(
+            rows.get(&[PathElement::Index(2), PathElement::Field("age".to_string())]),
+            Some(&Value::String("7".to_string().into()))
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use std::io::{Read, Write};
+
+use crate::{export::redact::Redaction, types::Value, Result};
+
+#[cfg(feature = "automerge")]
+pub mod automerge;
+#[cfg(feature = "csv")]
+pub mod csv;
+
+/**
+ *  document formats values can be read from and written to.
+ *  json has no binary type, blobs are written as arrays of bytes and read back as such.
+ *  */
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Format {
+    Cbor,
+    #[cfg(feature = "json")]
+    Json,
+}
+
+pub fn read_value<R: Read>(format: Format, reader: R) -> Result<Value> {
+    match format {
+        Format::Cbor => Ok(ciborium::from_reader(reader)?),
+        #[cfg(feature = "json")]
+        Format::Json => Ok(serde_json::from_reader(reader)?),
+    }
+}
+
+/// writes value redacted by redaction with maps ordered by key
+pub fn write_value<W: Write>(
+    format: Format,
+    value: &Value,
+    redaction: &Redaction,
+    writer: W,
+) -> Result<()> {
+    let value = redaction.apply(value);
+    match format {
+        Format::Cbor => Ok(ciborium::into_writer(&value.canonicalize(), writer)?),
+        #[cfg(feature = "json")]
+        Format::Json => Ok(serde_json::to_writer(writer, &value.canonicalize())?),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::{read_value, write_value, Format};
+    use crate::{export::redact::Redaction, types::Value};
+
+    fn sample() -> Value {
+        Value::Map(
+            HashMap::from_iter([
+                ("b".to_string(), Value::Integer(-4)),
+                ("a".to_string(), Value::Float(0.5)),
+                (
+                    "c".to_string(),
+                    Value::Array(
+                        vec![Value::Bool(true), Value::String("s".to_string().into())].into(),
+                    ),
+                ),
+            ])
+            .into(),
+        )
+    }
+
+    #[test]
+    fn cbor_round_trip() {
+        let mut buf = Vec::new();
+        write_value(Format::Cbor, &sample(), &Redaction::new(), &mut buf).expect("writing failed");
+        assert_eq!(
+            read_value(Format::Cbor, buf.as_slice()).expect("reading failed"),
+            sample()
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_round_trip() {
+        let mut buf = Vec::new();
+        write_value(Format::Json, &sample(), &Redaction::new(), &mut buf).expect("writing failed");
+        assert_eq!(
+            std::str::from_utf8(&buf).unwrap(),
+            r#"{"a":0.5,"b":-4,"c":[true,"s"]}"#
+        );
+        assert_eq!(
+            read_value(Format::Json, buf.as_slice()).expect("reading failed"),
+            sample()
+        );
+    }
+}
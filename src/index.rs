@@ -0,0 +1,231 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::Arc,
+};
+
+use crate::types::{change::ChangeContent, path_pattern::PathPattern, PathElement, Value};
+
+/// indexable leaf values. floats, blobs and containers are not indexed.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum IndexKey {
+    Integer(i64),
+    Bool(bool),
+    String(Arc<String>),
+}
+
+impl IndexKey {
+    pub fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Integer(v) => Some(IndexKey::Integer(*v)),
+            Value::Bool(v) => Some(IndexKey::Bool(*v)),
+            Value::String(v) => Some(IndexKey::String(v.clone())),
+            _ => None,
+        }
+    }
+}
+
+/// maps the values found at all paths matching a pattern back to their paths
+#[derive(Debug)]
+pub struct ValueIndex {
+    pattern: PathPattern,
+    by_key: HashMap<IndexKey, BTreeSet<Vec<PathElement>>>,
+    by_path: BTreeMap<Vec<PathElement>, IndexKey>,
+}
+
+impl ValueIndex {
+    pub fn new(pattern: PathPattern, value: &Value) -> Self {
+        let mut res = ValueIndex {
+            pattern,
+            by_key: HashMap::new(),
+            by_path: BTreeMap::new(),
+        };
+        res.scan(value, &mut Vec::new());
+        res
+    }
+
+    pub fn pattern(&self) -> &PathPattern {
+        &self.pattern
+    }
+
+    pub fn find(&self, key: &Value) -> Vec<Vec<PathElement>> {
+        IndexKey::from_value(key)
+            .and_then(|key| self.by_key.get(&key))
+            .map(|paths| paths.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /**
+     *  updates the index after changes were applied.
+     *  value is the state after all changes.
+     *  */
+    pub fn update<'l, I: IntoIterator<Item = &'l ChangeContent>>(
+        &mut self,
+        value: &Value,
+        changes: I,
+    ) {
+        for change in changes {
+            let root = change.affected_prefix();
+            let root = &root[..root.len().min(self.pattern.len())];
+            if self.pattern.matches_prefix(root) {
+                self.remove_prefix(root);
+                if let Some(node) = value.get(root) {
+                    self.scan(node, &mut root.to_vec());
+                }
+            }
+        }
+    }
+
+    fn remove_prefix(&mut self, prefix: &[PathElement]) {
+        let removed: Vec<_> = self
+            .by_path
+            .range(prefix.to_vec()..)
+            .take_while(|(path, _)| path.starts_with(prefix))
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in removed {
+            if let Some(key) = self.by_path.remove(&path) {
+                if let Some(paths) = self.by_key.get_mut(&key) {
+                    paths.remove(&path);
+                    if paths.is_empty() {
+                        self.by_key.remove(&key);
+                    }
+                }
+            }
+        }
+    }
+
+    /// indexes node located at path, path has to match a prefix of the pattern
+    fn scan(&mut self, node: &Value, path: &mut Vec<PathElement>) {
+        self.pattern.for_each_match(node, path, |path, node| {
+            if let Some(key) = IndexKey::from_value(node) {
+                self.by_key
+                    .entry(key.clone())
+                    .or_default()
+                    .insert(path.to_vec());
+                self.by_path.insert(path.to_vec(), key);
+            }
+        });
+    }
+}
+
+/// named set of indexes kept up to date with a single value
+#[derive(Debug, Default)]
+pub struct Indexes {
+    indexes: HashMap<String, ValueIndex>,
+}
+
+impl Indexes {
+    pub fn declare(&mut self, name: impl Into<String>, pattern: PathPattern, value: &Value) {
+        self.indexes
+            .insert(name.into(), ValueIndex::new(pattern, value));
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.indexes.remove(name).is_some()
+    }
+
+    pub fn update(&mut self, value: &Value, changes: &[ChangeContent]) {
+        for index in self.indexes.values_mut() {
+            index.update(value, changes)
+        }
+    }
+
+    /// reindexes value from scratch, for values that changed in unknown ways
+    pub fn rebuild(&mut self, value: &Value) {
+        for index in self.indexes.values_mut() {
+            *index = ValueIndex::new(index.pattern().clone(), value);
+        }
+    }
+
+    pub fn find(&self, index: &str, key: &Value) -> Option<Vec<Vec<PathElement>>> {
+        self.indexes.get(index).map(|index| index.find(key))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::Indexes;
+    use crate::types::{change::ChangeContent, PathElement, Value};
+
+    fn user(email: &str) -> Value {
+        Value::Map(
+            HashMap::from_iter([("email".to_string(), Value::String(email.to_string().into()))])
+                .into(),
+        )
+    }
+
+    fn email_path(index: u32) -> Vec<PathElement> {
+        vec![
+            PathElement::Field("users".to_string()),
+            PathElement::Index(index),
+            PathElement::Field("email".to_string()),
+        ]
+    }
+
+    #[test]
+    fn find_and_update() {
+        let mut value = Value::Map(
+            HashMap::from_iter([(
+                "users".to_string(),
+                Value::Array(vec![user("a@x"), user("b@x")].into()),
+            )])
+            .into(),
+        );
+        let mut indexes = Indexes::default();
+        indexes.declare("email", "users[*].email".parse().unwrap(), &value);
+        let key = Value::String("b@x".to_string().into());
+        assert_eq!(indexes.find("email", &key), Some(vec![email_path(1)]));
+        assert_eq!(indexes.find("missing", &key), None);
+
+        let changes = vec![ChangeContent::Insert {
+            path: vec![
+                PathElement::Field("users".to_string()),
+                PathElement::Index(0),
+            ].into(),
+            value: user("c@x"),
+        }];
+        value.apply_iter(&changes).unwrap();
+        indexes.update(&value, &changes);
+        assert_eq!(indexes.find("email", &key), Some(vec![email_path(2)]));
+
+        let changes = vec![ChangeContent::Replace {
+            path: email_path(2).into(),
+            old: key.clone(),
+            new: Value::String("d@x".to_string().into()),
+        }];
+        value.apply_iter(&changes).unwrap();
+        indexes.update(&value, &changes);
+        assert_eq!(indexes.find("email", &key), Some(vec![]));
+        assert_eq!(
+            indexes.find("email", &Value::String("d@x".to_string().into())),
+            Some(vec![email_path(2)])
+        );
+
+        // relative positions are resolved by reindexing their array
+        let changes = vec![ChangeContent::Replace {
+            path: vec![
+                PathElement::Field("users".to_string()),
+                PathElement::FromEnd(0),
+                PathElement::Field("email".to_string()),
+            ]
+            .into(),
+            old: Value::String("d@x".to_string().into()),
+            new: key.clone(),
+        }];
+        value.apply_iter(&changes).unwrap();
+        indexes.update(&value, &changes);
+        assert_eq!(indexes.find("email", &key), Some(vec![email_path(2)]));
+        let changes = vec![ChangeContent::Insert {
+            path: vec![PathElement::Field("users".to_string()), PathElement::Append].into(),
+            value: user("e@x"),
+        }];
+        value.apply_iter(&changes).unwrap();
+        indexes.update(&value, &changes);
+        assert_eq!(
+            indexes.find("email", &Value::String("e@x".to_string().into())),
+            Some(vec![email_path(3)])
+        );
+    }
+}
@@ -1,12 +1,29 @@
 #![allow(dead_code, unused_variables)]
 
 pub mod async_support;
+pub mod cancel;
+pub mod clock;
+pub mod config;
 pub mod conflict;
+#[cfg(feature = "derive")]
+pub mod document;
 pub mod error;
+pub mod export;
+pub mod fmt;
+pub mod graph;
+pub mod import;
+pub mod index;
+pub mod limit;
+pub mod lock;
+pub mod outbox;
+pub mod projection;
+pub mod review;
+pub mod schema;
 pub mod storage;
 pub mod types;
 pub mod value_store;
 pub mod util;
+pub mod validate;
 pub mod apply;
 
 pub use error::{Error, Result};
@@ -0,0 +1,171 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use futures_intrusive::sync::{Semaphore, SemaphoreReleaser};
+
+/// at most commits per time span, with bursts of up to commits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub commits: u32,
+    pub per: Duration,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/**
+ *  token bucket rate limiter per key, usually a branch.
+ *  keys without a limit of their own use the default limit.
+ *  */
+#[derive(Debug)]
+pub struct RateLimiter<K> {
+    default: RateLimit,
+    limits: HashMap<K, RateLimit>,
+    buckets: Mutex<HashMap<K, Bucket>>,
+}
+
+impl<K: Hash + Eq + Clone> RateLimiter<K> {
+    pub fn new(default: RateLimit) -> Self {
+        Self {
+            default,
+            limits: HashMap::new(),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_limit(mut self, key: K, limit: RateLimit) -> Self {
+        self.limits.insert(key, limit);
+        self
+    }
+
+    pub fn limit(&self, key: &K) -> RateLimit {
+        self.limits.get(key).copied().unwrap_or(self.default)
+    }
+
+    /// takes one commit from the budget of key, fails with the time until the next one is available
+    pub fn check(&self, key: &K, now: Instant) -> Result<(), Duration> {
+        let limit = self.limit(key);
+        let capacity = limit.commits.max(1) as f64;
+        let per_token = limit.per.as_secs_f64() / capacity;
+        let mut buckets = self.buckets.lock().expect("rate limiter poisoned");
+        let bucket = buckets.entry(key.clone()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed / per_token).min(capacity);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) * per_token))
+        }
+    }
+}
+
+/**
+ *  bounds the number of commits processed at once.
+ *  writers beyond the capacity wait in arrival order until a running commit finishes.
+ *  */
+pub struct CommitQueue {
+    semaphore: Semaphore,
+    capacity: usize,
+}
+
+/// slot in the CommitQueue, released on drop
+pub type CommitPermit<'l> = SemaphoreReleaser<'l>;
+
+impl CommitQueue {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            semaphore: Semaphore::new(true, capacity),
+            capacity,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// commits that could start right now
+    pub fn available(&self) -> usize {
+        self.semaphore.permits()
+    }
+
+    pub async fn enter(&self) -> CommitPermit<'_> {
+        self.semaphore.acquire(1).await
+    }
+
+    pub fn try_enter(&self) -> Option<CommitPermit<'_>> {
+        self.semaphore.try_acquire(1)
+    }
+
+    /// waits until all commits that entered before have finished, blocking the queue until dropped
+    pub async fn drain(&self) -> CommitPermit<'_> {
+        self.semaphore.acquire(self.capacity).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures_util::FutureExt;
+
+    use super::*;
+
+    #[test]
+    fn token_bucket() {
+        let limiter = RateLimiter::new(RateLimit {
+            commits: 2,
+            per: Duration::from_secs(1),
+        })
+        .with_limit(
+            "slow",
+            RateLimit {
+                commits: 1,
+                per: Duration::from_secs(10),
+            },
+        );
+        let start = Instant::now();
+        assert_eq!(limiter.check(&"main", start), Ok(()));
+        assert_eq!(limiter.check(&"main", start), Ok(()));
+        assert_eq!(
+            limiter.check(&"main", start),
+            Err(Duration::from_millis(500))
+        );
+        assert_eq!(
+            limiter.check(&"main", start + Duration::from_millis(500)),
+            Ok(())
+        );
+        assert_eq!(limiter.check(&"slow", start), Ok(()));
+        assert_eq!(
+            limiter.check(&"slow", start + Duration::from_secs(4)),
+            Err(Duration::from_secs(6))
+        );
+    }
+
+    #[test]
+    fn queue() {
+        let queue = CommitQueue::new(1);
+        let permit = queue.enter().now_or_never().unwrap();
+        assert!(queue.try_enter().is_none());
+        assert!(queue.enter().now_or_never().is_none());
+        drop(permit);
+        assert_eq!(queue.available(), 1);
+        assert!(queue.try_enter().is_some());
+
+        let permit = queue.enter().now_or_never().unwrap();
+        let mut drain = Box::pin(queue.drain());
+        assert!(drain.as_mut().now_or_never().is_none());
+        drop(permit);
+        assert!(drain.now_or_never().is_some());
+    }
+}
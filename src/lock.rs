@@ -0,0 +1,236 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    config::Config,
+    error::ValueStoreError,
+    types::{change::path_prefix_hash, change::ChangeContent, Path, PathElement, Value},
+    Result,
+};
+
+/// prefix of the branch config entries holding path locks
+pub const LOCK_PREFIX: &str = "lock.";
+
+/**
+ *  advisory lock on the subtree at path, held by owner until expires.
+ *  locks are stored in the branch config, so they are visible to every store on the same storage.
+ *  */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathLock {
+    pub path: Path,
+    pub owner: String,
+    pub expires: SystemTime,
+}
+
+/// config entry name of the lock on path
+pub fn lock_name(path: &[PathElement]) -> String {
+    format!("{LOCK_PREFIX}{:x}", path_prefix_hash(path))
+}
+
+/// milliseconds since the unix epoch, as persisted in config values
+pub(crate) fn millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+        .try_into()
+        .unwrap_or(i64::MAX)
+}
+
+impl PathLock {
+    /// locks can only be placed on fields and absolute indices, expiring at a representable time
+    pub fn new(path: &[PathElement], owner: &str, ttl: Duration, now: SystemTime) -> Result<Self> {
+        if path
+            .iter()
+            .any(|elem| !matches!(elem, PathElement::Field(_) | PathElement::Index(_)))
+        {
+            return Err(ValueStoreError::InvalidLockPath { path: path.into() }.into());
+        }
+        let expires = now
+            .checked_add(ttl)
+            .ok_or(ValueStoreError::InvalidLockTtl { ttl })?;
+        Ok(Self {
+            path: path.into(),
+            owner: owner.to_string(),
+            expires,
+        })
+    }
+
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires <= now
+    }
+
+    /// a change at path modifies the locked subtree or replaces one of its ancestors
+    pub fn covers(&self, path: &[PathElement]) -> bool {
+        self.path
+            .iter()
+            .zip(path)
+            .all(|(locked, elem)| match (locked, elem) {
+                (PathElement::Field(l), PathElement::Field(f)) => l == f,
+                (PathElement::Index(l), PathElement::Index(i)) => l == i,
+                // relative positions may resolve to the locked index
+                (PathElement::Index(_), PathElement::FromEnd(_) | PathElement::Append) => true,
+                _ => false,
+            })
+    }
+
+    /**
+     *  change modifies the locked subtree, replaces one of its ancestors or moves the subtree
+     *  by inserting or deleting an element before it in the same array.
+     *  */
+    pub fn is_affected_by(&self, change: &ChangeContent) -> bool {
+        let path = change.path();
+        if self.covers(path) {
+            return true;
+        }
+        if matches!(change, ChangeContent::Replace { .. }) {
+            return false;
+        }
+        let Some((PathElement::Index(index), parent)) = path.split_last() else {
+            return false;
+        };
+        matches!(self.path.get(parent.len()), Some(PathElement::Index(locked)) if index < locked)
+            && self.covers(parent)
+    }
+
+    pub fn to_value(&self) -> Value {
+        let path = self
+            .path
+            .iter()
+            .map(|elem| match elem {
+                PathElement::Field(name) => Value::String(Arc::new(name.clone())),
+                PathElement::Index(index) => Value::Integer((*index).into()),
+                _ => unreachable!("checked on construction"),
+            })
+            .collect::<Vec<_>>();
+        Value::Map(Arc::new(HashMap::from([
+            ("path".to_string(), Value::Array(Arc::new(path))),
+            (
+                "owner".to_string(),
+                Value::String(Arc::new(self.owner.clone())),
+            ),
+            ("expires".to_string(), Value::Integer(millis(self.expires))),
+        ])))
+    }
+
+    pub fn from_value(value: &Value) -> Option<Self> {
+        let Value::Map(map) = value else {
+            return None;
+        };
+        let Some(Value::Array(elems)) = map.get("path") else {
+            return None;
+        };
+        let path = elems
+            .iter()
+            .map(|elem| match elem {
+                Value::String(name) => Some(PathElement::Field(name.to_string())),
+                Value::Integer(index) => Some(PathElement::Index((*index).try_into().ok()?)),
+                _ => None,
+            })
+            .collect::<Option<Path>>()?;
+        let Some(Value::String(owner)) = map.get("owner") else {
+            return None;
+        };
+        let Some(Value::Integer(expires)) = map.get("expires") else {
+            return None;
+        };
+        Some(Self {
+            path,
+            owner: owner.to_string(),
+            expires: UNIX_EPOCH + Duration::from_millis((*expires).try_into().ok()?),
+        })
+    }
+}
+
+/// unexpired locks of a branch config
+pub fn active_locks(config: &Config, now: SystemTime) -> Vec<PathLock> {
+    config
+        .iter()
+        .filter(|(name, _)| name.starts_with(LOCK_PREFIX))
+        .filter_map(|(_, value)| PathLock::from_value(value))
+        .filter(|lock| !lock.is_expired(now))
+        .collect()
+}
+
+/// fails with the first lock of another owner covering one of changes
+pub fn check_locks(
+    locks: &[PathLock],
+    changes: &[ChangeContent],
+    owner: Option<&str>,
+) -> Result<()> {
+    for lock in locks {
+        if Some(lock.owner.as_str()) == owner {
+            continue;
+        }
+        if changes.iter().any(|change| lock.is_affected_by(change)) {
+            return Err(ValueStoreError::PathLocked {
+                path: lock.path.clone(),
+                owner: lock.owner.clone(),
+            }
+            .into());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::test_util::field;
+    use crate::Error;
+
+    #[test]
+    fn locks() {
+        let now = SystemTime::now();
+        let lock = PathLock::new(
+            &[field("doc"), PathElement::Index(2)],
+            "ann",
+            Duration::from_secs(60),
+            now,
+        )
+        .unwrap();
+        assert_eq!(
+            PathLock::from_value(&lock.to_value()).unwrap().path,
+            lock.path
+        );
+        assert!(!lock.is_expired(now));
+        assert!(lock.is_expired(now + Duration::from_secs(60)));
+
+        assert!(lock.covers(&[field("doc")]));
+        assert!(lock.covers(&[field("doc"), PathElement::Index(2), field("title")]));
+        assert!(lock.covers(&[field("doc"), PathElement::Append]));
+        assert!(!lock.covers(&[field("doc"), PathElement::Index(1)]));
+        assert!(!lock.covers(&[field("other")]));
+
+        let change = ChangeContent::Delete {
+            path: vec![field("doc")].into(),
+            old: Value::Bool(true),
+        };
+        let insert = |index| ChangeContent::Insert {
+            path: vec![field("doc"), PathElement::Index(index)].into(),
+            value: Value::Bool(true),
+        };
+        // inserting before the locked element moves it
+        assert!(lock.is_affected_by(&insert(1)));
+        assert!(!lock.is_affected_by(&insert(3)));
+        assert!(!lock.is_affected_by(&ChangeContent::Replace {
+            path: vec![field("doc"), PathElement::Index(1)].into(),
+            old: Value::Bool(true),
+            new: Value::Bool(false),
+        }));
+
+        let locks = [lock];
+        assert!(check_locks(&locks, std::slice::from_ref(&change), Some("ann")).is_ok());
+        assert!(matches!(
+            check_locks(&locks, &[change], Some("bob")),
+            Err(Error::ValueStore(ValueStoreError::PathLocked { .. }))
+        ));
+        assert!(PathLock::new(&[PathElement::Append], "ann", Duration::ZERO, now).is_err());
+        assert!(matches!(
+            PathLock::new(&[field("doc")], "ann", Duration::MAX, now),
+            Err(Error::ValueStore(ValueStoreError::InvalidLockTtl { .. }))
+        ));
+    }
+}
@@ -0,0 +1,236 @@
+use std::{future::Future, time::Duration};
+
+use uuid::Uuid;
+
+use crate::{
+    async_support::MaybeSend,
+    error::ValueStoreError,
+    storage::{retry::RetryPolicy, Storage},
+    types::change::Hash,
+    Result,
+};
+
+#[cfg(feature = "nats")]
+pub mod nats;
+
+/// a commit of repo as handed to sinks, content is the encoded change
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitSummary {
+    pub repo: Uuid,
+    pub seq: u64,
+    pub hash: Hash,
+    pub content: Vec<u8>,
+}
+
+/// destination for committed changes, e.g. a message broker
+pub trait CommitSink {
+    fn publish(&self, commit: &CommitSummary) -> impl Future<Output = Result<()>> + MaybeSend;
+}
+
+/**
+ *  forwards the change feed of a repo to a sink.
+ *  commits are published in sequence order and at least once, the cursor only moves
+ *  past a commit after the sink accepted it. failed publishes are retried according to
+ *  the retry policy, sleep waits for the backoff, usually the sleep of the async runtime.
+ *  */
+pub struct Outbox<S, Z> {
+    sink: S,
+    cursor: u64,
+    policy: RetryPolicy,
+    sleep: Z,
+}
+
+impl<S, Z, F> Outbox<S, Z>
+where
+    S: CommitSink,
+    Z: Fn(Duration) -> F,
+    F: Future<Output = ()>,
+{
+    /// cursor is the seq of the last commit already published, 0 for none
+    pub fn new(sink: S, cursor: u64, sleep: Z) -> Self {
+        Self {
+            sink,
+            cursor,
+            policy: RetryPolicy::default(),
+            sleep,
+        }
+    }
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+    pub fn cursor(&self) -> u64 {
+        self.cursor
+    }
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    /**
+     *  publishes all commits after the cursor. stops with the error of the first commit
+     *  the sink rejected on every attempt, later commits are retried on the next call.
+     *  returns the number of commits published.
+     *  */
+    pub async fn pump<St>(&mut self, storage: &St, repo: Uuid) -> Result<usize>
+    where
+        St: Storage,
+        St::ChangeId: Clone,
+    {
+        let repo_id = storage
+            .get_repo_id(repo)
+            .await?
+            .ok_or(ValueStoreError::UnknownRepo { uuid: repo })?;
+        let mut published = 0;
+        for (seq, change) in storage.changes_since(repo_id, self.cursor).await? {
+            let (hash, _) = storage.get_change_hash(change.clone()).await?;
+            let commit = CommitSummary {
+                repo,
+                seq,
+                hash,
+                content: storage.get_change_content(change).await?,
+            };
+            self.publish(&commit).await?;
+            self.cursor = seq;
+            published += 1;
+        }
+        Ok(published)
+    }
+
+    async fn publish(&self, commit: &CommitSummary) -> Result<()> {
+        let mut attempt = 0;
+        loop {
+            match self.sink.publish(commit).await {
+                Err(_) if attempt + 1 < self.policy.max_attempts => {
+                    (self.sleep)(self.policy.backoff(attempt)).await;
+                    attempt += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use futures_util::FutureExt;
+
+    use crate::error::Error;
+
+    use super::*;
+
+    struct Flaky {
+        failures: Mutex<u32>,
+        published: Mutex<Vec<(u64, Hash)>>,
+    }
+
+    impl Flaky {
+        fn new(failures: u32) -> Self {
+            Self {
+                failures: Mutex::new(failures),
+                published: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    impl CommitSink for Flaky {
+        async fn publish(&self, commit: &CommitSummary) -> Result<()> {
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                Err(Error::ValueStore(ValueStoreError::ReadOnly))
+            } else {
+                self.published
+                    .lock()
+                    .unwrap()
+                    .push((commit.seq, commit.hash));
+                Ok(())
+            }
+        }
+    }
+
+    const POLICY: RetryPolicy = RetryPolicy {
+        max_attempts: 3,
+        initial_backoff: Duration::from_millis(10),
+        max_backoff: Duration::from_millis(15),
+    };
+
+    #[test]
+    fn retries() {
+        let sleeps = Mutex::new(Vec::new());
+        let outbox = Outbox::new(Flaky::new(4), 0, |duration| {
+            sleeps.lock().unwrap().push(duration);
+            std::future::ready(())
+        })
+        .with_retry_policy(POLICY);
+        let commit = CommitSummary {
+            repo: Uuid::nil(),
+            seq: 1,
+            hash: Hash([1; 32]),
+            content: Vec::new(),
+        };
+        assert!(outbox.publish(&commit).now_or_never().unwrap().is_err());
+        assert!(outbox.publish(&commit).now_or_never().unwrap().is_ok());
+        assert_eq!(
+            *outbox.sink().published.lock().unwrap(),
+            vec![(1, Hash([1; 32]))]
+        );
+        let expected: Vec<_> = [10, 15, 10]
+            .into_iter()
+            .map(Duration::from_millis)
+            .collect();
+        assert_eq!(*sleeps.lock().unwrap(), expected);
+    }
+
+    #[cfg(feature = "db_redb")]
+    #[test]
+    fn pump() {
+        use crate::{types::hasher::HashAlgorithm, util::test_util::redb_storage};
+
+        let storage = redb_storage();
+        let repo = storage.create_repo(Uuid::nil()).unwrap();
+        for seq in 1..=3 {
+            let change = storage
+                .add_change(&Hash([seq; 32]), HashAlgorithm::Blake3, &[0x80], &[])
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+            storage
+                .insert_event(repo, seq.into(), change)
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+        // the sink rejects seq 2 on every attempt of the first pump
+        let mut outbox =
+            Outbox::new(Flaky::new(3), 1, |_| std::future::ready(())).with_retry_policy(POLICY);
+        assert!(outbox
+            .pump(&storage, Uuid::nil())
+            .now_or_never()
+            .unwrap()
+            .is_err());
+        assert_eq!(outbox.cursor(), 1);
+        assert_eq!(
+            outbox
+                .pump(&storage, Uuid::nil())
+                .now_or_never()
+                .unwrap()
+                .unwrap(),
+            2
+        );
+        assert_eq!(outbox.cursor(), 3);
+        assert_eq!(
+            outbox
+                .pump(&storage, Uuid::nil())
+                .now_or_never()
+                .unwrap()
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            *outbox.sink().published.lock().unwrap(),
+            vec![(2, Hash([2; 32])), (3, Hash([3; 32]))]
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use async_nats::{
+    header::NATS_MESSAGE_ID,
+    jetstream::{self, context::PublishError},
+    HeaderMap,
+};
+
+use crate::{error::Error, Result};
+
+use super::{CommitSink, CommitSummary};
+
+/// header holding the hash of the published change
+pub const HASH_HEADER: &str = "Value-Store-Hash";
+
+/**
+ *  publishes commits to JetStream, the commits of a repo to subject "{prefix}.{repo}".
+ *  a publish only succeeds once the stream acknowledged it. the message id is repo and seq,
+ *  so JetStream drops commits the outbox publishes again within its duplicate window.
+ *  */
+pub struct NatsSink {
+    context: jetstream::Context,
+    prefix: String,
+}
+
+impl NatsSink {
+    pub fn new(context: jetstream::Context, prefix: impl Into<String>) -> Self {
+        Self {
+            context,
+            prefix: prefix.into(),
+        }
+    }
+
+    fn subject(&self, commit: &CommitSummary) -> String {
+        format!("{}.{}", self.prefix, commit.repo)
+    }
+}
+
+fn headers(commit: &CommitSummary) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        NATS_MESSAGE_ID,
+        format!("{}.{}", commit.repo, commit.seq).as_str(),
+    );
+    headers.insert(HASH_HEADER, commit.hash.to_string().as_str());
+    headers
+}
+
+impl CommitSink for NatsSink {
+    async fn publish(&self, commit: &CommitSummary) -> Result<()> {
+        let ack = self
+            .context
+            .publish_with_headers(
+                self.subject(commit),
+                headers(commit),
+                commit.content.clone().into(),
+            )
+            .await?;
+        ack.await?;
+        Ok(())
+    }
+}
+
+impl From<PublishError> for Error {
+    fn from(value: PublishError) -> Self {
+        Self::Nats(value.into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::types::change::Hash;
+
+    #[test]
+    fn headers() {
+        let commit = CommitSummary {
+            repo: Uuid::from_u128(1),
+            seq: 7,
+            hash: Hash([1; 32]),
+            content: Vec::new(),
+        };
+        let headers = super::headers(&commit);
+        assert_eq!(
+            headers.get(NATS_MESSAGE_ID).map(|id| id.as_str()),
+            Some("00000000-0000-0000-0000-000000000001.7")
+        );
+        assert_eq!(
+            headers.get(HASH_HEADER).map(|hash| hash.as_str()),
+            Some(Hash([1; 32]).to_string().as_str())
+        );
+    }
+}
@@ -0,0 +1,267 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    fmt::PathDisplay,
+    types::{change::ChangeContent, path_pattern::PathPattern, PathElement, Value},
+};
+
+#[cfg(feature = "db_sqlite")]
+pub mod sqlite;
+
+/// column of a projected table, filled with the value at path relative to the row
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Column {
+    pub name: String,
+    pub path: Vec<PathElement>,
+}
+
+impl Column {
+    /// column holding the field name of the row map
+    pub fn field(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            path: vec![PathElement::Field(name.to_string())],
+        }
+    }
+}
+
+/// update of a projected table, rows are identified by the path of the row value rendered by PathDisplay
+#[derive(Debug, Clone, PartialEq)]
+pub enum RowChange {
+    Upsert {
+        key: String,
+        values: Vec<Option<Value>>,
+    },
+    Delete {
+        key: String,
+    },
+}
+
+/**
+ *  maps every value matching a pattern to a row of a relational table.
+ *  keeps the current rows, so updates after a commit only touch rows below changed paths.
+ *  inserting into or deleting from an array renames the rows of all later elements.
+ *  */
+#[derive(Debug)]
+pub struct Projection {
+    table: String,
+    pattern: PathPattern,
+    columns: Vec<Column>,
+    rows: BTreeMap<Vec<PathElement>, Vec<Option<Value>>>,
+}
+
+impl Projection {
+    pub fn new(table: impl Into<String>, pattern: PathPattern, columns: Vec<Column>) -> Self {
+        Self {
+            table: table.into(),
+            pattern,
+            columns,
+            rows: BTreeMap::new(),
+        }
+    }
+
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+    pub fn pattern(&self) -> &PathPattern {
+        &self.pattern
+    }
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// replaces all rows with the ones of value, returns the upserts for a fresh table
+    pub fn load(&mut self, value: &Value) -> Vec<RowChange> {
+        self.rows.clear();
+        self.scan(value, &mut Vec::new());
+        self.rows
+            .iter()
+            .map(|(path, values)| RowChange::Upsert {
+                key: PathDisplay(path).to_string(),
+                values: values.clone(),
+            })
+            .collect()
+    }
+
+    /**
+     *  row changes caused by changes, value is the state after all changes.
+     *  rows that didn't change are left out.
+     *  */
+    pub fn update<'l, I: IntoIterator<Item = &'l ChangeContent>>(
+        &mut self,
+        value: &Value,
+        changes: I,
+    ) -> Vec<RowChange> {
+        let mut old = BTreeMap::new();
+        let mut touched = BTreeSet::new();
+        for change in changes {
+            let root = change.affected_prefix();
+            let root = &root[..root.len().min(self.pattern.len())];
+            if self.pattern.matches_prefix(root) {
+                for path in self.rows_below(root) {
+                    let row = self.rows.remove(&path).expect("row exists");
+                    // rows touched before were already rescanned from the new value
+                    if !touched.contains(&path) {
+                        old.insert(path, row);
+                    }
+                }
+                if let Some(node) = value.get(root) {
+                    self.scan(node, &mut root.to_vec());
+                }
+                touched.extend(self.rows_below(root));
+            }
+        }
+        let mut res = Vec::new();
+        for path in old.keys() {
+            if !self.rows.contains_key(path) {
+                res.push(RowChange::Delete {
+                    key: PathDisplay(path).to_string(),
+                });
+            }
+        }
+        for path in touched {
+            match self.rows.get(&path) {
+                Some(row) if old.get(&path) != Some(row) => res.push(RowChange::Upsert {
+                    key: PathDisplay(&path).to_string(),
+                    values: row.clone(),
+                }),
+                _ => {}
+            }
+        }
+        res
+    }
+
+    fn rows_below(&self, prefix: &[PathElement]) -> Vec<Vec<PathElement>> {
+        self.rows
+            .range(prefix.to_vec()..)
+            .take_while(|(path, _)| path.starts_with(prefix))
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+
+    /// adds the rows below node located at path, path has to match a prefix of the pattern
+    fn scan(&mut self, node: &Value, path: &mut Vec<PathElement>) {
+        self.pattern.for_each_match(node, path, |path, node| {
+            let values = self
+                .columns
+                .iter()
+                .map(|column| node.get(&column.path).cloned())
+                .collect();
+            self.rows.insert(path.to_vec(), values);
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn user(name: &str, age: i64) -> Value {
+        HashMap::from([
+            ("name".to_string(), Value::from(name)),
+            ("age".to_string(), Value::from(age)),
+        ])
+        .into()
+    }
+
+    fn upsert(key: &str, name: &str, age: i64) -> RowChange {
+        RowChange::Upsert {
+            key: key.to_string(),
+            values: vec![Some(Value::from(name)), Some(Value::from(age))],
+        }
+    }
+
+    #[test]
+    fn incremental() {
+        let mut value: Value =
+            HashMap::from([("users".to_string(), vec![user("ann", 31), user("bob", 42)])]).into();
+        let mut projection = Projection::new(
+            "users",
+            "users[*]".parse().unwrap(),
+            vec![Column::field("name"), Column::field("age")],
+        );
+        assert_eq!(
+            projection.load(&value),
+            vec![upsert("users[0]", "ann", 31), upsert("users[1]", "bob", 42)]
+        );
+
+        let changes = vec![ChangeContent::Replace {
+            path: vec![
+                PathElement::Field("users".to_string()),
+                PathElement::Index(1),
+                PathElement::Field("age".to_string()),
+            ]
+            .into(),
+            old: Value::from(42),
+            new: Value::from(43),
+        }];
+        value.apply_iter(&changes).unwrap();
+        assert_eq!(
+            projection.update(&value, &changes),
+            vec![upsert("users[1]", "bob", 43)]
+        );
+
+        let changes = vec![ChangeContent::Delete {
+            path: vec![
+                PathElement::Field("users".to_string()),
+                PathElement::Index(0),
+            ]
+            .into(),
+            old: user("ann", 31),
+        }];
+        value.apply_iter(&changes).unwrap();
+        assert_eq!(
+            projection.update(&value, &changes),
+            vec![
+                RowChange::Delete {
+                    key: "users[1]".to_string()
+                },
+                upsert("users[0]", "bob", 43),
+            ]
+        );
+        assert_eq!(projection.len(), 1);
+    }
+
+    #[test]
+    fn relative_positions() {
+        let mut value: Value =
+            HashMap::from([("users".to_string(), vec![user("ann", 31), user("bob", 42)])]).into();
+        let mut projection = Projection::new(
+            "users",
+            "users[*]".parse().unwrap(),
+            vec![Column::field("name"), Column::field("age")],
+        );
+        projection.load(&value);
+
+        let changes = vec![
+            ChangeContent::Replace {
+                path: vec![
+                    PathElement::Field("users".to_string()),
+                    PathElement::FromEnd(0),
+                    PathElement::Field("age".to_string()),
+                ]
+                .into(),
+                old: Value::from(42),
+                new: Value::from(43),
+            },
+            ChangeContent::Insert {
+                path: vec![PathElement::Field("users".to_string()), PathElement::Append].into(),
+                value: user("cid", 7),
+            },
+        ];
+        value.apply_iter(&changes).unwrap();
+        assert_eq!(
+            projection.update(&value, &changes),
+            vec![upsert("users[1]", "bob", 43), upsert("users[2]", "cid", 7)]
+        );
+        assert_eq!(projection.len(), 3);
+    }
+}
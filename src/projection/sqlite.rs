@@ -0,0 +1,86 @@
+use sqlx::SqlitePool;
+
+use super::{Projection, RowChange};
+use crate::{types::Value, Result};
+
+/// column holding the row key, see super::RowChange
+pub const KEY_COLUMN: &str = "_key";
+
+fn quote(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+/// creates the table of projection if it doesn't exist yet, columns are left untyped
+pub async fn create_table(pool: &SqlitePool, projection: &Projection) -> Result<()> {
+    let columns: Vec<_> = projection
+        .columns()
+        .iter()
+        .map(|column| quote(&column.name))
+        .collect();
+    let sql = format!(
+        "CREATE TABLE IF NOT EXISTS {} ({} TEXT PRIMARY KEY NOT NULL{}{})",
+        quote(projection.table()),
+        quote(KEY_COLUMN),
+        if columns.is_empty() { "" } else { ", " },
+        columns.join(", ")
+    );
+    sqlx::query(&sql).execute(pool).await?;
+    Ok(())
+}
+
+/**
+ *  writes row changes of projection in a single transaction.
+ *  scalars are stored with their sqlite type, arrays and maps cbor encoded.
+ *  */
+pub async fn apply(
+    pool: &SqlitePool,
+    projection: &Projection,
+    changes: &[RowChange],
+) -> Result<()> {
+    let table = quote(projection.table());
+    let mut names = vec![quote(KEY_COLUMN)];
+    names.extend(
+        projection
+            .columns()
+            .iter()
+            .map(|column| quote(&column.name)),
+    );
+    let upsert = format!(
+        "INSERT OR REPLACE INTO {table} ({}) VALUES ({})",
+        names.join(", "),
+        vec!["?"; names.len()].join(", ")
+    );
+    let delete = format!("DELETE FROM {table} WHERE {} = ?", quote(KEY_COLUMN));
+    let mut trans = pool.begin().await?;
+    for change in changes {
+        match change {
+            RowChange::Upsert { key, values } => {
+                let mut query = sqlx::query(&upsert).bind(key);
+                for value in values {
+                    query = match value {
+                        None => query.bind(None::<i64>),
+                        Some(Value::Integer(v)) => query.bind(*v),
+                        Some(Value::Float(v)) => query.bind(*v),
+                        Some(Value::Bool(v)) => query.bind(*v),
+                        Some(Value::String(v)) => query.bind(v.to_string()),
+                        Some(Value::Blob(v)) => query.bind(v.data.clone()),
+                        Some(value) => {
+                            let mut content = Vec::new();
+                            ciborium::into_writer(value, &mut content)?;
+                            query.bind(content)
+                        }
+                    };
+                }
+                query.execute(trans.as_mut()).await?;
+            }
+            RowChange::Delete { key } => {
+                sqlx::query(&delete)
+                    .bind(key)
+                    .execute(trans.as_mut())
+                    .await?;
+            }
+        }
+    }
+    trans.commit().await?;
+    Ok(())
+}
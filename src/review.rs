@@ -0,0 +1,64 @@
+use std::collections::BTreeMap;
+
+use crate::types::Value;
+
+/// prefix of the change notes approving a proposal, followed by the reviewer
+pub const APPROVAL_PREFIX: &str = "approval.";
+
+/// note name recording the approval of reviewer
+pub fn approval_name(reviewer: &str) -> String {
+    format!("{APPROVAL_PREFIX}{reviewer}")
+}
+
+/**
+ *  approvals a proposal needs before ValueStore::promote moves it onto its target.
+ *  reviewers approve by setting the note approval_name(reviewer) of the proposal head to true,
+ *  so a commit to the proposal needs to be approved again.
+ *  */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ApprovalPolicy {
+    pub approvals: usize,
+}
+
+impl Default for ApprovalPolicy {
+    fn default() -> Self {
+        Self { approvals: 1 }
+    }
+}
+
+impl ApprovalPolicy {
+    /// reviewers approving a change with notes, ordered by name
+    pub fn approvers(notes: &BTreeMap<String, Value>) -> Vec<&str> {
+        notes
+            .iter()
+            .filter(|(_, value)| **value == Value::Bool(true))
+            .filter_map(|(name, _)| name.strip_prefix(APPROVAL_PREFIX))
+            .collect()
+    }
+
+    pub fn is_satisfied(&self, notes: &BTreeMap<String, Value>) -> bool {
+        Self::approvers(notes).len() >= self.approvals
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::{approval_name, ApprovalPolicy};
+    use crate::types::Value;
+
+    #[test]
+    fn approvals() {
+        let notes = BTreeMap::from([
+            (approval_name("bob"), Value::Bool(true)),
+            (approval_name("alice"), Value::Bool(true)),
+            (approval_name("carol"), Value::Bool(false)),
+            ("label".to_string(), Value::Bool(true)),
+        ]);
+        assert_eq!(ApprovalPolicy::approvers(&notes), vec!["alice", "bob"]);
+        assert!(ApprovalPolicy { approvals: 2 }.is_satisfied(&notes));
+        assert!(!ApprovalPolicy { approvals: 3 }.is_satisfied(&notes));
+        assert!(!ApprovalPolicy::default().is_satisfied(&BTreeMap::new()));
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    collections::BTreeMap,
+    ops::Bound::{Excluded, Unbounded},
+};
+
+use crate::{
+    apply::diff::diff,
+    export::redact::Redaction,
+    types::{change::ChangeContent, Value},
+    Result,
+};
+
+/// transforms a value of the previous schema version into the next one
+pub type Migration = fn(&mut Value) -> Result<()>;
+
+/**
+ *  outcome of Migrations::run. ValueStore::migrate commits the changes like any other
+ *  change set, with the version stored as Config::SCHEMA_VERSION in the same commit.
+ *  */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Migrated {
+    /// schema version after applying changes
+    pub version: u32,
+    pub changes: Vec<ChangeContent>,
+}
+
+/**
+ *  ordered set of data migrations of a repo.
+ *  version 0 is the value before any migration, migration n turns version n - 1 into n.
+ *  */
+#[derive(Debug, Default, Clone)]
+pub struct Migrations {
+    steps: BTreeMap<u32, Migration>,
+}
+
+impl Migrations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// panics if version is 0 or already registered
+    pub fn register(mut self, version: u32, migration: Migration) -> Self {
+        assert!(version > 0, "migration versions start at 1");
+        assert!(
+            self.steps.insert(version, migration).is_none(),
+            "migration {version} registered twice"
+        );
+        self
+    }
+
+    pub fn latest(&self) -> u32 {
+        self.steps.keys().next_back().copied().unwrap_or(0)
+    }
+
+    pub fn pending(&self, current: u32) -> impl Iterator<Item = u32> + '_ {
+        self.steps
+            .range((Excluded(current), Unbounded))
+            .map(|(version, _)| *version)
+    }
+
+    /**
+     *  applies all migrations after version current to value.
+     *  returns None if value is up to date, otherwise the changes equivalent to the migrations.
+     *  */
+    pub fn run(&self, value: &Value, current: u32) -> Result<Option<Migrated>> {
+        let mut migrated = value.clone();
+        let mut version = current;
+        for (step, migration) in self.steps.range((Excluded(current), Unbounded)) {
+            migration(&mut migrated)?;
+            version = *step;
+        }
+        if version == current {
+            return Ok(None);
+        }
+        Ok(Some(Migrated {
+            version,
+            changes: diff(value, &migrated, &Redaction::new()),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, sync::Arc};
+
+    use super::*;
+    use crate::types::PathElement;
+
+    fn rename_name(value: &mut Value) -> Result<()> {
+        if let Value::Map(map) = value {
+            let map = Arc::make_mut(map);
+            if let Some(name) = map.remove("name") {
+                map.insert("full_name".to_string(), name);
+            }
+        }
+        Ok(())
+    }
+
+    fn add_tags(value: &mut Value) -> Result<()> {
+        if let Value::Map(map) = value {
+            Arc::make_mut(map).insert("tags".to_string(), Value::Array(Arc::new(Vec::new())));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn run_pending() {
+        let migrations = Migrations::new()
+            .register(2, add_tags)
+            .register(1, rename_name);
+        assert_eq!(migrations.latest(), 2);
+        assert_eq!(migrations.pending(1).collect::<Vec<_>>(), vec![2]);
+        let value: Value = HashMap::from([("name".to_string(), "ann")]).into();
+
+        let migrated = migrations.run(&value, 0).unwrap().unwrap();
+        assert_eq!(migrated.version, 2);
+        let mut res = value.clone();
+        res.apply_iter(&migrated.changes).unwrap();
+        assert_eq!(
+            res.get(&[PathElement::Field("full_name".to_string())]),
+            Some(&Value::from("ann"))
+        );
+        assert!(res.get(&[PathElement::Field("tags".to_string())]).is_some());
+
+        let migrated = migrations.run(&value, 1).unwrap().unwrap();
+        assert_eq!(migrated.changes.len(), 1);
+        assert_eq!(migrations.run(&res, 2).unwrap(), None);
+        assert_eq!(migrations.pending(u32::MAX).count(), 0);
+        assert_eq!(migrations.run(&res, u32::MAX).unwrap(), None);
+    }
+}
@@ -0,0 +1,367 @@
+use std::{
+    future::Future,
+    hash::Hash as StdHash,
+    mem::size_of,
+    sync::{Arc, Mutex},
+};
+
+use lru::LruCache;
+use uuid::Uuid;
+
+use super::{Commit, ConfigEntry, Note, RepoStats, Storage};
+use crate::{
+    apply::split::SplitMarker,
+    async_support::{MaybeSend, MaybeSync},
+    types::{change::Hash, hasher::HashAlgorithm, PathElement},
+    Result,
+};
+
+/// bookkeeping of the lru list and the key counted for every entry in addition to its value
+const ENTRY_OVERHEAD: usize = 64;
+
+/// lru cache evicting entries once the sum of their sizes exceeds max_bytes
+struct Cache<K, V> {
+    entries: LruCache<K, V>,
+    bytes: usize,
+    max_bytes: usize,
+    size: fn(&V) -> usize,
+}
+
+impl<K: StdHash + Eq, V> Cache<K, V> {
+    fn new(max_bytes: usize, size: fn(&V) -> usize) -> Self {
+        Self {
+            entries: LruCache::unbounded(),
+            bytes: 0,
+            max_bytes,
+            size,
+        }
+    }
+
+    fn put(&mut self, key: K, value: V) {
+        let size = (self.size)(&value) + ENTRY_OVERHEAD;
+        if size > self.max_bytes {
+            return;
+        }
+        self.bytes += size;
+        if let Some(old) = self.entries.put(key, value) {
+            self.bytes -= (self.size)(&old) + ENTRY_OVERHEAD;
+        }
+        while self.bytes > self.max_bytes {
+            let Some((_, evicted)) = self.entries.pop_lru() else {
+                break;
+            };
+            self.bytes -= (self.size)(&evicted) + ENTRY_OVERHEAD;
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.bytes = 0;
+    }
+}
+
+/// cache of values shared with the callers
+type SharedCache<K, V> = Mutex<Cache<K, Arc<V>>>;
+
+/**
+ *  keeps the content and parents of recently read changes of inner in memory.
+ *  both never change once a change is stored, so entries are never invalidated.
+ *  all other operations are passed through.
+ *  */
+pub struct CachedStorage<S: Storage> {
+    inner: S,
+    contents: SharedCache<S::ChangeId, [u8]>,
+    rels: SharedCache<S::ChangeId, [S::ChangeId]>,
+}
+
+/// value of key from cache, otherwise loaded with load and added to cache
+async fn cached<K, V, F>(cache: &Mutex<Cache<K, V>>, key: K, load: F) -> Result<V>
+where
+    K: StdHash + Eq,
+    V: Clone,
+    F: Future<Output = Result<V>>,
+{
+    if let Some(value) = cache.lock().expect("cache poisoned").entries.get(&key) {
+        return Ok(value.clone());
+    }
+    let value = load.await?;
+    cache
+        .lock()
+        .expect("cache poisoned")
+        .put(key, value.clone());
+    Ok(value)
+}
+
+impl<S> CachedStorage<S>
+where
+    S: Storage,
+    S::ChangeId: StdHash + Eq,
+{
+    /**
+     *  caches change contents up to max_bytes and parent lists up to another max_bytes.
+     *  contents are cached encoded, so their length is their exact size.
+     *  */
+    pub fn new(inner: S, max_bytes: usize) -> Self {
+        Self {
+            inner,
+            contents: Mutex::new(Cache::new(max_bytes, |content| content.len())),
+            rels: Mutex::new(Cache::new(max_bytes, |rels| {
+                rels.len() * size_of::<S::ChangeId>()
+            })),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn clear(&self) {
+        self.contents.lock().expect("cache poisoned").clear();
+        self.rels.lock().expect("cache poisoned").clear();
+    }
+
+    /// bytes currently held by the content cache, including the per entry overhead
+    pub fn cached_bytes(&self) -> usize {
+        self.contents.lock().expect("cache poisoned").bytes
+    }
+}
+
+impl<S> CachedStorage<S>
+where
+    S: Storage,
+    S::ChangeId: StdHash + Eq + Clone,
+{
+    /// content of change shared with the cache, saving the copy of get_change_content
+    pub async fn change_content(&self, id: S::ChangeId) -> Result<Arc<[u8]>> {
+        cached(&self.contents, id.clone(), async {
+            Ok(self.inner.get_change_content(id).await?.into())
+        })
+        .await
+    }
+}
+
+impl<S> Storage for CachedStorage<S>
+where
+    S: Storage + MaybeSync,
+    S::ChangeId: StdHash + Eq + Clone + MaybeSend + MaybeSync,
+    S::BranchId: MaybeSend,
+    S::RepoId: MaybeSend,
+{
+    type ChangeId = S::ChangeId;
+    type BranchId = S::BranchId;
+    type RepoId = S::RepoId;
+
+    async fn add_change(
+        &self,
+        hash: &Hash,
+        algorithm: HashAlgorithm,
+        content: &[u8],
+        parents: &[Hash],
+    ) -> Result<Self::ChangeId> {
+        self.inner
+            .add_change(hash, algorithm, content, parents)
+            .await
+    }
+
+    async fn commit(
+        &self,
+        repo: Self::RepoId,
+        branch: Self::BranchId,
+        commit: Commit<'_>,
+    ) -> Result<(Self::ChangeId, u64)> {
+        self.inner.commit(repo, branch, commit).await
+    }
+
+    async fn get_change_id(&self, hash: Hash) -> Result<Option<Self::ChangeId>> {
+        self.inner.get_change_id(hash).await
+    }
+
+    async fn get_change_rels(&self, id: Self::ChangeId) -> Result<Vec<Self::ChangeId>> {
+        let rels = cached(&self.rels, id.clone(), async {
+            Ok(self.inner.get_change_rels(id).await?.into())
+        })
+        .await?;
+        Ok(rels.to_vec())
+    }
+
+    async fn get_change_content(&self, id: Self::ChangeId) -> Result<Vec<u8>> {
+        Ok(self.change_content(id).await?.to_vec())
+    }
+
+    async fn list_changes(&self, after: u64, limit: usize) -> Result<Vec<(u64, Self::ChangeId)>> {
+        self.inner.list_changes(after, limit).await
+    }
+
+    async fn get_change_hash(&self, id: Self::ChangeId) -> Result<(Hash, HashAlgorithm)> {
+        self.inner.get_change_hash(id).await
+    }
+
+    async fn changes_touching(
+        &self,
+        repo: Self::RepoId,
+        prefix: &[PathElement],
+    ) -> Result<Vec<Self::ChangeId>> {
+        self.inner.changes_touching(repo, prefix).await
+    }
+
+    async fn repo_stats(&self, repo: Self::RepoId) -> Result<RepoStats> {
+        self.inner.repo_stats(repo).await
+    }
+
+    async fn set_note(
+        &self,
+        change: Self::ChangeId,
+        name: &str,
+        content: Option<&[u8]>,
+    ) -> Result<()> {
+        self.inner.set_note(change, name, content).await
+    }
+
+    async fn get_notes(&self, change: Self::ChangeId) -> Result<Vec<Note>> {
+        self.inner.get_notes(change).await
+    }
+
+    async fn get_repo_id(&self, uuid: Uuid) -> Result<Option<Self::RepoId>> {
+        self.inner.get_repo_id(uuid).await
+    }
+
+    async fn create_repo(&self, uuid: Uuid) -> Result<Self::RepoId> {
+        self.inner.create_repo(uuid).await
+    }
+
+    async fn get_branches(&self, repo: Self::RepoId) -> Result<Vec<(Uuid, Self::BranchId)>> {
+        self.inner.get_branches(repo).await
+    }
+
+    async fn get_branch_id(
+        &self,
+        repo: Self::RepoId,
+        uuid: Uuid,
+    ) -> Result<Option<Self::BranchId>> {
+        self.inner.get_branch_id(repo, uuid).await
+    }
+
+    async fn get_branch_head(&self, branch: Self::BranchId) -> Result<Self::ChangeId> {
+        self.inner.get_branch_head(branch).await
+    }
+
+    async fn set_branch_head(
+        &self,
+        repo: Self::RepoId,
+        uuid: Uuid,
+        head: Self::ChangeId,
+    ) -> Result<Self::BranchId> {
+        self.inner.set_branch_head(repo, uuid, head).await
+    }
+
+    async fn set_repo_config(
+        &self,
+        repo: Self::RepoId,
+        name: &str,
+        content: Option<&[u8]>,
+    ) -> Result<()> {
+        self.inner.set_repo_config(repo, name, content).await
+    }
+
+    async fn set_branch_config(
+        &self,
+        branch: Self::BranchId,
+        name: &str,
+        content: Option<&[u8]>,
+    ) -> Result<()> {
+        self.inner.set_branch_config(branch, name, content).await
+    }
+
+    async fn get_repo_config(&self, repo: Self::RepoId) -> Result<Vec<ConfigEntry>> {
+        self.inner.get_repo_config(repo).await
+    }
+
+    async fn get_branch_config(&self, branch: Self::BranchId) -> Result<Vec<ConfigEntry>> {
+        self.inner.get_branch_config(branch).await
+    }
+
+    async fn set_branch_config_if(
+        &self,
+        branch: Self::BranchId,
+        prefix: &str,
+        expected: &[ConfigEntry],
+        entries: &[(String, Option<Vec<u8>>)],
+    ) -> Result<bool> {
+        self.inner
+            .set_branch_config_if(branch, prefix, expected, entries)
+            .await
+    }
+
+    async fn insert_event(
+        &self,
+        repo: Self::RepoId,
+        seq: u64,
+        change: Self::ChangeId,
+    ) -> Result<()> {
+        self.inner.insert_event(repo, seq, change).await
+    }
+
+    async fn get_group(
+        &self,
+        repo: Self::RepoId,
+        group: Uuid,
+    ) -> Result<Vec<(SplitMarker, Self::ChangeId)>> {
+        self.inner.get_group(repo, group).await
+    }
+
+    async fn changes_since(
+        &self,
+        repo: Self::RepoId,
+        seq: u64,
+    ) -> Result<Vec<(u64, Self::ChangeId)>> {
+        self.inner.changes_since(repo, seq).await
+    }
+
+    async fn close(&self) -> Result<()> {
+        self.inner.close().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use futures_util::FutureExt;
+
+    use super::*;
+
+    #[test]
+    fn lru() {
+        let cache = Mutex::new(Cache::new(2 * (ENTRY_OVERHEAD + 10), |v: &Arc<[u8]>| {
+            v.len()
+        }));
+        let loads = &AtomicU32::new(0);
+        let load = |key: u8, len: usize| async move {
+            loads.fetch_add(1, Ordering::Relaxed);
+            Ok(Arc::from(vec![key; len]))
+        };
+        let get = |key, len| {
+            cached(&cache, key, load(key, len))
+                .now_or_never()
+                .unwrap()
+                .unwrap()
+        };
+        assert_eq!(&*get(1, 10), &[1; 10]);
+        assert_eq!(&*get(1, 10), &[1; 10]);
+        assert_eq!(loads.load(Ordering::Relaxed), 1);
+        get(2, 10);
+        get(1, 10);
+        assert_eq!(loads.load(Ordering::Relaxed), 2);
+        // no room for a third entry, so 2 as the least recently used one is evicted
+        get(3, 9);
+        assert_eq!(loads.load(Ordering::Relaxed), 3);
+        get(1, 10);
+        get(2, 10);
+        assert_eq!(loads.load(Ordering::Relaxed), 4);
+        assert!(cache.lock().unwrap().bytes <= 2 * (ENTRY_OVERHEAD + 10));
+        // larger than the whole budget, never cached
+        get(4, 1000);
+        get(4, 1000);
+        assert_eq!(loads.load(Ordering::Relaxed), 6);
+    }
+}
@@ -0,0 +1,492 @@
+use std::any::Any;
+
+use uuid::Uuid;
+
+use crate::{
+    apply::split::SplitMarker,
+    async_support::{BoxFuture, MaybeSend, MaybeSync},
+    error::ValueStoreError,
+    storage::{Commit, ConfigEntry, Note, RepoStats, Storage},
+    types::{change::Hash, hasher::HashAlgorithm, PathElement},
+    Result,
+};
+
+/// storage id with its concrete type erased
+pub trait AnyId: Any + MaybeSend + MaybeSync {
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T: Any + MaybeSend + MaybeSync> AnyId for T {
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+pub type DynId = Box<dyn AnyId>;
+
+fn downcast<T: Any>(id: DynId) -> Result<T> {
+    id.into_any()
+        .downcast()
+        .map(|id| *id)
+        .map_err(|_| ValueStoreError::ForeignStorageId.into())
+}
+
+/**
+ *  object safe version of Storage, so backends can be chosen at runtime.
+ *  implemented for every Storage, ids passed in have to originate from the same backend.
+ *  */
+pub trait DynStorage: MaybeSend + MaybeSync {
+    fn add_change<'a>(
+        &'a self,
+        hash: &'a Hash,
+        algorithm: HashAlgorithm,
+        content: &'a [u8],
+        parents: &'a [Hash],
+    ) -> BoxFuture<'a, Result<DynId>>;
+    fn commit<'a>(
+        &'a self,
+        repo: DynId,
+        branch: DynId,
+        commit: Commit<'a>,
+    ) -> BoxFuture<'a, Result<(DynId, u64)>>;
+    fn get_change_id(&self, hash: Hash) -> BoxFuture<'_, Result<Option<DynId>>>;
+    fn get_change_rels(&self, id: DynId) -> BoxFuture<'_, Result<Vec<DynId>>>;
+    fn get_change_content(&self, id: DynId) -> BoxFuture<'_, Result<Vec<u8>>>;
+    fn list_changes(&self, after: u64, limit: usize) -> BoxFuture<'_, Result<Vec<(u64, DynId)>>>;
+    fn get_change_hash(&self, id: DynId) -> BoxFuture<'_, Result<(Hash, HashAlgorithm)>>;
+    fn changes_touching<'a>(
+        &'a self,
+        repo: DynId,
+        prefix: &'a [PathElement],
+    ) -> BoxFuture<'a, Result<Vec<DynId>>>;
+    fn repo_stats(&self, repo: DynId) -> BoxFuture<'_, Result<RepoStats>>;
+    fn set_note<'a>(
+        &'a self,
+        change: DynId,
+        name: &'a str,
+        content: Option<&'a [u8]>,
+    ) -> BoxFuture<'a, Result<()>>;
+    fn get_notes(&self, change: DynId) -> BoxFuture<'_, Result<Vec<Note>>>;
+    fn get_repo_id(&self, uuid: Uuid) -> BoxFuture<'_, Result<Option<DynId>>>;
+    fn create_repo(&self, uuid: Uuid) -> BoxFuture<'_, Result<DynId>>;
+    fn get_branches(&self, repo: DynId) -> BoxFuture<'_, Result<Vec<(Uuid, DynId)>>>;
+    fn get_branch_id(&self, repo: DynId, uuid: Uuid) -> BoxFuture<'_, Result<Option<DynId>>>;
+    fn get_branch_head(&self, branch: DynId) -> BoxFuture<'_, Result<DynId>>;
+    fn set_branch_head(&self, repo: DynId, uuid: Uuid, head: DynId)
+        -> BoxFuture<'_, Result<DynId>>;
+    fn set_repo_config<'a>(
+        &'a self,
+        repo: DynId,
+        name: &'a str,
+        content: Option<&'a [u8]>,
+    ) -> BoxFuture<'a, Result<()>>;
+    fn set_branch_config<'a>(
+        &'a self,
+        branch: DynId,
+        name: &'a str,
+        content: Option<&'a [u8]>,
+    ) -> BoxFuture<'a, Result<()>>;
+    fn get_repo_config(&self, repo: DynId) -> BoxFuture<'_, Result<Vec<ConfigEntry>>>;
+    fn get_branch_config(&self, branch: DynId) -> BoxFuture<'_, Result<Vec<ConfigEntry>>>;
+    fn set_branch_config_if<'a>(
+        &'a self,
+        branch: DynId,
+        prefix: &'a str,
+        expected: &'a [ConfigEntry],
+        entries: &'a [(String, Option<Vec<u8>>)],
+    ) -> BoxFuture<'a, Result<bool>>;
+    fn insert_event(&self, repo: DynId, seq: u64, change: DynId) -> BoxFuture<'_, Result<()>>;
+    fn get_group(
+        &self,
+        repo: DynId,
+        group: Uuid,
+    ) -> BoxFuture<'_, Result<Vec<(SplitMarker, DynId)>>>;
+    fn changes_since(&self, repo: DynId, seq: u64) -> BoxFuture<'_, Result<Vec<(u64, DynId)>>>;
+    fn close(&self) -> BoxFuture<'_, Result<()>>;
+}
+
+impl<S> DynStorage for S
+where
+    S: Storage + MaybeSend + MaybeSync,
+    S::ChangeId: AnyId,
+    S::BranchId: AnyId,
+    S::RepoId: AnyId,
+{
+    fn add_change<'a>(
+        &'a self,
+        hash: &'a Hash,
+        algorithm: HashAlgorithm,
+        content: &'a [u8],
+        parents: &'a [Hash],
+    ) -> BoxFuture<'a, Result<DynId>> {
+        Box::pin(async move {
+            let id = Storage::add_change(self, hash, algorithm, content, parents).await?;
+            Ok(Box::new(id) as DynId)
+        })
+    }
+
+    fn commit<'a>(
+        &'a self,
+        repo: DynId,
+        branch: DynId,
+        commit: Commit<'a>,
+    ) -> BoxFuture<'a, Result<(DynId, u64)>> {
+        Box::pin(async move {
+            let (id, seq) =
+                Storage::commit(self, downcast(repo)?, downcast(branch)?, commit).await?;
+            Ok((Box::new(id) as DynId, seq))
+        })
+    }
+
+    fn get_change_id(&self, hash: Hash) -> BoxFuture<'_, Result<Option<DynId>>> {
+        Box::pin(async move {
+            let id = Storage::get_change_id(self, hash).await?;
+            Ok(id.map(|id| Box::new(id) as DynId))
+        })
+    }
+
+    fn get_change_rels(&self, id: DynId) -> BoxFuture<'_, Result<Vec<DynId>>> {
+        Box::pin(async move {
+            let rels = Storage::get_change_rels(self, downcast(id)?).await?;
+            Ok(rels.into_iter().map(|id| Box::new(id) as DynId).collect())
+        })
+    }
+
+    fn get_change_content(&self, id: DynId) -> BoxFuture<'_, Result<Vec<u8>>> {
+        Box::pin(async move { Storage::get_change_content(self, downcast(id)?).await })
+    }
+
+    fn list_changes(&self, after: u64, limit: usize) -> BoxFuture<'_, Result<Vec<(u64, DynId)>>> {
+        Box::pin(async move {
+            let changes = Storage::list_changes(self, after, limit).await?;
+            Ok(changes
+                .into_iter()
+                .map(|(position, id)| (position, Box::new(id) as DynId))
+                .collect())
+        })
+    }
+
+    fn get_change_hash(&self, id: DynId) -> BoxFuture<'_, Result<(Hash, HashAlgorithm)>> {
+        Box::pin(async move { Storage::get_change_hash(self, downcast(id)?).await })
+    }
+
+    fn changes_touching<'a>(
+        &'a self,
+        repo: DynId,
+        prefix: &'a [PathElement],
+    ) -> BoxFuture<'a, Result<Vec<DynId>>> {
+        Box::pin(async move {
+            let changes = Storage::changes_touching(self, downcast(repo)?, prefix).await?;
+            Ok(changes.into_iter().map(|id| Box::new(id) as DynId).collect())
+        })
+    }
+
+    fn repo_stats(&self, repo: DynId) -> BoxFuture<'_, Result<RepoStats>> {
+        Box::pin(async move { Storage::repo_stats(self, downcast(repo)?).await })
+    }
+
+    fn set_note<'a>(
+        &'a self,
+        change: DynId,
+        name: &'a str,
+        content: Option<&'a [u8]>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move { Storage::set_note(self, downcast(change)?, name, content).await })
+    }
+
+    fn get_notes(&self, change: DynId) -> BoxFuture<'_, Result<Vec<Note>>> {
+        Box::pin(async move { Storage::get_notes(self, downcast(change)?).await })
+    }
+
+    fn get_repo_id(&self, uuid: Uuid) -> BoxFuture<'_, Result<Option<DynId>>> {
+        Box::pin(async move {
+            let id = Storage::get_repo_id(self, uuid).await?;
+            Ok(id.map(|id| Box::new(id) as DynId))
+        })
+    }
+
+    fn create_repo(&self, uuid: Uuid) -> BoxFuture<'_, Result<DynId>> {
+        Box::pin(async move {
+            let id = Storage::create_repo(self, uuid).await?;
+            Ok(Box::new(id) as DynId)
+        })
+    }
+
+    fn get_branches(&self, repo: DynId) -> BoxFuture<'_, Result<Vec<(Uuid, DynId)>>> {
+        Box::pin(async move {
+            let branches = Storage::get_branches(self, downcast(repo)?).await?;
+            Ok(branches
+                .into_iter()
+                .map(|(uuid, id)| (uuid, Box::new(id) as DynId))
+                .collect())
+        })
+    }
+
+    fn get_branch_id(&self, repo: DynId, uuid: Uuid) -> BoxFuture<'_, Result<Option<DynId>>> {
+        Box::pin(async move {
+            let id = Storage::get_branch_id(self, downcast(repo)?, uuid).await?;
+            Ok(id.map(|id| Box::new(id) as DynId))
+        })
+    }
+
+    fn get_branch_head(&self, branch: DynId) -> BoxFuture<'_, Result<DynId>> {
+        Box::pin(async move {
+            let id = Storage::get_branch_head(self, downcast(branch)?).await?;
+            Ok(Box::new(id) as DynId)
+        })
+    }
+
+    fn set_branch_head(
+        &self,
+        repo: DynId,
+        uuid: Uuid,
+        head: DynId,
+    ) -> BoxFuture<'_, Result<DynId>> {
+        Box::pin(async move {
+            let id = Storage::set_branch_head(self, downcast(repo)?, uuid, downcast(head)?).await?;
+            Ok(Box::new(id) as DynId)
+        })
+    }
+
+    fn set_repo_config<'a>(
+        &'a self,
+        repo: DynId,
+        name: &'a str,
+        content: Option<&'a [u8]>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move { Storage::set_repo_config(self, downcast(repo)?, name, content).await })
+    }
+
+    fn set_branch_config<'a>(
+        &'a self,
+        branch: DynId,
+        name: &'a str,
+        content: Option<&'a [u8]>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            Storage::set_branch_config(self, downcast(branch)?, name, content).await
+        })
+    }
+
+    fn get_repo_config(&self, repo: DynId) -> BoxFuture<'_, Result<Vec<ConfigEntry>>> {
+        Box::pin(async move { Storage::get_repo_config(self, downcast(repo)?).await })
+    }
+
+    fn get_branch_config(&self, branch: DynId) -> BoxFuture<'_, Result<Vec<ConfigEntry>>> {
+        Box::pin(async move { Storage::get_branch_config(self, downcast(branch)?).await })
+    }
+
+    fn set_branch_config_if<'a>(
+        &'a self,
+        branch: DynId,
+        prefix: &'a str,
+        expected: &'a [ConfigEntry],
+        entries: &'a [(String, Option<Vec<u8>>)],
+    ) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move {
+            Storage::set_branch_config_if(self, downcast(branch)?, prefix, expected, entries).await
+        })
+    }
+
+    fn insert_event(&self, repo: DynId, seq: u64, change: DynId) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            Storage::insert_event(self, downcast(repo)?, seq, downcast(change)?).await
+        })
+    }
+
+    fn get_group(
+        &self,
+        repo: DynId,
+        group: Uuid,
+    ) -> BoxFuture<'_, Result<Vec<(SplitMarker, DynId)>>> {
+        Box::pin(async move {
+            let parts = Storage::get_group(self, downcast(repo)?, group).await?;
+            Ok(parts
+                .into_iter()
+                .map(|(marker, id)| (marker, Box::new(id) as DynId))
+                .collect())
+        })
+    }
+
+    fn changes_since(&self, repo: DynId, seq: u64) -> BoxFuture<'_, Result<Vec<(u64, DynId)>>> {
+        Box::pin(async move {
+            let changes = Storage::changes_since(self, downcast(repo)?, seq).await?;
+            Ok(changes
+                .into_iter()
+                .map(|(seq, id)| (seq, Box::new(id) as DynId))
+                .collect())
+        })
+    }
+
+    fn close(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(Storage::close(self))
+    }
+}
+
+impl Storage for dyn DynStorage {
+    type ChangeId = DynId;
+    type BranchId = DynId;
+    type RepoId = DynId;
+
+    async fn add_change(
+        &self,
+        hash: &Hash,
+        algorithm: HashAlgorithm,
+        content: &[u8],
+        parents: &[Hash],
+    ) -> Result<Self::ChangeId> {
+        DynStorage::add_change(self, hash, algorithm, content, parents).await
+    }
+
+    async fn commit(
+        &self,
+        repo: Self::RepoId,
+        branch: Self::BranchId,
+        commit: Commit<'_>,
+    ) -> Result<(Self::ChangeId, u64)> {
+        DynStorage::commit(self, repo, branch, commit).await
+    }
+
+    async fn get_change_id(&self, hash: Hash) -> Result<Option<Self::ChangeId>> {
+        DynStorage::get_change_id(self, hash).await
+    }
+
+    async fn get_change_rels(&self, id: Self::ChangeId) -> Result<Vec<Self::ChangeId>> {
+        DynStorage::get_change_rels(self, id).await
+    }
+
+    async fn get_change_content(&self, id: Self::ChangeId) -> Result<Vec<u8>> {
+        DynStorage::get_change_content(self, id).await
+    }
+
+    async fn list_changes(&self, after: u64, limit: usize) -> Result<Vec<(u64, Self::ChangeId)>> {
+        DynStorage::list_changes(self, after, limit).await
+    }
+
+    async fn get_change_hash(&self, id: Self::ChangeId) -> Result<(Hash, HashAlgorithm)> {
+        DynStorage::get_change_hash(self, id).await
+    }
+
+    async fn changes_touching(
+        &self,
+        repo: Self::RepoId,
+        prefix: &[PathElement],
+    ) -> Result<Vec<Self::ChangeId>> {
+        DynStorage::changes_touching(self, repo, prefix).await
+    }
+
+    async fn repo_stats(&self, repo: Self::RepoId) -> Result<RepoStats> {
+        DynStorage::repo_stats(self, repo).await
+    }
+
+    async fn set_note(
+        &self,
+        change: Self::ChangeId,
+        name: &str,
+        content: Option<&[u8]>,
+    ) -> Result<()> {
+        DynStorage::set_note(self, change, name, content).await
+    }
+
+    async fn get_notes(&self, change: Self::ChangeId) -> Result<Vec<Note>> {
+        DynStorage::get_notes(self, change).await
+    }
+
+    async fn get_repo_id(&self, uuid: Uuid) -> Result<Option<Self::RepoId>> {
+        DynStorage::get_repo_id(self, uuid).await
+    }
+
+    async fn create_repo(&self, uuid: Uuid) -> Result<Self::RepoId> {
+        DynStorage::create_repo(self, uuid).await
+    }
+
+    async fn get_branches(&self, repo: Self::RepoId) -> Result<Vec<(Uuid, Self::BranchId)>> {
+        DynStorage::get_branches(self, repo).await
+    }
+
+    async fn get_branch_id(
+        &self,
+        repo: Self::RepoId,
+        uuid: Uuid,
+    ) -> Result<Option<Self::BranchId>> {
+        DynStorage::get_branch_id(self, repo, uuid).await
+    }
+
+    async fn get_branch_head(&self, branch: Self::BranchId) -> Result<Self::ChangeId> {
+        DynStorage::get_branch_head(self, branch).await
+    }
+
+    async fn set_branch_head(
+        &self,
+        repo: Self::RepoId,
+        uuid: Uuid,
+        head: Self::ChangeId,
+    ) -> Result<Self::BranchId> {
+        DynStorage::set_branch_head(self, repo, uuid, head).await
+    }
+
+    async fn set_repo_config(
+        &self,
+        repo: Self::RepoId,
+        name: &str,
+        content: Option<&[u8]>,
+    ) -> Result<()> {
+        DynStorage::set_repo_config(self, repo, name, content).await
+    }
+
+    async fn set_branch_config(
+        &self,
+        branch: Self::BranchId,
+        name: &str,
+        content: Option<&[u8]>,
+    ) -> Result<()> {
+        DynStorage::set_branch_config(self, branch, name, content).await
+    }
+
+    async fn get_repo_config(&self, repo: Self::RepoId) -> Result<Vec<ConfigEntry>> {
+        DynStorage::get_repo_config(self, repo).await
+    }
+
+    async fn get_branch_config(&self, branch: Self::BranchId) -> Result<Vec<ConfigEntry>> {
+        DynStorage::get_branch_config(self, branch).await
+    }
+
+    async fn set_branch_config_if(
+        &self,
+        branch: Self::BranchId,
+        prefix: &str,
+        expected: &[ConfigEntry],
+        entries: &[(String, Option<Vec<u8>>)],
+    ) -> Result<bool> {
+        DynStorage::set_branch_config_if(self, branch, prefix, expected, entries).await
+    }
+
+    async fn insert_event(
+        &self,
+        repo: Self::RepoId,
+        seq: u64,
+        change: Self::ChangeId,
+    ) -> Result<()> {
+        DynStorage::insert_event(self, repo, seq, change).await
+    }
+
+    async fn get_group(
+        &self,
+        repo: Self::RepoId,
+        group: Uuid,
+    ) -> Result<Vec<(SplitMarker, Self::ChangeId)>> {
+        DynStorage::get_group(self, repo, group).await
+    }
+
+    async fn changes_since(
+        &self,
+        repo: Self::RepoId,
+        seq: u64,
+    ) -> Result<Vec<(u64, Self::ChangeId)>> {
+        DynStorage::changes_since(self, repo, seq).await
+    }
+
+    async fn close(&self) -> Result<()> {
+        DynStorage::close(self).await
+    }
+}
@@ -0,0 +1,433 @@
+use std::{
+    future::Future,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use uuid::Uuid;
+
+use super::{Commit, ConfigEntry, Note, RepoStats, Storage};
+use crate::{
+    apply::split::SplitMarker,
+    async_support::{MaybeSend, MaybeSync},
+    error::ValueStoreError,
+    types::{change::Hash, hasher::HashAlgorithm, PathElement},
+    Result,
+};
+
+/// when the failing operation reaches the inner storage
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultPoint {
+    /// the operation is not run, like a crash before sending it
+    Before,
+    /// the operation is run but reported as failed, like a crash before the answer arrived
+    After,
+}
+
+/**
+ *  simulates a crash of inner for crash consistency tests.
+ *  the first fail_after operations pass, every later one fails with
+ *  ValueStoreError::InjectedFault until the storage is recovered.
+ *  */
+#[derive(Debug)]
+pub struct FaultStorage<S> {
+    inner: S,
+    fail_after: AtomicU64,
+    point: FaultPoint,
+    operations: AtomicU64,
+}
+
+impl<S> FaultStorage<S> {
+    pub fn new(inner: S, fail_after: u64, point: FaultPoint) -> Self {
+        Self {
+            inner,
+            fail_after: AtomicU64::new(fail_after),
+            point,
+            operations: AtomicU64::new(0),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// operations attempted so far, including failed ones
+    pub fn operations(&self) -> u64 {
+        self.operations.load(Ordering::Relaxed)
+    }
+
+    /// lets all further operations pass
+    pub fn recover(&self) {
+        self.fail_after.store(u64::MAX, Ordering::Relaxed);
+    }
+
+    async fn run<T, F: Future<Output = Result<T>>>(
+        &self,
+        operation: &'static str,
+        inner: F,
+    ) -> Result<T> {
+        let count = self.operations.fetch_add(1, Ordering::Relaxed);
+        if count < self.fail_after.load(Ordering::Relaxed) {
+            return inner.await;
+        }
+        if self.point == FaultPoint::After {
+            // the outcome is lost, just as the caller would lose it
+            let _ = inner.await;
+        }
+        Err(ValueStoreError::InjectedFault { operation }.into())
+    }
+}
+
+impl<S> Storage for FaultStorage<S>
+where
+    S: Storage + MaybeSync,
+    S::ChangeId: MaybeSend,
+    S::BranchId: MaybeSend,
+    S::RepoId: MaybeSend,
+{
+    type ChangeId = S::ChangeId;
+    type BranchId = S::BranchId;
+    type RepoId = S::RepoId;
+
+    async fn add_change(
+        &self,
+        hash: &Hash,
+        algorithm: HashAlgorithm,
+        content: &[u8],
+        parents: &[Hash],
+    ) -> Result<Self::ChangeId> {
+        self.run(
+            "add_change",
+            self.inner.add_change(hash, algorithm, content, parents),
+        )
+        .await
+    }
+
+    async fn commit(
+        &self,
+        repo: Self::RepoId,
+        branch: Self::BranchId,
+        commit: Commit<'_>,
+    ) -> Result<(Self::ChangeId, u64)> {
+        self.run("commit", self.inner.commit(repo, branch, commit))
+            .await
+    }
+
+    async fn get_change_id(&self, hash: Hash) -> Result<Option<Self::ChangeId>> {
+        self.run("get_change_id", self.inner.get_change_id(hash))
+            .await
+    }
+
+    async fn get_change_rels(&self, id: Self::ChangeId) -> Result<Vec<Self::ChangeId>> {
+        self.run("get_change_rels", self.inner.get_change_rels(id))
+            .await
+    }
+
+    async fn get_change_content(&self, id: Self::ChangeId) -> Result<Vec<u8>> {
+        self.run("get_change_content", self.inner.get_change_content(id))
+            .await
+    }
+
+    async fn list_changes(&self, after: u64, limit: usize) -> Result<Vec<(u64, Self::ChangeId)>> {
+        self.run("list_changes", self.inner.list_changes(after, limit))
+            .await
+    }
+
+    async fn get_change_hash(&self, id: Self::ChangeId) -> Result<(Hash, HashAlgorithm)> {
+        self.run("get_change_hash", self.inner.get_change_hash(id))
+            .await
+    }
+
+    async fn changes_touching(
+        &self,
+        repo: Self::RepoId,
+        prefix: &[PathElement],
+    ) -> Result<Vec<Self::ChangeId>> {
+        self.run(
+            "changes_touching",
+            self.inner.changes_touching(repo, prefix),
+        )
+        .await
+    }
+
+    async fn repo_stats(&self, repo: Self::RepoId) -> Result<RepoStats> {
+        self.run("repo_stats", self.inner.repo_stats(repo)).await
+    }
+
+    async fn set_note(
+        &self,
+        change: Self::ChangeId,
+        name: &str,
+        content: Option<&[u8]>,
+    ) -> Result<()> {
+        self.run("set_note", self.inner.set_note(change, name, content))
+            .await
+    }
+
+    async fn get_notes(&self, change: Self::ChangeId) -> Result<Vec<Note>> {
+        self.run("get_notes", self.inner.get_notes(change)).await
+    }
+
+    async fn get_repo_id(&self, uuid: Uuid) -> Result<Option<Self::RepoId>> {
+        self.run("get_repo_id", self.inner.get_repo_id(uuid)).await
+    }
+
+    async fn create_repo(&self, uuid: Uuid) -> Result<Self::RepoId> {
+        self.run("create_repo", self.inner.create_repo(uuid)).await
+    }
+
+    async fn get_branches(&self, repo: Self::RepoId) -> Result<Vec<(Uuid, Self::BranchId)>> {
+        self.run("get_branches", self.inner.get_branches(repo))
+            .await
+    }
+
+    async fn get_branch_id(
+        &self,
+        repo: Self::RepoId,
+        uuid: Uuid,
+    ) -> Result<Option<Self::BranchId>> {
+        self.run("get_branch_id", self.inner.get_branch_id(repo, uuid))
+            .await
+    }
+
+    async fn get_branch_head(&self, branch: Self::BranchId) -> Result<Self::ChangeId> {
+        self.run("get_branch_head", self.inner.get_branch_head(branch))
+            .await
+    }
+
+    async fn set_branch_head(
+        &self,
+        repo: Self::RepoId,
+        uuid: Uuid,
+        head: Self::ChangeId,
+    ) -> Result<Self::BranchId> {
+        self.run(
+            "set_branch_head",
+            self.inner.set_branch_head(repo, uuid, head),
+        )
+        .await
+    }
+
+    async fn set_repo_config(
+        &self,
+        repo: Self::RepoId,
+        name: &str,
+        content: Option<&[u8]>,
+    ) -> Result<()> {
+        self.run(
+            "set_repo_config",
+            self.inner.set_repo_config(repo, name, content),
+        )
+        .await
+    }
+
+    async fn set_branch_config(
+        &self,
+        branch: Self::BranchId,
+        name: &str,
+        content: Option<&[u8]>,
+    ) -> Result<()> {
+        self.run(
+            "set_branch_config",
+            self.inner.set_branch_config(branch, name, content),
+        )
+        .await
+    }
+
+    async fn get_repo_config(&self, repo: Self::RepoId) -> Result<Vec<ConfigEntry>> {
+        self.run("get_repo_config", self.inner.get_repo_config(repo))
+            .await
+    }
+
+    async fn get_branch_config(&self, branch: Self::BranchId) -> Result<Vec<ConfigEntry>> {
+        self.run("get_branch_config", self.inner.get_branch_config(branch))
+            .await
+    }
+
+    async fn set_branch_config_if(
+        &self,
+        branch: Self::BranchId,
+        prefix: &str,
+        expected: &[ConfigEntry],
+        entries: &[(String, Option<Vec<u8>>)],
+    ) -> Result<bool> {
+        self.run(
+            "set_branch_config_if",
+            self.inner
+                .set_branch_config_if(branch, prefix, expected, entries),
+        )
+        .await
+    }
+
+    async fn insert_event(
+        &self,
+        repo: Self::RepoId,
+        seq: u64,
+        change: Self::ChangeId,
+    ) -> Result<()> {
+        self.run("insert_event", self.inner.insert_event(repo, seq, change))
+            .await
+    }
+
+    async fn get_group(
+        &self,
+        repo: Self::RepoId,
+        group: Uuid,
+    ) -> Result<Vec<(SplitMarker, Self::ChangeId)>> {
+        self.run("get_group", self.inner.get_group(repo, group))
+            .await
+    }
+
+    async fn changes_since(
+        &self,
+        repo: Self::RepoId,
+        seq: u64,
+    ) -> Result<Vec<(u64, Self::ChangeId)>> {
+        self.run("changes_since", self.inner.changes_since(repo, seq))
+            .await
+    }
+
+    async fn close(&self) -> Result<()> {
+        self.run("close", self.inner.close()).await
+    }
+}
+
+#[cfg(all(test, feature = "db_redb"))]
+mod test {
+    use futures_util::FutureExt;
+
+    use super::*;
+    use crate::{
+        storage::{migrate::migrate, redb::RedbStorage},
+        types::{change::ChangeContent, Value},
+        util::test_util::redb_storage,
+    };
+
+    fn storage() -> RedbStorage {
+        let storage = redb_storage();
+        storage.create_repo(Uuid::nil()).unwrap();
+        storage
+    }
+
+    /// linear history of n commits with a note on every change
+    fn source(n: u8) -> RedbStorage {
+        let storage = storage();
+        let repo = storage.create_repo(Uuid::nil()).unwrap();
+        for i in 1..=n {
+            let content = vec![ChangeContent::Insert {
+                path: vec![PathElement::Field(i.to_string())].into(),
+                value: Value::Integer(i.into()),
+            }];
+            let mut buf = Vec::new();
+            ciborium::into_writer(&content, &mut buf).unwrap();
+            let parents = if i > 1 {
+                vec![Hash([i - 1; 32])]
+            } else {
+                vec![]
+            };
+            let id = storage
+                .add_change(&Hash([i; 32]), HashAlgorithm::Blake3, &buf, &parents)
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+            storage
+                .set_note(id, "n", Some(&[i]))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+            storage
+                .insert_event(repo, i.into(), id)
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+        storage
+    }
+
+    /// every stored change has all its parents and the commit log has no duplicates
+    fn assert_consistent(storage: &RedbStorage, n: u8) {
+        let repo = storage
+            .get_repo_id(Uuid::nil())
+            .now_or_never()
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let mut logged = Vec::new();
+        for (_, id) in storage
+            .changes_since(repo, 0)
+            .now_or_never()
+            .unwrap()
+            .unwrap()
+        {
+            logged.push(id);
+        }
+        let mut deduped = logged.clone();
+        deduped.sort();
+        deduped.dedup();
+        assert_eq!(deduped.len(), logged.len(), "duplicate commits in log");
+        for i in 1..=n {
+            let Some(id) = storage
+                .get_change_id(Hash([i; 32]))
+                .now_or_never()
+                .unwrap()
+                .unwrap()
+            else {
+                continue;
+            };
+            let parents = storage.get_change_rels(id).now_or_never().unwrap().unwrap();
+            assert_eq!(
+                parents.len(),
+                if i > 1 { 1 } else { 0 },
+                "dangling change {i}"
+            );
+        }
+    }
+
+    #[test]
+    fn interrupted_migration() {
+        let from = source(2);
+        for point in [FaultPoint::Before, FaultPoint::After] {
+            let mut fail_after = 0;
+            loop {
+                let to = FaultStorage::new(storage(), fail_after, point);
+                let res = migrate(&from, &to, &[Uuid::nil()]).now_or_never().unwrap();
+                assert_consistent(to.inner(), 2);
+                to.recover();
+                migrate(&from, &to, &[Uuid::nil()])
+                    .now_or_never()
+                    .unwrap()
+                    .unwrap();
+                assert_consistent(to.inner(), 2);
+                for i in 1..=2 {
+                    let id = to
+                        .get_change_id(Hash([i; 32]))
+                        .now_or_never()
+                        .unwrap()
+                        .unwrap()
+                        .unwrap();
+                    assert_eq!(
+                        to.get_notes(id).now_or_never().unwrap().unwrap(),
+                        vec![("n".to_string(), vec![i])]
+                    );
+                }
+                let repo = to
+                    .get_repo_id(Uuid::nil())
+                    .now_or_never()
+                    .unwrap()
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(
+                    to.changes_since(repo, 0)
+                        .now_or_never()
+                        .unwrap()
+                        .unwrap()
+                        .len(),
+                    2
+                );
+                if res.is_ok() {
+                    break;
+                }
+                fail_after += 1;
+            }
+        }
+    }
+}
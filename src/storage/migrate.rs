@@ -0,0 +1,319 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{ConfigEntry, Storage};
+use crate::{error::ValueStoreError, Result};
+
+/// repo config entry of the target holding the progress of the migration of the repo
+pub const MIGRATION_CURSOR: &str = "migration.cursor";
+/// changes and commits copied between two updates of the cursor
+const BATCH: usize = 256;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct MigrationReport {
+    /// changes copied, changes already present in the target are not counted
+    pub changes: u64,
+    pub notes: u64,
+    /// repo and branch config entries that were missing or differed in the target
+    pub config_entries: u64,
+    pub events: u64,
+    /// branches created or moved in the target
+    pub branches: u64,
+}
+
+/// position in the changes and the commit log of the source up to which a repo is copied
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+struct Cursor {
+    changes: u64,
+    events: u64,
+}
+
+async fn read_cursor<T: Storage>(to: &T, repo: T::RepoId) -> Result<Cursor> {
+    match to
+        .get_repo_config(repo)
+        .await?
+        .into_iter()
+        .find(|(name, _)| name == MIGRATION_CURSOR)
+    {
+        Some((_, content)) => Ok(ciborium::from_reader(content.as_slice())?),
+        None => Ok(Cursor::default()),
+    }
+}
+
+async fn write_cursor<T: Storage>(to: &T, repo: T::RepoId, cursor: Cursor) -> Result<()> {
+    let mut content = Vec::new();
+    ciborium::into_writer(&cursor, &mut content)?;
+    to.set_repo_config(repo, MIGRATION_CURSOR, Some(&content))
+        .await
+}
+
+/// entries of from that are missing or differ in existing, without the migration cursor
+fn changed_entries(from: Vec<ConfigEntry>, existing: &[ConfigEntry]) -> Vec<ConfigEntry> {
+    from.into_iter()
+        .filter(|entry| entry.0 != MIGRATION_CURSOR && !existing.contains(entry))
+        .collect()
+}
+
+/**
+ *  copies the change id of from into to unless it exists, together with its notes.
+ *  parents have to be copied before, notes are compared for changes that exist to complete
+ *  a copy interrupted after the change was written.
+ *  */
+async fn copy_change<F, T>(
+    from: &F,
+    to: &T,
+    id: F::ChangeId,
+    report: &mut MigrationReport,
+) -> Result<()>
+where
+    F: Storage,
+    F::ChangeId: Clone,
+    T: Storage,
+    T::ChangeId: Clone,
+{
+    let (hash, algorithm) = from.get_change_hash(id.clone()).await?;
+    let target = match to.get_change_id(hash).await? {
+        Some(target) => target,
+        None => {
+            let mut parents = Vec::new();
+            for parent in from.get_change_rels(id.clone()).await? {
+                parents.push(from.get_change_hash(parent).await?.0);
+            }
+            let content = from.get_change_content(id.clone()).await?;
+            let target = to.add_change(&hash, algorithm, &content, &parents).await?;
+            // read back, so a broken target fails here and not on first use
+            if to.get_change_content(target.clone()).await? != content {
+                return Err(ValueStoreError::CorruptHistory { change: Some(hash) }.into());
+            }
+            report.changes += 1;
+            target
+        }
+    };
+    let existing = to.get_notes(target.clone()).await?;
+    for (name, content) in changed_entries(from.get_notes(id).await?, &existing) {
+        to.set_note(target.clone(), &name, Some(&content)).await?;
+        report.notes += 1;
+    }
+    Ok(())
+}
+
+/// id in to of the change id of from
+async fn target_change<F: Storage, T: Storage>(
+    from: &F,
+    to: &T,
+    id: F::ChangeId,
+) -> Result<T::ChangeId> {
+    let (hash, _) = from.get_change_hash(id).await?;
+    Ok(to
+        .get_change_id(hash)
+        .await?
+        .ok_or(ValueStoreError::UnknownChange { hash })?)
+}
+
+/**
+ *  copies repos from one backend into another, creating them in the target if needed.
+ *  all stored changes are copied with their notes, followed by the commit log, the repo
+ *  config and the branches with their heads and config. the progress is kept in the
+ *  MIGRATION_CURSOR config entry of the target repo, so an aborted migration can be rerun and
+ *  continues where it stopped. changes are shared by all repos of a storage, for every repo
+ *  after the first they are only compared. split group markers are not copied.
+ *  */
+pub async fn migrate<F, T>(from: &F, to: &T, repos: &[Uuid]) -> Result<MigrationReport>
+where
+    F: Storage,
+    F::ChangeId: Clone,
+    F::RepoId: Clone,
+    F::BranchId: Clone,
+    T: Storage,
+    T::ChangeId: Clone,
+    T::RepoId: Clone,
+    T::BranchId: Clone,
+{
+    let mut report = MigrationReport::default();
+    for uuid in repos {
+        let source = from
+            .get_repo_id(*uuid)
+            .await?
+            .ok_or(ValueStoreError::UnknownRepo { uuid: *uuid })?;
+        let target = to.create_repo(*uuid).await?;
+        let mut cursor = read_cursor(to, target.clone()).await?;
+
+        loop {
+            let changes = from.list_changes(cursor.changes, BATCH).await?;
+            let Some((last, _)) = changes.last() else {
+                break;
+            };
+            cursor.changes = *last;
+            for (_, change) in changes {
+                copy_change(from, to, change, &mut report).await?;
+            }
+            write_cursor(to, target.clone(), cursor).await?;
+        }
+
+        let events = from.changes_since(source.clone(), cursor.events).await?;
+        for events in events.chunks(BATCH) {
+            for (seq, change) in events {
+                let change = target_change(from, to, change.clone()).await?;
+                to.insert_event(target.clone(), *seq, change).await?;
+                report.events += 1;
+                cursor.events = *seq;
+            }
+            write_cursor(to, target.clone(), cursor).await?;
+        }
+
+        let existing = to.get_repo_config(target.clone()).await?;
+        for (name, content) in
+            changed_entries(from.get_repo_config(source.clone()).await?, &existing)
+        {
+            to.set_repo_config(target.clone(), &name, Some(&content))
+                .await?;
+            report.config_entries += 1;
+        }
+
+        let existing = to.get_branches(target.clone()).await?;
+        for (uuid, branch) in from.get_branches(source.clone()).await? {
+            let config = from.get_branch_config(branch.clone()).await?;
+            let (hash, _) = from
+                .get_change_hash(from.get_branch_head(branch).await?)
+                .await?;
+            let current = match existing.iter().find(|(id, _)| *id == uuid) {
+                Some((_, branch)) => {
+                    let head = to.get_branch_head(branch.clone()).await?;
+                    Some((branch.clone(), to.get_change_hash(head).await?.0))
+                }
+                None => None,
+            };
+            let branch = match current {
+                Some((branch, head)) if head == hash => branch,
+                _ => {
+                    let head = to
+                        .get_change_id(hash)
+                        .await?
+                        .ok_or(ValueStoreError::UnknownChange { hash })?;
+                    report.branches += 1;
+                    to.set_branch_head(target.clone(), uuid, head).await?
+                }
+            };
+            let existing = to.get_branch_config(branch.clone()).await?;
+            for (name, content) in changed_entries(config, &existing) {
+                to.set_branch_config(branch.clone(), &name, Some(&content))
+                    .await?;
+                report.config_entries += 1;
+            }
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(all(test, feature = "db_redb"))]
+mod test {
+    use futures_util::FutureExt;
+
+    use super::*;
+    use crate::{
+        storage::redb::RedbStorage,
+        types::{
+            change::{ChangeContent, Hash},
+            hasher::HashAlgorithm,
+            PathElement, Value,
+        },
+        util::test_util::redb_storage,
+    };
+
+    fn add(storage: &RedbStorage, n: u8, parents: &[Hash]) -> <RedbStorage as Storage>::ChangeId {
+        let content = vec![ChangeContent::Insert {
+            path: vec![PathElement::Field(n.to_string())].into(),
+            value: Value::Integer(n as i64),
+        }];
+        let mut buf = Vec::new();
+        ciborium::into_writer(&content, &mut buf).unwrap();
+        storage
+            .add_change(&Hash([n; 32]), HashAlgorithm::Blake3, &buf, parents)
+            .now_or_never()
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn copies_history() {
+        let from = redb_storage();
+        let to = redb_storage();
+        let repo = from.create_repo(Uuid::nil()).unwrap();
+        to.create_repo(Uuid::nil()).unwrap();
+        add(&from, 1, &[]);
+        let second = add(&from, 2, &[Hash([1; 32])]);
+        let third = add(&from, 3, &[Hash([2; 32])]);
+        // only reachable from the branch, not from the log
+        let fourth = add(&from, 4, &[Hash([3; 32])]);
+        from.set_note(second, "ci", Some(b"ok"))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        from.set_repo_config(repo, "schema.version", Some(b"1"))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        from.insert_event(repo, 1, third)
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        let branch = RedbStorage::set_branch_head(&from, repo, Uuid::from_u128(2), fourth).unwrap();
+        from.set_branch_config(branch, "owner", Some(b"ann"))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+
+        let report = migrate(&from, &to, &[Uuid::nil()])
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            report,
+            MigrationReport {
+                changes: 4,
+                notes: 1,
+                config_entries: 2,
+                events: 1,
+                branches: 1,
+            }
+        );
+        let copied = to
+            .get_change_id(Hash([2; 32]))
+            .now_or_never()
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            to.get_notes(copied).now_or_never().unwrap().unwrap(),
+            vec![("ci".to_string(), b"ok".to_vec())]
+        );
+        let target = to
+            .get_repo_id(Uuid::nil())
+            .now_or_never()
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let branches = to.get_branches(target).now_or_never().unwrap().unwrap();
+        assert_eq!(branches.len(), 1);
+        let (uuid, branch) = branches[0];
+        assert_eq!(uuid, Uuid::from_u128(2));
+        let head = to.get_branch_head(branch).now_or_never().unwrap().unwrap();
+        assert_eq!(
+            to.get_change_hash(head).now_or_never().unwrap().unwrap().0,
+            Hash([4; 32])
+        );
+        assert_eq!(
+            to.get_branch_config(branch)
+                .now_or_never()
+                .unwrap()
+                .unwrap(),
+            vec![("owner".to_string(), b"ann".to_vec())]
+        );
+
+        let again = migrate(&from, &to, &[Uuid::nil()])
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(again, MigrationReport::default());
+    }
+}
@@ -1,17 +1,89 @@
 use std::future::Future;
 
-use crate::{async_support::MaybeSend, types::change::Hash, Result};
+use uuid::Uuid;
+
+use crate::{
+    apply::split::SplitMarker,
+    async_support::MaybeSend,
+    types::{
+        change::{path_prefix_hashes, ChangeContent, Hash},
+        hasher::HashAlgorithm,
+        PathElement,
+    },
+    Result,
+};
+
+pub const STATS_TOP_N: usize = 10;
+
+/// name and content of a change note
+pub type Note = (String, Vec<u8>);
+/// name and content of a configuration value
+pub type ConfigEntry = (String, Vec<u8>);
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RepoStats {
+    /// changes reachable from a branch head of the repo, see Storage::changes_touching
+    pub changes: u64,
+    pub branches: u64,
+    /// sum of the encoded change contents
+    pub payload_bytes: u64,
+    /// the STATS_TOP_N largest changes by encoded size, largest first
+    pub largest_changes: Vec<(Hash, u64)>,
+    /// the STATS_TOP_N paths modified by the most changes, most frequent first
+    pub hot_paths: Vec<(Vec<PathElement>, u64)>,
+    /// length of the longest parent chain per branch, including the head
+    pub branch_depths: Vec<(Uuid, u64)>,
+}
+
+/**
+ *  path prefix hashes under which a change is indexed for Storage::changes_touching.
+ *  content is the cbor encoded list of ChangeContent of the change.
+ *  */
+pub fn content_prefixes(content: &[u8]) -> Result<Vec<Hash>> {
+    let content: Vec<ChangeContent> = ciborium::from_reader(content)?;
+    Ok(path_prefix_hashes(&content))
+}
+
+/// change committed to a branch with Storage::commit
+#[derive(Debug, Clone, Copy)]
+pub struct Commit<'a> {
+    pub hash: &'a Hash,
+    pub algorithm: HashAlgorithm,
+    /// cbor encoded list of ChangeContent
+    pub content: &'a [u8],
+    pub parents: &'a [Hash],
+    /// branch config entries written with the change, None removes the entry
+    pub config: &'a [(String, Option<Vec<u8>>)],
+    /// part of a split change set the change belongs to, see Storage::get_group
+    pub group: Option<SplitMarker>,
+}
 
 pub trait Storage {
     type ChangeId;
     type BranchId;
     type RepoId;
+    /// stores a change unless it exists, its path prefixes are indexed, see content_prefixes
     fn add_change(
         &self,
         hash: &Hash,
+        algorithm: HashAlgorithm,
         content: &[u8],
         parents: &[Hash],
     ) -> impl Future<Output = Result<Self::ChangeId>> + MaybeSend;
+    /**
+     *  stores the change of commit, moves branch onto it and appends it to the commit log of
+     *  repo in one transaction, together with the config entries of commit. returns the
+     *  change and its sequence number in the log.
+     *  fails with ValueStoreError::HeadParentMismatch if the head of branch is not one of the
+     *  parents. committing the head again returns it without writing, so a commit whose
+     *  answer was lost can be retried.
+     *  */
+    fn commit(
+        &self,
+        repo: Self::RepoId,
+        branch: Self::BranchId,
+        commit: Commit<'_>,
+    ) -> impl Future<Output = Result<(Self::ChangeId, u64)>> + MaybeSend;
     fn get_change_id(
         &self,
         hash: Hash,
@@ -24,7 +96,139 @@ pub trait Storage {
         &self,
         id: Self::ChangeId,
     ) -> impl Future<Output = Result<Vec<u8>>> + MaybeSend;
+    /**
+     *  up to limit stored changes with a position greater than after, ordered by position.
+     *  changes are stored after their parents, so parents come first.
+     *  */
+    fn list_changes(
+        &self,
+        after: u64,
+        limit: usize,
+    ) -> impl Future<Output = Result<Vec<(u64, Self::ChangeId)>>> + MaybeSend;
+    /// hash of a stored change and the algorithm it was computed with
+    fn get_change_hash(
+        &self,
+        id: Self::ChangeId,
+    ) -> impl Future<Output = Result<(Hash, HashAlgorithm)>> + MaybeSend;
+    /**
+     *  changes reachable from a branch head of repo with a path starting with prefix.
+     *  reachability is recorded when a head moves, so history a head moved away from with
+     *  set_branch_head stays part of the repo.
+     *  */
+    fn changes_touching(
+        &self,
+        repo: Self::RepoId,
+        prefix: &[PathElement],
+    ) -> impl Future<Output = Result<Vec<Self::ChangeId>>> + MaybeSend;
+    fn repo_stats(&self, repo: Self::RepoId) -> impl Future<Output = Result<RepoStats>> + MaybeSend;
+    /// sets the note name of change, None removes it. notes are not part of the change hash.
+    fn set_note(
+        &self,
+        change: Self::ChangeId,
+        name: &str,
+        content: Option<&[u8]>,
+    ) -> impl Future<Output = Result<()>> + MaybeSend;
+    /// all notes of change ordered by name
+    fn get_notes(
+        &self,
+        change: Self::ChangeId,
+    ) -> impl Future<Output = Result<Vec<Note>>> + MaybeSend;
+    fn get_repo_id(&self, uuid: Uuid)
+        -> impl Future<Output = Result<Option<Self::RepoId>>> + MaybeSend;
+    /// id of the repository uuid, created if it doesn't exist
+    fn create_repo(&self, uuid: Uuid) -> impl Future<Output = Result<Self::RepoId>> + MaybeSend;
+    /// branches of repo with their uuid
+    fn get_branches(
+        &self,
+        repo: Self::RepoId,
+    ) -> impl Future<Output = Result<Vec<(Uuid, Self::BranchId)>>> + MaybeSend;
+    fn get_branch_id(
+        &self,
+        repo: Self::RepoId,
+        uuid: Uuid,
+    ) -> impl Future<Output = Result<Option<Self::BranchId>>> + MaybeSend;
+    fn get_branch_head(
+        &self,
+        branch: Self::BranchId,
+    ) -> impl Future<Output = Result<Self::ChangeId>> + MaybeSend;
+    /// points branch uuid of repo at head without logging a commit, creating the branch if needed
+    fn set_branch_head(
+        &self,
+        repo: Self::RepoId,
+        uuid: Uuid,
+        head: Self::ChangeId,
+    ) -> impl Future<Output = Result<Self::BranchId>> + MaybeSend;
+    /// sets a configuration value for all branches of repo, None removes it
+    fn set_repo_config(
+        &self,
+        repo: Self::RepoId,
+        name: &str,
+        content: Option<&[u8]>,
+    ) -> impl Future<Output = Result<()>> + MaybeSend;
+    /// sets a configuration value overriding the repo wide one, None removes it
+    fn set_branch_config(
+        &self,
+        branch: Self::BranchId,
+        name: &str,
+        content: Option<&[u8]>,
+    ) -> impl Future<Output = Result<()>> + MaybeSend;
+    fn get_repo_config(
+        &self,
+        repo: Self::RepoId,
+    ) -> impl Future<Output = Result<Vec<ConfigEntry>>> + MaybeSend;
+    fn get_branch_config(
+        &self,
+        branch: Self::BranchId,
+    ) -> impl Future<Output = Result<Vec<ConfigEntry>>> + MaybeSend;
+    /**
+     *  writes entries to the config of branch in one transaction if its entries with a name
+     *  starting with prefix are exactly expected, ordered by name. returns whether it wrote,
+     *  None removes an entry.
+     *  */
+    fn set_branch_config_if(
+        &self,
+        branch: Self::BranchId,
+        prefix: &str,
+        expected: &[ConfigEntry],
+        entries: &[(String, Option<Vec<u8>>)],
+    ) -> impl Future<Output = Result<bool>> + MaybeSend;
+    /**
+     *  records change as the commit with sequence number seq of repo, e.g. when copying a log.
+     *  recording the same commit again does nothing, a different change at seq fails with
+     *  ValueStoreError::LogConflict.
+     *  */
+    fn insert_event(
+        &self,
+        repo: Self::RepoId,
+        seq: u64,
+        change: Self::ChangeId,
+    ) -> impl Future<Output = Result<()>> + MaybeSend;
+    /// committed parts of the split change set group in repo, ordered by part
+    fn get_group(
+        &self,
+        repo: Self::RepoId,
+        group: Uuid,
+    ) -> impl Future<Output = Result<Vec<(SplitMarker, Self::ChangeId)>>> + MaybeSend;
+    /// commits to repo with a sequence number greater than seq, in order
+    fn changes_since(
+        &self,
+        repo: Self::RepoId,
+        seq: u64,
+    ) -> impl Future<Output = Result<Vec<(u64, Self::ChangeId)>>> + MaybeSend;
+    /// releases connections and files, later operations may fail
+    fn close(&self) -> impl Future<Output = Result<()>> + MaybeSend;
 }
 
+pub mod dynamic;
+pub use dynamic::DynStorage;
+
+pub mod cached;
+pub mod fault;
+pub mod migrate;
+pub mod retry;
+
 #[cfg(feature = "db_sqlite")]
 pub mod sqlite;
+
+#[cfg(feature = "db_redb")]
+pub mod redb;
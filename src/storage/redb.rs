@@ -0,0 +1,1030 @@
+use std::{cmp::Reverse, collections::HashMap, path::Path};
+
+use redb::{
+    Database, MultimapTableDefinition, ReadTransaction, ReadableMultimapTable, ReadableTable,
+    TableDefinition, TableHandle, WriteTransaction,
+};
+use uuid::Uuid;
+
+use crate::{
+    apply::split::SplitMarker,
+    error::ValueStoreError,
+    storage::{content_prefixes, Commit, ConfigEntry, Note, RepoStats, Storage, STATS_TOP_N},
+    types::{
+        change::{path_prefix_hash, ChangeContent, Hash},
+        hasher::HashAlgorithm,
+        PathElement,
+    },
+    Result,
+};
+
+/// id -> (hash, hash algorithm)
+const CHANGES: TableDefinition<u64, (&[u8], u8)> = TableDefinition::new("changes");
+const CHANGE_CONTENTS: TableDefinition<u64, &[u8]> = TableDefinition::new("change_contents");
+const CHANGE_IDS: TableDefinition<&[u8], u64> = TableDefinition::new("change_ids");
+/// child -> parent
+const CHANGE_RELS: MultimapTableDefinition<u64, u64> = MultimapTableDefinition::new("change_rels");
+/// path prefix hash -> change
+const CHANGE_PATHS: MultimapTableDefinition<&[u8], u64> =
+    MultimapTableDefinition::new("change_paths");
+const REPOS: TableDefinition<u128, u64> = TableDefinition::new("repositories");
+/// (repo, uuid) -> (id, head)
+const BRANCHES: TableDefinition<(u64, u128), (u64, u64)> = TableDefinition::new("branch");
+const CHANGE_NOTES: TableDefinition<(u64, &str), &[u8]> = TableDefinition::new("change_notes");
+const REPO_CONFIG: TableDefinition<(u64, &str), &[u8]> = TableDefinition::new("repo_config");
+const BRANCH_CONFIG: TableDefinition<(u64, &str), &[u8]> = TableDefinition::new("branch_config");
+/// (repo, seq) -> change
+const EVENTS: TableDefinition<(u64, u64), u64> = TableDefinition::new("events");
+/// (repo, group, part) -> (parts, change)
+const CHANGE_GROUPS: TableDefinition<(u64, u128, u32), (u32, u64)> =
+    TableDefinition::new("change_groups");
+/// (repo, change) of changes reachable from a branch head of repo, added whenever a head moves
+const REPO_CHANGES: TableDefinition<(u64, u64), ()> = TableDefinition::new("repo_changes");
+/// last id handed out per table
+const SEQUENCES: TableDefinition<&str, u64> = TableDefinition::new("sequences");
+
+/**
+ *  storage in a redb database, an embedded key value store written in rust.
+ *  redb is synchronous, every operation does its blocking io in the first poll of its future
+ *  and completes there. callers on an async runtime have to run the operations where blocking
+ *  is allowed, e.g. through spawn_blocking, the crate doesn't depend on a runtime to do it.
+ *  */
+pub struct RedbStorage {
+    db: Database,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ChangeId(u64);
+/// id of the branch together with its key in BRANCHES
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BranchId {
+    id: u64,
+    repo: u64,
+    uuid: u128,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RepoId(u64);
+
+fn next_id(trans: &WriteTransaction, table: &str) -> Result<u64> {
+    let mut sequences = trans.open_table(SEQUENCES)?;
+    let id = sequences.get(table)?.map_or(0, |id| id.value()) + 1;
+    sequences.insert(table, id)?;
+    Ok(id)
+}
+
+/// stores a change unless it exists, returns its id
+fn insert_change(
+    trans: &WriteTransaction,
+    hash: &Hash,
+    algorithm: HashAlgorithm,
+    content: &[u8],
+    parents: &[Hash],
+) -> Result<u64> {
+    if parents.contains(hash) {
+        return Err(ValueStoreError::CorruptHistory {
+            change: Some(*hash),
+        }
+        .into());
+    }
+    let prefixes = content_prefixes(content)?;
+    let existing = trans
+        .open_table(CHANGE_IDS)?
+        .get(hash.as_slice())?
+        .map(|id| id.value());
+    if let Some(id) = existing {
+        return Ok(id);
+    }
+    let id = next_id(trans, "changes")?;
+    let mut ids = trans.open_table(CHANGE_IDS)?;
+    let mut rels = trans.open_multimap_table(CHANGE_RELS)?;
+    // parents have to be stored before their children, which also rules out cycles
+    for parent in parents {
+        let parent = ids
+            .get(parent.as_slice())?
+            .ok_or(ValueStoreError::CorruptHistory {
+                change: Some(*hash),
+            })?
+            .value();
+        rels.insert(id, parent)?;
+    }
+    let mut paths = trans.open_multimap_table(CHANGE_PATHS)?;
+    for prefix in &prefixes {
+        paths.insert(prefix.as_slice(), id)?;
+    }
+    ids.insert(hash.as_slice(), id)?;
+    trans
+        .open_table(CHANGES)?
+        .insert(id, (hash.as_slice(), algorithm.id()))?;
+    trans.open_table(CHANGE_CONTENTS)?.insert(id, content)?;
+    Ok(id)
+}
+
+/// appends change to the commit log of repo, returns its sequence number
+fn push_event(trans: &WriteTransaction, repo: u64, change: u64) -> Result<u64> {
+    let mut events = trans.open_table(EVENTS)?;
+    let last = events
+        .range((repo, 0)..=(repo, u64::MAX))?
+        .next_back()
+        .transpose()?
+        .map_or(0, |(key, _)| key.value().1);
+    events.insert((repo, last + 1), change)?;
+    Ok(last + 1)
+}
+
+/// sequence number of the last commit of change to repo
+fn logged_seq(trans: &WriteTransaction, repo: u64, change: u64) -> Result<Option<u64>> {
+    for event in trans
+        .open_table(EVENTS)?
+        .range((repo, 0)..=(repo, u64::MAX))?
+        .rev()
+    {
+        let (key, logged) = event?;
+        if logged.value() == change {
+            return Ok(Some(key.value().1));
+        }
+    }
+    Ok(None)
+}
+
+/// records change and its ancestors as reachable in repo, stopping at changes already recorded
+fn add_repo_changes(trans: &WriteTransaction, repo: u64, change: u64) -> Result<()> {
+    let rels = trans.open_multimap_table(CHANGE_RELS)?;
+    let mut members = trans.open_table(REPO_CHANGES)?;
+    let mut queue = vec![change];
+    while let Some(id) = queue.pop() {
+        if members.insert((repo, id), ())?.is_none() {
+            for parent in rels.get(id)? {
+                queue.push(parent?.value());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// length of the longest parent chain starting at head, depths caches the results per change
+fn depth(trans: &ReadTransaction, head: u64, depths: &mut HashMap<u64, u64>) -> Result<u64> {
+    let rels = trans.open_multimap_table(CHANGE_RELS)?;
+    let mut stack = vec![(head, false)];
+    while let Some((id, expanded)) = stack.pop() {
+        if depths.contains_key(&id) {
+            continue;
+        }
+        let mut parents = Vec::new();
+        for parent in rels.get(id)? {
+            parents.push(parent?.value());
+        }
+        if expanded {
+            let depth = parents.iter().map(|p| depths[p]).max().unwrap_or(0) + 1;
+            depths.insert(id, depth);
+        } else {
+            stack.push((id, true));
+            stack.extend(
+                parents
+                    .into_iter()
+                    .filter(|p| !depths.contains_key(p))
+                    .map(|p| (p, false)),
+            );
+        }
+    }
+    Ok(depths[&head])
+}
+
+fn entries(
+    trans: &ReadTransaction,
+    table: TableDefinition<(u64, &str), &[u8]>,
+    id: u64,
+) -> Result<Vec<(String, Vec<u8>)>> {
+    prefixed_entries(&trans.open_table(table)?, id, "")
+}
+
+/// entries of id with a name starting with prefix, ordered by name
+fn prefixed_entries(
+    table: &impl ReadableTable<(u64, &'static str), &'static [u8]>,
+    id: u64,
+    prefix: &str,
+) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut res = Vec::new();
+    for entry in table.range((id, prefix)..)? {
+        let (key, value) = entry?;
+        let (owner, name) = key.value();
+        if owner != id || !name.starts_with(prefix) {
+            break;
+        }
+        res.push((name.to_string(), value.value().to_vec()));
+    }
+    Ok(res)
+}
+
+impl RedbStorage {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        Self::new(Database::create(path)?)
+    }
+
+    pub fn new(db: Database) -> Result<Self> {
+        let trans = db.begin_write()?;
+        trans.open_table(CHANGES)?;
+        trans.open_table(CHANGE_CONTENTS)?;
+        trans.open_table(CHANGE_IDS)?;
+        trans.open_multimap_table(CHANGE_RELS)?;
+        trans.open_multimap_table(CHANGE_PATHS)?;
+        trans.open_table(REPOS)?;
+        trans.open_table(BRANCHES)?;
+        trans.open_table(CHANGE_NOTES)?;
+        trans.open_table(REPO_CONFIG)?;
+        trans.open_table(BRANCH_CONFIG)?;
+        trans.open_table(EVENTS)?;
+        trans.open_table(CHANGE_GROUPS)?;
+        trans.open_table(SEQUENCES)?;
+        let indexed = trans
+            .list_tables()?
+            .any(|table| table.name() == REPO_CHANGES.name());
+        trans.open_table(REPO_CHANGES)?;
+        // databases created before the reachability index are indexed once
+        if !indexed {
+            let mut heads = Vec::new();
+            for branch in trans.open_table(BRANCHES)?.iter()? {
+                let (key, value) = branch?;
+                heads.push((key.value().0, value.value().1));
+            }
+            for (repo, head) in heads {
+                add_repo_changes(&trans, repo, head)?;
+            }
+        }
+        trans.commit()?;
+        Ok(Self { db })
+    }
+
+    /// id of the repository uuid, created if it doesn't exist
+    pub fn create_repo(&self, uuid: Uuid) -> Result<RepoId> {
+        let trans = self.db.begin_write()?;
+        let existing = trans
+            .open_table(REPOS)?
+            .get(uuid.as_u128())?
+            .map(|id| id.value());
+        let id = match existing {
+            Some(id) => id,
+            None => {
+                let id = next_id(&trans, "repositories")?;
+                trans.open_table(REPOS)?.insert(uuid.as_u128(), id)?;
+                id
+            }
+        };
+        trans.commit()?;
+        Ok(RepoId(id))
+    }
+
+    /// points branch uuid of repo at head, creating the branch if it doesn't exist
+    pub fn set_branch_head(&self, repo: RepoId, uuid: Uuid, head: ChangeId) -> Result<BranchId> {
+        let trans = self.db.begin_write()?;
+        if trans.open_table(CHANGES)?.get(head.0)?.is_none() {
+            return Err(ValueStoreError::CorruptHistory { change: None }.into());
+        }
+        let key = (repo.0, uuid.as_u128());
+        let existing = trans.open_table(BRANCHES)?.get(key)?.map(|b| b.value().0);
+        let id = match existing {
+            Some(id) => id,
+            None => next_id(&trans, "branch")?,
+        };
+        trans.open_table(BRANCHES)?.insert(key, (id, head.0))?;
+        add_repo_changes(&trans, repo.0, head.0)?;
+        trans.commit()?;
+        Ok(BranchId {
+            id,
+            repo: repo.0,
+            uuid: key.1,
+        })
+    }
+
+    fn set_entry(
+        &self,
+        table: TableDefinition<(u64, &str), &[u8]>,
+        id: u64,
+        name: &str,
+        content: Option<&[u8]>,
+    ) -> Result<()> {
+        let trans = self.db.begin_write()?;
+        {
+            let mut table = trans.open_table(table)?;
+            match content {
+                Some(content) => table.insert((id, name), content)?,
+                None => table.remove((id, name))?,
+            };
+        }
+        trans.commit()?;
+        Ok(())
+    }
+}
+
+impl Storage for RedbStorage {
+    type ChangeId = ChangeId;
+    type BranchId = BranchId;
+    type RepoId = RepoId;
+
+    async fn add_change(
+        &self,
+        hash: &Hash,
+        algorithm: HashAlgorithm,
+        content: &[u8],
+        parents: &[Hash],
+    ) -> Result<Self::ChangeId> {
+        let trans = self.db.begin_write()?;
+        let id = insert_change(&trans, hash, algorithm, content, parents)?;
+        trans.commit()?;
+        Ok(ChangeId(id))
+    }
+
+    async fn commit(
+        &self,
+        repo: Self::RepoId,
+        branch: Self::BranchId,
+        commit: Commit<'_>,
+    ) -> Result<(Self::ChangeId, u64)> {
+        let trans = self.db.begin_write()?;
+        let key = (branch.repo, branch.uuid);
+        let head = trans
+            .open_table(BRANCHES)?
+            .get(key)?
+            .ok_or(ValueStoreError::UnknownBranch {
+                uuid: Uuid::from_u128(branch.uuid),
+            })?
+            .value()
+            .1;
+        let existing = trans
+            .open_table(CHANGE_IDS)?
+            .get(commit.hash.as_slice())?
+            .map(|id| id.value());
+        if existing == Some(head) {
+            if let Some(seq) = logged_seq(&trans, repo.0, head)? {
+                return Ok((ChangeId(head), seq));
+            }
+        }
+        let head_is_parent = {
+            let ids = trans.open_table(CHANGE_IDS)?;
+            let mut res = false;
+            for parent in commit.parents {
+                res |= ids.get(parent.as_slice())?.map(|id| id.value()) == Some(head);
+            }
+            res
+        };
+        if !head_is_parent {
+            let changes = trans.open_table(CHANGES)?;
+            let head = changes
+                .get(head)?
+                .ok_or(ValueStoreError::CorruptHistory { change: None })?;
+            let parent = Hash::try_from(head.value().0)
+                .map_err(|_| ValueStoreError::CorruptHistory { change: None })?;
+            return Err(ValueStoreError::HeadParentMismatch { parent }.into());
+        }
+        let id = insert_change(
+            &trans,
+            commit.hash,
+            commit.algorithm,
+            commit.content,
+            commit.parents,
+        )?;
+        trans.open_table(BRANCHES)?.insert(key, (branch.id, id))?;
+        add_repo_changes(&trans, repo.0, id)?;
+        {
+            let mut config = trans.open_table(BRANCH_CONFIG)?;
+            for (name, content) in commit.config {
+                match content {
+                    Some(content) => {
+                        config.insert((branch.id, name.as_str()), content.as_slice())?
+                    }
+                    None => config.remove((branch.id, name.as_str()))?,
+                };
+            }
+        }
+        if let Some(group) = commit.group {
+            trans.open_table(CHANGE_GROUPS)?.insert(
+                (repo.0, group.group.as_u128(), group.part),
+                (group.parts, id),
+            )?;
+        }
+        let seq = push_event(&trans, repo.0, id)?;
+        trans.commit()?;
+        Ok((ChangeId(id), seq))
+    }
+
+    async fn get_change_id(&self, hash: Hash) -> Result<Option<Self::ChangeId>> {
+        let trans = self.db.begin_read()?;
+        let id = trans.open_table(CHANGE_IDS)?.get(hash.as_slice())?;
+        Ok(id.map(|id| ChangeId(id.value())))
+    }
+
+    async fn get_change_rels(&self, id: Self::ChangeId) -> Result<Vec<Self::ChangeId>> {
+        let trans = self.db.begin_read()?;
+        let changes = trans.open_table(CHANGES)?;
+        let mut parents = Vec::new();
+        for parent in trans.open_multimap_table(CHANGE_RELS)?.get(id.0)? {
+            let parent = parent?.value();
+            let hash = changes
+                .get(parent)?
+                .ok_or(ValueStoreError::CorruptHistory { change: None })?
+                .value()
+                .0
+                .to_vec();
+            parents.push((hash, ChangeId(parent)));
+        }
+        // same order as the sqlite backend
+        parents.sort_unstable();
+        Ok(parents.into_iter().map(|(_, id)| id).collect())
+    }
+
+    async fn get_change_content(&self, id: Self::ChangeId) -> Result<Vec<u8>> {
+        let trans = self.db.begin_read()?;
+        let content = trans.open_table(CHANGE_CONTENTS)?.get(id.0)?;
+        Ok(content
+            .ok_or(ValueStoreError::CorruptHistory { change: None })?
+            .value()
+            .to_vec())
+    }
+
+    async fn list_changes(&self, after: u64, limit: usize) -> Result<Vec<(u64, Self::ChangeId)>> {
+        let trans = self.db.begin_read()?;
+        let mut res = Vec::new();
+        if after == u64::MAX {
+            return Ok(res);
+        }
+        for change in trans.open_table(CHANGES)?.range(after + 1..)?.take(limit) {
+            let id = change?.0.value();
+            res.push((id, ChangeId(id)));
+        }
+        Ok(res)
+    }
+
+    async fn get_change_hash(&self, id: Self::ChangeId) -> Result<(Hash, HashAlgorithm)> {
+        let trans = self.db.begin_read()?;
+        let change = trans
+            .open_table(CHANGES)?
+            .get(id.0)?
+            .ok_or(ValueStoreError::CorruptHistory { change: None })?;
+        let (hash, algorithm) = change.value();
+        Ok((
+            Hash::try_from(hash).map_err(|_| ValueStoreError::CorruptHistory { change: None })?,
+            HashAlgorithm::from_id(algorithm)
+                .ok_or(ValueStoreError::CorruptHistory { change: None })?,
+        ))
+    }
+
+    async fn changes_touching(
+        &self,
+        repo: Self::RepoId,
+        prefix: &[PathElement],
+    ) -> Result<Vec<Self::ChangeId>> {
+        let trans = self.db.begin_read()?;
+        let members = trans.open_table(REPO_CHANGES)?;
+        let prefix = path_prefix_hash(prefix);
+        let mut res = Vec::new();
+        for change in trans
+            .open_multimap_table(CHANGE_PATHS)?
+            .get(prefix.as_slice())?
+        {
+            let change = change?.value();
+            if members.get((repo.0, change))?.is_some() {
+                res.push(ChangeId(change));
+            }
+        }
+        Ok(res)
+    }
+
+    async fn repo_stats(&self, repo: Self::RepoId) -> Result<RepoStats> {
+        let trans = self.db.begin_read()?;
+        let mut stats = RepoStats::default();
+        let mut paths: HashMap<Vec<PathElement>, u64> = HashMap::new();
+        let changes = trans.open_table(CHANGES)?;
+        let contents = trans.open_table(CHANGE_CONTENTS)?;
+        for member in trans
+            .open_table(REPO_CHANGES)?
+            .range((repo.0, 0)..=(repo.0, u64::MAX))?
+        {
+            let id = member?.0.value().1;
+            let content = contents
+                .get(id)?
+                .ok_or(ValueStoreError::CorruptHistory { change: None })?;
+            let content = content.value();
+            let size = content.len() as u64;
+            stats.changes += 1;
+            stats.payload_bytes += size;
+            let change = changes
+                .get(id)?
+                .ok_or(ValueStoreError::CorruptHistory { change: None })?;
+            let hash = Hash::try_from(change.value().0)
+                .map_err(|_| ValueStoreError::CorruptHistory { change: None })?;
+            stats.largest_changes.push((hash, size));
+            let content: Vec<ChangeContent> = ciborium::from_reader(content)?;
+            for change in content {
+                *paths.entry(change.path().to_vec()).or_default() += 1;
+            }
+        }
+        stats
+            .largest_changes
+            .sort_unstable_by_key(|(hash, size)| (Reverse(*size), *hash));
+        stats.largest_changes.truncate(STATS_TOP_N);
+        stats.hot_paths = paths.into_iter().collect();
+        stats
+            .hot_paths
+            .sort_unstable_by(|(p1, c1), (p2, c2)| c2.cmp(c1).then_with(|| p1.cmp(p2)));
+        stats.hot_paths.truncate(STATS_TOP_N);
+
+        // shared by all branches, so common history is only walked once
+        let mut depths = HashMap::new();
+        for branch in trans
+            .open_table(BRANCHES)?
+            .range((repo.0, 0)..=(repo.0, u128::MAX))?
+        {
+            let (key, value) = branch?;
+            stats.branches += 1;
+            stats.branch_depths.push((
+                Uuid::from_u128(key.value().1),
+                depth(&trans, value.value().1, &mut depths)?,
+            ));
+        }
+        Ok(stats)
+    }
+
+    async fn set_note(
+        &self,
+        change: Self::ChangeId,
+        name: &str,
+        content: Option<&[u8]>,
+    ) -> Result<()> {
+        self.set_entry(CHANGE_NOTES, change.0, name, content)
+    }
+
+    async fn get_notes(&self, change: Self::ChangeId) -> Result<Vec<Note>> {
+        entries(&self.db.begin_read()?, CHANGE_NOTES, change.0)
+    }
+
+    async fn get_repo_id(&self, uuid: Uuid) -> Result<Option<Self::RepoId>> {
+        let trans = self.db.begin_read()?;
+        let id = trans.open_table(REPOS)?.get(uuid.as_u128())?;
+        Ok(id.map(|id| RepoId(id.value())))
+    }
+
+    async fn create_repo(&self, uuid: Uuid) -> Result<Self::RepoId> {
+        RedbStorage::create_repo(self, uuid)
+    }
+
+    async fn get_branches(&self, repo: Self::RepoId) -> Result<Vec<(Uuid, Self::BranchId)>> {
+        let trans = self.db.begin_read()?;
+        let mut res = Vec::new();
+        for branch in trans
+            .open_table(BRANCHES)?
+            .range((repo.0, 0)..=(repo.0, u128::MAX))?
+        {
+            let (key, value) = branch?;
+            let (repo, uuid) = key.value();
+            let id = BranchId {
+                id: value.value().0,
+                repo,
+                uuid,
+            };
+            res.push((Uuid::from_u128(uuid), id));
+        }
+        Ok(res)
+    }
+
+    async fn get_branch_id(
+        &self,
+        repo: Self::RepoId,
+        uuid: Uuid,
+    ) -> Result<Option<Self::BranchId>> {
+        let trans = self.db.begin_read()?;
+        let branch = trans.open_table(BRANCHES)?.get((repo.0, uuid.as_u128()))?;
+        Ok(branch.map(|branch| BranchId {
+            id: branch.value().0,
+            repo: repo.0,
+            uuid: uuid.as_u128(),
+        }))
+    }
+
+    async fn get_branch_head(&self, branch: Self::BranchId) -> Result<Self::ChangeId> {
+        let trans = self.db.begin_read()?;
+        let head = trans
+            .open_table(BRANCHES)?
+            .get((branch.repo, branch.uuid))?
+            .ok_or(ValueStoreError::UnknownBranch {
+                uuid: Uuid::from_u128(branch.uuid),
+            })?
+            .value()
+            .1;
+        Ok(ChangeId(head))
+    }
+
+    async fn set_branch_head(
+        &self,
+        repo: Self::RepoId,
+        uuid: Uuid,
+        head: Self::ChangeId,
+    ) -> Result<Self::BranchId> {
+        RedbStorage::set_branch_head(self, repo, uuid, head)
+    }
+
+    async fn set_repo_config(
+        &self,
+        repo: Self::RepoId,
+        name: &str,
+        content: Option<&[u8]>,
+    ) -> Result<()> {
+        self.set_entry(REPO_CONFIG, repo.0, name, content)
+    }
+
+    async fn set_branch_config(
+        &self,
+        branch: Self::BranchId,
+        name: &str,
+        content: Option<&[u8]>,
+    ) -> Result<()> {
+        self.set_entry(BRANCH_CONFIG, branch.id, name, content)
+    }
+
+    async fn get_repo_config(&self, repo: Self::RepoId) -> Result<Vec<ConfigEntry>> {
+        entries(&self.db.begin_read()?, REPO_CONFIG, repo.0)
+    }
+
+    async fn get_branch_config(&self, branch: Self::BranchId) -> Result<Vec<ConfigEntry>> {
+        entries(&self.db.begin_read()?, BRANCH_CONFIG, branch.id)
+    }
+
+    async fn set_branch_config_if(
+        &self,
+        branch: Self::BranchId,
+        prefix: &str,
+        expected: &[ConfigEntry],
+        entries: &[(String, Option<Vec<u8>>)],
+    ) -> Result<bool> {
+        let trans = self.db.begin_write()?;
+        {
+            let mut config = trans.open_table(BRANCH_CONFIG)?;
+            if prefixed_entries(&config, branch.id, prefix)? != expected {
+                return Ok(false);
+            }
+            for (name, content) in entries {
+                match content {
+                    Some(content) => {
+                        config.insert((branch.id, name.as_str()), content.as_slice())?
+                    }
+                    None => config.remove((branch.id, name.as_str()))?,
+                };
+            }
+        }
+        trans.commit()?;
+        Ok(true)
+    }
+
+    async fn insert_event(
+        &self,
+        repo: Self::RepoId,
+        seq: u64,
+        change: Self::ChangeId,
+    ) -> Result<()> {
+        let trans = self.db.begin_write()?;
+        {
+            let mut events = trans.open_table(EVENTS)?;
+            let existing = events.get((repo.0, seq))?.map(|id| id.value());
+            match existing {
+                Some(existing) if existing == change.0 => {}
+                Some(_) => return Err(ValueStoreError::LogConflict { seq }.into()),
+                None => {
+                    events.insert((repo.0, seq), change.0)?;
+                }
+            }
+        }
+        trans.commit()?;
+        Ok(())
+    }
+
+    async fn get_group(
+        &self,
+        repo: Self::RepoId,
+        group: Uuid,
+    ) -> Result<Vec<(SplitMarker, Self::ChangeId)>> {
+        let trans = self.db.begin_read()?;
+        let key = (repo.0, group.as_u128());
+        let mut res = Vec::new();
+        for entry in trans
+            .open_table(CHANGE_GROUPS)?
+            .range((key.0, key.1, 0)..=(key.0, key.1, u32::MAX))?
+        {
+            let (key, value) = entry?;
+            let (parts, change) = value.value();
+            let marker = SplitMarker {
+                group,
+                part: key.value().2,
+                parts,
+            };
+            res.push((marker, ChangeId(change)));
+        }
+        Ok(res)
+    }
+
+    async fn changes_since(
+        &self,
+        repo: Self::RepoId,
+        seq: u64,
+    ) -> Result<Vec<(u64, Self::ChangeId)>> {
+        let trans = self.db.begin_read()?;
+        let mut res = Vec::new();
+        if seq == u64::MAX {
+            return Ok(res);
+        }
+        for event in trans
+            .open_table(EVENTS)?
+            .range((repo.0, seq + 1)..=(repo.0, u64::MAX))?
+        {
+            let (key, change) = event?;
+            res.push((key.value().1, ChangeId(change.value())));
+        }
+        Ok(res)
+    }
+
+    /// every write is committed durably, so there is nothing to flush
+    async fn close(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures_util::FutureExt;
+
+    use super::*;
+    use crate::{
+        types::Value,
+        util::test_util::{field, redb_storage},
+        Error,
+    };
+
+    fn add_at(storage: &RedbStorage, n: u8, parents: &[Hash], path: Vec<PathElement>) -> ChangeId {
+        let content = vec![ChangeContent::Insert {
+            path: path.into(),
+            value: Value::Bool(true),
+        }];
+        let mut buf = Vec::new();
+        ciborium::into_writer(&content, &mut buf).unwrap();
+        storage
+            .add_change(&Hash([n; 32]), HashAlgorithm::Blake3, &buf, parents)
+            .now_or_never()
+            .unwrap()
+            .unwrap()
+    }
+
+    fn add(storage: &RedbStorage, n: u8, parents: &[Hash], name: &str) -> ChangeId {
+        add_at(storage, n, parents, vec![field(name)])
+    }
+
+    #[test]
+    fn changes() {
+        let storage = redb_storage();
+        let root = add(&storage, 1, &[], "a");
+        let left = add(&storage, 3, &[Hash([1; 32])], "b");
+        let right = add(&storage, 2, &[Hash([1; 32])], "a");
+        let merge = add(&storage, 4, &[Hash([3; 32]), Hash([2; 32])], "c");
+        assert_eq!(add(&storage, 4, &[], "c"), merge);
+        assert!(matches!(
+            storage
+                .add_change(
+                    &Hash([5; 32]),
+                    HashAlgorithm::Blake3,
+                    &[0x80],
+                    &[Hash([9; 32])]
+                )
+                .now_or_never()
+                .unwrap(),
+            Err(crate::Error::ValueStore(
+                ValueStoreError::CorruptHistory { .. }
+            ))
+        ));
+        assert_eq!(
+            storage
+                .get_change_rels(merge)
+                .now_or_never()
+                .unwrap()
+                .unwrap(),
+            vec![right, left]
+        );
+
+        let repo = storage.create_repo(Uuid::nil()).unwrap();
+        let branch = storage.set_branch_head(repo, Uuid::max(), merge).unwrap();
+        assert_eq!(
+            storage
+                .get_branch_id(repo, Uuid::max())
+                .now_or_never()
+                .unwrap()
+                .unwrap(),
+            Some(branch)
+        );
+        let mut touching = storage
+            .changes_touching(repo, &[field("a")])
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        touching.sort();
+        assert_eq!(touching, vec![root, right]);
+        let stats = storage.repo_stats(repo).now_or_never().unwrap().unwrap();
+        assert_eq!(stats.changes, 4);
+        assert_eq!(stats.branch_depths, vec![(Uuid::max(), 3)]);
+    }
+
+    #[test]
+    fn stats() {
+        let storage = redb_storage();
+        add(&storage, 1, &[], "a");
+        add(&storage, 2, &[Hash([1; 32])], "b");
+        let left = add(&storage, 3, &[Hash([2; 32])], "a");
+        let right = add_at(
+            &storage,
+            4,
+            &[Hash([1; 32])],
+            vec![field("a"), field("long")],
+        );
+        let merge = add(&storage, 5, &[Hash([3; 32]), Hash([4; 32])], "a");
+        let repo = storage.create_repo(Uuid::nil()).unwrap();
+        storage
+            .set_branch_head(repo, Uuid::from_u128(1), merge)
+            .unwrap();
+        storage
+            .set_branch_head(repo, Uuid::from_u128(2), right)
+            .unwrap();
+        storage
+            .set_branch_head(repo, Uuid::from_u128(3), left)
+            .unwrap();
+
+        let stats = storage.repo_stats(repo).now_or_never().unwrap().unwrap();
+        assert_eq!(stats.changes, 5);
+        assert_eq!(stats.branches, 3);
+        let sizes = stats.largest_changes.iter().map(|(_, size)| size);
+        assert_eq!(sizes.sum::<u64>(), stats.payload_bytes);
+        assert_eq!(stats.largest_changes[0].0, Hash([4; 32]));
+        assert_eq!(
+            stats.hot_paths[0..2],
+            [(vec![field("a")], 3), (vec![field("a"), field("long")], 1)]
+        );
+        assert_eq!(
+            stats.branch_depths,
+            vec![
+                (Uuid::from_u128(1), 4),
+                (Uuid::from_u128(2), 2),
+                (Uuid::from_u128(3), 3)
+            ]
+        );
+
+        let trans = storage.db.begin_write().unwrap();
+        trans
+            .open_table(CHANGES)
+            .unwrap()
+            .insert(right.0, (&[4u8; 3][..], 0))
+            .unwrap();
+        trans.commit().unwrap();
+        assert!(matches!(
+            storage.repo_stats(repo).now_or_never().unwrap(),
+            Err(Error::ValueStore(ValueStoreError::CorruptHistory { .. }))
+        ));
+    }
+
+    #[test]
+    fn notes_and_events() {
+        let storage = redb_storage();
+        let change = add(&storage, 1, &[], "a");
+        let repo = storage.create_repo(Uuid::nil()).unwrap();
+        storage
+            .set_note(change, "b", Some(b"2"))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        storage
+            .set_note(change, "a", Some(b"1"))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        storage
+            .set_note(change, "b", None)
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            storage.get_notes(change).now_or_never().unwrap().unwrap(),
+            vec![("a".to_string(), b"1".to_vec())]
+        );
+        for seq in [1, 2, 3, 3] {
+            storage
+                .insert_event(repo, seq, change)
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+        let other = add(&storage, 2, &[], "other");
+        assert!(matches!(
+            storage.insert_event(repo, 3, other).now_or_never().unwrap(),
+            Err(Error::ValueStore(ValueStoreError::LogConflict { seq: 3 }))
+        ));
+        assert_eq!(
+            storage
+                .changes_since(repo, 1)
+                .now_or_never()
+                .unwrap()
+                .unwrap(),
+            vec![(2, change), (3, change)]
+        );
+    }
+
+    #[test]
+    fn reachability_backfill() {
+        let storage = redb_storage();
+        let root = add(&storage, 1, &[], "a");
+        let child = add(&storage, 2, &[Hash([1; 32])], "b");
+        let repo = storage.create_repo(Uuid::nil()).unwrap();
+        storage.set_branch_head(repo, Uuid::nil(), child).unwrap();
+        // a database from before the reachability index
+        let RedbStorage { db } = storage;
+        let trans = db.begin_write().unwrap();
+        trans.delete_table(REPO_CHANGES).unwrap();
+        trans.commit().unwrap();
+        let storage = RedbStorage::new(db).unwrap();
+        let mut touching = storage
+            .changes_touching(repo, &[])
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        touching.sort();
+        assert_eq!(touching, vec![root, child]);
+    }
+
+    #[test]
+    fn conditional_config() {
+        let storage = redb_storage();
+        let change = add(&storage, 1, &[], "a");
+        let repo = storage.create_repo(Uuid::nil()).unwrap();
+        let branch = storage.set_branch_head(repo, Uuid::nil(), change).unwrap();
+        let set = |expected: &[ConfigEntry], name: &str| {
+            let entries = [(name.to_string(), Some(b"1".to_vec()))];
+            storage
+                .set_branch_config_if(branch, "lock.", expected, &entries)
+                .now_or_never()
+                .unwrap()
+                .unwrap()
+        };
+        assert!(set(&[], "lock.a"));
+        // entries outside of the prefix don't matter
+        assert!(set(&[("lock.a".to_string(), b"1".to_vec())], "other"));
+        assert!(!set(&[], "lock.b"));
+        assert_eq!(
+            storage
+                .get_branch_config(branch)
+                .now_or_never()
+                .unwrap()
+                .unwrap(),
+            vec![
+                ("lock.a".to_string(), b"1".to_vec()),
+                ("other".to_string(), b"1".to_vec())
+            ]
+        );
+    }
+
+    #[test]
+    fn changes_touching() {
+        let storage = redb_storage();
+        let root = add_at(&storage, 1, &[], vec![field("a"), field("b")]);
+        let item = add_at(
+            &storage,
+            2,
+            &[Hash([1; 32])],
+            vec![field("a"), field("list"), PathElement::Index(3)],
+        );
+        // not reachable from any branch
+        add_at(&storage, 3, &[Hash([1; 32])], vec![field("a"), field("c")]);
+        let repo = storage.create_repo(Uuid::nil()).unwrap();
+        storage.set_branch_head(repo, Uuid::nil(), item).unwrap();
+
+        let touching = |prefix: &[PathElement]| {
+            let mut res = storage
+                .changes_touching(repo, prefix)
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+            res.sort();
+            res
+        };
+        assert_eq!(touching(&[]), vec![root, item]);
+        assert_eq!(touching(&[field("a")]), vec![root, item]);
+        assert_eq!(touching(&[field("a"), field("b")]), vec![root]);
+        assert_eq!(touching(&[field("a"), field("list")]), vec![item]);
+        assert_eq!(touching(&[field("a"), field("c")]), vec![]);
+        assert_eq!(touching(&[field("b")]), vec![]);
+
+        assert!(storage
+            .add_change(&Hash([4; 32]), HashAlgorithm::Blake3, b"no cbor", &[])
+            .now_or_never()
+            .unwrap()
+            .is_err());
+        assert_eq!(
+            storage
+                .get_change_id(Hash([4; 32]))
+                .now_or_never()
+                .unwrap()
+                .unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,334 @@
+use std::{future::Future, time::Duration};
+
+use uuid::Uuid;
+
+use super::{Commit, ConfigEntry, Note, RepoStats, Storage};
+use crate::{
+    apply::split::SplitMarker,
+    async_support::{MaybeSend, MaybeSync},
+    types::{change::Hash, hasher::HashAlgorithm, PathElement},
+    Result,
+};
+
+/// attempts include the first try, the backoff doubles after every failed attempt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// time to wait after the failed attempt, counting from 0
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(1 << attempt.min(31))
+            .min(self.max_backoff)
+    }
+}
+
+/**
+ *  retries operations of inner failing with a transient error, see Error::is_transient.
+ *  sleep waits for the given duration, usually the sleep of the async runtime in use.
+ *  every attempt of a write runs in its own transaction and writes are idempotent, so an
+ *  attempt that failed after its transaction committed is not stored twice by the retry.
+ *  */
+#[derive(Debug)]
+pub struct RetryStorage<S, Z> {
+    inner: S,
+    policy: RetryPolicy,
+    sleep: Z,
+}
+
+impl<S, Z, F> RetryStorage<S, Z>
+where
+    Z: Fn(Duration) -> F,
+    F: Future<Output = ()>,
+{
+    pub fn new(inner: S, policy: RetryPolicy, sleep: Z) -> Self {
+        Self {
+            inner,
+            policy,
+            sleep,
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn policy(&self) -> &RetryPolicy {
+        &self.policy
+    }
+
+    async fn retry<T, O, R>(&self, mut operation: O) -> Result<T>
+    where
+        O: FnMut() -> R,
+        R: Future<Output = Result<T>>,
+    {
+        let mut attempt = 0;
+        loop {
+            match operation().await {
+                Err(e) if e.is_transient() && attempt + 1 < self.policy.max_attempts => {
+                    (self.sleep)(self.policy.backoff(attempt)).await;
+                    attempt += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+impl<S, Z, F> Storage for RetryStorage<S, Z>
+where
+    S: Storage + MaybeSync,
+    S::ChangeId: Clone + MaybeSend + MaybeSync,
+    S::BranchId: Clone + MaybeSend + MaybeSync,
+    S::RepoId: Clone + MaybeSend + MaybeSync,
+    Z: Fn(Duration) -> F + MaybeSync,
+    F: Future<Output = ()> + MaybeSend,
+{
+    type ChangeId = S::ChangeId;
+    type BranchId = S::BranchId;
+    type RepoId = S::RepoId;
+
+    async fn add_change(
+        &self,
+        hash: &Hash,
+        algorithm: HashAlgorithm,
+        content: &[u8],
+        parents: &[Hash],
+    ) -> Result<Self::ChangeId> {
+        self.retry(|| self.inner.add_change(hash, algorithm, content, parents))
+            .await
+    }
+
+    async fn commit(
+        &self,
+        repo: Self::RepoId,
+        branch: Self::BranchId,
+        commit: Commit<'_>,
+    ) -> Result<(Self::ChangeId, u64)> {
+        self.retry(|| self.inner.commit(repo.clone(), branch.clone(), commit))
+            .await
+    }
+
+    async fn get_change_id(&self, hash: Hash) -> Result<Option<Self::ChangeId>> {
+        self.retry(|| self.inner.get_change_id(hash)).await
+    }
+
+    async fn get_change_rels(&self, id: Self::ChangeId) -> Result<Vec<Self::ChangeId>> {
+        self.retry(|| self.inner.get_change_rels(id.clone())).await
+    }
+
+    async fn get_change_content(&self, id: Self::ChangeId) -> Result<Vec<u8>> {
+        self.retry(|| self.inner.get_change_content(id.clone()))
+            .await
+    }
+
+    async fn list_changes(&self, after: u64, limit: usize) -> Result<Vec<(u64, Self::ChangeId)>> {
+        self.retry(|| self.inner.list_changes(after, limit)).await
+    }
+
+    async fn get_change_hash(&self, id: Self::ChangeId) -> Result<(Hash, HashAlgorithm)> {
+        self.retry(|| self.inner.get_change_hash(id.clone())).await
+    }
+
+    async fn changes_touching(
+        &self,
+        repo: Self::RepoId,
+        prefix: &[PathElement],
+    ) -> Result<Vec<Self::ChangeId>> {
+        self.retry(|| self.inner.changes_touching(repo.clone(), prefix))
+            .await
+    }
+
+    async fn repo_stats(&self, repo: Self::RepoId) -> Result<RepoStats> {
+        self.retry(|| self.inner.repo_stats(repo.clone())).await
+    }
+
+    async fn set_note(
+        &self,
+        change: Self::ChangeId,
+        name: &str,
+        content: Option<&[u8]>,
+    ) -> Result<()> {
+        self.retry(|| self.inner.set_note(change.clone(), name, content))
+            .await
+    }
+
+    async fn get_notes(&self, change: Self::ChangeId) -> Result<Vec<Note>> {
+        self.retry(|| self.inner.get_notes(change.clone())).await
+    }
+
+    async fn get_repo_id(&self, uuid: Uuid) -> Result<Option<Self::RepoId>> {
+        self.retry(|| self.inner.get_repo_id(uuid)).await
+    }
+
+    async fn create_repo(&self, uuid: Uuid) -> Result<Self::RepoId> {
+        self.retry(|| self.inner.create_repo(uuid)).await
+    }
+
+    async fn get_branches(&self, repo: Self::RepoId) -> Result<Vec<(Uuid, Self::BranchId)>> {
+        self.retry(|| self.inner.get_branches(repo.clone())).await
+    }
+
+    async fn get_branch_id(
+        &self,
+        repo: Self::RepoId,
+        uuid: Uuid,
+    ) -> Result<Option<Self::BranchId>> {
+        self.retry(|| self.inner.get_branch_id(repo.clone(), uuid))
+            .await
+    }
+
+    async fn get_branch_head(&self, branch: Self::BranchId) -> Result<Self::ChangeId> {
+        self.retry(|| self.inner.get_branch_head(branch.clone()))
+            .await
+    }
+
+    async fn set_branch_head(
+        &self,
+        repo: Self::RepoId,
+        uuid: Uuid,
+        head: Self::ChangeId,
+    ) -> Result<Self::BranchId> {
+        self.retry(|| self.inner.set_branch_head(repo.clone(), uuid, head.clone()))
+            .await
+    }
+
+    async fn set_repo_config(
+        &self,
+        repo: Self::RepoId,
+        name: &str,
+        content: Option<&[u8]>,
+    ) -> Result<()> {
+        self.retry(|| self.inner.set_repo_config(repo.clone(), name, content))
+            .await
+    }
+
+    async fn set_branch_config(
+        &self,
+        branch: Self::BranchId,
+        name: &str,
+        content: Option<&[u8]>,
+    ) -> Result<()> {
+        self.retry(|| self.inner.set_branch_config(branch.clone(), name, content))
+            .await
+    }
+
+    async fn get_repo_config(&self, repo: Self::RepoId) -> Result<Vec<ConfigEntry>> {
+        self.retry(|| self.inner.get_repo_config(repo.clone()))
+            .await
+    }
+
+    async fn get_branch_config(&self, branch: Self::BranchId) -> Result<Vec<ConfigEntry>> {
+        self.retry(|| self.inner.get_branch_config(branch.clone()))
+            .await
+    }
+
+    async fn set_branch_config_if(
+        &self,
+        branch: Self::BranchId,
+        prefix: &str,
+        expected: &[ConfigEntry],
+        entries: &[(String, Option<Vec<u8>>)],
+    ) -> Result<bool> {
+        self.retry(|| {
+            self.inner
+                .set_branch_config_if(branch.clone(), prefix, expected, entries)
+        })
+        .await
+    }
+
+    async fn insert_event(
+        &self,
+        repo: Self::RepoId,
+        seq: u64,
+        change: Self::ChangeId,
+    ) -> Result<()> {
+        self.retry(|| self.inner.insert_event(repo.clone(), seq, change.clone()))
+            .await
+    }
+
+    async fn get_group(
+        &self,
+        repo: Self::RepoId,
+        group: Uuid,
+    ) -> Result<Vec<(SplitMarker, Self::ChangeId)>> {
+        self.retry(|| self.inner.get_group(repo.clone(), group))
+            .await
+    }
+
+    async fn changes_since(
+        &self,
+        repo: Self::RepoId,
+        seq: u64,
+    ) -> Result<Vec<(u64, Self::ChangeId)>> {
+        self.retry(|| self.inner.changes_since(repo.clone(), seq))
+            .await
+    }
+
+    async fn close(&self) -> Result<()> {
+        self.inner.close().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn backoff() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(0), Duration::from_millis(10));
+        assert_eq!(policy.backoff(2), Duration::from_millis(40));
+        assert_eq!(policy.backoff(40), Duration::from_secs(1));
+    }
+
+    #[cfg(feature = "db_sqlx")]
+    #[test]
+    fn retries_transient() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        use futures_util::{future::ready, FutureExt};
+
+        use crate::{error::ValueStoreError, Error};
+
+        let storage = RetryStorage::new((), RetryPolicy::default(), |_| ready(()));
+        let attempts = AtomicU32::new(0);
+        let res = storage
+            .retry(|| {
+                let attempt = attempts.fetch_add(1, Ordering::Relaxed);
+                ready(if attempt < 2 {
+                    Err(Error::Sqlx(sqlx::Error::PoolTimedOut))
+                } else {
+                    Ok(attempt)
+                })
+            })
+            .now_or_never()
+            .unwrap();
+        assert!(matches!(res, Ok(2)));
+
+        attempts.store(0, Ordering::Relaxed);
+        let res: Result<()> = storage
+            .retry(|| {
+                attempts.fetch_add(1, Ordering::Relaxed);
+                ready(Err(ValueStoreError::ReadOnly.into()))
+            })
+            .now_or_never()
+            .unwrap();
+        assert!(res.is_err());
+        assert_eq!(attempts.load(Ordering::Relaxed), 1);
+    }
+}
@@ -1,10 +1,24 @@
+use std::{cmp::Reverse, collections::HashMap, str::FromStr};
+
 use futures_util::TryStreamExt;
-use sqlx::SqlitePool;
+use sqlx::{sqlite::SqliteConnectOptions, SqliteConnection, SqlitePool};
+use uuid::Uuid;
 
-use crate::{storage::Storage, types::change::Hash, Result};
+use crate::{
+    apply::split::SplitMarker,
+    error::ValueStoreError,
+    storage::{content_prefixes, Commit, ConfigEntry, Note, RepoStats, Storage, STATS_TOP_N},
+    types::{
+        change::{path_prefix_hash, ChangeContent, Hash},
+        hasher::HashAlgorithm,
+        PathElement,
+    },
+    Result,
+};
 
 pub struct SqliteStorage {
     inner: SqlitePool,
+    read_only: bool,
 }
 
 impl SqliteStorage {
@@ -13,14 +27,143 @@ impl SqliteStorage {
     }
     pub async fn new(pool: SqlitePool) -> Result<Self> {
         sqlx::migrate!("migrations/sqlite").run(&pool).await?;
-        Ok(Self { inner: pool })
+        Self::backfill_path_index(&pool).await?;
+        Ok(Self {
+            inner: pool,
+            read_only: false,
+        })
+    }
+    /**
+     *  opens an existing database without running migrations or index maintenance.
+     *  the schema has to be up to date already, writes fail with ValueStoreError::ReadOnly.
+     *  */
+    pub async fn connect_read_only(url: &str) -> Result<Self> {
+        let options = SqliteConnectOptions::from_str(url)?.read_only(true);
+        Ok(Self {
+            inner: SqlitePool::connect_with(options).await?,
+            read_only: true,
+        })
+    }
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /**
+     *  indexes changes stored before the path index existed, once after the migration adding
+     *  it. changes whose content can't be decoded as a list of ChangeContent are marked as
+     *  unindexable instead of blocking all others. finding no unindexed change is a lookup
+     *  in a partial index, so this is cheap once the backfill is done.
+     *  */
+    async fn backfill_path_index(pool: &SqlitePool) -> Result<()> {
+        let mut trans = pool.begin().await?;
+        let unindexed = sqlx::query!("SELECT id, content FROM changes WHERE paths_indexed == 0")
+            .fetch_all(trans.as_mut())
+            .await?;
+        for change in unindexed {
+            let Ok(prefixes) = content_prefixes(&change.content) else {
+                sqlx::query!("UPDATE changes SET paths_indexed = 2 WHERE id == ?", change.id)
+                    .execute(trans.as_mut())
+                    .await?;
+                continue;
+            };
+            for prefix in prefixes {
+                let prefix = prefix.as_slice();
+                sqlx::query!(
+                    "INSERT OR IGNORE INTO change_paths (change, prefix) VALUES (?, ?)",
+                    change.id,
+                    prefix
+                )
+                .execute(trans.as_mut())
+                .await?;
+            }
+            sqlx::query!("UPDATE changes SET paths_indexed = 1 WHERE id == ?", change.id)
+                .execute(trans.as_mut())
+                .await?;
+        }
+        trans.commit().await?;
+        Ok(())
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct ChangeId(i64);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct BranchId(i64);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct RepoId(i64);
 
+/// records change and its ancestors as reachable in repo, stopping at changes already recorded
+async fn add_repo_changes(conn: &mut SqliteConnection, repo: i64, change: i64) -> Result<()> {
+    sqlx::query!(
+        "WITH RECURSIVE missing(id) AS (SELECT ?1 WHERE NOT EXISTS (SELECT 1 FROM repo_changes WHERE repo == ?2 AND change == ?1) UNION SELECT change_rels.parent FROM change_rels JOIN missing ON change_rels.child == missing.id WHERE NOT EXISTS (SELECT 1 FROM repo_changes WHERE repo == ?2 AND change == change_rels.parent)) INSERT OR IGNORE INTO repo_changes (repo, change) SELECT ?2, id FROM missing",
+        change,
+        repo
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// stores a change unless it exists, returns its id
+async fn insert_change(
+    conn: &mut SqliteConnection,
+    hash: &Hash,
+    algorithm: HashAlgorithm,
+    content: &[u8],
+    parents: &[Hash],
+) -> Result<i64> {
+    if parents.contains(hash) {
+        return Err(ValueStoreError::CorruptHistory { change: Some(*hash) }.into());
+    }
+    let prefixes = content_prefixes(content)?;
+    let change = *hash;
+    let hash = hash.as_slice();
+    let algorithm = algorithm.id();
+    let id = if let Some(Some(id)) = sqlx::query_scalar!(
+        "INSERT OR IGNORE INTO changes (hash, hash_alg, content, paths_indexed) VALUES (?, ?, ?, 1) RETURNING id",
+        hash,
+        algorithm,
+        content
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    {
+        // parents have to be stored before their children, which also rules out cycles
+        for parent in parents {
+            let parent_hash = parent.as_slice();
+            let parent = sqlx::query_scalar!("SELECT id FROM changes WHERE hash==?", parent_hash)
+                .fetch_optional(&mut *conn)
+                .await?
+                .ok_or(ValueStoreError::CorruptHistory {
+                    change: Some(change),
+                })?;
+            sqlx::query!(
+                "INSERT INTO change_rels (parent,child) VALUES (?,?)",
+                parent,
+                id
+            )
+            .execute(&mut *conn)
+            .await?;
+        }
+        for prefix in &prefixes {
+            let prefix = prefix.as_slice();
+            sqlx::query!(
+                "INSERT OR IGNORE INTO change_paths (change, prefix) VALUES (?, ?)",
+                id,
+                prefix
+            )
+            .execute(&mut *conn)
+            .await?;
+        }
+        id
+    } else {
+        sqlx::query_scalar!("SELECT id FROM changes WHERE hash==?", hash)
+            .fetch_one(&mut *conn)
+            .await?
+    };
+    Ok(id)
+}
+
 impl Storage for SqliteStorage {
     type ChangeId = ChangeId;
     type BranchId = BranchId;
@@ -29,39 +172,120 @@ impl Storage for SqliteStorage {
     async fn add_change(
         &self,
         hash: &Hash,
+        algorithm: HashAlgorithm,
         content: &[u8],
         parents: &[Hash],
     ) -> Result<Self::ChangeId> {
+        if self.read_only {
+            return Err(ValueStoreError::ReadOnly.into());
+        }
         let mut trans = self.inner.begin().await?;
-        let hash = hash.as_slice();
-        let id = if let Some(Some(id)) = sqlx::query_scalar!(
-            "INSERT OR IGNORE INTO changes (hash, content) VALUES (?, ?) RETURNING id",
-            hash,
-            content
+        let id = insert_change(trans.as_mut(), hash, algorithm, content, parents).await?;
+        trans.commit().await?;
+        Ok(ChangeId(id))
+    }
+
+    async fn commit(
+        &self,
+        repo: Self::RepoId,
+        branch: Self::BranchId,
+        commit: Commit<'_>,
+    ) -> Result<(Self::ChangeId, u64)> {
+        if self.read_only {
+            return Err(ValueStoreError::ReadOnly.into());
+        }
+        let mut trans = self.inner.begin().await?;
+        let head = sqlx::query_scalar!("SELECT head FROM branch WHERE id == ?", branch.0)
+            .fetch_one(trans.as_mut())
+            .await?;
+        let hash = commit.hash.as_slice();
+        let existing = sqlx::query_scalar!("SELECT id FROM changes WHERE hash==?", hash)
+            .fetch_optional(trans.as_mut())
+            .await?;
+        if existing == Some(head) {
+            let seq = sqlx::query_scalar!(
+                "SELECT seq FROM events WHERE repo == ? AND change == ? ORDER BY seq DESC LIMIT 1",
+                repo.0,
+                head
+            )
+            .fetch_optional(trans.as_mut())
+            .await?;
+            if let Some(seq) = seq {
+                return Ok((ChangeId(head), seq as u64));
+            }
+        }
+        let mut head_is_parent = false;
+        for parent in commit.parents {
+            let parent = parent.as_slice();
+            head_is_parent |= sqlx::query_scalar!("SELECT id FROM changes WHERE hash==?", parent)
+                .fetch_optional(trans.as_mut())
+                .await?
+                == Some(head);
+        }
+        if !head_is_parent {
+            let parent = sqlx::query_scalar!("SELECT hash FROM changes WHERE id == ?", head)
+                .fetch_one(trans.as_mut())
+                .await?;
+            let parent = Hash::try_from(parent.as_slice())
+                .map_err(|_| ValueStoreError::CorruptHistory { change: None })?;
+            return Err(ValueStoreError::HeadParentMismatch { parent }.into());
+        }
+        let id = insert_change(
+            trans.as_mut(),
+            commit.hash,
+            commit.algorithm,
+            commit.content,
+            commit.parents,
         )
-        .fetch_optional(trans.as_mut())
-        .await?
-        {
-            for parent in parents {
-                let parent = parent.as_slice();
-                let parent = sqlx::query_scalar!("SELECT id FROM changes WHERE hash==?", parent)
-                    .fetch_one(trans.as_mut())
-                    .await?;
+        .await?;
+        // a connection that moved the head since it was read makes this write fail as busy
+        sqlx::query!("UPDATE branch SET head = ? WHERE id == ?", id, branch.0)
+            .execute(trans.as_mut())
+            .await?;
+        add_repo_changes(trans.as_mut(), repo.0, id).await?;
+        for (name, content) in commit.config {
+            if let Some(content) = content {
+                sqlx::query!(
+                    "INSERT INTO branch_config (branch, name, content) VALUES (?, ?, ?) ON CONFLICT (branch, name) DO UPDATE SET content = excluded.content",
+                    branch.0,
+                    name,
+                    content
+                )
+                .execute(trans.as_mut())
+                .await?;
+            } else {
                 sqlx::query!(
-                    "INSERT INTO change_rels (parent,child) VALUES (?,?)",
-                    parent,
-                    id
+                    "DELETE FROM branch_config WHERE branch == ? AND name == ?",
+                    branch.0,
+                    name
                 )
                 .execute(trans.as_mut())
                 .await?;
             }
-            id
-        } else {
-            sqlx::query_scalar!("SELECT id FROM changes WHERE hash==?", hash)
-                .fetch_one(trans.as_mut())
-                .await?
-        };
-        Ok(ChangeId(id))
+        }
+        if let Some(group) = commit.group {
+            let grp = group.group.as_bytes().as_slice();
+            sqlx::query!(
+                "INSERT INTO change_groups (repo, grp, part, parts, change) VALUES (?, ?, ?, ?, ?) ON CONFLICT (repo, grp, part) DO UPDATE SET parts = excluded.parts, change = excluded.change",
+                repo.0,
+                grp,
+                group.part,
+                group.parts,
+                id
+            )
+            .execute(trans.as_mut())
+            .await?;
+        }
+        let seq = sqlx::query_scalar!(
+            r#"INSERT INTO events (repo, seq, change) SELECT ?, COALESCE(MAX(seq), 0) + 1, ? FROM events WHERE repo == ? RETURNING seq as "seq!: i64""#,
+            repo.0,
+            id,
+            repo.0
+        )
+        .fetch_one(trans.as_mut())
+        .await?;
+        trans.commit().await?;
+        Ok((ChangeId(id), seq as u64))
     }
 
     async fn get_change_id(&self, hash: Hash) -> Result<Option<Self::ChangeId>> {
@@ -92,4 +316,450 @@ impl Storage for SqliteStorage {
                 .await?,
         )
     }
+
+    async fn list_changes(&self, after: u64, limit: usize) -> Result<Vec<(u64, Self::ChangeId)>> {
+        let after = i64::try_from(after).unwrap_or(i64::MAX);
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        Ok(sqlx::query_scalar!(
+            "SELECT id FROM changes WHERE id > ? ORDER BY id ASC LIMIT ?",
+            after,
+            limit
+        )
+        .fetch(&self.inner)
+        .map_ok(|id| (id as u64, ChangeId(id)))
+        .try_collect()
+        .await?)
+    }
+
+    async fn get_change_hash(&self, id: Self::ChangeId) -> Result<(Hash, HashAlgorithm)> {
+        let change = sqlx::query!("SELECT hash, hash_alg FROM changes WHERE id == ?", id.0)
+            .fetch_one(&self.inner)
+            .await?;
+        let hash = Hash::try_from(change.hash.as_slice())
+            .map_err(|_| ValueStoreError::CorruptHistory { change: None })?;
+        let algorithm = u8::try_from(change.hash_alg)
+            .ok()
+            .and_then(HashAlgorithm::from_id)
+            .ok_or(ValueStoreError::CorruptHistory { change: Some(hash) })?;
+        Ok((hash, algorithm))
+    }
+
+    async fn changes_touching(
+        &self,
+        repo: Self::RepoId,
+        prefix: &[PathElement],
+    ) -> Result<Vec<Self::ChangeId>> {
+        let prefix = path_prefix_hash(prefix);
+        let prefix = prefix.as_slice();
+        Ok(
+            sqlx::query_scalar!(
+                "SELECT change_paths.change FROM change_paths JOIN repo_changes ON change_paths.change == repo_changes.change WHERE repo_changes.repo == ? AND change_paths.prefix == ? ORDER BY change_paths.change ASC",
+                repo.0,
+                prefix
+            )
+            .fetch(&self.inner)
+            .map_ok(ChangeId)
+            .try_collect()
+            .await?,
+        )
+    }
+
+    async fn repo_stats(&self, repo: Self::RepoId) -> Result<RepoStats> {
+        let mut stats = RepoStats::default();
+        let mut paths: HashMap<Vec<PathElement>, u64> = HashMap::new();
+        let mut changes = sqlx::query!(
+            "SELECT changes.hash, changes.content FROM changes JOIN repo_changes ON changes.id == repo_changes.change WHERE repo_changes.repo == ?",
+            repo.0
+        )
+        .fetch(&self.inner);
+        while let Some(change) = changes.try_next().await? {
+            let size = change.content.len() as u64;
+            stats.changes += 1;
+            stats.payload_bytes += size;
+            let hash = Hash::try_from(change.hash.as_slice())
+                .map_err(|_| ValueStoreError::CorruptHistory { change: None })?;
+            stats.largest_changes.push((hash, size));
+            let content: Vec<ChangeContent> = ciborium::from_reader(change.content.as_slice())?;
+            for change in content {
+                *paths.entry(change.path().to_vec()).or_default() += 1;
+            }
+        }
+        drop(changes);
+        stats.largest_changes.sort_unstable_by_key(|(hash, size)| (Reverse(*size), *hash));
+        stats.largest_changes.truncate(STATS_TOP_N);
+        stats.hot_paths = paths.into_iter().collect();
+        stats
+            .hot_paths
+            .sort_unstable_by(|(p1, c1), (p2, c2)| c2.cmp(c1).then_with(|| p1.cmp(p2)));
+        stats.hot_paths.truncate(STATS_TOP_N);
+
+        let branches = sqlx::query!("SELECT uuid, head FROM branch WHERE repo == ?", repo.0)
+            .fetch_all(&self.inner)
+            .await?;
+        stats.branches = branches.len() as u64;
+        // changes are stored after their parents, so in id order the depths of all parents
+        // are known when a child is reached. changes without parents have depth 1.
+        let mut rels = sqlx::query!(
+            "SELECT change_rels.child, change_rels.parent FROM change_rels JOIN repo_changes ON change_rels.child == repo_changes.change WHERE repo_changes.repo == ? ORDER BY change_rels.child ASC",
+            repo.0
+        )
+        .fetch(&self.inner);
+        let mut depths: HashMap<i64, u64> = HashMap::new();
+        while let Some(rel) = rels.try_next().await? {
+            let depth = depths.get(&rel.parent).copied().unwrap_or(1) + 1;
+            let entry = depths.entry(rel.child).or_default();
+            *entry = depth.max(*entry);
+        }
+        drop(rels);
+        for branch in branches {
+            let depth = depths.get(&branch.head).copied().unwrap_or(1);
+            stats
+                .branch_depths
+                .push((Uuid::from_slice(&branch.uuid)?, depth));
+        }
+        Ok(stats)
+    }
+
+    async fn set_note(
+        &self,
+        change: Self::ChangeId,
+        name: &str,
+        content: Option<&[u8]>,
+    ) -> Result<()> {
+        if self.read_only {
+            return Err(ValueStoreError::ReadOnly.into());
+        }
+        if let Some(content) = content {
+            sqlx::query!(
+                "INSERT INTO change_notes (change, name, content) VALUES (?, ?, ?) ON CONFLICT (change, name) DO UPDATE SET content = excluded.content",
+                change.0,
+                name,
+                content
+            )
+            .execute(&self.inner)
+            .await?;
+        } else {
+            sqlx::query!(
+                "DELETE FROM change_notes WHERE change == ? AND name == ?",
+                change.0,
+                name
+            )
+            .execute(&self.inner)
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn get_notes(&self, change: Self::ChangeId) -> Result<Vec<Note>> {
+        Ok(sqlx::query!(
+            "SELECT name, content FROM change_notes WHERE change == ? ORDER BY name ASC",
+            change.0
+        )
+        .fetch(&self.inner)
+        .map_ok(|note| (note.name, note.content))
+        .try_collect()
+        .await?)
+    }
+
+    async fn get_repo_id(&self, uuid: Uuid) -> Result<Option<Self::RepoId>> {
+        let uuid = uuid.as_bytes().as_slice();
+        Ok(
+            sqlx::query_scalar!("SELECT id FROM repositories WHERE uuid == ?", uuid)
+                .fetch_optional(&self.inner)
+                .await?
+                .map(RepoId),
+        )
+    }
+
+    async fn create_repo(&self, uuid: Uuid) -> Result<Self::RepoId> {
+        if self.read_only {
+            return Err(ValueStoreError::ReadOnly.into());
+        }
+        let uuid = uuid.as_bytes().as_slice();
+        sqlx::query!(
+            "INSERT INTO repositories (uuid, descr) VALUES (?, '') ON CONFLICT (uuid) DO NOTHING",
+            uuid
+        )
+        .execute(&self.inner)
+        .await?;
+        let id = sqlx::query_scalar!("SELECT id FROM repositories WHERE uuid == ?", uuid)
+            .fetch_one(&self.inner)
+            .await?;
+        Ok(RepoId(id))
+    }
+
+    async fn get_branches(&self, repo: Self::RepoId) -> Result<Vec<(Uuid, Self::BranchId)>> {
+        let branches = sqlx::query!("SELECT uuid, id FROM branch WHERE repo == ?", repo.0)
+            .fetch_all(&self.inner)
+            .await?;
+        let mut res = Vec::with_capacity(branches.len());
+        for branch in branches {
+            res.push((Uuid::from_slice(&branch.uuid)?, BranchId(branch.id)));
+        }
+        Ok(res)
+    }
+
+    async fn get_branch_id(
+        &self,
+        repo: Self::RepoId,
+        uuid: Uuid,
+    ) -> Result<Option<Self::BranchId>> {
+        let uuid = uuid.as_bytes().as_slice();
+        Ok(sqlx::query_scalar!(
+            "SELECT id FROM branch WHERE repo == ? AND uuid == ?",
+            repo.0,
+            uuid
+        )
+        .fetch_optional(&self.inner)
+        .await?
+        .map(BranchId))
+    }
+
+    async fn get_branch_head(&self, branch: Self::BranchId) -> Result<Self::ChangeId> {
+        Ok(ChangeId(
+            sqlx::query_scalar!("SELECT head FROM branch WHERE id == ?", branch.0)
+                .fetch_one(&self.inner)
+                .await?,
+        ))
+    }
+
+    async fn set_branch_head(
+        &self,
+        repo: Self::RepoId,
+        uuid: Uuid,
+        head: Self::ChangeId,
+    ) -> Result<Self::BranchId> {
+        if self.read_only {
+            return Err(ValueStoreError::ReadOnly.into());
+        }
+        let uuid = uuid.as_bytes().as_slice();
+        let mut trans = self.inner.begin().await?;
+        sqlx::query!(
+            "INSERT INTO branch (uuid, repo, head, descr) VALUES (?, ?, ?, '') ON CONFLICT (uuid, repo) DO UPDATE SET head = excluded.head",
+            uuid,
+            repo.0,
+            head.0
+        )
+        .execute(trans.as_mut())
+        .await?;
+        add_repo_changes(trans.as_mut(), repo.0, head.0).await?;
+        let id = sqlx::query_scalar!(
+            "SELECT id FROM branch WHERE repo == ? AND uuid == ?",
+            repo.0,
+            uuid
+        )
+        .fetch_one(trans.as_mut())
+        .await?;
+        trans.commit().await?;
+        Ok(BranchId(id))
+    }
+
+    async fn set_repo_config(
+        &self,
+        repo: Self::RepoId,
+        name: &str,
+        content: Option<&[u8]>,
+    ) -> Result<()> {
+        if self.read_only {
+            return Err(ValueStoreError::ReadOnly.into());
+        }
+        if let Some(content) = content {
+            sqlx::query!(
+                "INSERT INTO repo_config (repo, name, content) VALUES (?, ?, ?) ON CONFLICT (repo, name) DO UPDATE SET content = excluded.content",
+                repo.0,
+                name,
+                content
+            )
+            .execute(&self.inner)
+            .await?;
+        } else {
+            sqlx::query!(
+                "DELETE FROM repo_config WHERE repo == ? AND name == ?",
+                repo.0,
+                name
+            )
+            .execute(&self.inner)
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn set_branch_config(
+        &self,
+        branch: Self::BranchId,
+        name: &str,
+        content: Option<&[u8]>,
+    ) -> Result<()> {
+        if self.read_only {
+            return Err(ValueStoreError::ReadOnly.into());
+        }
+        if let Some(content) = content {
+            sqlx::query!(
+                "INSERT INTO branch_config (branch, name, content) VALUES (?, ?, ?) ON CONFLICT (branch, name) DO UPDATE SET content = excluded.content",
+                branch.0,
+                name,
+                content
+            )
+            .execute(&self.inner)
+            .await?;
+        } else {
+            sqlx::query!(
+                "DELETE FROM branch_config WHERE branch == ? AND name == ?",
+                branch.0,
+                name
+            )
+            .execute(&self.inner)
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn get_repo_config(&self, repo: Self::RepoId) -> Result<Vec<ConfigEntry>> {
+        Ok(sqlx::query!(
+            "SELECT name, content FROM repo_config WHERE repo == ? ORDER BY name ASC",
+            repo.0
+        )
+        .fetch(&self.inner)
+        .map_ok(|entry| (entry.name, entry.content))
+        .try_collect()
+        .await?)
+    }
+
+    async fn get_branch_config(&self, branch: Self::BranchId) -> Result<Vec<ConfigEntry>> {
+        Ok(sqlx::query!(
+            "SELECT name, content FROM branch_config WHERE branch == ? ORDER BY name ASC",
+            branch.0
+        )
+        .fetch(&self.inner)
+        .map_ok(|entry| (entry.name, entry.content))
+        .try_collect()
+        .await?)
+    }
+
+    async fn set_branch_config_if(
+        &self,
+        branch: Self::BranchId,
+        prefix: &str,
+        expected: &[ConfigEntry],
+        entries: &[(String, Option<Vec<u8>>)],
+    ) -> Result<bool> {
+        if self.read_only {
+            return Err(ValueStoreError::ReadOnly.into());
+        }
+        let mut trans = self.inner.begin().await?;
+        let current: Vec<ConfigEntry> = sqlx::query!(
+            "SELECT name, content FROM branch_config WHERE branch == ? AND substr(name, 1, length(?)) == ? ORDER BY name ASC",
+            branch.0,
+            prefix,
+            prefix
+        )
+        .fetch(trans.as_mut())
+        .map_ok(|entry| (entry.name, entry.content))
+        .try_collect()
+        .await?;
+        if current != expected {
+            return Ok(false);
+        }
+        // a connection that changed the entries since they were read makes the writes fail as busy
+        for (name, content) in entries {
+            if let Some(content) = content {
+                sqlx::query!(
+                    "INSERT INTO branch_config (branch, name, content) VALUES (?, ?, ?) ON CONFLICT (branch, name) DO UPDATE SET content = excluded.content",
+                    branch.0,
+                    name,
+                    content
+                )
+                .execute(trans.as_mut())
+                .await?;
+            } else {
+                sqlx::query!(
+                    "DELETE FROM branch_config WHERE branch == ? AND name == ?",
+                    branch.0,
+                    name
+                )
+                .execute(trans.as_mut())
+                .await?;
+            }
+        }
+        trans.commit().await?;
+        Ok(true)
+    }
+
+    async fn insert_event(
+        &self,
+        repo: Self::RepoId,
+        seq: u64,
+        change: Self::ChangeId,
+    ) -> Result<()> {
+        if self.read_only {
+            return Err(ValueStoreError::ReadOnly.into());
+        }
+        let seq_id = seq as i64;
+        sqlx::query!(
+            "INSERT INTO events (repo, seq, change) VALUES (?, ?, ?) ON CONFLICT (repo, seq) DO NOTHING",
+            repo.0,
+            seq_id,
+            change.0
+        )
+        .execute(&self.inner)
+        .await?;
+        // events are never changed, so the one at seq is still there
+        let existing = sqlx::query_scalar!(
+            "SELECT change FROM events WHERE repo == ? AND seq == ?",
+            repo.0,
+            seq_id
+        )
+        .fetch_one(&self.inner)
+        .await?;
+        if existing != change.0 {
+            return Err(ValueStoreError::LogConflict { seq }.into());
+        }
+        Ok(())
+    }
+
+    async fn get_group(
+        &self,
+        repo: Self::RepoId,
+        group: Uuid,
+    ) -> Result<Vec<(SplitMarker, Self::ChangeId)>> {
+        let grp = group.as_bytes().as_slice();
+        let rows = sqlx::query!(
+            "SELECT part, parts, change FROM change_groups WHERE repo == ? AND grp == ? ORDER BY part ASC",
+            repo.0,
+            grp
+        )
+        .fetch_all(&self.inner)
+        .await?;
+        rows.into_iter()
+            .map(|row| {
+                let (Ok(part), Ok(parts)) = (row.part.try_into(), row.parts.try_into()) else {
+                    return Err(ValueStoreError::CorruptHistory { change: None }.into());
+                };
+                let marker = SplitMarker { group, part, parts };
+                Ok((marker, ChangeId(row.change)))
+            })
+            .collect()
+    }
+
+    async fn changes_since(
+        &self,
+        repo: Self::RepoId,
+        seq: u64,
+    ) -> Result<Vec<(u64, Self::ChangeId)>> {
+        let seq = seq as i64;
+        Ok(sqlx::query!(
+            "SELECT seq, change FROM events WHERE repo == ? AND seq > ? ORDER BY seq ASC",
+            repo.0,
+            seq
+        )
+        .fetch(&self.inner)
+        .map_ok(|event| (event.seq as u64, ChangeId(event.change)))
+        .try_collect()
+        .await?)
+    }
+
+    async fn close(&self) -> Result<()> {
+        self.inner.close().await;
+        Ok(())
+    }
 }
@@ -1,6 +1,7 @@
 use std::{
     cmp::Ordering::{Equal, Greater, Less},
     fmt::{self, LowerHex, UpperHex},
+    str::FromStr,
 };
 
 use serde::{
@@ -9,42 +10,254 @@ use serde::{
     Deserialize, Serialize,
 };
 
+use sha2::{Digest, Sha256};
+
 use crate::error::ValueStoreError;
 
-use super::{PathElement, Value};
+use super::{hasher::HashAlgorithm, value::cbor_header_size, Path, PathElement, Value};
 
 #[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
 pub enum ChangeContent {
     Insert {
-        path: Vec<PathElement>,
+        path: Path,
         value: Value,
     },
     Replace {
-        path: Vec<PathElement>,
+        path: Path,
         old: Value,
         new: Value,
     },
     Delete {
-        path: Vec<PathElement>,
+        path: Path,
         old: Value,
     },
 }
 
-pub type Hash = [u8; 32];
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Hash(pub [u8; 32]);
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct HashParseError {
+    pub position: usize,
+}
+
+impl Hash {
+    /// number of hex digits returned by short
+    pub const SHORT_LEN: usize = 12;
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+    pub fn as_slice(&self) -> &[u8] {
+        &self.0
+    }
+    /// abbreviated lowercase hex representation for display to users
+    pub fn short(&self) -> String {
+        let mut res = self.to_string();
+        res.truncate(Self::SHORT_LEN);
+        res
+    }
+}
+
+impl From<[u8; 32]> for Hash {
+    fn from(value: [u8; 32]) -> Self {
+        Hash(value)
+    }
+}
+
+impl From<Hash> for [u8; 32] {
+    fn from(value: Hash) -> Self {
+        value.0
+    }
+}
+
+impl TryFrom<&[u8]> for Hash {
+    type Error = std::array::TryFromSliceError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        Ok(Hash(value.try_into()?))
+    }
+}
+
+impl AsRef<[u8]> for Hash {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// `#` prefixes the output with 0x
+impl LowerHex for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            f.write_str("0x")?;
+        }
+        for v in self.0 {
+            write!(f, "{v:02x}")?
+        }
+        Ok(())
+    }
+}
+
+impl UpperHex for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            f.write_str("0x")?;
+        }
+        for v in self.0 {
+            write!(f, "{v:02X}")?
+        }
+        Ok(())
+    }
+}
 
-pub fn format_hash_lower(hash: &Hash, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    f.write_str("0x")?;
-    for v in hash {
-        LowerHex::fmt(v, f)?
+impl fmt::Display for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        LowerHex::fmt(self, f)
     }
-    Ok(())
 }
-pub fn format_hash_upper(hash: &Hash, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    f.write_str("0x")?;
-    for v in hash {
-        UpperHex::fmt(v, f)?
+
+impl fmt::Debug for Hash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Hash({self})")
     }
-    Ok(())
+}
+
+impl FromStr for Hash {
+    type Err = HashParseError;
+
+    /// parses 64 hex digits of either case, optionally prefixed with 0x
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (offset, digits) = match s.strip_prefix("0x") {
+            Some(digits) => (2, digits.as_bytes()),
+            None => (0, s.as_bytes()),
+        };
+        let mut res = [0u8; 32];
+        for (i, byte) in res.iter_mut().enumerate() {
+            let mut v = 0;
+            for pos in [2 * i, 2 * i + 1] {
+                let digit = digits
+                    .get(pos)
+                    .and_then(|d| (*d as char).to_digit(16))
+                    .ok_or(HashParseError {
+                        position: offset + pos,
+                    })?;
+                v = (v << 4) | digit as u8;
+            }
+            *byte = v;
+        }
+        if digits.len() > 64 {
+            Err(HashParseError {
+                position: offset + 64,
+            })
+        } else {
+            Ok(Hash(res))
+        }
+    }
+}
+
+impl fmt::Display for HashParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid hash at position {}", self.position)
+    }
+}
+
+impl std::error::Error for HashParseError {}
+
+impl Serialize for Hash {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+struct HashVisitor {}
+
+impl<'de> Visitor<'de> for HashVisitor {
+    type Value = Hash;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("32 bytes")
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Hash::try_from(v).map_err(|_| E::invalid_length(v.len(), &self))
+    }
+
+    /// hashes used to be serialized as a sequence of 32 integers
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        let mut res = [0u8; 32];
+        for (i, byte) in res.iter_mut().enumerate() {
+            *byte = seq
+                .next_element()?
+                .ok_or_else(|| <A::Error as de::Error>::invalid_length(i, &self))?;
+        }
+        if seq.next_element::<u8>()?.is_some() {
+            return Err(<A::Error as de::Error>::invalid_length(33, &self));
+        }
+        Ok(Hash(res))
+    }
+}
+
+impl<'de> Deserialize<'de> for Hash {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_bytes(HashVisitor {})
+    }
+}
+
+/**
+ *  hashes every prefix of path (including the empty one) incrementally.
+ *  prefix hashes are used by storage to index which changes touch a subtree.
+ *  */
+fn push_prefix_hashes(path: &[PathElement], out: &mut Vec<Hash>) {
+    let mut hasher = Sha256::new();
+    out.push(Hash(hasher.clone().finalize().into()));
+    for elem in path {
+        match elem {
+            PathElement::Field(name) => {
+                hasher.update(b"f");
+                hasher.update((name.len() as u64).to_le_bytes());
+                hasher.update(name.as_bytes());
+            }
+            PathElement::Index(index) => {
+                hasher.update(b"i");
+                hasher.update(index.to_le_bytes());
+            }
+            PathElement::Append => hasher.update(b"a"),
+            PathElement::FromEnd(back) => {
+                hasher.update(b"e");
+                hasher.update(back.to_le_bytes());
+            }
+        }
+        out.push(Hash(hasher.clone().finalize().into()));
+    }
+}
+
+pub fn path_prefix_hash(prefix: &[PathElement]) -> Hash {
+    let mut hashes = Vec::with_capacity(prefix.len() + 1);
+    push_prefix_hashes(prefix, &mut hashes);
+    hashes[prefix.len()]
+}
+
+/// sorted and deduplicated prefix hashes of all paths touched by changes
+pub fn path_prefix_hashes<'l, I: IntoIterator<Item = &'l ChangeContent>>(changes: I) -> Vec<Hash> {
+    let mut hashes = Vec::new();
+    for change in changes {
+        push_prefix_hashes(change.path(), &mut hashes);
+    }
+    hashes.sort_unstable();
+    hashes.dedup();
+    hashes
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -73,6 +286,30 @@ pub struct Change {
     pub content: Vec<ChangeContent>,
 }
 
+impl Parents {
+    pub fn iter(&self) -> impl Iterator<Item = &Hash> {
+        let (p1, p2) = match self {
+            Parents::One(p) => (p, None),
+            Parents::Two(p1, p2) => (p1, Some(p2)),
+        };
+        std::iter::once(p1).chain(p2)
+    }
+}
+
+impl Change {
+    /**
+     *  hash of a change with the given parents and encoded content.
+     *  the algorithm id is part of the hashed data so hashes of different algorithms never collide.
+     *  */
+    pub fn compute_hash(algorithm: HashAlgorithm, parents: &Parents, content: &[u8]) -> Hash {
+        let id = [algorithm.id()];
+        let mut parts: Vec<&[u8]> = vec![&id];
+        parts.extend(parents.iter().map(|p| p.as_slice()));
+        parts.push(content);
+        algorithm.hash_parts(parts)
+    }
+}
+
 impl Serialize for Parents {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -140,6 +377,80 @@ impl<'de> Deserialize<'de> for Parents {
 }
 
 impl ChangeContent {
+    pub fn path(&self) -> &[PathElement] {
+        match self {
+            ChangeContent::Insert { path, .. } => path,
+            ChangeContent::Replace { path, .. } => path,
+            ChangeContent::Delete { path, .. } => path,
+        }
+    }
+    /**
+     *  prefix of the path below which the change may have modified or moved values.
+     *  inserting into or deleting from an array shifts all later siblings, so the prefix ends
+     *  at the array. relative positions can only be resolved against the value before the
+     *  change, so it also ends at the array of the first Append or FromEnd.
+     *  */
+    pub fn affected_prefix(&self) -> &[PathElement] {
+        let path = self.path();
+        let end = match (self, path.last()) {
+            (ChangeContent::Insert { .. } | ChangeContent::Delete { .. }, Some(last))
+                if !matches!(last, PathElement::Field(_)) =>
+            {
+                path.len() - 1
+            }
+            _ => path.len(),
+        };
+        let relative = path
+            .iter()
+            .position(|elem| matches!(elem, PathElement::Append | PathElement::FromEnd(_)))
+            .unwrap_or(end);
+        &path[..end.min(relative)]
+    }
+    /// upper bound of the cbor encoded size in bytes, see [Value::encoded_size_hint]
+    pub fn encoded_size_hint(&self) -> usize {
+        let text = |s: &str| cbor_header_size(s.len() as u64) + s.len();
+        let path = |path: &[PathElement]| {
+            cbor_header_size(path.len() as u64)
+                + path
+                    .iter()
+                    .map(|elem| match elem {
+                        PathElement::Field(name) => text(name),
+                        PathElement::Index(index) => cbor_header_size(*index as u64),
+                        PathElement::Append => 1,
+                        PathElement::FromEnd(back) => cbor_header_size(*back as u64),
+                    })
+                    .sum::<usize>()
+        };
+        // externally tagged: a map from the variant name to a map of the fields
+        let (variant, fields) = match self {
+            ChangeContent::Insert { path: p, value } => (
+                "Insert",
+                text("value") + value.encoded_size_hint() + text("path") + path(p),
+            ),
+            ChangeContent::Replace { path: p, old, new } => (
+                "Replace",
+                text("old")
+                    + old.encoded_size_hint()
+                    + text("new")
+                    + new.encoded_size_hint()
+                    + text("path")
+                    + path(p),
+            ),
+            ChangeContent::Delete { path: p, old } => (
+                "Delete",
+                text("old") + old.encoded_size_hint() + text("path") + path(p),
+            ),
+        };
+        2 + text(variant) + fields
+    }
+    /// canonical order of changes, see [crate::apply::coalesce::canonical_order]
+    pub fn canonicalize(changes: Vec<ChangeContent>) -> Vec<ChangeContent> {
+        crate::apply::coalesce::canonical_order(changes)
+    }
+    /// collapses redundant changes, see [crate::apply::coalesce::coalesce]
+    pub fn coalesce(changes: Vec<ChangeContent>) -> Vec<ChangeContent> {
+        crate::apply::coalesce::coalesce(changes)
+    }
     pub fn revert(self)->Self{
         match self{
             ChangeContent::Insert { path, value } => ChangeContent::Delete { path , old: value },
@@ -148,3 +459,73 @@ impl ChangeContent {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hash_hex_roundtrip() {
+        let mut bytes = [0u8; 32];
+        bytes[0] = 0x0a;
+        bytes[31] = 0xff;
+        let hash = Hash(bytes);
+        let hex = hash.to_string();
+        assert_eq!(hex.len(), 64);
+        assert!(hex.starts_with("0a00"));
+        assert_eq!(hash.short(), "0a0000000000");
+        assert_eq!(hex.parse(), Ok(hash));
+        assert_eq!(format!("{hash:#X}").parse(), Ok(hash));
+        assert_eq!(hex[..63].parse::<Hash>(), Err(HashParseError { position: 63 }));
+        assert_eq!(
+            format!("{hex}0").parse::<Hash>(),
+            Err(HashParseError { position: 64 })
+        );
+        assert_eq!("0xg".parse::<Hash>(), Err(HashParseError { position: 2 }));
+    }
+
+    #[test]
+    fn hash_serde() {
+        let hash = Hash([7; 32]);
+        let mut buf = Vec::new();
+        ciborium::into_writer(&hash, &mut buf).unwrap();
+        assert_eq!(buf.len(), 34);
+        assert_eq!(ciborium::from_reader::<Hash, _>(buf.as_slice()).unwrap(), hash);
+        buf.clear();
+        ciborium::into_writer(&[7u8; 32], &mut buf).unwrap();
+        assert_eq!(ciborium::from_reader::<Hash, _>(buf.as_slice()).unwrap(), hash);
+    }
+
+    #[test]
+    fn size_hint() {
+        use std::{collections::HashMap, sync::Arc};
+
+        let changes = [
+            ChangeContent::Insert {
+                path: vec![PathElement::Field("users".to_string()), PathElement::Append].into(),
+                value: Value::from(HashMap::from([
+                    ("name".to_string(), Value::String(Arc::new("a".repeat(300)))),
+                    ("age".to_string(), Value::Integer(-70000)),
+                ])),
+            },
+            ChangeContent::Replace {
+                path: vec![PathElement::Index(1000), PathElement::FromEnd(30)].into(),
+                old: Value::Bool(true),
+                new: Value::Array(Arc::new(vec![Value::Integer(24); 30])),
+            },
+            ChangeContent::Delete {
+                path: vec![].into(),
+                old: Value::Integer(i64::MIN),
+            },
+        ];
+        for change in changes {
+            let mut buf = Vec::new();
+            ciborium::into_writer(&change, &mut buf).unwrap();
+            assert_eq!(change.encoded_size_hint(), buf.len(), "{change:?}");
+        }
+        let float = Value::Float(1.5);
+        let mut buf = Vec::new();
+        ciborium::into_writer(&float, &mut buf).unwrap();
+        assert!(float.encoded_size_hint() >= buf.len());
+    }
+}
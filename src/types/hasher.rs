@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+
+use super::change::Hash;
+
+/// algorithms change hashes can be computed with. the discriminant is stored with each change.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum HashAlgorithm {
+    #[default]
+    Blake3 = 1,
+    Sha256 = 2,
+}
+
+pub trait Hasher: Default {
+    const ALGORITHM: HashAlgorithm;
+    fn update(&mut self, data: &[u8]);
+    fn finalize(self) -> Hash;
+}
+
+#[derive(Default)]
+pub struct Blake3Hasher(blake3::Hasher);
+
+impl Hasher for Blake3Hasher {
+    const ALGORITHM: HashAlgorithm = HashAlgorithm::Blake3;
+
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize(self) -> Hash {
+        Hash(*self.0.finalize().as_bytes())
+    }
+}
+
+#[derive(Default)]
+pub struct Sha256Hasher(sha2::Sha256);
+
+impl Hasher for Sha256Hasher {
+    const ALGORITHM: HashAlgorithm = HashAlgorithm::Sha256;
+
+    fn update(&mut self, data: &[u8]) {
+        sha2::Digest::update(&mut self.0, data);
+    }
+
+    fn finalize(self) -> Hash {
+        Hash(sha2::Digest::finalize(self.0).into())
+    }
+}
+
+impl HashAlgorithm {
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(HashAlgorithm::Blake3),
+            2 => Some(HashAlgorithm::Sha256),
+            _ => None,
+        }
+    }
+
+    /// hashes the concatenation of parts
+    pub fn hash_parts<'l, I: IntoIterator<Item = &'l [u8]>>(self, parts: I) -> Hash {
+        fn hash<H: Hasher, I: IntoIterator<Item = T>, T: AsRef<[u8]>>(parts: I) -> Hash {
+            let mut hasher = H::default();
+            for part in parts {
+                hasher.update(part.as_ref());
+            }
+            hasher.finalize()
+        }
+        match self {
+            HashAlgorithm::Blake3 => hash::<Blake3Hasher, _, _>(parts),
+            HashAlgorithm::Sha256 => hash::<Sha256Hasher, _, _>(parts),
+        }
+    }
+
+    pub fn hash(self, data: &[u8]) -> Hash {
+        self.hash_parts([data])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::HashAlgorithm;
+
+    #[test]
+    fn known_hashes() {
+        assert_eq!(
+            HashAlgorithm::Sha256.hash(b"abc").as_slice()[..4],
+            [0xba, 0x78, 0x16, 0xbf]
+        );
+        assert_eq!(
+            HashAlgorithm::Blake3.hash(b"abc").as_slice()[..4],
+            [0x64, 0x37, 0xb3, 0xac]
+        );
+        assert_eq!(
+            HashAlgorithm::Blake3.hash_parts([b"a".as_slice(), b"bc"]),
+            HashAlgorithm::Blake3.hash(b"abc")
+        );
+    }
+
+    #[test]
+    fn ids() {
+        for alg in [HashAlgorithm::Blake3, HashAlgorithm::Sha256] {
+            assert_eq!(HashAlgorithm::from_id(alg.id()), Some(alg));
+        }
+        assert_eq!(HashAlgorithm::from_id(0), None);
+    }
+}
@@ -1,10 +1,14 @@
 pub mod path_element;
-pub mod change_tree;
+pub mod path_pattern;
 pub use path_element::PathElement;
+pub mod path;
+pub use path::{Path, PathRef};
 
 pub mod value;
 pub use value::Value;
+pub mod value_hash;
 
 pub mod change;
+pub mod hasher;
 
 pub mod repository;
@@ -0,0 +1,157 @@
+use std::{borrow::Borrow, fmt::Debug, ops::Deref, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use super::PathElement;
+
+/// longest path accepted where paths are processed recursively, like ChangeTree
+pub const MAX_DEPTH: usize = 512;
+
+/// shared immutable path, cloning only bumps a reference count
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Default)]
+pub struct Path(Arc<[PathElement]>);
+
+/// borrowed path
+pub type PathRef<'l> = &'l [PathElement];
+
+impl Path {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn as_slice(&self) -> PathRef<'_> {
+        &self.0
+    }
+    /// this path extended by elem
+    pub fn join(&self, elem: PathElement) -> Self {
+        self.iter().cloned().chain(Some(elem)).collect()
+    }
+    pub fn parent(&self) -> Option<Self> {
+        self.split_last().map(|(_, parent)| parent.into())
+    }
+}
+
+impl Deref for Path {
+    type Target = [PathElement];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Borrow<[PathElement]> for Path {
+    fn borrow(&self) -> &[PathElement] {
+        &self.0
+    }
+}
+
+impl AsRef<[PathElement]> for Path {
+    fn as_ref(&self) -> &[PathElement] {
+        &self.0
+    }
+}
+
+impl From<Vec<PathElement>> for Path {
+    fn from(value: Vec<PathElement>) -> Self {
+        Path(value.into())
+    }
+}
+
+impl From<&[PathElement]> for Path {
+    fn from(value: &[PathElement]) -> Self {
+        Path(value.into())
+    }
+}
+
+impl<const N: usize> From<[PathElement; N]> for Path {
+    fn from(value: [PathElement; N]) -> Self {
+        Path(Arc::new(value))
+    }
+}
+
+impl From<Path> for Vec<PathElement> {
+    fn from(value: Path) -> Self {
+        value.0.to_vec()
+    }
+}
+
+impl FromIterator<PathElement> for Path {
+    fn from_iter<T: IntoIterator<Item = PathElement>>(iter: T) -> Self {
+        Path(iter.into_iter().collect())
+    }
+}
+
+impl<'l> IntoIterator for &'l Path {
+    type Item = &'l PathElement;
+    type IntoIter = std::slice::Iter<'l, PathElement>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl PartialEq<[PathElement]> for Path {
+    fn eq(&self, other: &[PathElement]) -> bool {
+        *self.0 == *other
+    }
+}
+
+impl PartialEq<Vec<PathElement>> for Path {
+    fn eq(&self, other: &Vec<PathElement>) -> bool {
+        *self.0 == **other
+    }
+}
+
+impl Debug for Path {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&*self.0, f)
+    }
+}
+
+impl Serialize for Path {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Path {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Vec::<PathElement>::deserialize(deserializer).map(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cheap_clone() {
+        let path = Path::from([PathElement::Field("a".to_string()), PathElement::Index(1)]);
+        let clone = path.clone();
+        assert!(Arc::ptr_eq(&path.0, &clone.0));
+        assert_eq!(
+            path.parent(),
+            Some(Path::from([PathElement::Field("a".to_string())]))
+        );
+        assert_eq!(path.parent().unwrap().join(PathElement::Index(1)), path);
+    }
+
+    #[test]
+    fn serde() {
+        let path = Path::from([PathElement::Field("a".to_string()), PathElement::Index(1)]);
+        let mut path_buf = Vec::new();
+        ciborium::into_writer(&path, &mut path_buf).unwrap();
+        let mut vec_buf = Vec::new();
+        ciborium::into_writer(&path.to_vec(), &mut vec_buf).unwrap();
+        assert_eq!(path_buf, vec_buf);
+        assert_eq!(
+            ciborium::from_reader::<Path, _>(path_buf.as_slice()).unwrap(),
+            path
+        );
+    }
+}
@@ -2,22 +2,51 @@ use std::fmt::Debug;
 
 use serde::{Deserialize, Serialize};
 
-#[derive(PartialEq, Eq, Clone)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
 pub enum PathElement {
     Field(String),
     Index(u32),
+    /// end of an array, resolved when an insert is applied
+    Append,
+    /// array index counted from the end, 0 is the last element
+    FromEnd(u32),
 }
 
 pub enum PathElementRef<'s>{
     Field(&'s str),
-    Index(u32)
+    Index(u32),
+    Append,
+    FromEnd(u32),
 }
 
 impl PathElement{
+    /**
+     *  index into an array of length len. FromEnd resolves relative to the end,
+     *  Append and out of bounds indices don't address an element.
+     *  */
+    pub fn resolve_index(&self, len: usize) -> Option<usize> {
+        match self {
+            PathElement::Index(index) if (*index as usize) < len => Some(*index as usize),
+            PathElement::FromEnd(back) => len.checked_sub(*back as usize + 1),
+            _ => None,
+        }
+    }
+    /// position an insert at this element ends up at, may be len for pushing
+    pub fn resolve_insert_index(&self, len: usize) -> Option<usize> {
+        match self {
+            PathElement::Index(index) if (*index as usize) <= len => Some(*index as usize),
+            PathElement::FromEnd(back) => len.checked_sub(*back as usize),
+            PathElement::Append => Some(len),
+            _ => None,
+        }
+    }
+
     pub fn as_ref(&self)->PathElementRef<'_>{
         match self{
             PathElement::Field(name) => PathElementRef::Field(name),
             PathElement::Index(index) => PathElementRef::Index(*index),
+            PathElement::Append => PathElementRef::Append,
+            PathElement::FromEnd(back) => PathElementRef::FromEnd(*back),
         }
     }
 }
@@ -27,6 +56,8 @@ impl<'a> PathElementRef<'a> {
         match self{
             PathElementRef::Field(name) => PathElement::Field((*name).to_owned()),
             PathElementRef::Index(index) => PathElement::Index(*index),
+            PathElementRef::Append => PathElement::Append,
+            PathElementRef::FromEnd(back) => PathElement::FromEnd(*back),
         }
     }
 }
@@ -37,16 +68,37 @@ impl serde::de::Visitor<'_> for PathElementVisitor {
     type Value = PathElement;
 
     fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-        formatter.write_str("either a string or u32")
+        formatter.write_str("either a string, an integer in the range of i33 or null")
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(PathElement::Append)
+    }
+
+    fn visit_none<E>(self) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(PathElement::Append)
     }
 
     fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
     where
         E: serde::de::Error,
     {
-        Ok(PathElement::Index(u32::try_from(v).map_err(|_| {
-            serde::de::Error::invalid_value(serde::de::Unexpected::Signed(v), &self)
-        })?))
+        if v < 0 {
+            // -1 is the last element
+            Ok(PathElement::FromEnd(u32::try_from(-(v + 1)).map_err(|_| {
+                serde::de::Error::invalid_value(serde::de::Unexpected::Signed(v), &self)
+            })?))
+        } else {
+            Ok(PathElement::Index(u32::try_from(v).map_err(|_| {
+                serde::de::Error::invalid_value(serde::de::Unexpected::Signed(v), &self)
+            })?))
+        }
     }
 
     fn visit_u32<E>(self, v: u32) -> Result<Self::Value, E>
@@ -95,6 +147,8 @@ impl Serialize for PathElement {
         match self {
             PathElement::Field(name) => Serialize::serialize(name, serializer),
             PathElement::Index(index) => Serialize::serialize(index, serializer),
+            PathElement::Append => serializer.serialize_unit(),
+            PathElement::FromEnd(back) => serializer.serialize_i64(-(*back as i64) - 1),
         }
     }
 }
@@ -104,13 +158,15 @@ impl Debug for PathElement {
         match self {
             PathElement::Field(name) => Debug::fmt(name, f),
             PathElement::Index(index) => Debug::fmt(index, f),
+            PathElement::Append => f.write_str("Append"),
+            PathElement::FromEnd(back) => write!(f, "-{}", *back as u64 + 1),
         }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use serde_test::{assert_tokens, Token, assert_de_tokens};
+    use serde_test::{assert_tokens, Token, assert_de_tokens, assert_de_tokens_error};
     use ciborium::{into_writer,from_reader};
 
     use super::PathElement;
@@ -138,9 +194,37 @@ mod test {
         assert_de_tokens(&PathElement::Field("name".to_string()), &[Token::BorrowedStr("name")]);
     }
 
+    #[test]
+    fn append_ser_de() {
+        assert_tokens(&PathElement::Append, &[Token::Unit]);
+        assert_de_tokens(&PathElement::Append, &[Token::None]);
+    }
+
+    #[test]
+    fn from_end_ser_de() {
+        assert_tokens(&PathElement::FromEnd(0), &[Token::I64(-1)]);
+        assert_tokens(&PathElement::FromEnd(u32::MAX), &[Token::I64(-(1 << 32))]);
+        assert_de_tokens(&PathElement::FromEnd(2), &[Token::I8(-3)]);
+        assert_de_tokens_error::<PathElement>(
+            &[Token::I64(-(1 << 32) - 1)],
+            "invalid value: integer `-4294967297`, expected either a string, an integer in the range of i33 or null",
+        );
+    }
+
+    #[test]
+    fn resolve() {
+        assert_eq!(PathElement::FromEnd(0).resolve_index(3), Some(2));
+        assert_eq!(PathElement::FromEnd(3).resolve_index(3), None);
+        assert_eq!(PathElement::Index(3).resolve_index(3), None);
+        assert_eq!(PathElement::FromEnd(0).resolve_insert_index(3), Some(3));
+        assert_eq!(PathElement::FromEnd(3).resolve_insert_index(3), Some(0));
+        assert_eq!(PathElement::Append.resolve_insert_index(3), Some(3));
+        assert_eq!(PathElement::Index(4).resolve_insert_index(3), None);
+    }
+
     #[test]
     fn cbor_round_trip(){
-        let val = [PathElement::Field("test".to_string()),PathElement::Index(1337),PathElement::Field("value".to_string()),PathElement::Index(0)];
+        let val = [PathElement::Field("test".to_string()),PathElement::Index(1337),PathElement::Field("value".to_string()),PathElement::Index(0),PathElement::Append,PathElement::FromEnd(7)];
         let mut serialized=Vec::new();
         into_writer(&val, &mut serialized).expect("serializing failed");
         let res:Vec<PathElement>=from_reader(serialized.as_slice()).expect("de-serializing failed");
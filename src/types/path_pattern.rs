@@ -0,0 +1,280 @@
+use std::{fmt, str::FromStr};
+
+use super::{PathElement, Value};
+
+/// element of a path pattern like `users[*].email`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum PatternElement {
+    Field(String),
+    Index(u32),
+    AnyField,
+    AnyIndex,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PathPattern(pub Vec<PatternElement>);
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct PatternParseError {
+    pub position: usize,
+}
+
+impl PatternElement {
+    pub fn matches(&self, elem: &PathElement) -> bool {
+        match (self, elem) {
+            (PatternElement::Field(pattern), PathElement::Field(name)) => pattern == name,
+            (PatternElement::Index(pattern), PathElement::Index(index)) => pattern == index,
+            (PatternElement::AnyField, PathElement::Field(_)) => true,
+            (PatternElement::AnyIndex, PathElement::Index(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+impl PathPattern {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    pub fn matches(&self, path: &[PathElement]) -> bool {
+        path.len() == self.0.len() && self.matches_prefix(path)
+    }
+    /// path could be extended to a match of this pattern
+    pub fn matches_prefix(&self, path: &[PathElement]) -> bool {
+        path.len() <= self.0.len()
+            && self
+                .0
+                .iter()
+                .zip(path.iter())
+                .all(|(pattern, elem)| pattern.matches(elem))
+    }
+    /**
+     *  calls f with the path and node of every match below node.
+     *  node is located at path, which has to match a prefix of the pattern.
+     *  */
+    pub fn for_each_match<F: FnMut(&[PathElement], &Value)>(
+        &self,
+        node: &Value,
+        path: &mut Vec<PathElement>,
+        mut f: F,
+    ) {
+        self.walk(node, path, &mut f);
+    }
+    fn walk<F: FnMut(&[PathElement], &Value)>(
+        &self,
+        node: &Value,
+        path: &mut Vec<PathElement>,
+        f: &mut F,
+    ) {
+        let Some(elem) = self.0.get(path.len()) else {
+            return f(path, node);
+        };
+        match (elem, node) {
+            (PatternElement::Field(name), Value::Map(map)) => {
+                if let Some(child) = map.get(name) {
+                    path.push(PathElement::Field(name.clone()));
+                    self.walk(child, path, f);
+                    path.pop();
+                }
+            }
+            (PatternElement::AnyField, Value::Map(map)) => {
+                for (name, child) in map.iter() {
+                    path.push(PathElement::Field(name.clone()));
+                    self.walk(child, path, f);
+                    path.pop();
+                }
+            }
+            (PatternElement::Index(index), Value::Array(arr)) => {
+                if let Some(child) = arr.get(*index as usize) {
+                    path.push(PathElement::Index(*index));
+                    self.walk(child, path, f);
+                    path.pop();
+                }
+            }
+            (PatternElement::AnyIndex, Value::Array(arr)) => {
+                for (index, child) in arr.iter().enumerate() {
+                    path.push(PathElement::Index(index as u32));
+                    self.walk(child, path, f);
+                    path.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl FromStr for PathPattern {
+    type Err = PatternParseError;
+
+    /**
+     *  parses patterns of the form `users[*].email`, `matrix[0][*]` or `*.name`.
+     *  fields are separated by dots, `*` matches any field, `[*]` any index.
+     *  */
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut res = Vec::new();
+        let bytes = s.as_bytes();
+        let mut pos = 0;
+        let mut expect_field = true;
+        while pos < bytes.len() {
+            match bytes[pos] {
+                b'[' => {
+                    let end = s[pos..]
+                        .find(']')
+                        .map(|end| end + pos)
+                        .ok_or(PatternParseError { position: pos })?;
+                    let inner = &s[pos + 1..end];
+                    if inner == "*" {
+                        res.push(PatternElement::AnyIndex)
+                    } else {
+                        res.push(PatternElement::Index(
+                            inner
+                                .parse()
+                                .map_err(|_| PatternParseError { position: pos + 1 })?,
+                        ))
+                    }
+                    pos = end + 1;
+                    expect_field = false;
+                }
+                b'.' if !expect_field => {
+                    pos += 1;
+                    expect_field = true;
+                }
+                _ if expect_field => {
+                    let end = s[pos..]
+                        .find(['.', '['])
+                        .map(|end| end + pos)
+                        .unwrap_or(s.len());
+                    if end == pos {
+                        return Err(PatternParseError { position: pos });
+                    }
+                    let name = &s[pos..end];
+                    if name == "*" {
+                        res.push(PatternElement::AnyField)
+                    } else {
+                        res.push(PatternElement::Field(name.to_string()))
+                    }
+                    pos = end;
+                    expect_field = false;
+                }
+                _ => return Err(PatternParseError { position: pos }),
+            }
+        }
+        if expect_field && !s.is_empty() {
+            Err(PatternParseError { position: pos })
+        } else {
+            Ok(PathPattern(res))
+        }
+    }
+}
+
+impl fmt::Display for PathPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (pos, elem) in self.0.iter().enumerate() {
+            match elem {
+                PatternElement::Field(name) => {
+                    if pos != 0 {
+                        f.write_str(".")?
+                    }
+                    f.write_str(name)?
+                }
+                PatternElement::AnyField => {
+                    if pos != 0 {
+                        f.write_str(".")?
+                    }
+                    f.write_str("*")?
+                }
+                PatternElement::Index(index) => write!(f, "[{index}]")?,
+                PatternElement::AnyIndex => f.write_str("[*]")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for PatternParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid path pattern at position {}", self.position)
+    }
+}
+
+impl std::error::Error for PatternParseError {}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::{PathPattern, PatternElement};
+    use crate::{
+        types::{PathElement, Value},
+        util::test_util::{field, map},
+    };
+
+    #[test]
+    fn for_each_match() {
+        let user = |name: &str| map([("name", Value::String(Arc::new(name.to_string())))]);
+        let value = map([(
+            "users",
+            Value::Array(Arc::new(vec![user("ann"), Value::Integer(1), user("bob")])),
+        )]);
+        let pattern: PathPattern = "users[*].name".parse().unwrap();
+        let mut matches = Vec::new();
+        pattern.for_each_match(&value, &mut Vec::new(), |path, node| {
+            matches.push((path.to_vec(), node.clone()))
+        });
+        let name = |index: u32, name: &str| {
+            (
+                vec![field("users"), PathElement::Index(index), field("name")],
+                Value::String(Arc::new(name.to_string())),
+            )
+        };
+        assert_eq!(matches, vec![name(0, "ann"), name(2, "bob")]);
+    }
+
+    #[test]
+    fn parse_pattern() {
+        assert_eq!(
+            "users[*].email".parse(),
+            Ok(PathPattern(vec![
+                PatternElement::Field("users".to_string()),
+                PatternElement::AnyIndex,
+                PatternElement::Field("email".to_string()),
+            ]))
+        );
+        assert_eq!(
+            "*[3][*]".parse(),
+            Ok(PathPattern(vec![
+                PatternElement::AnyField,
+                PatternElement::Index(3),
+                PatternElement::AnyIndex,
+            ]))
+        );
+        assert_eq!("".parse(), Ok(PathPattern(vec![])));
+        assert!("users.".parse::<PathPattern>().is_err());
+        assert!("users[x]".parse::<PathPattern>().is_err());
+        assert!("users[1".parse::<PathPattern>().is_err());
+        assert!(".users".parse::<PathPattern>().is_err());
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for pattern in ["users[*].email", "*[3][*]", "a.*.b"] {
+            assert_eq!(pattern.parse::<PathPattern>().unwrap().to_string(), pattern)
+        }
+    }
+
+    #[test]
+    fn match_path() {
+        let pattern: PathPattern = "users[*].email".parse().unwrap();
+        let path = [
+            PathElement::Field("users".to_string()),
+            PathElement::Index(7),
+            PathElement::Field("email".to_string()),
+        ];
+        assert!(pattern.matches(&path));
+        assert!(pattern.matches_prefix(&path[..2]));
+        assert!(!pattern.matches(&path[..2]));
+        assert!(!pattern.matches_prefix(&[PathElement::Field("groups".to_string())]));
+    }
+}
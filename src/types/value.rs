@@ -1,19 +1,50 @@
-use std::{collections::HashMap, fmt::Debug, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    mem::size_of,
+    sync::Arc,
+};
 
 use serde::{
     de::{self, Visitor},
-    ser, Deserialize, Serialize,
+    ser::{self, SerializeMap, SerializeSeq},
+    Deserialize, Serialize,
 };
 
 use crate::{apply::ApplyChange, error::ValueStoreError};
 
 use super::PathElement;
 
+#[derive(Clone)]
 pub struct Blob {
     pub mime: String,
     pub data: Vec<u8>,
 }
 
+/// how floats are compared when validating changes and diffing
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum FloatPolicy {
+    /// all NaNs are equal to each other, everything else compares with ==, like Value::eq
+    #[default]
+    CanonicalNan,
+    /// equal bit patterns, so 0.0 and -0.0 differ and NaNs only match the same payload
+    BitExact,
+    /// all NaNs are equal, other floats if they differ by at most epsilon. not transitive.
+    Epsilon(f64),
+}
+
+impl FloatPolicy {
+    pub fn floats_equal(self, v1: f64, v2: f64) -> bool {
+        match self {
+            FloatPolicy::CanonicalNan => (v1.is_nan() && v2.is_nan()) || v1 == v2,
+            FloatPolicy::BitExact => v1.to_bits() == v2.to_bits(),
+            FloatPolicy::Epsilon(epsilon) => {
+                (v1.is_nan() && v2.is_nan()) || v1 == v2 || (v1 - v2).abs() <= epsilon
+            }
+        }
+    }
+}
+
 #[derive(Clone)]
 pub enum Value {
     Integer(i64),
@@ -25,6 +56,33 @@ pub enum Value {
     Map(Arc<HashMap<String, Value>>),
 }
 
+impl Drop for Value {
+    /// moves the children of uniquely owned containers onto a stack, so deep values don't
+    /// overflow the call stack when dropped
+    fn drop(&mut self) {
+        fn take_children(value: &mut Value, stack: &mut Vec<Value>) {
+            match value {
+                Value::Array(array) => {
+                    if let Some(array) = Arc::get_mut(array) {
+                        stack.append(array)
+                    }
+                }
+                Value::Map(map) => {
+                    if let Some(map) = Arc::get_mut(map) {
+                        stack.extend(map.drain().map(|(_, value)| value))
+                    }
+                }
+                _ => {}
+            }
+        }
+        let mut stack = Vec::new();
+        take_children(self, &mut stack);
+        while let Some(mut value) = stack.pop() {
+            take_children(&mut value, &mut stack);
+        }
+    }
+}
+
 impl Debug for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -46,6 +104,11 @@ impl Serialize for Value {
     {
         match self {
             Value::Integer(v) => serializer.serialize_i64(*v),
+            // NaN payloads are dropped, so equal values encode and hash the same.
+            // changes encoded before kept the payload. their stored content is unchanged, but
+            // a hash recomputed from their decoded values differs, so ValueStore::add_change
+            // rejects them with ValueStoreError::HashMismatch.
+            Value::Float(v) if v.is_nan() => serializer.serialize_f64(f64::NAN),
             Value::Float(v) => serializer.serialize_f64(*v),
             Value::Bool(v) => serializer.serialize_bool(*v),
             Value::String(v) => serializer.serialize_str(v),
@@ -217,50 +280,60 @@ impl<'de> Deserialize<'de> for Value {
 
 impl Value {
     pub fn get(&self, path: &[PathElement]) -> Option<&Value> {
-        if let Some((this, next)) = path.split_first() {
-            match (this, self) {
-                (PathElement::Field(name), Value::Map(map)) => {
-                    if let Some(entry) = map.get(name) {
-                        entry.get(next)
-                    } else {
-                        None
-                    }
-                }
-                (PathElement::Index(index), Value::Array(arr)) => {
-                    if let Some(entry) = arr.get(*index as usize) {
-                        entry.get(next)
-                    } else {
-                        None
-                    }
+        // iterative, so deep documents can't exhaust the stack
+        let mut current = self;
+        for elem in path {
+            current = match (elem, current) {
+                (PathElement::Field(name), Value::Map(map)) => map.get(name)?,
+                (PathElement::Index(_) | PathElement::FromEnd(_), Value::Array(arr)) => {
+                    &arr[elem.resolve_index(arr.len())?]
                 }
-                _ => None,
+                _ => return None,
+            };
+        }
+        Some(current)
+    }
+    /// equality with floats compared according to policy
+    pub fn eq_with(&self, other: &Value, policy: FloatPolicy) -> bool {
+        if policy == FloatPolicy::CanonicalNan {
+            return self == other;
+        }
+        match (self, other) {
+            (Value::Float(v1), Value::Float(v2)) => policy.floats_equal(*v1, *v2),
+            (Value::Array(v1), Value::Array(v2)) => {
+                Arc::ptr_eq(v1, v2)
+                    || (v1.len() == v2.len()
+                        && v1
+                            .iter()
+                            .zip(v2.iter())
+                            .all(|(v1, v2)| v1.eq_with(v2, policy)))
             }
-        } else {
-            Some(self)
+            (Value::Map(v1), Value::Map(v2)) => {
+                Arc::ptr_eq(v1, v2)
+                    || (v1.len() == v2.len()
+                        && v1
+                            .iter()
+                            .all(|(k, v1)| v2.get(k).is_some_and(|v2| v1.eq_with(v2, policy))))
+            }
+            _ => self == other,
         }
     }
+    pub fn get_many(&self, paths: &[&[PathElement]]) -> Vec<Option<&Value>> {
+        paths.iter().map(|path| self.get(path)).collect()
+    }
     pub fn get_mut(&mut self, path: &[PathElement]) -> Option<&mut Value> {
-        if let Some((this, next)) = path.split_first() {
-            match (this, self) {
-                (PathElement::Field(name), Value::Map(map)) => {
-                    if let Some(entry) = Arc::make_mut(map).get_mut(name) {
-                        entry.get_mut(next)
-                    } else {
-                        None
-                    }
-                }
-                (PathElement::Index(index), Value::Array(arr)) => {
-                    if let Some(entry) = Arc::make_mut(arr).get_mut(*index as usize) {
-                        entry.get_mut(next)
-                    } else {
-                        None
-                    }
+        let mut current = self;
+        for elem in path {
+            current = match (elem, current) {
+                (PathElement::Field(name), Value::Map(map)) => Arc::make_mut(map).get_mut(name)?,
+                (PathElement::Index(_) | PathElement::FromEnd(_), Value::Array(arr)) => {
+                    let index = elem.resolve_index(arr.len())?;
+                    &mut Arc::make_mut(arr)[index]
                 }
-                _ => None,
-            }
-        } else {
-            Some(self)
+                _ => return None,
+            };
         }
+        Some(current)
     }
     pub fn apply_iter<'l, I: IntoIterator<Item = &'l C>, C: ApplyChange + 'l>(
         &'l mut self,
@@ -275,6 +348,278 @@ impl Value {
     pub fn apply<C: ApplyChange>(&mut self, change: &C) -> Result<(), ValueStoreError> {
         change.apply(self)
     }
+
+    /// entries of a map ordered by key, None for all other values
+    pub fn iter_sorted(&self) -> Option<std::vec::IntoIter<(&String, &Value)>> {
+        if let Value::Map(map) = self {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_unstable_by_key(|(key, _)| *key);
+            Some(entries.into_iter())
+        } else {
+            None
+        }
+    }
+
+    /**
+     *  view of this value with all nested maps ordered by key.
+     *  serializing it is deterministic, so it can be used for hashing and golden files.
+     *  */
+    pub fn canonicalize(&self) -> Canonical<'_> {
+        Canonical(self)
+    }
+
+    /**
+     *  approximate memory used by this value in bytes, including the value itself.
+     *  if count_shared is false every Arc node is only counted the first time it is encountered,
+     *  so values sharing subtrees with each other can be summed up with the same seen set.
+     *  */
+    pub fn approximate_size(&self, count_shared: bool) -> usize {
+        self.approximate_size_with(count_shared, &mut HashSet::new())
+    }
+
+    pub fn approximate_size_with(&self, count_shared: bool, seen: &mut HashSet<usize>) -> usize {
+        // strong and weak count in front of the Arc content
+        const ARC_HEADER: usize = 2 * size_of::<usize>();
+        let mut first_seen = |ptr: *const ()| count_shared || seen.insert(ptr as usize);
+        size_of::<Value>()
+            + match self {
+                Value::Integer(_) | Value::Float(_) | Value::Bool(_) => 0,
+                Value::String(s) => {
+                    if first_seen(Arc::as_ptr(s).cast()) {
+                        ARC_HEADER + size_of::<String>() + s.capacity()
+                    } else {
+                        0
+                    }
+                }
+                Value::Blob(b) => {
+                    if first_seen(Arc::as_ptr(b).cast()) {
+                        ARC_HEADER + size_of::<Blob>() + b.mime.capacity() + b.data.capacity()
+                    } else {
+                        0
+                    }
+                }
+                Value::Array(a) => {
+                    if first_seen(Arc::as_ptr(a).cast()) {
+                        ARC_HEADER
+                            + size_of::<Vec<Value>>()
+                            + (a.capacity() - a.len()) * size_of::<Value>()
+                            + a.iter()
+                                .map(|v| v.approximate_size_with(count_shared, seen))
+                                .sum::<usize>()
+                    } else {
+                        0
+                    }
+                }
+                Value::Map(m) => {
+                    if first_seen(Arc::as_ptr(m).cast()) {
+                        // one control byte per bucket
+                        ARC_HEADER
+                            + size_of::<HashMap<String, Value>>()
+                            + (m.capacity() - m.len()) * (size_of::<(String, Value)>() + 1)
+                            + m.iter()
+                                .map(|(k, v)| {
+                                    size_of::<String>()
+                                        + 1
+                                        + k.capacity()
+                                        + v.approximate_size_with(count_shared, seen)
+                                })
+                                .sum::<usize>()
+                    } else {
+                        0
+                    }
+                }
+            }
+    }
+
+    /**
+     *  counts the reference counted nodes of this value by whether they are shared.
+     *  nodes below a shared node are not visited, they are shared through it.
+     *  */
+    pub fn sharing_stats(&self) -> SharingStats {
+        let mut stats = SharingStats::default();
+        self.sharing_stats_with(&mut stats);
+        stats
+    }
+
+    fn sharing_stats_with(&self, stats: &mut SharingStats) {
+        let count = match self {
+            Value::Integer(_) | Value::Float(_) | Value::Bool(_) => return,
+            Value::String(s) => Arc::strong_count(s),
+            Value::Blob(b) => Arc::strong_count(b),
+            Value::Array(a) => Arc::strong_count(a),
+            Value::Map(m) => Arc::strong_count(m),
+        };
+        if count > 1 {
+            stats.shared += 1;
+            return;
+        }
+        stats.unique += 1;
+        match self {
+            Value::Array(a) => a.iter().for_each(|v| v.sharing_stats_with(stats)),
+            Value::Map(m) => m.values().for_each(|v| v.sharing_stats_with(stats)),
+            _ => {}
+        }
+    }
+
+    /// upper bound of the cbor encoded size in bytes, exact unless the value contains floats
+    pub fn encoded_size_hint(&self) -> usize {
+        match self {
+            Value::Integer(v) if *v < 0 => cbor_header_size(!*v as u64),
+            Value::Integer(v) => cbor_header_size(*v as u64),
+            // floats are shortened to half or single precision if lossless
+            Value::Float(_) => 9,
+            Value::Bool(_) => 1,
+            Value::String(s) => cbor_header_size(s.len() as u64) + s.len(),
+            Value::Blob(b) => {
+                let len = 1 + b.mime.len() + b.data.len();
+                cbor_header_size(len as u64) + len
+            }
+            Value::Array(a) => {
+                cbor_header_size(a.len() as u64)
+                    + a.iter().map(Value::encoded_size_hint).sum::<usize>()
+            }
+            Value::Map(m) => {
+                cbor_header_size(m.len() as u64)
+                    + m.iter()
+                        .map(|(k, v)| {
+                            cbor_header_size(k.len() as u64) + k.len() + v.encoded_size_hint()
+                        })
+                        .sum::<usize>()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SharingStats {
+    /// nodes only referenced from the value
+    pub unique: usize,
+    /// nodes referenced from elsewhere as well
+    pub shared: usize,
+}
+
+fn assert_same_node(before: &Value, after: &Value, path: &mut Vec<PathElement>) {
+    let same = match (before, after) {
+        (Value::Integer(_) | Value::Float(_) | Value::Bool(_), _) => return,
+        (Value::String(v1), Value::String(v2)) => Arc::ptr_eq(v1, v2),
+        (Value::Blob(v1), Value::Blob(v2)) => Arc::ptr_eq(v1, v2),
+        (Value::Array(v1), Value::Array(v2)) => Arc::ptr_eq(v1, v2),
+        (Value::Map(v1), Value::Map(v2)) => Arc::ptr_eq(v1, v2),
+        _ => false,
+    };
+    assert!(same, "unexpected copy of the value at {path:?}");
+}
+
+fn assert_no_clone_below(
+    before: &Value,
+    after: &Value,
+    changed: &[&[PathElement]],
+    path: &mut Vec<PathElement>,
+) {
+    if changed.iter().any(|changed| path.starts_with(changed)) {
+        return;
+    }
+    if !changed.iter().any(|changed| changed.starts_with(path)) {
+        assert_same_node(before, after, path);
+        return;
+    }
+    // ancestors of a change are copied, their other children have to be shared
+    match (before, after) {
+        (Value::Map(m1), Value::Map(m2)) => {
+            for (key, v1) in m1.iter() {
+                if let Some(v2) = m2.get(key) {
+                    path.push(PathElement::Field(key.clone()));
+                    assert_no_clone_below(v1, v2, changed, path);
+                    path.pop();
+                }
+            }
+        }
+        // elements shift if the length changed, so they can't be matched up by index
+        (Value::Array(a1), Value::Array(a2)) if a1.len() == a2.len() => {
+            for (index, (v1, v2)) in a1.iter().zip(a2.iter()).enumerate() {
+                path.push(PathElement::Index(index as u32));
+                assert_no_clone_below(v1, v2, changed, path);
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+/**
+ *  test helper panicking if after copied a subtree of before that is not on one of the
+ *  changed paths, instead of sharing it. checks that updates stay copy on write.
+ *  only runs with debug assertions.
+ *  */
+pub fn debug_assert_no_unexpected_clone(
+    before: &Value,
+    after: &Value,
+    changed: &[&[PathElement]],
+) {
+    if cfg!(debug_assertions) {
+        assert_no_clone_below(before, after, changed, &mut Vec::new());
+    }
+}
+
+/// bytes of a cbor item header carrying n as length or integer
+pub(crate) fn cbor_header_size(n: u64) -> usize {
+    match n {
+        0..=23 => 1,
+        24..=0xff => 2,
+        0x100..=0xffff => 3,
+        0x1_0000..=0xffff_ffff => 5,
+        _ => 9,
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Canonical<'v>(pub &'v Value);
+
+impl Serialize for Canonical<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self.0 {
+            Value::Array(arr) => {
+                let mut seq = serializer.serialize_seq(Some(arr.len()))?;
+                for v in arr.iter() {
+                    seq.serialize_element(&Canonical(v))?;
+                }
+                seq.end()
+            }
+            Value::Map(map) => {
+                let mut res = serializer.serialize_map(Some(map.len()))?;
+                for (k, v) in self.0.iter_sorted().into_iter().flatten() {
+                    res.serialize_entry(k, &Canonical(v))?;
+                }
+                res.end()
+            }
+            v => Serialize::serialize(v, serializer),
+        }
+    }
+}
+
+impl Debug for Canonical<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Value::Array(arr) => f
+                .debug_list()
+                .entries(arr.iter().map(Canonical))
+                .finish(),
+            Value::Map(_) => f
+                .debug_map()
+                .entries(
+                    self.0
+                        .iter_sorted()
+                        .into_iter()
+                        .flatten()
+                        .map(|(k, v)| (k, Canonical(v))),
+                )
+                .finish(),
+            v => Debug::fmt(v, f),
+        }
+    }
 }
 impl Default for Value {
     fn default() -> Self {
@@ -282,24 +627,92 @@ impl Default for Value {
     }
 }
 
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Integer(value)
+    }
+}
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Float(value)
+    }
+}
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::String(Arc::new(value))
+    }
+}
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(Arc::new(value.to_string()))
+    }
+}
+impl From<Blob> for Value {
+    fn from(value: Blob) -> Self {
+        Value::Blob(Arc::new(value))
+    }
+}
+impl<V: Into<Value>> From<Vec<V>> for Value {
+    fn from(value: Vec<V>) -> Self {
+        Value::Array(Arc::new(value.into_iter().map(Into::into).collect()))
+    }
+}
+impl<V: Into<Value>> From<HashMap<String, V>> for Value {
+    fn from(value: HashMap<String, V>) -> Self {
+        Value::Map(Arc::new(
+            value.into_iter().map(|(k, v)| (k, v.into())).collect(),
+        ))
+    }
+}
+
 impl PartialEq for Value {
     fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Value::Integer(v1), Value::Integer(v2)) => v1 == v2,
-            (Value::Float(v1), Value::Float(v2)) => {
-                if v1.is_nan() && v2.is_nan() {
-                    true
-                } else {
-                    v1 == v2
+        // compared with an explicit stack, values can be nested too deep for recursion
+        let mut pending = vec![(self, other)];
+        while let Some(pair) = pending.pop() {
+            let equal = match pair {
+                (Value::Integer(v1), Value::Integer(v2)) => v1 == v2,
+                (Value::Float(v1), Value::Float(v2)) => {
+                    if v1.is_nan() && v2.is_nan() {
+                        true
+                    } else {
+                        v1 == v2
+                    }
                 }
+                (Value::Bool(v1), Value::Bool(v2)) => v1 == v2,
+                // shared subtrees are equal without comparing them
+                (Value::String(v1), Value::String(v2)) => Arc::ptr_eq(v1, v2) || v1 == v2,
+                (Value::Array(v1), Value::Array(v2)) if Arc::ptr_eq(v1, v2) => true,
+                (Value::Array(v1), Value::Array(v2)) => {
+                    pending.extend(v1.iter().zip(v2.iter()));
+                    v1.len() == v2.len()
+                }
+                (Value::Map(v1), Value::Map(v2)) if Arc::ptr_eq(v1, v2) => true,
+                (Value::Map(v1), Value::Map(v2)) => {
+                    v1.len() == v2.len()
+                        && v1.iter().all(|(key, v1)| match v2.get(key) {
+                            Some(v2) => {
+                                pending.push((v1, v2));
+                                true
+                            }
+                            None => false,
+                        })
+                }
+                (Value::Blob(v1), Value::Blob(v2)) => {
+                    Arc::ptr_eq(v1, v2) || (v1.mime == v2.mime && v1.data == v2.data)
+                }
+                _ => false,
+            };
+            if !equal {
+                return false;
             }
-            (Value::Bool(v1), Value::Bool(v2)) => v1 == v2,
-            (Value::String(v1), Value::String(v2)) => v1 == v2,
-            (Value::Array(v1), Value::Array(v2)) => v1 == v2,
-            (Value::Map(v1), Value::Map(v2)) => v1 == v2,
-            (Value::Blob(v1), Value::Blob(v2)) => v1.mime == v2.mime && v1.data == v2.data,
-            _ => false,
         }
+        true
     }
 }
 
@@ -312,7 +725,10 @@ mod test {
     use ciborium::{from_reader, into_writer};
     use serde_test::{assert_de_tokens, assert_tokens, Token};
 
-    use super::{Blob, Value};
+    use std::{mem::size_of, sync::Arc};
+
+    use super::{debug_assert_no_unexpected_clone, Blob, FloatPolicy, SharingStats, Value};
+    use crate::types::{change::ChangeContent, PathElement};
 
     #[test]
     fn value_eq_float() {
@@ -332,6 +748,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn value_float_ser_de() {
         assert_tokens(&Value::Float(3.14), &[Token::F64(3.14)]);
     }
@@ -482,4 +899,189 @@ mod test {
             "value differs after round trip"
         )
     }
+
+    #[test]
+    fn value_from_end() {
+        let mut val = Value::Array(vec![Value::Integer(1), Value::Integer(2)].into());
+        assert_eq!(val.get(&[PathElement::FromEnd(0)]), Some(&Value::Integer(2)));
+        assert_eq!(val.get(&[PathElement::FromEnd(1)]), Some(&Value::Integer(1)));
+        assert_eq!(val.get(&[PathElement::FromEnd(2)]), None);
+        val.apply(&ChangeContent::Replace {
+            path: vec![PathElement::FromEnd(0)].into(),
+            old: Value::Integer(2),
+            new: Value::Integer(3),
+        })
+        .expect("replace last");
+        val.apply(&ChangeContent::Insert {
+            path: vec![PathElement::FromEnd(0)].into(),
+            value: Value::Integer(4),
+        })
+        .expect("insert at end");
+        val.apply(&ChangeContent::Delete {
+            path: vec![PathElement::FromEnd(2)].into(),
+            old: Value::Integer(1),
+        })
+        .expect("delete first");
+        assert_eq!(
+            val,
+            Value::Array(vec![Value::Integer(3), Value::Integer(4)].into())
+        );
+    }
+
+    #[test]
+    fn value_get_many() {
+        let val = Value::Map(
+            HashMap::from_iter([(
+                "a".to_string(),
+                Value::Array(vec![Value::Integer(1)].into()),
+            )])
+            .into(),
+        );
+        let a = [PathElement::Field("a".to_string())];
+        let a0 = [PathElement::Field("a".to_string()), PathElement::Index(0)];
+        let b = [PathElement::Field("b".to_string())];
+        assert_eq!(
+            val.get_many(&[&a0, &b, &[], &a]),
+            vec![Some(&Value::Integer(1)), None, Some(&val), val.get(&a)]
+        );
+    }
+
+    #[test]
+    fn value_canonical() {
+        let keys: Vec<String> = (0..32).map(|i| format!("key{i}")).collect();
+        let v1 = Value::Map(
+            HashMap::from_iter(keys.iter().map(|k| (k.clone(), Value::Bool(true)))).into(),
+        );
+        let v2 = Value::Map(
+            HashMap::from_iter(keys.iter().rev().map(|k| (k.clone(), Value::Bool(true)))).into(),
+        );
+        let mut s1 = Vec::new();
+        into_writer(&v1.canonicalize(), &mut s1).expect("serializing failed");
+        let mut s2 = Vec::new();
+        into_writer(&v2.canonicalize(), &mut s2).expect("serializing failed");
+        assert_eq!(s1, s2);
+        let res: Value = from_reader(s1.as_slice()).expect("de-serializing failed");
+        assert_eq!(v1, res);
+        let mut sorted = keys.clone();
+        sorted.sort();
+        assert!(v1
+            .iter_sorted()
+            .expect("is a map")
+            .map(|(k, _)| k)
+            .eq(sorted.iter()));
+        assert!(Value::Integer(1).iter_sorted().is_none());
+    }
+
+    #[test]
+    fn value_size() {
+        let s = Value::String(Arc::new("x".repeat(100)));
+        let shared = Value::Array(Arc::new(vec![s.clone(), s.clone()]));
+        let unshared = shared.approximate_size(true);
+        let deduplicated = shared.approximate_size(false);
+        assert!(unshared >= 200);
+        assert!(deduplicated >= 100 && deduplicated < unshared - 100);
+        assert_eq!(Value::Integer(1).approximate_size(false), size_of::<Value>());
+    }
+
+    fn cow_value() -> Value {
+        Value::from(HashMap::from([
+            (
+                "a".to_string(),
+                Value::from(HashMap::from([(
+                    "x".to_string(),
+                    Value::Array(Arc::new(vec![Value::Integer(1), Value::Integer(2)])),
+                )])),
+            ),
+            (
+                "b".to_string(),
+                Value::from(HashMap::from([(
+                    "y".to_string(),
+                    Value::String(Arc::new("text".to_string())),
+                )])),
+            ),
+        ]))
+    }
+
+    #[test]
+    fn copy_on_write() {
+        let before = cow_value();
+        assert_eq!(
+            before.sharing_stats(),
+            SharingStats {
+                unique: 5,
+                shared: 0
+            }
+        );
+        let path = [
+            PathElement::Field("a".to_string()),
+            PathElement::Field("x".to_string()),
+            PathElement::Index(0),
+        ];
+        let mut after = before.clone();
+        after
+            .apply(&ChangeContent::Replace {
+                path: path.as_slice().into(),
+                old: Value::Integer(1),
+                new: Value::Integer(5),
+            })
+            .unwrap();
+        debug_assert_no_unexpected_clone(&before, &after, &[&path]);
+        // root, a and a.x were copied, b is shared with before
+        assert_eq!(
+            after.sharing_stats(),
+            SharingStats {
+                unique: 3,
+                shared: 1
+            }
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "unexpected copy")]
+    fn detects_clone() {
+        let before = cow_value();
+        let after = cow_value();
+        debug_assert_no_unexpected_clone(
+            &before,
+            &after,
+            &[&[PathElement::Field("a".to_string())]],
+        );
+    }
+
+    #[test]
+    fn deep_get() {
+        let depth = 5000;
+        let mut value = Value::Integer(1);
+        for _ in 0..depth {
+            value = Value::Array(Arc::new(vec![value]));
+        }
+        let path = vec![PathElement::Index(0); depth];
+        assert_eq!(value.get(&path), Some(&Value::Integer(1)));
+        *value.get_mut(&path).unwrap() = Value::Integer(2);
+        assert_eq!(value.get(&path), Some(&Value::Integer(2)));
+        assert_eq!(value.get(&vec![PathElement::Index(0); depth + 1]), None);
+        let mut other = Value::Integer(2);
+        for _ in 0..depth {
+            other = Value::Array(Arc::new(vec![other]));
+        }
+        assert_eq!(value, other);
+        *other.get_mut(&path).unwrap() = Value::Integer(3);
+        assert_ne!(value, other);
+    }
+
+    #[test]
+    fn float_policy() {
+        let nan = Value::Float(f64::from_bits(f64::NAN.to_bits() | 1));
+        let (mut s1, mut s2) = (Vec::new(), Vec::new());
+        into_writer(&nan, &mut s1).unwrap();
+        into_writer(&Value::Float(f64::NAN), &mut s2).unwrap();
+        assert_eq!(s1, s2);
+
+        let v1 = Value::Array(Arc::new(vec![Value::Float(1.0), nan.clone()]));
+        let v2 = Value::Array(Arc::new(vec![Value::Float(1.0 + 1e-12), nan]));
+        assert!(!v1.eq_with(&v2, FloatPolicy::CanonicalNan));
+        assert!(v1.eq_with(&v2, FloatPolicy::Epsilon(1e-9)));
+        assert!(!Value::Float(0.0).eq_with(&Value::Float(-0.0), FloatPolicy::BitExact));
+        assert!(Value::Float(0.0).eq_with(&Value::Float(-0.0), FloatPolicy::CanonicalNan));
+    }
 }
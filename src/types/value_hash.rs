@@ -0,0 +1,310 @@
+use std::{collections::HashMap, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    change::Hash,
+    hasher::{Blake3Hasher, Hasher},
+    PathElement, Value,
+};
+
+/**
+ *  merkle style hashes of values. every node hashes the hashes of its children,
+ *  so equal hashes mean equal values (with the float semantics of Value::eq).
+ *  */
+impl Value {
+    pub fn structural_hash(&self) -> Hash {
+        self.structural_hash_inner(&mut |v| v.structural_hash())
+    }
+
+    fn structural_hash_inner<F: FnMut(&Value) -> Hash>(&self, child: &mut F) -> Hash {
+        let mut hasher = Blake3Hasher::default();
+        match self {
+            Value::Integer(v) => {
+                hasher.update(b"i");
+                hasher.update(&v.to_le_bytes());
+            }
+            Value::Float(v) => {
+                // all NaNs and both zeros compare equal
+                let v = if v.is_nan() {
+                    f64::NAN
+                } else if *v == 0.0 {
+                    0.0
+                } else {
+                    *v
+                };
+                hasher.update(b"f");
+                hasher.update(&v.to_bits().to_le_bytes());
+            }
+            Value::Bool(v) => hasher.update(if *v { b"t" } else { b"b" }),
+            Value::String(v) => {
+                hasher.update(b"s");
+                hasher.update(v.as_bytes());
+            }
+            Value::Blob(v) => {
+                hasher.update(b"x");
+                hasher.update(&(v.mime.len() as u64).to_le_bytes());
+                hasher.update(v.mime.as_bytes());
+                hasher.update(&v.data);
+            }
+            Value::Array(v) => return hash_array(v.iter().map(child)),
+            Value::Map(v) => {
+                let mut entries: Vec<_> = v.iter().collect();
+                entries.sort_unstable_by_key(|(key, _)| *key);
+                return hash_map(entries.into_iter().map(|(key, v)| (key.as_str(), child(v))));
+            }
+        }
+        hasher.finalize()
+    }
+
+    /**
+     *  returns the value at path with a proof linking it to the structural hash of self.
+     *  Append can't be proven and results in None like a missing value.
+     *  */
+    pub fn prove(&self, path: &[PathElement]) -> Option<(&Value, MerkleProof)> {
+        let mut steps = Vec::with_capacity(path.len());
+        let mut current = self;
+        for element in path {
+            current = match (element, current) {
+                (PathElement::Field(key), Value::Map(map)) => {
+                    let next = map.get(key)?;
+                    let mut entries: Vec<_> = map
+                        .iter()
+                        .map(|(key, v)| (key.clone(), v.structural_hash()))
+                        .collect();
+                    entries.sort_unstable_by(|(k1, _), (k2, _)| k1.cmp(k2));
+                    steps.push(ProofStep::Map {
+                        key: key.clone(),
+                        entries,
+                    });
+                    next
+                }
+                (PathElement::Index(_) | PathElement::FromEnd(_), Value::Array(arr)) => {
+                    let index = element.resolve_index(arr.len())?;
+                    steps.push(ProofStep::Array {
+                        index: index as u32,
+                        children: arr.iter().map(Value::structural_hash).collect(),
+                    });
+                    &arr[index]
+                }
+                _ => return None,
+            };
+        }
+        Some((current, MerkleProof { steps }))
+    }
+}
+
+fn hash_array(children: impl Iterator<Item = Hash>) -> Hash {
+    let mut hasher = Blake3Hasher::default();
+    hasher.update(b"a");
+    for child in children {
+        hasher.update(child.as_slice());
+    }
+    hasher.finalize()
+}
+
+/// entries have to be sorted by key
+fn hash_map<'l>(entries: impl Iterator<Item = (&'l str, Hash)>) -> Hash {
+    let mut hasher = Blake3Hasher::default();
+    hasher.update(b"m");
+    for (key, child) in entries {
+        hasher.update(&(key.len() as u64).to_le_bytes());
+        hasher.update(key.as_bytes());
+        hasher.update(child.as_slice());
+    }
+    hasher.finalize()
+}
+
+/// one level of a MerkleProof, the hashes of all children of a node
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProofStep {
+    Array {
+        index: u32,
+        children: Vec<Hash>,
+    },
+    /// entries sorted by key
+    Map {
+        key: String,
+        entries: Vec<(String, Hash)>,
+    },
+}
+
+/**
+ *  proof that a value is located at a path inside a value with known structural hash.
+ *  lets clients check values read from a server they don't trust.
+ *  the root is the structural hash of the whole value, change hashes cover the change content
+ *  and its parents but not the resulting value. clients need the root hash from a trusted
+ *  source, e.g. computed by a replica they trust, the store doesn't record it.
+ *  */
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct MerkleProof {
+    pub steps: Vec<ProofStep>,
+}
+
+impl MerkleProof {
+    /**
+     *  checks that value is located at path inside the value hashing to root.
+     *  every step has to match the element of path at its level, FromEnd is resolved against
+     *  the length of the proven array, Append never matches.
+     *  */
+    pub fn verify(&self, root: &Hash, path: &[PathElement], value: &Value) -> bool {
+        if path.len() != self.steps.len() {
+            return false;
+        }
+        let mut hash = value.structural_hash();
+        for (step, element) in self.steps.iter().zip(path).rev() {
+            hash = match (step, element) {
+                (
+                    ProofStep::Array { index, children },
+                    PathElement::Index(_) | PathElement::FromEnd(_),
+                ) => {
+                    if element.resolve_index(children.len()) != Some(*index as usize) {
+                        return false;
+                    }
+                    hash_array(children.iter().enumerate().map(|(i, child)| {
+                        if i == *index as usize {
+                            hash
+                        } else {
+                            *child
+                        }
+                    }))
+                }
+                (ProofStep::Map { key, entries }, PathElement::Field(field)) => {
+                    if key != field || !entries.iter().any(|(k, _)| k == key) {
+                        return false;
+                    }
+                    hash_map(entries.iter().map(|(k, child)| {
+                        if k == key {
+                            (k.as_str(), hash)
+                        } else {
+                            (k.as_str(), *child)
+                        }
+                    }))
+                }
+                _ => return false,
+            };
+        }
+        hash == *root
+    }
+}
+
+/**
+ *  remembers structural hashes of arrays and maps by Arc node.
+ *  hashed nodes are kept alive by the cache, so a node address is never reused while cached.
+ *  apply, conflict detection and diff don't use it, they compare values with Value::eq.
+ *  it pays off for callers comparing the same large values repeatedly, e.g. against many versions.
+ *  */
+#[derive(Default)]
+pub struct StructuralHashCache {
+    nodes: HashMap<usize, (Value, Hash)>,
+}
+
+impl StructuralHashCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn hash(&mut self, value: &Value) -> Hash {
+        let node = match value {
+            Value::Array(v) => Arc::as_ptr(v) as usize,
+            Value::Map(v) => Arc::as_ptr(v) as usize,
+            value => return value.structural_hash(),
+        };
+        if let Some((_, hash)) = self.nodes.get(&node) {
+            return *hash;
+        }
+        let hash = value.structural_hash_inner(&mut |v| self.hash(v));
+        self.nodes.insert(node, (value.clone(), hash));
+        hash
+    }
+
+    /// true if both values are equal, skipping subtrees already hashed
+    pub fn equal(&mut self, v1: &Value, v2: &Value) -> bool {
+        self.hash(v1) == self.hash(v2)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.nodes.clear()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::test_util::map;
+
+    #[test]
+    fn hash_follows_eq() {
+        let v1 = map([
+            ("a", Value::Float(0.0)),
+            ("b", Value::Array(Arc::new(vec![Value::Float(f64::NAN)]))),
+        ]);
+        let v2 = map([
+            ("b", Value::Array(Arc::new(vec![Value::Float(-f64::NAN)]))),
+            ("a", Value::Float(-0.0)),
+        ]);
+        assert_eq!(v1, v2);
+        assert_eq!(v1.structural_hash(), v2.structural_hash());
+        assert_ne!(
+            v1.structural_hash(),
+            map([("a", Value::Float(0.0))]).structural_hash()
+        );
+        assert_ne!(
+            Value::Integer(1).structural_hash(),
+            Value::Bool(true).structural_hash()
+        );
+    }
+
+    #[test]
+    fn cache() {
+        let shared = Value::Array(Arc::new(vec![Value::Integer(1), Value::Integer(2)]));
+        let v1 = map([("a", shared.clone())]);
+        let v2 = map([("a", shared.clone()), ("b", Value::Integer(3))]);
+        let mut cache = StructuralHashCache::new();
+        assert_eq!(cache.hash(&v1), v1.structural_hash());
+        assert_eq!(cache.len(), 2);
+        assert!(!cache.equal(&v1, &v2));
+        // v2 and its children, shared was hashed already
+        assert_eq!(cache.len(), 3);
+        assert!(cache.equal(&shared, &shared.clone()));
+    }
+
+    #[test]
+    fn proof() {
+        let value = map([
+            ("a", Value::Integer(1)),
+            (
+                "b",
+                Value::Array(Arc::new(vec![Value::Integer(2), Value::Integer(3)])),
+            ),
+        ]);
+        let root = value.structural_hash();
+        let path = [PathElement::Field("b".to_string()), PathElement::FromEnd(0)];
+        let (found, proof) = value.prove(&path).unwrap();
+        assert_eq!(found, &Value::Integer(3));
+        assert!(proof.verify(&root, &path, found));
+        let index = [PathElement::Field("b".to_string()), PathElement::Index(1)];
+        assert!(proof.verify(&root, &index, found));
+        assert!(!proof.verify(&root, &path, &Value::Integer(4)));
+        assert!(!proof.verify(&Value::Integer(1).structural_hash(), &path, found));
+        // a valid proof for another node doesn't prove the requested path
+        let (other, other_proof) = value.prove(&[PathElement::Field("a".to_string())]).unwrap();
+        assert!(!other_proof.verify(&root, &path, other));
+        let first = [PathElement::Field("b".to_string()), PathElement::Index(0)];
+        assert!(!proof.verify(&root, &first, found));
+        let append = [PathElement::Field("b".to_string()), PathElement::Append];
+        assert!(!proof.verify(&root, &append, found));
+        assert!(!proof.verify(&root, &path[..1], found));
+        assert!(value
+            .prove(&[PathElement::Field("c".to_string())])
+            .is_none());
+    }
+}
@@ -1 +1,3 @@
 pub mod stack_list;
+#[cfg(test)]
+pub(crate) mod test_util;
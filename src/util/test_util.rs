@@ -0,0 +1,25 @@
+use std::sync::Arc;
+
+use crate::types::{PathElement, Value};
+
+pub fn field(name: &str) -> PathElement {
+    PathElement::Field(name.to_string())
+}
+
+pub fn map<const N: usize>(entries: [(&str, Value); N]) -> Value {
+    Value::Map(Arc::new(
+        entries
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    ))
+}
+
+/// empty storage kept in memory
+#[cfg(feature = "db_redb")]
+pub fn redb_storage() -> crate::storage::redb::RedbStorage {
+    let db = redb::Database::builder()
+        .create_with_backend(redb::backends::InMemoryBackend::new())
+        .unwrap();
+    crate::storage::redb::RedbStorage::new(db).unwrap()
+}
@@ -0,0 +1,193 @@
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use crate::{
+    error::ValueStoreError,
+    types::{change::ChangeContent, Path, PathElement, Value},
+};
+
+/// note attached to changes committed with mismatching mime types under MimePolicy::Warn
+pub const MIME_MISMATCH_NOTE: &str = "mime.mismatch";
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MimePolicy {
+    /// fail validation on the first mismatch
+    Reject,
+    /// report mismatches but keep the declared mime types
+    Warn,
+    /// replace declared mime types with the detected ones
+    Fix,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub struct MimeMismatch {
+    pub path: Path,
+    pub declared: String,
+    pub detected: &'static str,
+}
+
+/// essence of a mime type, without parameters and lowercased
+fn essence(mime: &str) -> String {
+    mime.split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+/**
+ *  mime type detected from the magic bytes of data if it differs from declared.
+ *  data without a recognizable signature never mismatches.
+ *  */
+pub fn sniff_mismatch(declared: &str, data: &[u8]) -> Option<&'static str> {
+    infer::get(data)
+        .map(|kind| kind.mime_type())
+        .filter(|detected| essence(declared) != *detected)
+}
+
+/// collects the blobs below value whose content doesn't match their declared mime type
+fn check_value(
+    value: &Value,
+    path: &mut Vec<PathElement>,
+    policy: MimePolicy,
+    res: &mut Vec<MimeMismatch>,
+) -> Result<(), ValueStoreError> {
+    match value {
+        Value::Blob(blob) => {
+            if let Some(detected) = sniff_mismatch(&blob.mime, &blob.data) {
+                if policy == MimePolicy::Reject {
+                    return Err(ValueStoreError::MimeMismatch {
+                        path: path.clone().into(),
+                        declared: blob.mime.clone(),
+                        detected,
+                    });
+                }
+                res.push(MimeMismatch {
+                    path: path.as_slice().into(),
+                    declared: blob.mime.clone(),
+                    detected,
+                });
+            }
+        }
+        Value::Array(arr) => {
+            for (index, v) in arr.iter().enumerate() {
+                path.push(PathElement::Index(index as u32));
+                check_value(v, path, policy, res)?;
+                path.pop();
+            }
+        }
+        Value::Map(map) => {
+            for (name, v) in map.iter() {
+                path.push(PathElement::Field(name.clone()));
+                check_value(v, path, policy, res)?;
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/**
+ *  validates the mime types of all blobs added by changes against their content.
+ *  returns the mismatches found, with MimePolicy::Fix they have already been corrected.
+ *  */
+pub fn validate_blob_mimes(
+    changes: &mut [ChangeContent],
+    policy: MimePolicy,
+) -> Result<Vec<MimeMismatch>, ValueStoreError> {
+    let mut res = Vec::new();
+    for change in changes {
+        match change {
+            ChangeContent::Insert { path, value }
+            | ChangeContent::Replace {
+                path, new: value, ..
+            } => {
+                let found = res.len();
+                check_value(value, &mut path.to_vec(), policy, &mut res)?;
+                if policy == MimePolicy::Fix {
+                    // only the ancestors of mismatching blobs are unshared
+                    for mismatch in &res[found..] {
+                        if let Some(Value::Blob(blob)) = value.get_mut(&mismatch.path[path.len()..])
+                        {
+                            Arc::make_mut(blob).mime = mismatch.detected.to_string();
+                        }
+                    }
+                }
+            }
+            ChangeContent::Delete { .. } => {}
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::{validate_blob_mimes, MimePolicy};
+    use crate::{
+        error::ValueStoreError,
+        types::{change::ChangeContent, value::Blob, Path, PathElement, Value},
+    };
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    fn changes(mime: &str) -> Vec<ChangeContent> {
+        vec![ChangeContent::Insert {
+            path: vec![PathElement::Field("img".to_string())].into(),
+            value: Value::Array(
+                vec![Value::Blob(
+                    Blob {
+                        mime: mime.to_string(),
+                        data: PNG.to_vec(),
+                    }
+                    .into(),
+                )]
+                .into(),
+            ),
+        }]
+    }
+
+    #[test]
+    fn matching_mime() {
+        for policy in [MimePolicy::Reject, MimePolicy::Warn, MimePolicy::Fix] {
+            assert_eq!(
+                validate_blob_mimes(&mut changes("image/PNG"), policy).unwrap(),
+                vec![]
+            );
+        }
+    }
+
+    #[test]
+    fn mismatching_mime() {
+        let path = vec![PathElement::Field("img".to_string()), PathElement::Index(0)];
+        assert!(matches!(
+            validate_blob_mimes(&mut changes("image/jpeg"), MimePolicy::Reject),
+            Err(ValueStoreError::MimeMismatch {
+                detected: "image/png",
+                ..
+            })
+        ));
+        let mut warned = changes("image/jpeg");
+        let res = validate_blob_mimes(&mut warned, MimePolicy::Warn).unwrap();
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].path, Path::from(path));
+        assert_eq!(warned, changes("image/jpeg"));
+        // reading doesn't unshare the checked values
+        let blobs = |changes: &[ChangeContent]| match &changes[0] {
+            ChangeContent::Insert {
+                value: Value::Array(blobs),
+                ..
+            } => blobs.clone(),
+            _ => unreachable!(),
+        };
+        let shared = blobs(&warned);
+        validate_blob_mimes(&mut warned, MimePolicy::Warn).unwrap();
+        assert!(Arc::ptr_eq(&shared, &blobs(&warned)));
+        let mut fixed = changes("image/jpeg");
+        validate_blob_mimes(&mut fixed, MimePolicy::Fix).unwrap();
+        assert_eq!(fixed, changes("image/png"));
+    }
+}
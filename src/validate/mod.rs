@@ -0,0 +1,3 @@
+#[cfg(feature = "mime_sniff")]
+pub mod mime;
+pub mod normalize;
@@ -0,0 +1,102 @@
+use crate::{
+    apply::coalesce::canonical_order,
+    error::ValueStoreError,
+    types::{change::ChangeContent, Value},
+};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct NormalizeOptions {
+    /// fail with ValueStoreError::EmptyChangeSet if no change is left
+    pub reject_empty: bool,
+}
+
+/**
+ *  the part of normalize_changes that doesn't need the current value: removes replaces
+ *  without effect and rejects empty change sets if configured.
+ *  ValueStore::with_normalizer applies it to every change set it commits.
+ *  */
+pub fn strip_changes(
+    changes: &mut Vec<ChangeContent>,
+    options: NormalizeOptions,
+) -> Result<(), ValueStoreError> {
+    changes
+        .retain(|change| !matches!(change, ChangeContent::Replace { old, new, .. } if old == new));
+    if options.reject_empty && changes.is_empty() {
+        return Err(ValueStoreError::EmptyChangeSet);
+    }
+    Ok(())
+}
+
+/**
+ *  brings a change set into canonical form before it is committed on top of current.
+ *  replaces without effect are removed and the rest is brought into canonical_order.
+ *  fails if a change doesn't apply, e.g. indexing into a map.
+ *  */
+pub fn normalize_changes(
+    current: &Value,
+    changes: &mut Vec<ChangeContent>,
+    options: NormalizeOptions,
+) -> Result<(), ValueStoreError> {
+    strip_changes(changes, options)?;
+    *changes = canonical_order(std::mem::take(changes));
+    current.clone().apply_iter(changes.iter())
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{types::PathElement, util::test_util::field};
+
+    fn insert(path: Vec<PathElement>, value: i64) -> ChangeContent {
+        ChangeContent::Insert {
+            path: path.into(),
+            value: Value::Integer(value),
+        }
+    }
+
+    #[test]
+    fn normalize() {
+        let current = Value::Map(Arc::new(
+            [("a".to_string(), Value::Array(Arc::new(vec![])))].into(),
+        ));
+        let mut changes = vec![
+            ChangeContent::Replace {
+                path: vec![field("a")].into(),
+                old: Value::Array(Arc::new(vec![])),
+                new: Value::Array(Arc::new(vec![])),
+            },
+            insert(vec![field("c")], 1),
+            insert(vec![field("b")], 2),
+            insert(vec![field("a"), PathElement::Append], 3),
+            insert(vec![field("e")], 4),
+            insert(vec![field("d")], 5),
+        ];
+        normalize_changes(&current, &mut changes, NormalizeOptions::default()).unwrap();
+        assert_eq!(
+            changes,
+            vec![
+                insert(vec![field("a"), PathElement::Append], 3),
+                insert(vec![field("b")], 2),
+                insert(vec![field("c")], 1),
+                insert(vec![field("d")], 5),
+                insert(vec![field("e")], 4),
+            ]
+        );
+
+        let mut changes = vec![insert(vec![field("a"), field("b")], 1)];
+        assert!(matches!(
+            normalize_changes(&current, &mut changes, NormalizeOptions::default()),
+            Err(ValueStoreError::InvalidChange { .. })
+        ));
+        assert!(matches!(
+            normalize_changes(
+                &current,
+                &mut Vec::new(),
+                NormalizeOptions { reject_empty: true }
+            ),
+            Err(ValueStoreError::EmptyChangeSet)
+        ));
+    }
+}
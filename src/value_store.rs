@@ -1,33 +1,2016 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    io::{Read, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
 use uuid::Uuid;
 
+#[cfg(feature = "mime_sniff")]
+use crate::validate::mime::{self, MimePolicy};
 use crate::{
-    types::change::{Change, ChangeContent, Hash},
+    apply::{
+        diff, simple,
+        split::{self, SplitMarker},
+    },
+    clock::{Clock, IdGenerator, RandomIds, SystemClock},
+    config::Config,
+    error::ValueStoreError,
+    export::redact::Redaction,
+    import::{self, Format},
+    index::Indexes,
+    limit::{CommitPermit, CommitQueue, RateLimiter},
+    lock::{self, PathLock},
+    review::{ApprovalPolicy, APPROVAL_PREFIX},
+    schema::Migrations,
+    storage::{dynamic::DynId, Commit, DynStorage, RepoStats},
+    types::{
+        change::{Change, ChangeContent, Hash, Parents},
+        hasher::HashAlgorithm,
+        path_pattern::PathPattern,
+        value::{cbor_header_size, FloatPolicy},
+        Path, PathElement, Value,
+    },
+    validate::normalize::{self, NormalizeOptions},
     Result,
 };
 
-struct ValueStore {}
+/// prefix of the branch config entries mapping operation ids to the change they created
+const OPERATION_PREFIX: &str = "op.";
+/// how long operation ids are remembered unless configured otherwise
+const DEFAULT_OPERATION_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// config value of an operation id, the change it created and when
+fn operation_value(hash: Hash, time: SystemTime) -> Value {
+    Value::Map(Arc::new(HashMap::from([
+        (
+            "hash".to_string(),
+            Value::String(Arc::new(hash.to_string())),
+        ),
+        ("time".to_string(), Value::Integer(lock::millis(time))),
+    ])))
+}
+
+fn operation_from_value(value: &Value) -> Option<(Hash, SystemTime)> {
+    let Value::Map(map) = value else {
+        return None;
+    };
+    let (Some(Value::String(hash)), Some(Value::Integer(time))) =
+        (map.get("hash"), map.get("time"))
+    else {
+        return None;
+    };
+    Some((
+        hash.parse().ok()?,
+        UNIX_EPOCH + Duration::from_millis((*time).try_into().ok()?),
+    ))
+}
+
+/// last data migration recorded in config, 0 if none
+fn schema_version(config: &Config) -> Result<u32> {
+    match config.get_int(Config::SCHEMA_VERSION) {
+        Some(version) => u32::try_from(version).map_err(|_| {
+            ValueStoreError::InvalidConfig {
+                name: Config::SCHEMA_VERSION.to_string(),
+            }
+            .into()
+        }),
+        None => Ok(0),
+    }
+}
+
+fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+    let mut content = Vec::new();
+    ciborium::into_writer(value, &mut content)?;
+    Ok(content)
+}
+
+/// content of a change set on top of parent in canonical order, and its hash
+fn encode_change_set(parent: Hash, changes: &[ChangeContent]) -> Result<(Hash, Vec<u8>)> {
+    let content = encode(&ChangeContent::canonicalize(changes.to_vec()))?;
+    let hash = Change::compute_hash(HashAlgorithm::default(), &Parents::One(parent), &content);
+    Ok((hash, content))
+}
+
+struct ValueStore {
+    storage: Arc<dyn DynStorage>,
+    read_only: bool,
+    rate_limiter: Option<RateLimiter<Uuid>>,
+    commit_queue: Option<CommitQueue>,
+    closed: AtomicBool,
+    clock: Arc<dyn Clock>,
+    ids: Arc<dyn IdGenerator>,
+    max_change_size: Option<usize>,
+    operation_retention: Duration,
+    normalizer: Option<NormalizeOptions>,
+    #[cfg(feature = "mime_sniff")]
+    mime_policy: Option<MimePolicy>,
+    approval_policy: ApprovalPolicy,
+    float_policy: FloatPolicy,
+    indexes: Mutex<HashMap<(Uuid, Uuid), IndexedBranch>>,
+}
+
+/// indexes of a branch and the value they were built from, None if they have to be rebuilt
+struct IndexedBranch {
+    head: Option<Hash>,
+    value: Value,
+    indexes: Indexes,
+}
+
+/// changes ready for encoding and the notes to attach to their commit
+struct Prepared<'c> {
+    changes: Cow<'c, [ChangeContent]>,
+    notes: Vec<(&'static str, Vec<u8>)>,
+}
 
 #[derive(Debug)]
 struct BranchId(pub Uuid);
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct QueueHealth {
+    pub capacity: usize,
+    pub available: usize,
+}
+
+/// state of a store for service health endpoints
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Health {
+    /// error of the storage probe, None if the storage answered
+    pub storage_error: Option<String>,
+    pub read_only: bool,
+    pub commit_queue: Option<QueueHealth>,
+}
+
+impl Health {
+    /// the store can serve requests
+    pub fn is_ready(&self) -> bool {
+        self.storage_error.is_none()
+    }
+}
+
 #[derive(Debug)]
 struct RepoId(pub Uuid);
 
 impl ValueStore {
+    pub fn new(storage: Arc<dyn DynStorage>) -> Self {
+        Self {
+            storage,
+            read_only: false,
+            rate_limiter: None,
+            commit_queue: None,
+            closed: AtomicBool::new(false),
+            clock: Arc::new(SystemClock),
+            ids: Arc::new(RandomIds),
+            max_change_size: None,
+            operation_retention: DEFAULT_OPERATION_RETENTION,
+            normalizer: None,
+            #[cfg(feature = "mime_sniff")]
+            mime_policy: None,
+            approval_policy: ApprovalPolicy::default(),
+            float_policy: FloatPolicy::default(),
+            indexes: Mutex::new(HashMap::new()),
+        }
+    }
+    /// store rejecting all mutating operations with ValueStoreError::ReadOnly
+    pub fn open_read_only(storage: Arc<dyn DynStorage>) -> Self {
+        Self {
+            storage,
+            read_only: true,
+            rate_limiter: None,
+            commit_queue: None,
+            closed: AtomicBool::new(false),
+            clock: Arc::new(SystemClock),
+            ids: Arc::new(RandomIds),
+            max_change_size: None,
+            operation_retention: DEFAULT_OPERATION_RETENTION,
+            normalizer: None,
+            #[cfg(feature = "mime_sniff")]
+            mime_policy: None,
+            approval_policy: ApprovalPolicy::default(),
+            float_policy: FloatPolicy::default(),
+            indexes: Mutex::new(HashMap::new()),
+        }
+    }
+    /// commits exceeding the limit of their branch fail with ValueStoreError::RateLimited
+    pub fn with_rate_limiter(mut self, limiter: RateLimiter<Uuid>) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+    /// at most capacity commits are processed at once, later ones wait for a free slot
+    pub fn with_commit_queue(mut self, capacity: usize) -> Self {
+        self.commit_queue = Some(CommitQueue::new(capacity));
+        self
+    }
+    /// clock used for lock expiry and rate limits instead of the system time
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+    /// operation ids older than retention are forgotten, a retry after that commits again
+    pub fn with_operation_retention(mut self, retention: Duration) -> Self {
+        self.operation_retention = retention;
+        self
+    }
+    /// approvals promote requires on the head of a proposal
+    pub fn with_approval_policy(mut self, policy: ApprovalPolicy) -> Self {
+        self.approval_policy = policy;
+        self
+    }
+    pub fn with_id_generator(mut self, ids: Arc<dyn IdGenerator>) -> Self {
+        self.ids = ids;
+        self
+    }
+    /// uuid for a new repo or branch
+    pub fn new_id(&self) -> Uuid {
+        self.ids.new_id()
+    }
+    /// change sets with a larger estimated encoding fail with ValueStoreError::ChangeTooLarge
+    pub fn with_max_change_size(mut self, bytes: usize) -> Self {
+        self.max_change_size = Some(bytes);
+        self
+    }
+    /// comparison of floats when replaying and diffing values of branches
+    pub fn with_float_policy(mut self, policy: FloatPolicy) -> Self {
+        self.float_policy = policy;
+        self
+    }
+    /**
+     *  change sets committed on top of the head are normalized with normalize::strip_changes,
+     *  they are stored in canonical order anyway. changes created elsewhere are committed as
+     *  they are. checking paths against the value of the branch is left to callers holding it,
+     *  see normalize::normalize_changes.
+     *  */
+    pub fn with_normalizer(mut self, options: NormalizeOptions) -> Self {
+        self.normalizer = Some(options);
+        self
+    }
+    /**
+     *  blobs added by commits are checked against their declared mime type. with
+     *  MimePolicy::Warn the mismatches are attached to the commit as mime::MIME_MISMATCH_NOTE,
+     *  changes created elsewhere can't be fixed and are rejected instead.
+     *  */
+    #[cfg(feature = "mime_sniff")]
+    pub fn with_mime_policy(mut self, policy: MimePolicy) -> Self {
+        self.mime_policy = Some(policy);
+        self
+    }
+    /**
+     *  applies the validation options of the store to changes before they are encoded.
+     *  rewrite is false for changes with a fixed hash, they are only checked.
+     *  */
+    fn prepare<'c>(&self, changes: &'c [ChangeContent], rewrite: bool) -> Result<Prepared<'c>> {
+        let mut prepared = Prepared {
+            changes: Cow::Borrowed(changes),
+            notes: Vec::new(),
+        };
+        if let (Some(options), true) = (self.normalizer, rewrite) {
+            let mut changes = prepared.changes.into_owned();
+            normalize::strip_changes(&mut changes, options)?;
+            prepared.changes = Cow::Owned(changes);
+        }
+        #[cfg(feature = "mime_sniff")]
+        if let Some(policy) = self.mime_policy {
+            let policy = match policy {
+                MimePolicy::Fix if !rewrite => MimePolicy::Reject,
+                policy => policy,
+            };
+            let mut changes = prepared.changes.into_owned();
+            let mismatches = mime::validate_blob_mimes(&mut changes, policy)?;
+            if !mismatches.is_empty() && policy == MimePolicy::Warn {
+                prepared
+                    .notes
+                    .push((mime::MIME_MISMATCH_NOTE, encode(&mismatches)?));
+            }
+            prepared.changes = Cow::Owned(changes);
+        }
+        Ok(prepared)
+    }
+    /// attaches the notes of a prepared change set to its commit
+    async fn add_notes(&self, hash: Hash, notes: Vec<(&'static str, Vec<u8>)>) -> Result<()> {
+        for (name, content) in notes {
+            let id = self.change_id(hash).await?;
+            self.storage.set_note(id, name, Some(&content)).await?;
+        }
+        Ok(())
+    }
+    /// estimated encoded size of changes, checked against the limit before anything is written
+    pub fn check_change_size(&self, changes: &[ChangeContent]) -> Result<usize> {
+        let size = changes
+            .iter()
+            .map(ChangeContent::encoded_size_hint)
+            .sum::<usize>()
+            + cbor_header_size(changes.len() as u64);
+        match self.max_change_size {
+            Some(limit) if size > limit => {
+                Err(ValueStoreError::ChangeTooLarge { size, limit }.into())
+            }
+            _ => Ok(size),
+        }
+    }
+    /**
+     *  splits changes into parts within the max change size, each tagged with a marker of
+     *  a fresh group. the parts are meant to be committed in order with add_split_part.
+     *  */
+    pub fn split_change_set(
+        &self,
+        changes: Vec<ChangeContent>,
+    ) -> Result<Vec<(SplitMarker, Vec<ChangeContent>)>> {
+        let parts = match self.max_change_size {
+            Some(limit) => split::split(changes, limit)?,
+            None => vec![changes],
+        };
+        let group = self.new_id();
+        let count = parts.len() as u32;
+        Ok(parts
+            .into_iter()
+            .enumerate()
+            .map(|(part, changes)| {
+                let marker = SplitMarker {
+                    group,
+                    part: part as u32,
+                    parts: count,
+                };
+                (marker, changes)
+            })
+            .collect())
+    }
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+    /// probes the storage with a cheap lookup, never fails itself
+    pub async fn health(&self) -> Health {
+        Health {
+            storage_error: self
+                .storage
+                .get_repo_id(Uuid::nil())
+                .await
+                .err()
+                .map(|e| e.to_string()),
+            read_only: self.read_only,
+            commit_queue: self.commit_queue.as_ref().map(|queue| QueueHealth {
+                capacity: queue.capacity(),
+                available: queue.available(),
+            }),
+        }
+    }
+    fn check_writable(&self) -> Result<()> {
+        if self.closed.load(Ordering::Acquire) {
+            Err(ValueStoreError::Closed.into())
+        } else if self.read_only {
+            Err(ValueStoreError::ReadOnly.into())
+        } else {
+            Ok(())
+        }
+    }
+    /**
+     *  rejects new writes with ValueStoreError::Closed, waits for queued commits to finish
+     *  and closes the storage. commits in progress without a commit queue are not awaited.
+     *  */
+    pub async fn close(&self) -> Result<()> {
+        self.closed.store(true, Ordering::Release);
+        if let Some(queue) = &self.commit_queue {
+            let _drained = queue.drain().await;
+        }
+        self.storage.close().await
+    }
+    async fn admit_commit(&self, branch: &BranchId) -> Result<Option<CommitPermit<'_>>> {
+        if let Some(limiter) = &self.rate_limiter {
+            limiter
+                .check(&branch.0, self.clock.instant())
+                .map_err(|retry_after| ValueStoreError::RateLimited { retry_after })?;
+        }
+        Ok(match &self.commit_queue {
+            Some(queue) => Some(queue.enter().await),
+            None => None,
+        })
+    }
+    async fn change_id(&self, hash: Hash) -> Result<DynId> {
+        self.storage
+            .get_change_id(hash)
+            .await?
+            .ok_or(ValueStoreError::UnknownChange { hash }.into())
+    }
+    /// size and shape of the history of repo, for capacity planning
+    pub async fn stats(&self, repo: &RepoId) -> Result<RepoStats> {
+        self.storage.repo_stats(self.repo_id(repo).await?).await
+    }
+    /// annotations attached to a change after it was created
+    pub async fn notes(&self, hash: Hash) -> Result<BTreeMap<String, Value>> {
+        let id = self.change_id(hash).await?;
+        let mut res = BTreeMap::new();
+        for (name, content) in self.storage.get_notes(id).await? {
+            res.insert(name, ciborium::from_reader(content.as_slice())?);
+        }
+        Ok(res)
+    }
+    /// replaces the note name of a change, None removes it. the change itself is not modified.
+    pub async fn set_note(&self, hash: Hash, name: &str, value: Option<&Value>) -> Result<()> {
+        self.check_writable()?;
+        let id = self.change_id(hash).await?;
+        let content = value.map(encode).transpose()?;
+        self.storage.set_note(id, name, content.as_deref()).await
+    }
+    /// committed changes of repo belonging to group, ordered by part
+    pub async fn group_changes(
+        &self,
+        repo: &RepoId,
+        group: Uuid,
+    ) -> Result<Vec<(SplitMarker, Hash)>> {
+        let mut res = Vec::new();
+        let repo_id = self.repo_id(repo).await?;
+        for (marker, id) in self.storage.get_group(repo_id, group).await? {
+            let (hash, _) = self.storage.get_change_hash(id).await?;
+            res.push((marker, hash));
+        }
+        Ok(res)
+    }
+    /// every part of group is committed, readers should treat incomplete groups as absent
+    pub async fn is_group_complete(&self, repo: &RepoId, group: Uuid) -> Result<bool> {
+        let changes = self.group_changes(repo, group).await?;
+        Ok(changes
+            .first()
+            .is_some_and(|(marker, _)| marker.parts as usize == changes.len()))
+    }
+    async fn repo_id(&self, repo: &RepoId) -> Result<DynId> {
+        self.storage
+            .get_repo_id(repo.0)
+            .await?
+            .ok_or(ValueStoreError::UnknownRepo { uuid: repo.0 }.into())
+    }
+    async fn branch_id(&self, repo: DynId, branch: &BranchId) -> Result<DynId> {
+        self.storage
+            .get_branch_id(repo, branch.0)
+            .await?
+            .ok_or(ValueStoreError::UnknownBranch { uuid: branch.0 }.into())
+    }
+    /// configuration of repo, with the values of branch taking precedence if given
+    pub async fn config(&self, repo: &RepoId, branch: Option<&BranchId>) -> Result<Config> {
+        let repo_entries = self
+            .storage
+            .get_repo_config(self.repo_id(repo).await?)
+            .await?;
+        let mut branch_entries = match branch {
+            // ids are consumed by storage calls, so the repo is looked up again
+            Some(branch) => {
+                let branch_id = self.branch_id(self.repo_id(repo).await?, branch).await?;
+                self.storage.get_branch_config(branch_id).await?
+            }
+            None => Vec::new(),
+        };
+        // operation ids are bookkeeping of add_change_set_idempotent
+        branch_entries.retain(|(name, _)| !name.starts_with(OPERATION_PREFIX));
+        Config::from_entries(repo_entries, branch_entries)
+    }
+    /// operation ids of branch with the change they created and when, None if unreadable
+    async fn operations(
+        &self,
+        repo: &RepoId,
+        branch: &BranchId,
+    ) -> Result<Vec<(String, Option<(Hash, SystemTime)>)>> {
+        let branch_id = self.branch_id(self.repo_id(repo).await?, branch).await?;
+        let mut res = Vec::new();
+        for (name, content) in self.storage.get_branch_config(branch_id).await? {
+            if name.starts_with(OPERATION_PREFIX) {
+                let value: Value = ciborium::from_reader(content.as_slice())?;
+                res.push((name, operation_from_value(&value)));
+            }
+        }
+        Ok(res)
+    }
+    /// sets a configuration value of repo, or only of branch if given. None removes it.
+    pub async fn set_config(
+        &self,
+        repo: &RepoId,
+        branch: Option<&BranchId>,
+        name: &str,
+        value: Option<&Value>,
+    ) -> Result<()> {
+        self.check_writable()?;
+        let content = value.map(encode).transpose()?;
+        let repo_id = self.repo_id(repo).await?;
+        match branch {
+            Some(branch) => {
+                let branch_id = self.branch_id(repo_id, branch).await?;
+                self.storage
+                    .set_branch_config(branch_id, name, content.as_deref())
+                    .await
+            }
+            None => {
+                self.storage
+                    .set_repo_config(repo_id, name, content.as_deref())
+                    .await
+            }
+        }
+    }
+    /// last data migration applied to branch, 0 if none
+    pub async fn schema_version(&self, repo: &RepoId, branch: &BranchId) -> Result<u32> {
+        schema_version(&self.config(repo, Some(branch)).await?)
+    }
+    /**
+     *  records that the changes of a migration up to version were committed on branch.
+     *  migrate records the version together with the changes, this is for branches created
+     *  with data of a known version.
+     *  */
+    pub async fn set_schema_version(
+        &self,
+        repo: &RepoId,
+        branch: &BranchId,
+        version: u32,
+    ) -> Result<()> {
+        self.set_config(
+            repo,
+            Some(branch),
+            Config::SCHEMA_VERSION,
+            Some(&Value::Integer(version.into())),
+        )
+        .await
+    }
+    /**
+     *  applies the migrations pending for branch to current, the value of its head, and
+     *  commits the resulting changes together with the new schema version, so the data and
+     *  its version can't diverge. returns the hash of the commit, None if branch is up to date.
+     *  */
+    pub async fn migrate(
+        &self,
+        repo: &RepoId,
+        branch: &BranchId,
+        migrations: &Migrations,
+        current: &Value,
+    ) -> Result<Option<Hash>> {
+        self.check_writable()?;
+        // read before the version, a concurrent migration moves the head
+        let head = self.head(repo, branch).await?;
+        let config = self.config(repo, Some(branch)).await?;
+        let Some(migrated) = migrations.run(current, schema_version(&config)?)? else {
+            return Ok(None);
+        };
+        let prepared = self.prepare(&migrated.changes, true)?;
+        self.check_change_size(&prepared.changes)?;
+        let _permit = self.admit_commit(branch).await?;
+        self.run_hooks(&config, None, None, &prepared.changes)?;
+        let (hash, content) = encode_change_set(head, &prepared.changes)?;
+        let version = Value::Integer(migrated.version.into());
+        let entries = [(Config::SCHEMA_VERSION.to_string(), Some(encode(&version)?))];
+        let commit = Commit {
+            hash: &hash,
+            algorithm: HashAlgorithm::default(),
+            content: &content,
+            parents: &[head],
+            config: &entries,
+            group: None,
+        };
+        self.commit(repo, branch, commit).await?;
+        self.update_indexes(repo, branch, head, hash, &prepared.changes);
+        self.add_notes(hash, prepared.notes).await?;
+        Ok(Some(hash))
+    }
+    /**
+     *  writes the branch config entries returned by update for the current config and time,
+     *  unless a lock of branch changed in between, then update runs again on the new config.
+     *  */
+    async fn update_locks<T>(
+        &self,
+        repo: &RepoId,
+        branch: &BranchId,
+        update: impl Fn(&Config, SystemTime) -> Result<(Vec<(String, Option<Vec<u8>>)>, T)>,
+    ) -> Result<T> {
+        loop {
+            let repo_entries = self
+                .storage
+                .get_repo_config(self.repo_id(repo).await?)
+                .await?;
+            let branch_id = self.branch_id(self.repo_id(repo).await?, branch).await?;
+            let branch_entries = self.storage.get_branch_config(branch_id).await?;
+            let held: Vec<_> = branch_entries
+                .iter()
+                .filter(|(name, _)| name.starts_with(lock::LOCK_PREFIX))
+                .cloned()
+                .collect();
+            let config = Config::from_entries(repo_entries, branch_entries)?;
+            let (entries, res) = update(&config, self.clock.now())?;
+            let branch_id = self.branch_id(self.repo_id(repo).await?, branch).await?;
+            if self
+                .storage
+                .set_branch_config_if(branch_id, lock::LOCK_PREFIX, &held, &entries)
+                .await?
+            {
+                return Ok(res);
+            }
+        }
+    }
+    /**
+     *  locks the subtree at path of branch for owner, renewing a lock owner already holds.
+     *  fails if another owner holds a lock on an overlapping path, of two owners racing for
+     *  the same path only one succeeds. commits touching the subtree fail unless made by owner.
+     *  */
+    pub async fn lock(
+        &self,
+        repo: &RepoId,
+        branch: &BranchId,
+        path: &[PathElement],
+        owner: &str,
+        ttl: Duration,
+    ) -> Result<PathLock> {
+        self.check_writable()?;
+        self.update_locks(repo, branch, |config, now| {
+            let new = PathLock::new(path, owner, ttl, now)?;
+            for held in lock::active_locks(config, now) {
+                if held.owner != owner && held.covers(path) {
+                    return Err(ValueStoreError::PathLocked {
+                        path: held.path,
+                        owner: held.owner,
+                    }
+                    .into());
+                }
+            }
+            let entries = vec![(lock::lock_name(path), Some(encode(&new.to_value())?))];
+            Ok((entries, new))
+        })
+        .await
+    }
+    /// releases the lock on path, locks of other owners can only be released once expired
+    pub async fn unlock(
+        &self,
+        repo: &RepoId,
+        branch: &BranchId,
+        path: &[PathElement],
+        owner: &str,
+    ) -> Result<()> {
+        self.check_writable()?;
+        let name = lock::lock_name(path);
+        self.update_locks(repo, branch, |config, now| {
+            match config.get(&name).and_then(PathLock::from_value) {
+                Some(held) if held.owner != owner && !held.is_expired(now) => {
+                    Err(ValueStoreError::PathLocked {
+                        path: held.path,
+                        owner: held.owner,
+                    }
+                    .into())
+                }
+                Some(_) => Ok((vec![(name.clone(), None)], ())),
+                None => Ok((Vec::new(), ())),
+            }
+        })
+        .await
+    }
+    /// unexpired locks of branch
+    pub async fn locks(&self, repo: &RepoId, branch: &BranchId) -> Result<Vec<PathLock>> {
+        let config = self.config(repo, Some(branch)).await?;
+        Ok(lock::active_locks(&config, self.clock.now()))
+    }
+    /// fails with ValueStoreError::PathLocked if changes touch a subtree locked by someone else
+    pub async fn check_locks(
+        &self,
+        repo: &RepoId,
+        branch: &BranchId,
+        changes: &[ChangeContent],
+        owner: Option<&str>,
+    ) -> Result<()> {
+        let locks = self.locks(repo, branch).await?;
+        lock::check_locks(&locks, changes, owner)
+    }
+    /**
+     *  checks of a commit of changes by owner to a branch with config. locks of other owners
+     *  are always enforced, ignore_hook only skips hooks that can be skipped and there are none.
+     *  */
+    fn run_hooks(
+        &self,
+        config: &Config,
+        _ignore_hook: Option<u64>,
+        owner: Option<&str>,
+        changes: &[ChangeContent],
+    ) -> Result<()> {
+        let locks = lock::active_locks(config, self.clock.now());
+        lock::check_locks(&locks, changes, owner)
+    }
+    /// hash of the head of branch
+    async fn head(&self, repo: &RepoId, branch: &BranchId) -> Result<Hash> {
+        let branch_id = self.branch_id(self.repo_id(repo).await?, branch).await?;
+        let head = self.storage.get_branch_head(branch_id).await?;
+        let (hash, _) = self.storage.get_change_hash(head).await?;
+        Ok(hash)
+    }
+    async fn content(&self, hash: Hash) -> Result<Vec<ChangeContent>> {
+        let content = self.storage.get_change_content(self.change_id(hash).await?);
+        Ok(ciborium::from_reader(content.await?.as_slice())?)
+    }
+    /// parent with the lowest hash, None for a root change
+    async fn first_parent(&self, hash: Hash) -> Result<Option<Hash>> {
+        let parents = self.storage.get_change_rels(self.change_id(hash).await?);
+        match parents.await?.into_iter().next() {
+            Some(parent) => Ok(Some(self.storage.get_change_hash(parent).await?.0)),
+            None => Ok(None),
+        }
+    }
+    /**
+     *  value of branch, replaying its history from the root. like GitExport the content of
+     *  a change is applied to the value of its first parent, old values are compared with
+     *  the float policy.
+     *  */
+    pub async fn value(&self, repo: &RepoId, branch: &BranchId) -> Result<Value> {
+        self.value_at(self.head(repo, branch).await?).await
+    }
+    async fn value_at(&self, head: Hash) -> Result<Value> {
+        let mut contents = Vec::new();
+        let mut next = Some(head);
+        while let Some(hash) = next {
+            contents.push(self.content(hash).await?);
+            next = self.first_parent(hash).await?;
+        }
+        let mut value = Value::default();
+        for change in contents.iter().rev().flatten() {
+            simple::apply_with(&mut value, change, self.float_policy)?;
+        }
+        Ok(value)
+    }
+    /**
+     *  declares the index name over the values matching pattern in branch, see index::Indexes.
+     *  indexed branches keep their value in memory. commits of change sets through the store
+     *  update the indexes in place, other moves of the head rebuild them on the next lookup.
+     *  */
+    pub async fn declare_index(
+        &self,
+        repo: &RepoId,
+        branch: &BranchId,
+        name: &str,
+        pattern: PathPattern,
+    ) -> Result<()> {
+        let head = self.head(repo, branch).await?;
+        let value = self.value_at(head).await?;
+        let mut indexed = self.indexes.lock().expect("indexes poisoned");
+        let entry = indexed
+            .entry((repo.0, branch.0))
+            .or_insert_with(|| IndexedBranch {
+                head: None,
+                value: Value::default(),
+                indexes: Indexes::default(),
+            });
+        if entry.head != Some(head) {
+            entry.indexes.rebuild(&value);
+            entry.head = Some(head);
+            entry.value = value;
+        }
+        entry.indexes.declare(name, pattern, &entry.value);
+        Ok(())
+    }
+    /// removes the index name of branch, false if it didn't exist
+    pub fn remove_index(&self, repo: &RepoId, branch: &BranchId, name: &str) -> bool {
+        let mut indexed = self.indexes.lock().expect("indexes poisoned");
+        indexed
+            .get_mut(&(repo.0, branch.0))
+            .is_some_and(|entry| entry.indexes.remove(name))
+    }
+    /// paths of the values equal to key in the index of branch, None if it wasn't declared
+    pub async fn find_indexed(
+        &self,
+        repo: &RepoId,
+        branch: &BranchId,
+        index: &str,
+        key: &Value,
+    ) -> Result<Option<Vec<Vec<PathElement>>>> {
+        let head = self.head(repo, branch).await?;
+        let stale = match self
+            .indexes
+            .lock()
+            .expect("indexes poisoned")
+            .get(&(repo.0, branch.0))
+        {
+            Some(entry) => entry.head != Some(head),
+            None => return Ok(None),
+        };
+        // the value is rebuilt without holding the lock
+        let value = match stale {
+            true => Some(self.value_at(head).await?),
+            false => None,
+        };
+        let mut indexed = self.indexes.lock().expect("indexes poisoned");
+        let Some(entry) = indexed.get_mut(&(repo.0, branch.0)) else {
+            return Ok(None);
+        };
+        if let Some(value) = value {
+            entry.indexes.rebuild(&value);
+            entry.head = Some(head);
+            entry.value = value;
+        }
+        Ok(entry.indexes.find(index, key))
+    }
+    /// moves the indexes of branch from parent to the commit hash made of changes
+    fn update_indexes(
+        &self,
+        repo: &RepoId,
+        branch: &BranchId,
+        parent: Hash,
+        hash: Hash,
+        changes: &[ChangeContent],
+    ) {
+        let mut indexed = self.indexes.lock().expect("indexes poisoned");
+        let Some(entry) = indexed.get_mut(&(repo.0, branch.0)) else {
+            return;
+        };
+        if entry.head == Some(hash) {
+            return;
+        }
+        let applied = entry.head == Some(parent)
+            && changes
+                .iter()
+                .try_for_each(|change| {
+                    simple::apply_with(&mut entry.value, change, self.float_policy)
+                })
+                .is_ok();
+        if applied {
+            entry.indexes.update(&entry.value, changes);
+            entry.head = Some(hash);
+        } else {
+            entry.head = None;
+        }
+    }
+    /**
+     *  changes turning the value of from into the value of to, see apply::diff. floats are
+     *  compared with the float policy, values are redacted before comparing.
+     *  */
+    pub async fn diff(
+        &self,
+        repo: &RepoId,
+        from: &BranchId,
+        to: &BranchId,
+        redaction: &Redaction,
+    ) -> Result<Vec<ChangeContent>> {
+        let old = self.value(repo, from).await?;
+        let new = self.value(repo, to).await?;
+        Ok(diff::diff_with(&old, &new, self.float_policy, redaction))
+    }
+    /**
+     *  stores the change of commit on branch and moves its head onto it, see Storage::commit.
+     *  returns the sequence number of the commit.
+     *  */
+    async fn commit(&self, repo: &RepoId, branch: &BranchId, commit: Commit<'_>) -> Result<u64> {
+        let repo_id = self.repo_id(repo).await?;
+        let branch_id = self.branch_id(self.repo_id(repo).await?, branch).await?;
+        let (_, seq) = self.storage.commit(repo_id, branch_id, commit).await?;
+        Ok(seq)
+    }
+    /**
+     *  commits a change created elsewhere to branch, the head of branch has to be one of its
+     *  parents. the hash is checked against parents and content. committing the head again
+     *  does nothing, so a commit can be retried if its outcome is unknown.
+     *  owner is checked against the path locks of branch, see lock.
+     *  */
     pub async fn add_change(
+        &self,
         branch: BranchId,
         repo: RepoId,
         ignore_hook: Option<u64>,
+        owner: Option<&str>,
         change: &Change,
     ) -> Result<()> {
-        Ok(())
+        self.check_writable()?;
+        let prepared = self.prepare(&change.content, false)?;
+        self.check_change_size(&change.content)?;
+        let content = encode(&change.content)?;
+        if Change::compute_hash(HashAlgorithm::default(), &change.parents, &content) != change.hash
+        {
+            return Err(ValueStoreError::HashMismatch { hash: change.hash }.into());
+        }
+        let _permit = self.admit_commit(&branch).await?;
+        let config = self.config(&repo, Some(&branch)).await?;
+        self.run_hooks(&config, ignore_hook, owner, &change.content)?;
+        let parents: Vec<_> = change.parents.iter().copied().collect();
+        let commit = Commit {
+            hash: &change.hash,
+            algorithm: HashAlgorithm::default(),
+            content: &content,
+            parents: &parents,
+            config: &[],
+            group: None,
+        };
+        self.commit(&repo, &branch, commit).await?;
+        if let Some(parent) = parents.first() {
+            self.update_indexes(&repo, &branch, *parent, change.hash, &change.content);
+        }
+        self.add_notes(change.hash, prepared.notes).await
     }
+    /**
+     *  commits changes as a child of the head of branch and returns its hash. the changes are
+     *  stored in canonical order, see ChangeContent::canonicalize, and not checked against
+     *  the value of branch. owner is checked against the path locks of branch, see lock.
+     *  */
     pub async fn add_chage_sets(
+        &self,
         branch: BranchId,
         repo: RepoId,
         ignore_hook: Option<u64>,
+        owner: Option<&str>,
         changes: &[ChangeContent],
     ) -> Result<Hash> {
-        todo!()
+        self.commit_change_set(branch, repo, ignore_hook, owner, changes, None)
+            .await
+    }
+    /// commits a part of split_change_set like add_chage_sets, stored with its marker
+    pub async fn add_split_part(
+        &self,
+        branch: BranchId,
+        repo: RepoId,
+        ignore_hook: Option<u64>,
+        owner: Option<&str>,
+        marker: SplitMarker,
+        changes: &[ChangeContent],
+    ) -> Result<Hash> {
+        self.commit_change_set(branch, repo, ignore_hook, owner, changes, Some(marker))
+            .await
+    }
+    async fn commit_change_set(
+        &self,
+        branch: BranchId,
+        repo: RepoId,
+        ignore_hook: Option<u64>,
+        owner: Option<&str>,
+        changes: &[ChangeContent],
+        group: Option<SplitMarker>,
+    ) -> Result<Hash> {
+        self.check_writable()?;
+        let prepared = self.prepare(changes, true)?;
+        self.check_change_size(&prepared.changes)?;
+        let _permit = self.admit_commit(&branch).await?;
+        let config = self.config(&repo, Some(&branch)).await?;
+        self.run_hooks(&config, ignore_hook, owner, &prepared.changes)?;
+        let head = self.head(&repo, &branch).await?;
+        let (hash, content) = encode_change_set(head, &prepared.changes)?;
+        let commit = Commit {
+            hash: &hash,
+            algorithm: HashAlgorithm::default(),
+            content: &content,
+            parents: &[head],
+            config: &[],
+            group,
+        };
+        self.commit(&repo, &branch, commit).await?;
+        self.update_indexes(&repo, &branch, head, hash, &prepared.changes);
+        self.add_notes(hash, prepared.notes).await?;
+        Ok(hash)
+    }
+    /**
+     *  commits changes unless op_id was already committed to branch, in which case the hash
+     *  of that commit is returned, so clients can safely retry commits that may have succeeded.
+     *  operation ids are written to the branch config in the same transaction as the change,
+     *  left out of config() and ignored and eventually removed after the operation retention.
+     *  of two concurrent calls with the same op_id one fails with
+     *  ValueStoreError::HeadParentMismatch.
+     *  */
+    pub async fn add_change_set_idempotent(
+        &self,
+        branch: BranchId,
+        repo: RepoId,
+        op_id: Uuid,
+        ignore_hook: Option<u64>,
+        owner: Option<&str>,
+        changes: &[ChangeContent],
+    ) -> Result<Hash> {
+        self.check_writable()?;
+        self.check_change_size(changes)?;
+        let _permit = self.admit_commit(&branch).await?;
+        // read before the operation ids, a commit of op_id in between moves the head
+        let head = self.head(&repo, &branch).await?;
+        let operations = self.operations(&repo, &branch).await?;
+        let config = self.config(&repo, Some(&branch)).await?;
+        let now = self.clock.now();
+        let expired = |time: &SystemTime| {
+            time.checked_add(self.operation_retention)
+                .is_some_and(|expiry| expiry <= now)
+        };
+        let name = format!("{OPERATION_PREFIX}{op_id}");
+        match operations.iter().find(|(id, _)| *id == name) {
+            Some((_, None)) => {
+                return Err(ValueStoreError::CorruptHistory { change: None }.into());
+            }
+            Some((_, Some((hash, time)))) if !expired(time) => return Ok(*hash),
+            // an expired id is committed again like a new one
+            _ => {}
+        }
+        let prepared = self.prepare(changes, true)?;
+        self.run_hooks(&config, ignore_hook, owner, &prepared.changes)?;
+        let (hash, content) = encode_change_set(head, &prepared.changes)?;
+        let mut entries = operations
+            .into_iter()
+            .filter(|(id, operation)| {
+                *id != name && operation.is_none_or(|(_, time)| expired(&time))
+            })
+            .map(|(id, _)| (id, None))
+            .collect::<Vec<_>>();
+        entries.push((name, Some(encode(&operation_value(hash, now))?)));
+        let commit = Commit {
+            hash: &hash,
+            algorithm: HashAlgorithm::default(),
+            content: &content,
+            parents: &[head],
+            config: &entries,
+            group: None,
+        };
+        self.commit(&repo, &branch, commit).await?;
+        self.update_indexes(&repo, &branch, head, hash, &prepared.changes);
+        self.add_notes(hash, prepared.notes).await?;
+        Ok(hash)
+    }
+    /**
+     *  creates a proposal branch at the head of target. commits to it don't touch target
+     *  until they are promoted, storage can't remove branches so the proposal stays around.
+     *  */
+    pub async fn propose(&self, repo: &RepoId, target: &BranchId) -> Result<BranchId> {
+        self.check_writable()?;
+        let repo_id = self.repo_id(repo).await?;
+        let target_id = self.branch_id(self.repo_id(repo).await?, target).await?;
+        let head = self.storage.get_branch_head(target_id).await?;
+        let uuid = self.new_id();
+        self.storage.set_branch_head(repo_id, uuid, head).await?;
+        Ok(BranchId(uuid))
+    }
+    /**
+     *  commits the changes of proposal since the head of target as one change on target,
+     *  if the proposal head is approved according to the approval policy. the approvals are
+     *  copied to the new change. fails with ValueStoreError::HeadParentMismatch if target
+     *  moved since the proposal was created or last promoted.
+     *  */
+    pub async fn promote(
+        &self,
+        repo: RepoId,
+        proposal: &BranchId,
+        target: BranchId,
+        owner: Option<&str>,
+    ) -> Result<Hash> {
+        self.check_writable()?;
+        let head = self.head(&repo, proposal).await?;
+        let notes = self.notes(head).await?;
+        if !self.approval_policy.is_satisfied(&notes) {
+            return Err(ValueStoreError::NotApproved {
+                approvals: ApprovalPolicy::approvers(&notes).len(),
+                required: self.approval_policy.approvals,
+            }
+            .into());
+        }
+        let base = self.head(&repo, &target).await?;
+        let mut contents = Vec::new();
+        let mut next = head;
+        while next != base {
+            contents.push(self.content(next).await?);
+            next = self
+                .first_parent(next)
+                .await?
+                .ok_or(ValueStoreError::HeadParentMismatch { parent: base })?;
+        }
+        let changes: Vec<_> = contents.into_iter().rev().flatten().collect();
+        if changes.is_empty() {
+            return Err(ValueStoreError::EmptyChangeSet.into());
+        }
+        self.check_change_size(&changes)?;
+        let _permit = self.admit_commit(&target).await?;
+        let config = self.config(&repo, Some(&target)).await?;
+        self.run_hooks(&config, None, owner, &changes)?;
+        let (hash, content) = encode_change_set(base, &changes)?;
+        let commit = Commit {
+            hash: &hash,
+            algorithm: HashAlgorithm::default(),
+            content: &content,
+            parents: &[base],
+            config: &[],
+            group: None,
+        };
+        self.commit(&repo, &target, commit).await?;
+        self.update_indexes(&repo, &target, base, hash, &changes);
+        for (name, value) in notes.range(APPROVAL_PREFIX.to_string()..) {
+            if !name.starts_with(APPROVAL_PREFIX) {
+                break;
+            }
+            let id = self.change_id(hash).await?;
+            self.storage
+                .set_note(id, name, Some(&encode(value)?))
+                .await?;
+        }
+        Ok(hash)
+    }
+    /**
+     *  sets branch to the document read from reader, committed like add_chage_sets as one
+     *  change replacing the root value.
+     *  */
+    pub async fn init_from_reader<R: Read>(
+        &self,
+        repo: RepoId,
+        branch: BranchId,
+        format: Format,
+        reader: R,
+    ) -> Result<Hash> {
+        let new = import::read_value(format, reader)?;
+        let old = self.value(&repo, &branch).await?;
+        let changes = [ChangeContent::Replace {
+            path: Path::default(),
+            old,
+            new,
+        }];
+        self.commit_change_set(branch, repo, None, None, &changes, None)
+            .await
+    }
+    /// writes the value of branch redacted by redaction to writer, see import::write_value
+    pub async fn export_value<W: Write>(
+        &self,
+        repo: &RepoId,
+        branch: &BranchId,
+        format: Format,
+        redaction: &Redaction,
+        writer: W,
+    ) -> Result<()> {
+        let value = self.value(repo, branch).await?;
+        import::write_value(format, &value, redaction, writer)
+    }
+}
+
+#[cfg(all(test, feature = "db_redb"))]
+mod test {
+    use futures_executor::block_on;
+    use futures_util::FutureExt;
+
+    use super::*;
+    use crate::{
+        clock::ManualClock,
+        export::redact::{self, RedactAction},
+        review,
+        storage::{
+            fault::{FaultPoint, FaultStorage},
+            redb::RedbStorage,
+            Storage,
+        },
+        util::test_util::{field, redb_storage},
+        Error,
+    };
+
+    const REPO: Uuid = Uuid::from_u128(1);
+    const BRANCH: Uuid = Uuid::from_u128(2);
+    const ROOT: Hash = Hash([0; 32]);
+
+    /// storage holding REPO with BRANCH at ROOT
+    fn storage() -> RedbStorage {
+        let storage = redb_storage();
+        let content = encode(&Vec::<ChangeContent>::new()).unwrap();
+        let root = Storage::add_change(&storage, &ROOT, HashAlgorithm::default(), &content, &[])
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        let repo = storage.create_repo(REPO).unwrap();
+        storage.set_branch_head(repo, BRANCH, root).unwrap();
+        storage
+    }
+
+    fn store() -> ValueStore {
+        ValueStore::new(Arc::new(storage()))
+    }
+
+    fn change(parent: Hash, name: &str) -> Change {
+        let content = vec![ChangeContent::Insert {
+            path: vec![field(name)].into(),
+            value: Value::Bool(true),
+        }];
+        let parents = Parents::One(parent);
+        let hash = Change::compute_hash(
+            HashAlgorithm::default(),
+            &parents,
+            &encode(&content).unwrap(),
+        );
+        Change {
+            hash,
+            parents,
+            content,
+        }
+    }
+
+    fn log(store: &ValueStore) -> Vec<u64> {
+        let repo = store
+            .repo_id(&RepoId(REPO))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        let events = store.storage.changes_since(repo, 0).now_or_never().unwrap();
+        events.unwrap().into_iter().map(|(seq, _)| seq).collect()
+    }
+
+    fn add(store: &ValueStore, change: &Change) -> Result<()> {
+        store
+            .add_change(BranchId(BRANCH), RepoId(REPO), None, None, change)
+            .now_or_never()
+            .unwrap()
+    }
+
+    /**
+     *  commits `commits` change sets from each of `writers` threads to BRANCH, retrying on
+     *  head conflicts, and checks that the branch history is a single chain holding every
+     *  commit in log order. works with any storage, the futures are driven by block_on.
+     *  */
+    fn stress(store: &ValueStore, writers: usize, commits: usize) {
+        let committed: Vec<Hash> = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..writers)
+                .map(|writer| {
+                    scope.spawn(move || {
+                        let mut hashes = Vec::new();
+                        for i in 0..commits {
+                            let changes = change(ROOT, &format!("{writer}.{i}")).content;
+                            loop {
+                                let commit = store.add_chage_sets(
+                                    BranchId(BRANCH),
+                                    RepoId(REPO),
+                                    None,
+                                    None,
+                                    &changes,
+                                );
+                                match block_on(commit) {
+                                    Ok(hash) => break hashes.push(hash),
+                                    Err(Error::ValueStore(
+                                        ValueStoreError::HeadParentMismatch { .. },
+                                    )) => continue,
+                                    Err(err) => panic!("commit failed: {err:?}"),
+                                }
+                            }
+                        }
+                        hashes
+                    })
+                })
+                .collect();
+            threads
+                .into_iter()
+                .flat_map(|thread| thread.join().unwrap())
+                .collect()
+        });
+
+        // walk the branch from its head down to ROOT
+        let branch = block_on(async {
+            let repo = store.repo_id(&RepoId(REPO)).await?;
+            store.branch_id(repo, &BranchId(BRANCH)).await
+        })
+        .unwrap();
+        let head = block_on(store.storage.get_branch_head(branch)).unwrap();
+        let (mut hash, _) = block_on(store.storage.get_change_hash(head)).unwrap();
+        let mut chain = Vec::new();
+        while hash != ROOT {
+            chain.push(hash);
+            let id = block_on(store.change_id(hash)).unwrap();
+            let mut parents = block_on(store.storage.get_change_rels(id)).unwrap();
+            assert_eq!(parents.len(), 1, "head chain is not linear");
+            hash = block_on(store.storage.get_change_hash(parents.pop().unwrap()))
+                .unwrap()
+                .0;
+        }
+        chain.reverse();
+
+        assert_eq!(chain.len(), writers * commits);
+        let mut sorted_chain = chain.clone();
+        sorted_chain.sort_unstable();
+        let mut sorted_committed = committed;
+        sorted_committed.sort_unstable();
+        assert_eq!(sorted_chain, sorted_committed, "lost updates");
+
+        let repo = block_on(store.repo_id(&RepoId(REPO))).unwrap();
+        let logged: Vec<Hash> = block_on(store.storage.changes_since(repo, 0))
+            .unwrap()
+            .into_iter()
+            .map(|(_, id)| block_on(store.storage.get_change_hash(id)).unwrap().0)
+            .collect();
+        assert_eq!(logged, chain);
+    }
+
+    #[test]
+    fn concurrent_writers() {
+        stress(&store(), 4, 25);
+        stress(&store().with_commit_queue(1), 4, 25);
+    }
+
+    #[test]
+    fn add_change() {
+        let store = store();
+        let first = change(ROOT, "a");
+        add(&store, &first).unwrap();
+        assert_eq!(log(&store), vec![1]);
+
+        // committing the head again is a retry and logs nothing
+        add(&store, &first).unwrap();
+        assert_eq!(log(&store), vec![1]);
+
+        assert!(matches!(
+            add(&store, &change(ROOT, "b")),
+            Err(Error::ValueStore(ValueStoreError::HeadParentMismatch { parent }))
+                if parent == first.hash
+        ));
+
+        let mut tampered = change(first.hash, "c");
+        tampered.hash = ROOT;
+        assert!(matches!(
+            add(&store, &tampered),
+            Err(Error::ValueStore(ValueStoreError::HashMismatch { .. }))
+        ));
+        add(&store, &change(first.hash, "c")).unwrap();
+        assert_eq!(log(&store), vec![1, 2]);
+    }
+
+    #[test]
+    fn import_export() {
+        let store = store();
+        let document = Value::Map(HashMap::from([("a".to_string(), Value::Integer(1))]).into());
+        let mut buf = Vec::new();
+        import::write_value(Format::Cbor, &document, &Redaction::new(), &mut buf).unwrap();
+        let root = store
+            .init_from_reader(RepoId(REPO), BranchId(BRANCH), Format::Cbor, buf.as_slice())
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        add(&store, &change(root, "b")).unwrap();
+
+        let mut exported = Vec::new();
+        store
+            .export_value(
+                &RepoId(REPO),
+                &BranchId(BRANCH),
+                Format::Cbor,
+                &Redaction::new().with_rule("b".parse().unwrap(), RedactAction::Mask),
+                &mut exported,
+            )
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        let value = import::read_value(Format::Cbor, exported.as_slice()).unwrap();
+        let Value::Map(map) = &value else {
+            panic!("not a map");
+        };
+        assert_eq!(map.get("a"), Some(&Value::Integer(1)));
+        assert_eq!(
+            map.get("b"),
+            Some(&Value::String(Arc::new(redact::MASK.to_string())))
+        );
+        assert_eq!(log(&store), vec![1, 2]);
+    }
+
+    #[test]
+    fn indexes() {
+        let storage: Arc<dyn DynStorage> = Arc::new(storage());
+        let store = ValueStore::new(storage.clone());
+        let (repo, branch) = (RepoId(REPO), BranchId(BRANCH));
+        let find = || {
+            let mut paths = store
+                .find_indexed(&repo, &branch, "flags", &Value::Bool(true))
+                .now_or_never()
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            paths.sort();
+            paths
+        };
+        store
+            .declare_index(&repo, &branch, "flags", "*".parse().unwrap())
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(find(), Vec::<Vec<PathElement>>::new());
+        let head = store
+            .add_chage_sets(
+                BranchId(BRANCH),
+                RepoId(REPO),
+                None,
+                None,
+                &change(ROOT, "a").content,
+            )
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        add(&store, &change(head, "b")).unwrap();
+        assert_eq!(find(), vec![vec![field("a")], vec![field("b")]]);
+        // commits of another store move the head behind the back of the indexes
+        ValueStore::new(storage)
+            .add_chage_sets(
+                BranchId(BRANCH),
+                RepoId(REPO),
+                None,
+                None,
+                &change(ROOT, "c").content,
+            )
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            find(),
+            vec![vec![field("a")], vec![field("b")], vec![field("c")]]
+        );
+        assert!(store.remove_index(&repo, &branch, "flags"));
+        assert_eq!(
+            store
+                .find_indexed(&repo, &branch, "flags", &Value::Bool(true))
+                .now_or_never()
+                .unwrap()
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn float_policy() {
+        let storage: Arc<dyn DynStorage> = Arc::new(storage());
+        let store = ValueStore::new(storage.clone());
+        let x = || -> Path { vec![field("x")].into() };
+        let commit = |branch: Uuid, change: ChangeContent| {
+            store
+                .add_chage_sets(BranchId(branch), RepoId(REPO), None, None, &[change])
+                .now_or_never()
+                .unwrap()
+                .unwrap()
+        };
+        commit(
+            BRANCH,
+            ChangeContent::Insert {
+                path: x(),
+                value: Value::Float(0.0),
+            },
+        );
+        let other = store
+            .propose(&RepoId(REPO), &BranchId(BRANCH))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        commit(
+            other.0,
+            ChangeContent::Replace {
+                path: x(),
+                old: Value::Float(-0.0),
+                new: Value::Float(0.05),
+            },
+        );
+        let diff = |store: &ValueStore| {
+            store
+                .diff(&RepoId(REPO), &BranchId(BRANCH), &other, &Redaction::new())
+                .now_or_never()
+                .unwrap()
+        };
+        assert_eq!(diff(&store).unwrap().len(), 1);
+        let epsilon = ValueStore::new(storage.clone()).with_float_policy(FloatPolicy::Epsilon(0.1));
+        assert_eq!(diff(&epsilon).unwrap(), Vec::new());
+        // -0.0 only matches 0.0 when replaying with bit exact comparison
+        let exact = ValueStore::new(storage).with_float_policy(FloatPolicy::BitExact);
+        assert!(matches!(
+            diff(&exact),
+            Err(Error::ValueStore(ValueStoreError::InvalidChange { .. }))
+        ));
+    }
+
+    #[test]
+    fn promote() {
+        let store = store().with_approval_policy(ApprovalPolicy { approvals: 2 });
+        let proposal = store
+            .propose(&RepoId(REPO), &BranchId(BRANCH))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        let commit = |name: &str| {
+            store
+                .add_chage_sets(
+                    BranchId(proposal.0),
+                    RepoId(REPO),
+                    None,
+                    None,
+                    &change(ROOT, name).content,
+                )
+                .now_or_never()
+                .unwrap()
+                .unwrap()
+        };
+        commit("a");
+        let head = commit("b");
+        let promote = || {
+            store
+                .promote(RepoId(REPO), &proposal, BranchId(BRANCH), None)
+                .now_or_never()
+                .unwrap()
+        };
+        let approve = |hash: Hash, reviewer: &str| {
+            let name = review::approval_name(reviewer);
+            store
+                .set_note(hash, &name, Some(&Value::Bool(true)))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        };
+        approve(head, "alice");
+        assert!(matches!(
+            promote(),
+            Err(Error::ValueStore(ValueStoreError::NotApproved {
+                approvals: 1,
+                required: 2
+            }))
+        ));
+        approve(head, "bob");
+        let promoted = promote().unwrap();
+        assert_eq!(
+            store.notes(promoted).now_or_never().unwrap().unwrap().len(),
+            2
+        );
+        let value = store
+            .value(&RepoId(REPO), &BranchId(BRANCH))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            value,
+            Value::Map(
+                HashMap::from([
+                    ("a".to_string(), Value::Bool(true)),
+                    ("b".to_string(), Value::Bool(true)),
+                ])
+                .into()
+            )
+        );
+
+        // the target moved on, the proposal has to start over
+        let head = commit("c");
+        approve(head, "alice");
+        approve(head, "bob");
+        add(&store, &change(promoted, "d")).unwrap();
+        assert!(matches!(
+            promote(),
+            Err(Error::ValueStore(
+                ValueStoreError::HeadParentMismatch { .. }
+            ))
+        ));
+    }
+
+    #[test]
+    fn notes() {
+        let store = store();
+        let label = Value::String(Arc::new("reviewed".to_string()));
+        let notes = || store.notes(ROOT).now_or_never().unwrap().unwrap();
+        store
+            .set_note(ROOT, "label", Some(&label))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(notes(), BTreeMap::from([("label".to_string(), label)]));
+        store
+            .set_note(ROOT, "label", None)
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert!(notes().is_empty());
+        // notes don't change the history
+        assert_eq!(log(&store), Vec::<u64>::new());
+        assert!(matches!(
+            store.notes(Hash([7; 32])).now_or_never().unwrap(),
+            Err(Error::ValueStore(ValueStoreError::UnknownChange { .. }))
+        ));
+    }
+
+    #[test]
+    fn config() {
+        let store = store();
+        let set = |branch: Option<&BranchId>, name: &str, value: Option<Value>| {
+            store
+                .set_config(&RepoId(REPO), branch, name, value.as_ref())
+                .now_or_never()
+                .unwrap()
+        };
+        let text = |text: &str| Some(Value::String(Arc::new(text.to_string())));
+        set(None, "merge", text("ours")).unwrap();
+        set(Some(&BranchId(BRANCH)), "merge", text("theirs")).unwrap();
+        let config = |branch: Option<&BranchId>| {
+            store
+                .config(&RepoId(REPO), branch)
+                .now_or_never()
+                .unwrap()
+                .unwrap()
+        };
+        assert_eq!(config(None).get_str("merge"), Some("ours"));
+        assert_eq!(
+            config(Some(&BranchId(BRANCH))).get_str("merge"),
+            Some("theirs")
+        );
+        set(Some(&BranchId(BRANCH)), "merge", None).unwrap();
+        assert_eq!(
+            config(Some(&BranchId(BRANCH))).get_str("merge"),
+            Some("ours")
+        );
+
+        let version = || {
+            store
+                .schema_version(&RepoId(REPO), &BranchId(BRANCH))
+                .now_or_never()
+                .unwrap()
+        };
+        assert_eq!(version().unwrap(), 0);
+        store
+            .set_schema_version(&RepoId(REPO), &BranchId(BRANCH), 3)
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(version().unwrap(), 3);
+        set(None, Config::SCHEMA_VERSION, Some(Value::Integer(-1))).unwrap();
+        // the branch value takes precedence
+        assert_eq!(version().unwrap(), 3);
+        set(Some(&BranchId(BRANCH)), Config::SCHEMA_VERSION, None).unwrap();
+        assert!(matches!(
+            version(),
+            Err(Error::ValueStore(ValueStoreError::InvalidConfig { .. }))
+        ));
+        assert!(matches!(
+            set(Some(&BranchId(Uuid::max())), "merge", None),
+            Err(Error::ValueStore(ValueStoreError::UnknownBranch { .. }))
+        ));
+    }
+
+    #[test]
+    fn health_and_close() {
+        let store = store().with_commit_queue(2);
+        let health = store.health().now_or_never().unwrap();
+        assert!(health.is_ready());
+        assert!(!health.read_only);
+        assert_eq!(
+            health.commit_queue,
+            Some(QueueHealth {
+                capacity: 2,
+                available: 2
+            })
+        );
+
+        store.close().now_or_never().unwrap().unwrap();
+        assert!(matches!(
+            add(&store, &change(ROOT, "a")),
+            Err(Error::ValueStore(ValueStoreError::Closed))
+        ));
+        assert!(matches!(
+            store.set_note(ROOT, "label", None).now_or_never().unwrap(),
+            Err(Error::ValueStore(ValueStoreError::Closed))
+        ));
+
+        let store = ValueStore::open_read_only(Arc::new(storage()));
+        assert!(store.health().now_or_never().unwrap().read_only);
+        assert!(matches!(
+            add(&store, &change(ROOT, "a")),
+            Err(Error::ValueStore(ValueStoreError::ReadOnly))
+        ));
+        assert_eq!(log(&store), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn change_size() {
+        let changes = change(ROOT, "a").content;
+        let size = store().check_change_size(&changes).unwrap();
+        assert_eq!(size, encode(&changes).unwrap().len());
+
+        let store = store().with_max_change_size(size - 1);
+        assert!(matches!(
+            store.check_change_size(&changes),
+            Err(Error::ValueStore(ValueStoreError::ChangeTooLarge { limit, .. }))
+                if limit == size - 1
+        ));
+        assert!(matches!(
+            store
+                .add_chage_sets(BranchId(BRANCH), RepoId(REPO), None, None, &changes)
+                .now_or_never()
+                .unwrap(),
+            Err(Error::ValueStore(ValueStoreError::ChangeTooLarge { .. }))
+        ));
+        assert_eq!(log(&store), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn stats() {
+        let store = store();
+        let first = change(ROOT, "a");
+        add(&store, &first).unwrap();
+        add(&store, &change(first.hash, "b")).unwrap();
+        let stats = store.stats(&RepoId(REPO)).now_or_never().unwrap().unwrap();
+        assert_eq!(stats.changes, 3);
+        assert_eq!(stats.branches, 1);
+        assert_eq!(stats.branch_depths, vec![(BRANCH, 3)]);
+        assert_eq!(stats.hot_paths.len(), 2);
+        assert!(matches!(
+            store.stats(&RepoId(Uuid::max())).now_or_never().unwrap(),
+            Err(Error::ValueStore(ValueStoreError::UnknownRepo { .. }))
+        ));
+    }
+
+    #[test]
+    fn commit_faults() {
+        let first = change(ROOT, "a");
+        let operations = {
+            let storage = Arc::new(FaultStorage::new(storage(), u64::MAX, FaultPoint::Before));
+            add(&ValueStore::new(storage.clone()), &first).unwrap();
+            storage.operations()
+        };
+        for point in [FaultPoint::Before, FaultPoint::After] {
+            for fail_after in 0..operations {
+                let storage = Arc::new(FaultStorage::new(storage(), fail_after, point));
+                let store = ValueStore::new(storage.clone());
+                assert!(matches!(
+                    add(&store, &first),
+                    Err(Error::ValueStore(ValueStoreError::InjectedFault { .. }))
+                ));
+                storage.recover();
+                // retrying after the crash neither fails nor logs the change twice
+                add(&store, &first).unwrap();
+                assert_eq!(log(&store), vec![1]);
+                add(&store, &change(first.hash, "b")).unwrap();
+                assert_eq!(log(&store), vec![1, 2]);
+            }
+        }
+    }
+
+    #[test]
+    fn idempotent() {
+        let clock = Arc::new(ManualClock::new(UNIX_EPOCH));
+        let store = store()
+            .with_clock(clock.clone())
+            .with_operation_retention(Duration::from_secs(60));
+        let commit = |op_id: u128, name: &str| {
+            let changes = change(ROOT, name).content;
+            store
+                .add_change_set_idempotent(
+                    BranchId(BRANCH),
+                    RepoId(REPO),
+                    Uuid::from_u128(op_id),
+                    None,
+                    None,
+                    &changes,
+                )
+                .now_or_never()
+                .unwrap()
+                .unwrap()
+        };
+        let operations = || {
+            store
+                .operations(&RepoId(REPO), &BranchId(BRANCH))
+                .now_or_never()
+                .unwrap()
+                .unwrap()
+                .len()
+        };
+        let first = commit(1, "a");
+        assert_eq!(commit(1, "a"), first);
+        assert_eq!(log(&store), vec![1]);
+        assert_eq!(operations(), 1);
+        let config = store
+            .config(&RepoId(REPO), Some(&BranchId(BRANCH)))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert!(!config
+            .iter()
+            .any(|(name, _)| name.starts_with(OPERATION_PREFIX)));
+
+        // an expired id is not honoured even before it was removed
+        clock.advance(Duration::from_secs(61));
+        let again = commit(1, "a");
+        assert_ne!(again, first);
+        assert_eq!(log(&store), vec![1, 2]);
+        assert_eq!(operations(), 1);
+
+        let second = commit(2, "b");
+        assert_eq!(operations(), 2);
+        assert_eq!(commit(2, "b"), second);
+        clock.advance(Duration::from_secs(61));
+        commit(3, "c");
+        // the expired ids were removed with the third commit
+        assert_eq!(operations(), 1);
+        assert_eq!(log(&store), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn canonical_change_sets() {
+        let commit = |changes: Vec<ChangeContent>| {
+            store()
+                .add_chage_sets(BranchId(BRANCH), RepoId(REPO), None, None, &changes)
+                .now_or_never()
+                .unwrap()
+                .unwrap()
+        };
+        let (a, b) = (change(ROOT, "a").content, change(ROOT, "b").content);
+        assert_eq!(
+            commit([a.clone(), b.clone()].concat()),
+            commit([b, a].concat())
+        );
+    }
+
+    #[test]
+    fn locked_commits() {
+        let store = store();
+        let (repo, branch) = (RepoId(REPO), BranchId(BRANCH));
+        let lock = |path: &[PathElement], owner: &str| {
+            store
+                .lock(&repo, &branch, path, owner, Duration::from_secs(60))
+                .now_or_never()
+                .unwrap()
+        };
+        lock(&[field("a")], "ann").unwrap();
+        // renewing is fine, an overlapping lock of someone else isn't
+        lock(&[field("a")], "ann").unwrap();
+        assert!(matches!(
+            lock(&[field("a"), field("b")], "bob"),
+            Err(Error::ValueStore(ValueStoreError::PathLocked { .. }))
+        ));
+        assert_eq!(
+            store
+                .locks(&repo, &branch)
+                .now_or_never()
+                .unwrap()
+                .unwrap()
+                .len(),
+            1
+        );
+        let commit = |name: &str, ignore_hook: Option<u64>, owner: Option<&str>| {
+            store
+                .add_chage_sets(
+                    BranchId(BRANCH),
+                    RepoId(REPO),
+                    ignore_hook,
+                    owner,
+                    &change(ROOT, name).content,
+                )
+                .now_or_never()
+                .unwrap()
+        };
+        assert!(matches!(
+            commit("a", None, None),
+            Err(Error::ValueStore(ValueStoreError::PathLocked { .. }))
+        ));
+        // hooks can't be used to skip locks
+        assert!(matches!(
+            commit("a", Some(1), Some("bob")),
+            Err(Error::ValueStore(ValueStoreError::PathLocked { .. }))
+        ));
+        commit("b", None, None).unwrap();
+        commit("a", None, Some("ann")).unwrap();
+        assert_eq!(log(&store), vec![1, 2]);
+
+        assert!(matches!(
+            store
+                .unlock(&repo, &branch, &[field("a")], "bob")
+                .now_or_never()
+                .unwrap(),
+            Err(Error::ValueStore(ValueStoreError::PathLocked { .. }))
+        ));
+        store
+            .unlock(&repo, &branch, &[field("a")], "ann")
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        lock(&[field("a")], "bob").unwrap();
+    }
+
+    #[test]
+    fn migrate() {
+        fn add_tags(value: &mut Value) -> Result<()> {
+            if let Value::Map(map) = value {
+                Arc::make_mut(map).insert("tags".to_string(), Value::Array(Arc::default()));
+            }
+            Ok(())
+        }
+        let store = store();
+        let (repo, branch) = (RepoId(REPO), BranchId(BRANCH));
+        let migrations = Migrations::new().register(1, add_tags);
+        let current = Value::Map(Arc::default());
+        let migrate = || {
+            store
+                .migrate(&repo, &branch, &migrations, &current)
+                .now_or_never()
+                .unwrap()
+                .unwrap()
+        };
+        let hash = migrate().unwrap();
+        assert_eq!(
+            store.head(&repo, &branch).now_or_never().unwrap().unwrap(),
+            hash
+        );
+        assert_eq!(
+            store
+                .schema_version(&repo, &branch)
+                .now_or_never()
+                .unwrap()
+                .unwrap(),
+            1
+        );
+        assert_eq!(log(&store), vec![1]);
+        // the recorded version keeps the migration from running again
+        assert_eq!(migrate(), None);
+        assert_eq!(log(&store), vec![1]);
+    }
+
+    #[test]
+    fn normalizer() {
+        let store = store().with_normalizer(NormalizeOptions { reject_empty: true });
+        let commit = |changes: &[ChangeContent]| {
+            store
+                .add_chage_sets(BranchId(BRANCH), RepoId(REPO), None, None, changes)
+                .now_or_never()
+                .unwrap()
+        };
+        let noop = ChangeContent::Replace {
+            path: vec![field("a")].into(),
+            old: Value::Bool(true),
+            new: Value::Bool(true),
+        };
+        assert!(matches!(
+            commit(std::slice::from_ref(&noop)),
+            Err(Error::ValueStore(ValueStoreError::EmptyChangeSet))
+        ));
+        let changes = change(ROOT, "b").content;
+        let hash = commit(&[changes.clone(), vec![noop]].concat()).unwrap();
+        assert_eq!(hash, encode_change_set(ROOT, &changes).unwrap().0);
+        assert_eq!(log(&store), vec![1]);
+    }
+
+    #[cfg(feature = "mime_sniff")]
+    #[test]
+    fn mime_policy() {
+        use crate::types::value::Blob;
+
+        let png = |mime: &str| {
+            vec![ChangeContent::Insert {
+                path: vec![field("img")].into(),
+                value: Value::Blob(Arc::new(Blob {
+                    mime: mime.to_string(),
+                    data: b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec(),
+                })),
+            }]
+        };
+        let commit = |store: &ValueStore| {
+            store
+                .add_chage_sets(
+                    BranchId(BRANCH),
+                    RepoId(REPO),
+                    None,
+                    None,
+                    &png("image/gif"),
+                )
+                .now_or_never()
+                .unwrap()
+        };
+        let rejecting = store().with_mime_policy(MimePolicy::Reject);
+        assert!(matches!(
+            commit(&rejecting),
+            Err(Error::ValueStore(ValueStoreError::MimeMismatch { .. }))
+        ));
+
+        let fixing = store().with_mime_policy(MimePolicy::Fix);
+        let hash = commit(&fixing).unwrap();
+        assert_eq!(hash, encode_change_set(ROOT, &png("image/png")).unwrap().0);
+        // changes with a fixed hash can't be fixed
+        let content = png("image/gif");
+        let change = Change {
+            hash: Change::compute_hash(
+                HashAlgorithm::default(),
+                &Parents::One(hash),
+                &encode(&content).unwrap(),
+            ),
+            parents: Parents::One(hash),
+            content,
+        };
+        assert!(matches!(
+            add(&fixing, &change),
+            Err(Error::ValueStore(ValueStoreError::MimeMismatch { .. }))
+        ));
+
+        let warning = store().with_mime_policy(MimePolicy::Warn);
+        let hash = commit(&warning).unwrap();
+        let notes = warning.notes(hash).now_or_never().unwrap().unwrap();
+        assert!(notes.contains_key(mime::MIME_MISMATCH_NOTE));
+    }
+
+    #[test]
+    fn groups() {
+        let store = store().with_max_change_size(40);
+        let changes = ["a", "b", "c"]
+            .into_iter()
+            .flat_map(|name| change(ROOT, name).content)
+            .collect();
+        let parts = store.split_change_set(changes).unwrap();
+        assert!(parts.len() > 1);
+        let group = parts[0].0.group;
+        let complete = || {
+            store
+                .is_group_complete(&RepoId(REPO), group)
+                .now_or_never()
+                .unwrap()
+                .unwrap()
+        };
+        let mut hashes = Vec::new();
+        for (marker, changes) in &parts {
+            assert!(!complete());
+            let hash = store
+                .add_split_part(BranchId(BRANCH), RepoId(REPO), None, None, *marker, changes)
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+            hashes.push((*marker, hash));
+        }
+        assert!(complete());
+        let committed = store
+            .group_changes(&RepoId(REPO), group)
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(committed, hashes);
     }
 }